@@ -0,0 +1,109 @@
+//
+// shell.rs -- interactive database explorer, in the style of gdbmtool
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+// Usage: cargo run --example shell --features diagnostic -- FILE
+//
+// Opens FILE read-write, creating it if missing, and reads commands from
+// standard input.  Type "help" for the list of commands.
+
+use std::io::{self, BufRead, Write};
+
+use gdbm_native::{Gdbm, OpenOptions, ReadWrite, Result};
+
+const HELP: &str = "\
+fetch KEY          print the value of KEY
+store KEY VALUE    set the value of KEY
+delete KEY         remove KEY
+list               print every key and value
+count              print the number of records
+header             print the database header
+dir                print the bucket directory
+bucket N           print the bucket of directory entry N
+avail              print the header free space list
+cache              print bucket cache statistics
+sync               write changes to disk
+help               print this list
+quit               sync and exit";
+
+fn run(db: &mut Gdbm<ReadWrite>, line: &str, out: &mut impl Write) -> Result<()> {
+    let mut words = line.splitn(3, char::is_whitespace);
+    let command = words.next().unwrap_or_default();
+    let args = words.collect::<Vec<_>>();
+
+    match (command, args.as_slice()) {
+        ("fetch", [key]) => match db.get::<_, Vec<u8>>(*key)? {
+            Some(value) => writeln!(out, "{}", String::from_utf8_lossy(&value))?,
+            None => writeln!(out, "no such key")?,
+        },
+        ("store", [key, value]) => {
+            db.insert(key.to_string(), value.to_string())?;
+        }
+        ("delete", [key]) => {
+            if db.remove(*key)?.is_none() {
+                writeln!(out, "no such key")?;
+            }
+        }
+        ("list", []) => {
+            for kv in db.iter::<Vec<u8>, Vec<u8>>() {
+                let (key, value) = kv?;
+                writeln!(
+                    out,
+                    "{}: {}",
+                    String::from_utf8_lossy(&key),
+                    String::from_utf8_lossy(&value)
+                )?;
+            }
+        }
+        ("count", []) => writeln!(out, "{}", db.len()?)?,
+        ("header", []) => db.show_header(out)?,
+        ("dir", []) => db.show_directory(out)?,
+        ("bucket", [n]) => match n.parse() {
+            Ok(n) => db.show_bucket(n, out)?,
+            Err(_) => writeln!(out, "bad directory entry: {}", n)?,
+        },
+        ("avail", []) => db.show_avail(out)?,
+        ("cache", []) => db.show_cache(out)?,
+        ("sync", []) => db.sync()?,
+        ("help", []) => writeln!(out, "{}", HELP)?,
+        ("", []) => {}
+        _ => writeln!(out, "unknown command or wrong arguments; try help")?,
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("usage: shell FILE");
+        std::process::exit(1);
+    };
+
+    let mut db = OpenOptions::new().write().create().open(&path)?;
+    let mut out = io::stdout();
+
+    print!("gdbm> ");
+    out.flush()?;
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        match line.trim() {
+            "quit" => break,
+            line => {
+                if let Err(e) = run(&mut db, line, &mut out) {
+                    writeln!(out, "error: {}", e)?;
+                }
+            }
+        }
+
+        print!("gdbm> ");
+        out.flush()?;
+    }
+
+    db.close()
+}
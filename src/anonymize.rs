@@ -0,0 +1,244 @@
+//
+// anonymize.rs -- keys and values replaced by digests, for exports
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+use std::collections::HashSet;
+use std::io;
+
+use crate::{Anonymize, Error, Result};
+
+const BLOCK_SIZE: usize = 64;
+const DIGEST_SIZE: usize = 32;
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+const H0: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+fn compress(state: &mut [u32; 8], block: &[u8]) {
+    let mut w = [0u32; 64];
+    block
+        .chunks_exact(4)
+        .zip(w.iter_mut())
+        .for_each(|(word, w)| *w = u32::from_be_bytes(word.try_into().unwrap()));
+    (16..64).for_each(|i| {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(w[i - 7])
+            .wrapping_add(s1);
+    });
+
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    (0..64).for_each(|i| {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = h
+            .wrapping_add(s1)
+            .wrapping_add(ch)
+            .wrapping_add(K[i])
+            .wrapping_add(w[i]);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(t1);
+        d = c;
+        c = b;
+        b = a;
+        a = t1.wrapping_add(t2);
+    });
+
+    state
+        .iter_mut()
+        .zip([a, b, c, d, e, f, g, h])
+        .for_each(|(state, v)| *state = state.wrapping_add(v));
+}
+
+// SHA-256 of the concatenation of parts
+fn sha256(parts: &[&[u8]]) -> [u8; DIGEST_SIZE] {
+    let length = parts.iter().map(|part| part.len()).sum::<usize>();
+    let padding = BLOCK_SIZE - (length + 8) % BLOCK_SIZE;
+    let mut message = parts.concat();
+    message.push(0x80);
+    message.resize(length + padding + 8, 0);
+    message[length + padding..].copy_from_slice(&(length as u64 * 8).to_be_bytes());
+
+    let mut state = H0;
+    message
+        .chunks_exact(BLOCK_SIZE)
+        .for_each(|block| compress(&mut state, block));
+
+    let mut digest = [0; DIGEST_SIZE];
+    digest
+        .chunks_exact_mut(4)
+        .zip(state)
+        .for_each(|(out, word)| out.copy_from_slice(&word.to_be_bytes()));
+    digest
+}
+
+// HMAC-SHA-256 (RFC 2104) of the concatenation of parts
+fn hmac_sha256(secret: &[u8], parts: &[&[u8]]) -> [u8; DIGEST_SIZE] {
+    let mut key = [0; BLOCK_SIZE];
+    match secret.len() > BLOCK_SIZE {
+        true => key[..DIGEST_SIZE].copy_from_slice(&sha256(&[secret])),
+        false => key[..secret.len()].copy_from_slice(secret),
+    }
+
+    let ipad = key.map(|b| b ^ 0x36);
+    let opad = key.map(|b| b ^ 0x5c);
+    let inner = sha256(&[&[&ipad[..]], parts].concat());
+    sha256(&[&opad, &inner])
+}
+
+// data replaced by as many bytes of HMAC-SHA-256 in counter mode:  the
+// digests under secret of each big-endian u32 block counter and data
+pub fn mask(secret: &[u8], data: &[u8]) -> Vec<u8> {
+    mask_round(secret, data, 0)
+}
+
+// mask(), derived again for rounds above 0 by following data with the
+// big-endian u32 round
+fn mask_round(secret: &[u8], data: &[u8], round: u32) -> Vec<u8> {
+    let round = round.to_be_bytes();
+    let suffix: &[u8] = match round {
+        [0, 0, 0, 0] => &[],
+        _ => &round,
+    };
+    let mut masked = (0..data.len().div_ceil(DIGEST_SIZE) as u32)
+        .flat_map(|counter| hmac_sha256(secret, &[&counter.to_be_bytes(), data, suffix]))
+        .collect::<Vec<_>>();
+    masked.truncate(data.len());
+    masked
+}
+
+// The records of one export, as exported under the anonymize option.  An
+// anonymized key equal to one already exported is derived again, so short
+// keys don't collide.
+pub struct Anonymizer<'a> {
+    anonymize: Option<&'a Anonymize>,
+    keys: HashSet<Vec<u8>>,
+}
+
+impl<'a> Anonymizer<'a> {
+    pub fn new(anonymize: Option<&'a Anonymize>) -> Anonymizer<'a> {
+        Anonymizer {
+            anonymize,
+            keys: HashSet::new(),
+        }
+    }
+
+    pub fn record(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<(Vec<u8>, Vec<u8>)> {
+        match self.anonymize {
+            Some(Anonymize { secret, keys: true }) => {
+                let key = (0..=u32::MAX)
+                    .map(|round| mask_round(secret, &key, round))
+                    .find(|masked| !self.keys.contains(masked))
+                    .ok_or_else(|| Error::Io(io::Error::other("anonymized keys exhausted")))?;
+                self.keys.insert(key.clone());
+                Ok((key, mask(secret, &value)))
+            }
+            Some(Anonymize { secret, .. }) => Ok((key, mask(secret, &value))),
+            None => Ok((key, value)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hex(data: &[u8]) -> String {
+        data.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    #[test]
+    fn sha256_vectors() {
+        assert_eq!(
+            hex(&sha256(&[b""])),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(&sha256(&[b"a", b"bc"])),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex(&sha256(&[
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            ])),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn hmac_vectors() {
+        // RFC 4231 test cases 1, 2 and 6
+        assert_eq!(
+            hex(&hmac_sha256(&[0x0b; 20], &[b"Hi There"])),
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+        );
+        assert_eq!(
+            hex(&hmac_sha256(
+                b"Jefe",
+                &[b"what do ya want ", b"for nothing?"]
+            )),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            hex(&hmac_sha256(
+                &[0xaa; 131],
+                &[b"Test Using Larger Than Block-Size Key - Hash Key First"]
+            )),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[test]
+    fn mask_sizes() {
+        [0, 1, 32, 33, 100].into_iter().for_each(|size| {
+            let data = vec![7; size];
+            assert_eq!(mask(b"secret", &data).len(), size);
+            assert_eq!(mask(b"secret", &data), mask(b"secret", &data));
+        });
+        assert_ne!(mask(b"secret", b"value"), mask(b"other", b"value"));
+        assert_ne!(mask(b"secret", b"value"), mask(b"secret", b"valuf"));
+        assert_ne!(
+            mask(b"secret", b"value"),
+            mask_round(b"secret", b"value", 1)
+        );
+    }
+
+    #[test]
+    fn anonymizer_short_keys() {
+        let anonymize = Anonymize {
+            secret: [7; 32],
+            keys: true,
+        };
+        let mut anonymizer = Anonymizer::new(Some(&anonymize));
+        let keys = (0..=u8::MAX)
+            .map(|b| anonymizer.record(vec![b], Vec::new()).unwrap().0)
+            .collect::<HashSet<_>>();
+        assert_eq!(keys.len(), 256);
+        assert!(keys.iter().all(|key| key.len() == 1));
+    }
+}
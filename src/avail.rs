@@ -63,7 +63,7 @@ impl AvailElem {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct AvailBlock {
     pub sz: u32,
     pub next_block: u64,
@@ -79,6 +79,7 @@ impl AvailBlock {
             }
     }
 
+    #[cfg(feature = "write")]
     pub fn new(sz: u32, next_block: u64, elems: Vec<AvailElem>) -> Self {
         Self {
             sz,
@@ -107,8 +108,6 @@ impl AvailBlock {
         // maintain intrinsic: avail is always sorted by size
         elems.sort();
 
-        // todo: check for overlapping segments
-
         Ok(Self {
             sz,
             next_block,
@@ -116,10 +115,12 @@ impl AvailBlock {
         })
     }
 
+    #[cfg(feature = "write")]
     pub fn remove_elem(&mut self, sz: u32) -> Option<(u64, u32)> {
         remove_elem(&mut self.elems, sz)
     }
 
+    #[cfg(feature = "write")]
     pub fn insert_elem(&mut self, offset: u64, length: u32) {
         insert_elem(&mut self.elems, offset, length)
     }
@@ -145,6 +146,7 @@ impl AvailBlock {
 
     // Merge elements from self and other and retuen a new AvailableBlock.
     // Retuns Some(block) if combined elements fit in bolck, otherwise None.
+    #[cfg(feature = "write")]
     pub fn merge(&self, other: &Self) -> Option<Self> {
         // gather offsets and length from both blocks
         let mut offsets_and_lengths = self
@@ -187,7 +189,23 @@ impl AvailBlock {
         })
     }
 
+    // Index of an element overlapping another, if any.  Overlapping free
+    // space would be allocated twice, so callers treat it as corruption.
+    pub fn overlapping(&self) -> Option<usize> {
+        let mut order = (0..self.elems.len()).collect::<Vec<_>>();
+        order.sort_by_key(|i| self.elems[*i].addr);
+
+        order
+            .windows(2)
+            .find(|pair| {
+                let (first, second) = (&self.elems[pair[0]], &self.elems[pair[1]]);
+                first.addr + first.sz as u64 > second.addr
+            })
+            .map(|pair| pair[1])
+    }
+
     // resize Self and return a Vec of elements that can no longer be accommodated.
+    #[cfg(feature = "write")]
     pub fn resize(&mut self, size: u32) -> Vec<(u64, u32)> {
         self.sz = size;
         self.elems
@@ -197,6 +215,7 @@ impl AvailBlock {
     }
 
     // extent returns the size of this block when serialized
+    #[cfg(feature = "write")]
     pub fn extent(&self, layout: &Layout) -> u32 {
         Self::sizeof(layout, self.elems.len() as u32)
     }
@@ -279,6 +298,31 @@ mod tests {
     }
 
     #[test]
+    fn overlapping() {
+        let block = |elems: &[(u64, u32)]| super::AvailBlock {
+            sz: 6,
+            next_block: 0,
+            elems: elems
+                .iter()
+                .map(|(addr, sz)| AvailElem {
+                    addr: *addr,
+                    sz: *sz,
+                })
+                .collect(),
+        };
+
+        assert_eq!(block(&[]).overlapping(), None);
+        // adjacent, not overlapping
+        assert_eq!(block(&[(1000, 10), (1010, 10)]).overlapping(), None);
+        assert_eq!(block(&[(1000, 100), (1050, 10)]).overlapping(), Some(1));
+        assert_eq!(
+            block(&[(1095, 10), (2000, 5), (1000, 100)]).overlapping(),
+            Some(0)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "write")]
     fn test_merge_block() {
         struct Test<'a> {
             name: &'a str,
@@ -12,7 +12,7 @@ use std::collections::HashMap;
 use std::io::{self, Read, Write};
 
 use crate::avail::{self, AvailElem};
-use crate::hashutil::{hash_key, PartialKey};
+use crate::hashutil::PartialKey;
 use crate::ser::{read32, read64, write32, write64, Alignment, Layout, Offset};
 
 #[derive(Debug, Copy, Clone)]
@@ -44,9 +44,9 @@ impl BucketElement {
         }
     }
 
-    pub fn new(key: &[u8], data: &[u8], offset: u64) -> Self {
+    pub fn new(key: &[u8], data: &[u8], offset: u64, hash: u32) -> Self {
         Self {
-            hash: hash_key(key),
+            hash,
             key_start: PartialKey::new(key),
             data_ofs: offset,
             key_size: key.len() as u32,
@@ -109,6 +109,8 @@ pub struct Bucket {
 
 impl Bucket {
     pub const AVAIL: u32 = 6;
+    // CRC-32 of the serialized bucket, following it in extended databases
+    pub const CHECKSUM_SIZE: u32 = 4;
 
     pub fn new(bits: u32, len: usize, avail: Vec<AvailElem>, elements: Vec<BucketElement>) -> Self {
         elements.into_iter().fold(
@@ -136,7 +138,7 @@ impl Bucket {
         }
 
         // read av_count entries from bucket_avail[]
-        let avail = (0..av_count)
+        let mut avail = (0..av_count)
             .map(|_| AvailElem::from_reader(layout, reader))
             .collect::<io::Result<Vec<_>>>()?;
 
@@ -144,7 +146,8 @@ impl Bucket {
         (av_count..Self::AVAIL)
             .try_for_each(|_| AvailElem::from_reader(layout, reader).map(|_| ()))?;
 
-        // todo: validate and assure-sorted avail[]
+        // maintain intrinsic: avail is always sorted by size
+        avail.sort();
 
         // read misc. section
         let bits = read32(layout.endian, reader)?;
@@ -260,6 +263,7 @@ impl Bucket {
         let (elems0, elems1) = self
             .tab
             .iter()
+            .filter(|elem| elem.is_occupied())
             .copied()
             .partition::<Vec<_>, _>(|elem| elem.hash & mask == 0);
 
@@ -301,6 +305,7 @@ impl BucketCache {
         }
     }
 
+    #[cfg(any(feature = "write", feature = "diagnostic"))]
     pub fn dirty_list(&self) -> Vec<(u64, &Bucket)> {
         let mut dl = self
             .buckets
@@ -314,12 +319,22 @@ impl BucketCache {
             .collect()
     }
 
+    #[cfg(feature = "write")]
     pub fn clear_dirty(&mut self) {
         self.buckets
             .values_mut()
             .for_each(|bucket| bucket.dirty = false);
     }
 
+    pub fn cachesize(&self) -> usize {
+        self.cachesize
+    }
+
+    /// Offsets of the cached buckets, most recently used first.
+    pub fn offsets(&self) -> &[u64] {
+        &self.queue
+    }
+
     pub fn contains(&self, bucket_ofs: u64) -> bool {
         self.buckets.contains_key(&bucket_ofs)
     }
@@ -368,6 +383,7 @@ impl BucketCache {
         self.queue.iter().copied().next()
     }
 
+    #[cfg(feature = "write")]
     pub fn current_bucket_mut(&mut self) -> Option<&mut Bucket> {
         self.queue
             .first()
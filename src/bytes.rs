@@ -50,15 +50,19 @@ impl From<Bytes> for Vec<u8> {
     }
 }
 
+// Keys encoded from numbers fit in a buffer of this many bytes.
+const INLINE_SIZE: usize = 16;
+
 pub enum BytesRef<'a> {
-    WithBuffer(Vec<u8>),
+    // encoded key held on the stack, and its length
+    Inline([u8; INLINE_SIZE], usize),
     Reference(&'a [u8]),
 }
 
 impl<'a> AsRef<[u8]> for BytesRef<'a> {
     fn as_ref(&self) -> &[u8] {
         match self {
-            Self::WithBuffer(b) => b.as_ref(),
+            Self::Inline(b, len) => &b[..*len],
             Self::Reference(r) => r,
         }
     }
@@ -84,8 +88,10 @@ impl<'a> From<&'a String> for BytesRef<'a> {
 
 impl<'a> From<&'a usize> for BytesRef<'a> {
     fn from(u: &'a usize) -> BytesRef<'a> {
-        let buf = u.to_be_bytes().to_vec();
-        Self::WithBuffer(buf)
+        let bytes = u.to_be_bytes();
+        let mut buf = [0; INLINE_SIZE];
+        buf[..bytes.len()].copy_from_slice(&bytes);
+        Self::Inline(buf, bytes.len())
     }
 }
 
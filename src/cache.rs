@@ -0,0 +1,253 @@
+//
+// cache.rs -- capacity-bounded LRU cache database
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::bytes::{Bytes, BytesRef};
+use crate::{Error, Gdbm, ReadWrite, Result};
+
+// Each stored value is prefixed by the big-endian access stamp of its record.
+const STAMP_SIZE: usize = 8;
+
+/// Limits enforced by a [`BoundedCache`].  Unset limits are not enforced.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CacheLimits {
+    /// Maximum number of records.
+    pub max_records: Option<usize>,
+    /// Maximum total size of keys and values, in bytes.
+    pub max_bytes: Option<u64>,
+}
+
+struct Entry {
+    stamp: u64,
+    size: u64,
+}
+
+/// A database used as a persistent, capacity-bounded cache.  Inserting
+/// beyond the limits evicts the least recently used records.
+///
+/// Values are stored with an access stamp prefix, so a database used as a
+/// cache should only be accessed through `BoundedCache`.  Lookups update
+/// recency in memory; `sync` writes it back.
+pub struct BoundedCache {
+    db: Gdbm<ReadWrite>,
+    limits: CacheLimits,
+
+    // access log: records by key, and keys by stamp, oldest first
+    entries: HashMap<Vec<u8>, Entry>,
+    order: BTreeMap<u64, Vec<u8>>,
+    // records whose stamp is newer in memory than in the database
+    touched: HashSet<Vec<u8>>,
+    next_stamp: u64,
+    bytes: u64,
+}
+
+fn stamped(stamp: u64, value: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(STAMP_SIZE + value.len());
+    data.extend_from_slice(&stamp.to_be_bytes());
+    data.extend_from_slice(value);
+    data
+}
+
+fn unstamped(data: &[u8]) -> Result<(u64, &[u8])> {
+    (data.len() >= STAMP_SIZE)
+        .then(|| {
+            let (stamp, value) = data.split_at(STAMP_SIZE);
+            (u64::from_be_bytes(stamp.try_into().unwrap()), value)
+        })
+        .ok_or(Error::BadCacheEntry)
+}
+
+impl BoundedCache {
+    /// Use db as a cache, loading the access log from its records and
+    /// evicting records beyond the limits.
+    pub fn new(mut db: Gdbm<ReadWrite>, limits: CacheLimits) -> Result<BoundedCache> {
+        let records = db
+            .iter::<Vec<u8>, Vec<u8>>()
+            .map(|kv| {
+                kv.and_then(|(key, data)| {
+                    unstamped(&data).map(|(stamp, value)| {
+                        let size = (key.len() + value.len()) as u64;
+                        (key, Entry { stamp, size })
+                    })
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut cache = BoundedCache {
+            db,
+            limits,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            touched: HashSet::new(),
+            next_stamp: 0,
+            bytes: 0,
+        };
+
+        records.into_iter().for_each(|(key, entry)| {
+            cache.next_stamp = cache.next_stamp.max(entry.stamp + 1);
+            cache.track(key, entry);
+        });
+
+        cache.evict()?;
+
+        Ok(cache)
+    }
+
+    pub fn limits(&self) -> CacheLimits {
+        self.limits
+    }
+
+    /// Number of records held.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Total size of keys and values held, in bytes.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    pub fn contains_key<'a, K: Into<BytesRef<'a>>>(&self, key: K) -> bool {
+        self.entries.contains_key(key.into().as_ref())
+    }
+
+    /// Look up a record, marking it as most recently used.
+    pub fn get<'a, K: Into<BytesRef<'a>>, V: From<Bytes>>(&mut self, key: K) -> Result<Option<V>> {
+        let key = key.into();
+        let Some(data) = self.db.get::<_, Vec<u8>>(key.as_ref())? else {
+            return Ok(None);
+        };
+
+        let value = unstamped(&data)?.1.to_vec();
+        let key = key.as_ref().to_vec();
+        self.touch(&key);
+        self.touched.insert(key);
+
+        Ok(Some(Bytes::from(value).into()))
+    }
+
+    /// Insert or replace a record, then evict least recently used records
+    /// until the cache is within its limits.  Returns the evicted keys.
+    pub fn insert<K: Into<Bytes>, V: Into<Bytes>>(
+        &mut self,
+        key: K,
+        value: V,
+    ) -> Result<Vec<Vec<u8>>> {
+        let key = key.into().into_vec();
+        let value = value.into().into_vec();
+        let stamp = self.stamp();
+
+        self.db.insert(key.clone(), stamped(stamp, &value))?;
+
+        self.untrack(&key);
+        let size = (key.len() + value.len()) as u64;
+        self.track(key, Entry { stamp, size });
+
+        self.evict()
+    }
+
+    pub fn remove<'a, K: Into<BytesRef<'a>>>(&mut self, key: K) -> Result<Option<Vec<u8>>> {
+        let key = key.into();
+        match self.db.remove(key.as_ref())? {
+            Some(data) => {
+                self.untrack(key.as_ref());
+                Ok(Some(unstamped(&data)?.1.to_vec()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Write recency changes from lookups back to the database, then sync it.
+    ///
+    /// Each record looked up since the last sync is read and stored again
+    /// with its new stamp, so this costs a lookup and an insert per record.
+    /// Records not yet rewritten when an error occurs are rewritten by the
+    /// next sync.
+    pub fn sync(&mut self) -> Result<()> {
+        let touched = self.touched.iter().cloned().collect::<Vec<_>>();
+        touched.into_iter().try_for_each(|key| -> Result<()> {
+            if let Some(stamp) = self.entries.get(&key).map(|entry| entry.stamp) {
+                if let Some(data) = self.db.get::<_, Vec<u8>>(&key)? {
+                    let value = unstamped(&data)?.1;
+                    self.db.insert(key.clone(), stamped(stamp, value))?;
+                }
+            }
+
+            self.touched.remove(&key);
+            Ok(())
+        })?;
+
+        self.db.sync()
+    }
+
+    /// Sync, then return the underlying database.
+    pub fn into_inner(mut self) -> Result<Gdbm<ReadWrite>> {
+        self.sync().map(|_| self.db)
+    }
+
+    fn stamp(&mut self) -> u64 {
+        self.next_stamp += 1;
+        self.next_stamp - 1
+    }
+
+    fn track(&mut self, key: Vec<u8>, entry: Entry) {
+        self.bytes += entry.size;
+        self.order.insert(entry.stamp, key.clone());
+        self.entries.insert(key, entry);
+    }
+
+    fn untrack(&mut self, key: &[u8]) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.bytes -= entry.size;
+            self.order.remove(&entry.stamp);
+        }
+        self.touched.remove(key);
+    }
+
+    fn touch(&mut self, key: &[u8]) {
+        let stamp = self.stamp();
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.order.remove(&entry.stamp);
+            self.order.insert(stamp, key.to_vec());
+            entry.stamp = stamp;
+        }
+    }
+
+    fn over_limits(&self) -> bool {
+        self.limits
+            .max_records
+            .is_some_and(|max| self.entries.len() > max)
+            || self.limits.max_bytes.is_some_and(|max| self.bytes > max)
+    }
+
+    fn evict(&mut self) -> Result<Vec<Vec<u8>>> {
+        let mut evicted = Vec::new();
+
+        while self.over_limits() {
+            // untracked only once removed, to be evicted again should the
+            // remove fail
+            let Some((_, key)) = self.order.first_key_value() else {
+                break;
+            };
+            let key = key.clone();
+
+            self.db.remove(&key)?;
+            self.untrack(&key);
+            evicted.push(key);
+        }
+
+        Ok(evicted)
+    }
+}
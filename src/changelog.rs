@@ -0,0 +1,74 @@
+//
+// changelog.rs -- keys changed through a handle, for differential exports
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+use std::collections::HashMap;
+
+// The keys stored or removed through a handle, each with the generation of
+// its last change.  Changes before the generation the log was started at
+// are unknown.
+pub struct Changelog {
+    start: u64,
+    changed: HashMap<Vec<u8>, u64>,
+}
+
+impl Changelog {
+    pub fn new(start: u64) -> Self {
+        Changelog {
+            start,
+            changed: HashMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, key: &[u8], generation: u64) {
+        match self.changed.get_mut(key) {
+            Some(changed) => *changed = generation,
+            None => {
+                self.changed.insert(key.to_vec(), generation);
+            }
+        }
+    }
+
+    // the keys changed after generation, sorted, or None if changes then
+    // may not have been logged, or generation is past current
+    pub fn since(&self, generation: u64, current: u64) -> Option<Vec<Vec<u8>>> {
+        if generation < self.start || generation > current {
+            return None;
+        }
+
+        let mut keys = self
+            .changed
+            .iter()
+            .filter(|(_, changed)| **changed > generation)
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        keys.sort();
+
+        Some(keys)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn since() {
+        let mut log = Changelog::new(5);
+        log.record(b"a", 6);
+        log.record(b"b", 7);
+        log.record(b"a", 8);
+
+        assert_eq!(log.since(4, 8), None);
+        assert_eq!(log.since(5, 8), Some(vec![b"a".to_vec(), b"b".to_vec()]));
+        assert_eq!(log.since(7, 8), Some(vec![b"a".to_vec()]));
+        assert_eq!(log.since(8, 8), Some(vec![]));
+        assert_eq!(log.since(9, 8), None);
+    }
+}
@@ -0,0 +1,59 @@
+//
+// codec.rs -- value transformations applied on write and read
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+use crate::Result;
+
+/// A reversible transformation of record values, such as compression or
+/// encryption.  Keys are stored unchanged.
+pub trait Codec: Send + Sync {
+    /// Transform a value before it is stored.
+    fn encode(&self, value: Vec<u8>) -> Result<Vec<u8>>;
+    /// Recover a value from its stored form.
+    fn decode(&self, data: Vec<u8>) -> Result<Vec<u8>>;
+}
+
+/// The codecs of a database, in the order they were added to
+/// [`OpenOptions`](crate::OpenOptions).  Values are encoded by each codec in
+/// turn, and decoded in reverse order.
+#[derive(Clone, Default)]
+pub struct Codecs(Vec<Arc<dyn Codec>>);
+
+impl Codecs {
+    pub(crate) fn push(&mut self, codec: Box<dyn Codec>) {
+        self.0.push(codec.into());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    #[cfg(feature = "write")]
+    pub(crate) fn encode(&self, value: Vec<u8>) -> Result<Vec<u8>> {
+        self.0
+            .iter()
+            .try_fold(value, |value, codec| codec.encode(value))
+    }
+
+    pub(crate) fn decode(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+        self.0
+            .iter()
+            .rev()
+            .try_fold(data, |data, codec| codec.decode(data))
+    }
+}
+
+impl Debug for Codecs {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "Codecs({})", self.0.len())
+    }
+}
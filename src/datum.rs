@@ -0,0 +1,105 @@
+//
+// datum.rs -- keys and values that display readably
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+use std::fmt::{self, Debug, Display, Formatter};
+
+use crate::bytes::{Bytes, BytesRef};
+
+/// A key or value as bytes, formatted as a quoted string when the bytes are
+/// valid UTF-8, and as hex (`0x...`) otherwise, so binary data stays
+/// readable and unambiguous in logs.
+///
+/// Errors carry keys as `Datum`, and iterators can return them, as in
+/// `db.iter::<Datum, Datum>()`.
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Datum(Vec<u8>);
+
+impl Datum {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// The bytes as a string, if they are valid UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        std::str::from_utf8(&self.0).ok()
+    }
+
+    pub fn into_vec(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl Display for Datum {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.as_str() {
+            Some(s) => write!(f, "{:?}", s),
+            None => {
+                write!(f, "0x")?;
+                self.0.iter().try_for_each(|b| write!(f, "{:02x}", b))
+            }
+        }
+    }
+}
+
+impl Debug for Datum {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Datum({})", self)
+    }
+}
+
+impl AsRef<[u8]> for Datum {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for Datum {
+    fn from(v: Vec<u8>) -> Self {
+        Self(v)
+    }
+}
+
+impl From<&[u8]> for Datum {
+    fn from(bs: &[u8]) -> Self {
+        Self(bs.to_vec())
+    }
+}
+
+impl From<Bytes> for Datum {
+    fn from(b: Bytes) -> Self {
+        Self(b.into_vec())
+    }
+}
+
+impl From<Datum> for Bytes {
+    fn from(d: Datum) -> Self {
+        Bytes::from(d.0)
+    }
+}
+
+impl<'a> From<&'a Datum> for BytesRef<'a> {
+    fn from(d: &'a Datum) -> BytesRef<'a> {
+        BytesRef::from(d.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn display() {
+        assert_eq!(Datum::from(b"key".to_vec()).to_string(), "\"key\"");
+        assert_eq!(Datum::from(b"a\"b\n".to_vec()).to_string(), "\"a\\\"b\\n\"");
+        assert_eq!(Datum::from(vec![0xff, 0x00, 0x1a]).to_string(), "0xff001a");
+        assert_eq!(Datum::default().to_string(), "\"\"");
+        assert_eq!(format!("{:?}", Datum::from(vec![0x80])), "Datum(0x80)");
+    }
+}
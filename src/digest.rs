@@ -0,0 +1,88 @@
+//
+// digest.rs -- large keys stored by digest
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+use crate::{Error, Result};
+
+// An extended database created with digest keys stores each key longer
+// than its threshold under a digest key: PREFIX followed by the 128-bit
+// FNV-1a hash of the key.  The record value is then the big-endian u64
+// length of the key, the key, and the value.
+//
+// Keys starting with PREFIX are stored by digest whatever their length, so
+// any stored key of that form is a digest key.
+const PREFIX: &[u8; 8] = b"\0gdbmkd\0";
+const DIGEST_SIZE: usize = 16;
+
+const FNV_OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+const FNV_PRIME: u128 = 0x0000000001000000000000000000013b;
+
+fn fnv1a_128(data: &[u8]) -> u128 {
+    data.iter().fold(FNV_OFFSET_BASIS, |hash, b| {
+        (hash ^ *b as u128).wrapping_mul(FNV_PRIME)
+    })
+}
+
+// whether a key of a database with the given threshold is stored by digest
+pub fn is_large(key: &[u8], threshold: u32) -> bool {
+    key.len() > threshold as usize || key.starts_with(PREFIX)
+}
+
+pub fn digest_key(key: &[u8]) -> Vec<u8> {
+    [&PREFIX[..], &fnv1a_128(key).to_be_bytes()].concat()
+}
+
+pub fn is_digest_key(key: &[u8]) -> bool {
+    key.len() == PREFIX.len() + DIGEST_SIZE && key.starts_with(PREFIX)
+}
+
+#[cfg(feature = "write")]
+pub fn pack(key: &[u8], value: &[u8]) -> Vec<u8> {
+    [&(key.len() as u64).to_be_bytes(), key, value].concat()
+}
+
+pub fn unpack(mut data: Vec<u8>) -> Result<(Vec<u8>, Vec<u8>)> {
+    let length = data
+        .first_chunk::<8>()
+        .map(|length| u64::from_be_bytes(*length))
+        .filter(|length| *length <= (data.len() - 8) as u64)
+        .ok_or(Error::BadDigestRecord)? as usize;
+
+    let value = data.split_off(8 + length);
+    data.drain(..8);
+
+    Ok((data, value))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fnv1a() {
+        // reference values of 128-bit FNV-1a
+        assert_eq!(fnv1a_128(b""), FNV_OFFSET_BASIS);
+        assert_eq!(fnv1a_128(b"a"), 0xd228cb696f1a8caf78912b704e4a8964);
+    }
+
+    #[test]
+    #[cfg(feature = "write")]
+    fn pack_unpack() {
+        let data = pack(b"key", b"value");
+        assert_eq!(unpack(data).unwrap(), (b"key".to_vec(), b"value".to_vec()));
+        assert!(unpack(pack(b"key", b"")[..10].to_vec()).is_err());
+        assert!(unpack(vec![0; 4]).is_err());
+
+        assert!(is_digest_key(&digest_key(b"key")));
+        assert!(!is_digest_key(b"key"));
+        assert!(is_large(&digest_key(b"key"), 100));
+        assert!(is_large(&[0; 101], 100));
+        assert!(!is_large(&[0; 100], 100));
+    }
+}
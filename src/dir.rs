@@ -8,35 +8,19 @@
 // file in the root directory of this project.
 // SPDX-License-Identifier: MIT
 
+use std::collections::HashSet;
 use std::io::{self, Read, Write};
 
-use crate::hashutil::HASH_BITS;
 use crate::ser::{read32, read64, write32, write64, Layout, Offset};
 
-pub fn build_dir_size(offset: Offset, block_sz: u32) -> (u32, u32) {
-    let block_sz = block_sz.max(512);
-
-    let mut dir_size = 8 * match offset {
-        Offset::Small => 4,
-        Offset::LFS => 8,
-    };
-    let mut dir_bits = 3;
-
-    while dir_size < block_sz && dir_bits < HASH_BITS - 3 {
-        dir_size <<= 1;
-        dir_bits += 1;
-    }
-
-    (dir_size, dir_bits)
-}
-
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Directory {
     pub dir: Vec<u64>,
     pub dirty: bool,
 }
 
 impl Directory {
+    #[cfg(feature = "write")]
     pub fn new(bucket_offsets: Vec<u64>) -> Self {
         Self {
             dir: bucket_offsets,
@@ -66,19 +50,21 @@ impl Directory {
     }
 
     // double the dir size by duplicating every element
+    #[cfg(feature = "write")]
     pub fn extend(&self) -> Self {
         Self {
             dir: self
                 .dir
                 .iter()
                 .cloned()
-                .flat_map(|offset| std::iter::repeat(offset).take(2))
+                .flat_map(|offset| std::iter::repeat_n(offset, 2))
                 .collect(),
             dirty: true,
         }
     }
 
     // serialized size of this instance
+    #[cfg(feature = "write")]
     pub fn extent(&self, layout: &Layout) -> u32 {
         match layout.offset {
             Offset::Small => self.dir.len() as u32 * 4,
@@ -93,10 +79,41 @@ impl Directory {
             .all(|&offset| offset >= start && offset + bucket_size as u64 <= end)
     }
 
+    // The runs of entries pointing to each bucket, as (start, length).  Each
+    // bucket must be pointed to by a single run whose length is a power of
+    // two and whose start is a multiple of its length.  Returns the index of
+    // the first entry breaking this rule otherwise.
+    pub fn runs(&self) -> std::result::Result<Vec<(usize, usize)>, usize> {
+        let mut seen = HashSet::new();
+        let mut runs = Vec::new();
+        let mut start = 0;
+
+        while start < self.dir.len() {
+            let offset = self.dir[start];
+            let length = self.dir[start..]
+                .iter()
+                .take_while(|entry| **entry == offset)
+                .count();
+
+            if !seen.insert(offset) {
+                return Err(start);
+            }
+            if !length.is_power_of_two() || !start.is_multiple_of(length) {
+                return Err(start);
+            }
+
+            runs.push((start, length));
+            start += length;
+        }
+
+        Ok(runs)
+    }
+
     // update_bucket_split is called after a bucket is split.
     // It finds the range of dir entries matching the one at offset,
     // based on dir_bits and bucket_bits.
     // It then replaces the second half of those offsets with the new bucket offset.
+    #[cfg(feature = "write")]
     pub fn update_bucket_split(
         &mut self,
         dir_bits: u32,
@@ -119,10 +136,28 @@ impl Directory {
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "write"))]
 mod test {
     use super::Directory;
 
+    #[test]
+    fn runs() {
+        let runs = |dir: &[u64]| Directory::new(dir.to_vec()).runs();
+
+        assert_eq!(runs(&[1, 1, 1, 1]), Ok(vec![(0, 4)]));
+        assert_eq!(
+            runs(&[1, 1, 2, 3, 4, 4, 4, 4]),
+            Ok(vec![(0, 2), (2, 1), (3, 1), (4, 4)])
+        );
+
+        // length not a power of two
+        assert_eq!(runs(&[1, 1, 1, 2]), Err(0));
+        // misaligned run
+        assert_eq!(runs(&[1, 2, 2, 3]), Err(1));
+        // bucket pointed to by two runs
+        assert_eq!(runs(&[1, 2, 1, 3]), Err(2));
+    }
+
     #[test]
     fn test_extend() {
         struct Test<'a> {
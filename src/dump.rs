@@ -0,0 +1,199 @@
+//
+// dump.rs -- binary dumps with an index of their records
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::bytes::{Bytes, BytesRef};
+use crate::ser::{read64, write32, write64, Alignment, Endian};
+use crate::{Error, Result};
+
+// An indexed binary dump is a binary dump followed by an index of its
+// records:
+//
+//   the header and records of a binary dump
+//   a datum length of all ones, ending the records
+//   per record: u64 key length, key, u64 value offset, u64 value length
+//   u64 index offset, u64 number of records, INDEX_MAGIC
+//
+// Numbers are big-endian, and offsets are from the start of the dump.
+// Importers stop at the end of the records, so the index is ignored.
+const INDEX_MAGIC: &[u8; 8] = b"GDBMIDX1";
+const TRAILER_SIZE: i64 = 24;
+
+// key, value offset and value length of each record
+pub(crate) type DumpIndex = Vec<(Vec<u8>, u64, u64)>;
+
+pub(crate) fn write_index(
+    outf: &mut (impl Write + Seek),
+    alignment: Alignment,
+    start: u64,
+    index: DumpIndex,
+) -> io::Result<()> {
+    match alignment {
+        Alignment::Align32 => write32(Endian::Big, outf, u32::MAX)?,
+        Alignment::Align64 => write64(Endian::Big, outf, u64::MAX)?,
+    }
+
+    let index_offset = outf.stream_position()? - start;
+    let count = index.len() as u64;
+    index.into_iter().try_for_each(|(key, offset, length)| {
+        write64(Endian::Big, outf, key.len() as u64)?;
+        outf.write_all(&key)?;
+        write64(Endian::Big, outf, offset)?;
+        write64(Endian::Big, outf, length)
+    })?;
+
+    write64(Endian::Big, outf, index_offset)?;
+    write64(Endian::Big, outf, count)?;
+    outf.write_all(INDEX_MAGIC)
+}
+
+/// Reads values from a binary dump written with
+/// [`ExportOptions::bin_index`](crate::ExportOptions::bin_index), without
+/// loading it into a database.  Only the keys are held in memory.
+pub struct DumpReader<F> {
+    f: F,
+    // value offset and length by key
+    index: HashMap<Vec<u8>, (u64, u64)>,
+}
+
+impl<F: Read + Seek> DumpReader<F> {
+    /// Read the index of the dump in f, which starts at the start of f.
+    pub fn new(mut f: F) -> Result<Self> {
+        let bad_index = |message| Error::Io(io::Error::new(ErrorKind::InvalidData, message));
+
+        f.seek(SeekFrom::End(-TRAILER_SIZE))
+            .map_err(|_| bad_index("no dump index"))?;
+        let index_offset = read64(Endian::Big, &mut f)?;
+        let count = read64(Endian::Big, &mut f)?;
+        let mut magic = [0; INDEX_MAGIC.len()];
+        f.read_exact(&mut magic)?;
+        if &magic != INDEX_MAGIC {
+            return Err(bad_index("no dump index"));
+        }
+
+        f.seek(SeekFrom::Start(index_offset))?;
+        let mut reader = io::BufReader::new(&mut f);
+        let index = (0..count)
+            .map(|_| {
+                let mut key = vec![0; read64(Endian::Big, &mut reader)? as usize];
+                reader.read_exact(&mut key)?;
+                let offset = read64(Endian::Big, &mut reader)?;
+                let length = read64(Endian::Big, &mut reader)?;
+                Ok((key, (offset, length)))
+            })
+            .collect::<io::Result<HashMap<_, _>>>()
+            .map_err(|e| match e.kind() {
+                ErrorKind::UnexpectedEof => bad_index("truncated dump index"),
+                _ => Error::Io(e),
+            })?;
+
+        Ok(DumpReader { f, index })
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn contains_key<'a, K: Into<BytesRef<'a>>>(&self, key: K) -> bool {
+        self.index.contains_key(key.into().as_ref())
+    }
+
+    /// The keys of the dump, in no particular order.
+    pub fn keys(&self) -> impl Iterator<Item = &[u8]> {
+        self.index.keys().map(Vec::as_slice)
+    }
+
+    pub fn get<'a, K: Into<BytesRef<'a>>, V: From<Bytes>>(&mut self, key: K) -> Result<Option<V>> {
+        let Some(&(offset, length)) = self.index.get(key.into().as_ref()) else {
+            return Ok(None);
+        };
+
+        let mut value = vec![0; length as usize];
+        self.f.seek(SeekFrom::Start(offset))?;
+        self.f.read_exact(&mut value)?;
+
+        Ok(Some(Bytes::from(value).into()))
+    }
+}
+
+// A metadata dump holds the header, directory, buckets and avail blocks of
+// a database, without its records:
+//
+//   META_MAGIC, u64 file size
+//   per block: u64 offset, u64 length, the block as stored
+//   u64 all ones, ending the blocks
+//
+// Numbers are big-endian.
+const META_MAGIC: &[u8; 8] = b"GDBMMETA";
+
+pub(crate) fn write_metadata(
+    outf: &mut impl Write,
+    file_size: u64,
+    blocks: Vec<(u64, Vec<u8>)>,
+) -> io::Result<()> {
+    outf.write_all(META_MAGIC)?;
+    write64(Endian::Big, outf, file_size)?;
+
+    blocks.into_iter().try_for_each(|(offset, block)| {
+        write64(Endian::Big, outf, offset)?;
+        write64(Endian::Big, outf, block.len() as u64)?;
+        outf.write_all(&block)
+    })?;
+
+    write64(Endian::Big, outf, u64::MAX)
+}
+
+/// Recreate the database file at path from a dump written by
+/// [`Gdbm::export_metadata`](crate::Gdbm::export_metadata).  The records
+/// are all zeros, but the file has the structure of the original, for
+/// inspecting its layout.  An existing file at path is replaced.
+pub fn restore_metadata<P: AsRef<Path>>(reader: &mut impl Read, path: P) -> Result<()> {
+    let bad_dump = || Error::Io(io::Error::new(ErrorKind::InvalidData, "bad metadata dump"));
+
+    let mut magic = [0; META_MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if &magic != META_MAGIC {
+        return Err(bad_dump());
+    }
+    let file_size = read64(Endian::Big, reader)?;
+
+    let mut f = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+    f.set_len(file_size)?;
+
+    loop {
+        let offset = read64(Endian::Big, reader)?;
+        if offset == u64::MAX {
+            break;
+        }
+        let length = read64(Endian::Big, reader)?;
+        if offset.checked_add(length).is_none_or(|end| end > file_size) {
+            return Err(bad_dump());
+        }
+
+        let mut block = vec![0; length as usize];
+        reader.read_exact(&mut block)?;
+        f.seek(SeekFrom::Start(offset))?;
+        f.write_all(&block)?;
+    }
+
+    f.sync_all().map_err(Error::Io)
+}
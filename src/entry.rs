@@ -0,0 +1,116 @@
+//
+// entry.rs -- read-modify-write of one record with a single lookup
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+use crate::bytes::Bytes;
+use crate::{Gdbm, ReadWrite, Result};
+
+// a record as found by int_get(): the key it is stored under, its element
+// in the current bucket, and its value
+struct Found {
+    key: Vec<u8>,
+    elem_ofs: usize,
+    value: Vec<u8>,
+}
+
+/// A key of a database and its record, if any, from
+/// [`Gdbm::entry`](crate::Gdbm::entry).  The key is looked up once: its
+/// record is modified, or inserted, without looking it up again.
+pub struct Entry<'a> {
+    db: &'a mut Gdbm<ReadWrite>,
+    // the key as stored, after any normalization, and its location
+    key: Vec<u8>,
+    loc: (u32, usize, u32),
+    found: Option<Found>,
+}
+
+impl<'a> Entry<'a> {
+    pub(crate) fn new(db: &'a mut Gdbm<ReadWrite>, key: Bytes) -> Result<Entry<'a>> {
+        let normalized = db.normalized(key.as_ref()).into_owned();
+        let loc = db.locate(&normalized);
+        let found = match db.int_get(&normalized, loc)? {
+            Some((elem_ofs, value)) => Some(Found {
+                key: normalized.clone(),
+                elem_ofs,
+                value,
+            }),
+            None if db.probe_unnormalized(key.as_ref(), &normalized) => db
+                .int_get(key.as_ref(), db.locate(key.as_ref()))?
+                .map(|(elem_ofs, value)| Found {
+                    key: key.as_ref().to_vec(),
+                    elem_ofs,
+                    value,
+                }),
+            None => None,
+        };
+
+        Ok(Entry {
+            db,
+            key: normalized,
+            loc,
+            found,
+        })
+    }
+
+    /// Whether the key has a record.
+    pub fn is_occupied(&self) -> bool {
+        self.found.is_some()
+    }
+
+    /// The value of the record, if the key has one.
+    pub fn value(&self) -> Option<&[u8]> {
+        self.found.as_ref().map(|found| found.value.as_slice())
+    }
+
+    /// Replace the value of the record, if the key has one, with its value
+    /// as changed by modify.  Does nothing if the key has no record.
+    pub fn and_modify<F: FnOnce(&mut Vec<u8>)>(mut self, modify: F) -> Result<Self> {
+        if let Some(found) = self.found.as_mut() {
+            modify(&mut found.value);
+            found.elem_ofs = self.db.replace_found(
+                &found.key,
+                found.elem_ofs,
+                self.key.clone(),
+                found.value.clone(),
+                self.loc,
+            )?;
+            found.key.clone_from(&self.key);
+            // storing may have extended the directory
+            self.loc = self.db.locate(&self.key);
+        }
+
+        Ok(self)
+    }
+
+    /// The value of the record, first inserting one with value default if
+    /// the key has none.
+    pub fn or_insert<D: Into<Bytes>, V: From<Bytes>>(self, default: D) -> Result<V> {
+        self.or_insert_with(|| default)
+    }
+
+    /// The value of the record, first inserting one with the value computed
+    /// by default if the key has none.
+    pub fn or_insert_with<D, V, F>(self, default: F) -> Result<V>
+    where
+        D: Into<Bytes>,
+        V: From<Bytes>,
+        F: FnOnce() -> D,
+    {
+        let value = match self.found {
+            Some(found) => found.value,
+            None => {
+                let value = default().into().into_vec();
+                self.db.insert_vacant(self.key, value.clone(), self.loc)?;
+                value
+            }
+        };
+
+        Ok(Bytes::from(value).into())
+    }
+}
@@ -1,5 +1,8 @@
 use std::{fmt::Display, fmt::Formatter, io};
 
+use crate::storage::ShortWrite;
+use crate::Datum;
+
 #[derive(Debug)]
 pub enum Error {
     /// IO error.
@@ -35,7 +38,68 @@ pub enum Error {
         /// Directory length in bytes.
         length: u32,
     },
-    /// Attempting write operation on readonly database.
+    /// Directory entries pointing to a bucket are not a single run, of a
+    /// power of two entries, starting at a multiple of its length.
+    BadDirectoryEntry {
+        /// Index of the first bad entry.
+        index: usize,
+        /// Bucket file offset.
+        offset: u64,
+    },
+    /// Bucket bits disagree with the number of directory entries pointing
+    /// to the bucket.
+    BadBucketBits {
+        /// Bucket file offset.
+        offset: u64,
+        /// Bucket bits.
+        bits: u32,
+        /// Bits implied by the directory.
+        expected_bits: u32,
+    },
+    /// A bucket holds a record whose hash belongs to another bucket.
+    BadBucketElement {
+        /// Bucket file offset.
+        offset: u64,
+        /// Elem number.
+        elem: usize,
+    },
+    /// The bucket holding a key was set aside as damaged in quarantine mode.
+    Quarantined {
+        /// Bucket file offset.
+        offset: u64,
+    },
+    /// A write to the database file failed part way, for repair.
+    ShortWrite {
+        /// Start of the write in file.
+        offset: u64,
+        /// Bytes to be written.
+        length: usize,
+        /// Bytes written, from offset, before the failure.
+        written: usize,
+        /// Cause of the failure.
+        error: io::Error,
+    },
+    /// A time-bounded operation passed its deadline.
+    TimedOut,
+    /// Another handle held the file lock for longer than the lock timeout.
+    LockTimeout,
+    /// The database file was truncated, or replaced at its path, underneath
+    /// the handle.  Open it again to read the current file.
+    FileReplaced,
+    /// The database was sealed read-only by
+    /// [`Gdbm::seal`](crate::Gdbm::seal), and can only be opened for
+    /// writing with [`force_unseal`](crate::OpenOptions::force_unseal).
+    Sealed,
+    /// The database's user version isn't the one expected by
+    /// [`expect_user_version`](crate::OpenOptions::expect_user_version).
+    VersionMismatch {
+        /// User version of the database.
+        found: u16,
+        /// User version expected.
+        expected: u16,
+    },
+    /// Attempting write operation on readonly database, or the filesystem
+    /// holding the database is readonly.
     WriteToReadonly,
     /// Block size found in header is impossibly small.
     BadHeaderBlockSize {
@@ -80,6 +144,23 @@ pub enum Error {
     },
     /// Bucket elements in header inconsistent with bucket size.
     BadHeaderBucketElems { elems: u32, expected: u32 },
+    /// Block size of a new database leaves buckets too small to split.
+    BadGeometryBucketElems {
+        /// Block size.
+        block_size: u32,
+        /// Number of elements per bucket.
+        elems: u32,
+        /// Minimum number of elements per bucket.
+        minimum: u32,
+    },
+    /// The directory of a new database doesn't fill one block, or leaves no
+    /// hash bits for buckets to split on.
+    BadGeometryDirectory {
+        /// Block size.
+        block_size: u32,
+        /// Directory bits.
+        bits: u32,
+    },
     /// Free space offset is outside of file.
     BadAvailElem {
         /// Start of the avail block in the file.
@@ -93,6 +174,17 @@ pub enum Error {
         /// File size.
         file_size: u64,
     },
+    /// Free space in an avail block overlaps other free space.
+    BadAvailOverlap {
+        /// Start of the avail block in the file.
+        block_offset: u64,
+        /// Elem number.
+        elem: usize,
+        /// Offset of free space.
+        offset: u64,
+        /// Size of free space.
+        size: u32,
+    },
     /// Avail size is 0 or blocksize in header not sufficient for header + available block.
     BadHeaderAvail {
         /// Number of avail elements per block in header.
@@ -109,6 +201,84 @@ pub enum Error {
         /// Maximum expected from header.
         maximum: u32,
     },
+    /// Growing the database would put data beyond the reach of its offsets.
+    OffsetOverflow {
+        /// Offset of the new space.
+        offset: u64,
+        /// Length of the new space.
+        length: u32,
+        /// Largest offset the database can represent.
+        maximum: u64,
+    },
+    /// More keys than a bucket holds share the hash of the key being
+    /// inserted, so no bucket split can make room for it.  The key is not
+    /// stored; keys like it need varying, say by a prefix, to be stored.
+    HashSaturation {
+        /// Hash of the key.
+        hash: u32,
+    },
+    /// Growing the database would exceed its configured maximum file size.
+    DatabaseFull {
+        /// File size the database would have grown to.
+        size: u64,
+        /// Configured maximum file size.
+        maximum: u64,
+    },
+    /// A record of a cache database is too short to hold its access stamp.
+    BadCacheEntry,
+    /// A record of a secondary index does not hold a list of primary keys.
+    BadIndexEntry,
+    /// A composite key is too short for the fields being read, or has
+    /// fields left over.
+    BadCompositeKey,
+    /// A record stored under a digest key does not hold its key.
+    BadDigestRecord,
+    /// A codec could not encode or decode a value.
+    Codec(String),
+    /// Key already exists and InsertMode::ErrorIfExists was given.
+    KeyExists,
+    /// Key not found by get_required, remove_required or update_required.
+    KeyNotFound(Datum),
+    /// Bucket contents do not match their checksum.
+    BadBucketChecksum {
+        /// Bucket file offset.
+        offset: u64,
+    },
+    /// Free space listed in a bucket lies outside the data area, or overlaps
+    /// other free space or a record.
+    BadBucketAvail {
+        /// Bucket file offset.
+        offset: u64,
+        /// Elem number.
+        elem: usize,
+        /// Offset of free space.
+        avail_offset: u64,
+        /// Size of free space.
+        size: u32,
+    },
+    /// Metadata read back after a sync differs from what was written.
+    SyncMismatch {
+        /// Start of the mismatched metadata in file.
+        offset: u64,
+        /// Length of the metadata in bytes.
+        length: u32,
+    },
+    /// The database rebuilt by compact() differs from the original, which
+    /// was left unchanged.
+    CompactMismatch {
+        /// The mismatched key, or None if the record counts differ.
+        key: Option<Datum>,
+    },
+    /// The changes since a generation are unknown to the handle: it was
+    /// opened without the changelog option, or after that generation.
+    ChangesUnavailable {
+        /// Generation the changes were asked for since.
+        generation: u64,
+    },
+    /// The header or directory was invalid, with the file changing between
+    /// reads, as while another process writes it without locking.  Opening
+    /// it again later may succeed.
+    TransientInconsistency(Box<Error>),
     /// Numsync version must be 0.
     BadNumsyncVersion {
         /// Numsync version from header.
@@ -122,8 +292,42 @@ impl Display for Error {
     }
 }
 
+impl Error {
+    // Offset of the bucket this error reports damaged, if any.
+    pub(crate) fn damaged_bucket(&self) -> Option<u64> {
+        match self {
+            Error::BadBucket { offset, .. }
+            | Error::BadBucketChecksum { offset }
+            | Error::BadBucketAvail { offset, .. }
+            | Error::BadBucketBits { offset, .. }
+            | Error::BadBucketElement { offset, .. } => Some(*offset),
+            _ => None,
+        }
+    }
+
+    // Classify an I/O error from a write: a readonly filesystem is reported
+    // as WriteToReadonly so callers can fall back to readonly operation.
+    #[cfg(feature = "write")]
+    pub(crate) fn from_write(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::ReadOnlyFilesystem => Error::WriteToReadonly,
+            _ => Error::from(e),
+        }
+    }
+}
+
 impl From<io::Error> for Error {
     fn from(e: io::Error) -> Self {
-        Error::Io(e)
+        if !e.get_ref().is_some_and(|inner| inner.is::<ShortWrite>()) {
+            return Error::Io(e);
+        }
+
+        let short = e.into_inner().unwrap().downcast::<ShortWrite>().unwrap();
+        Error::ShortWrite {
+            offset: short.offset,
+            length: short.length,
+            written: short.written,
+            error: short.error,
+        }
     }
 }
@@ -0,0 +1,62 @@
+//
+// event.rs -- notifications of database growth and slow operations
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+use std::time::Duration;
+
+/// Structural changes and slow operations reported to a database's event hook.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Event {
+    /// A full bucket was split in two.
+    BucketSplit {
+        /// Offset of the bucket that was split.
+        offset: u64,
+        /// Offset of the new bucket.
+        new_offset: u64,
+        /// Hash bits used by both buckets after the split.
+        bits: u32,
+    },
+    /// The directory doubled in size.
+    DirectoryExtended {
+        /// Directory bits after extension.
+        bits: u32,
+        /// New directory offset.
+        offset: u64,
+        /// New directory size in bytes.
+        size: u32,
+    },
+    /// The header avail list was full; half of it moved to a new block.
+    AvailBlockPushed {
+        /// Offset of the new avail block.
+        offset: u64,
+        /// Number of elements moved to it.
+        elems: usize,
+    },
+    /// An operation took at least as long as the slow operation threshold.
+    SlowOperation {
+        operation: Operation,
+        /// Time taken.
+        elapsed: Duration,
+        /// Offset of the last bucket used, if any.
+        bucket_offset: Option<u64>,
+        /// Buckets read from the file rather than found in the cache.
+        buckets_read: u64,
+        /// Bytes read from the file.
+        bytes_read: u64,
+    },
+}
+
+/// Operations timed against the slow operation threshold.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Operation {
+    Get,
+    Insert,
+    Remove,
+    Sync,
+}
@@ -0,0 +1,15 @@
+//
+// hash.rs -- key hashing, as in the file format
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+//! Key hashing, as in the file format: where a key lives in any GDBM
+//! database, given its directory bits and bucket elements.  For debugging
+//! and for sharding layers that must place keys as GDBM does.
+
+pub use crate::hashutil::{bucket_dir, hash_key, key_loc, HASH_BITS};
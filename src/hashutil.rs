@@ -11,6 +11,7 @@
 use std::io::{self, Read, Write};
 use std::iter::repeat;
 
+/// Bits of a key hash.
 pub const HASH_BITS: u32 = 31;
 
 #[derive(Copy, Clone, Debug, Default, PartialEq)]
@@ -42,7 +43,7 @@ impl PartialKey {
     }
 }
 
-// core gdbm hashing function
+/// The hash of a key, as stored in its bucket element.
 pub fn hash_key(key: &[u8]) -> u32 {
     let mut value: u32 = key.len() as u32;
     value = value.wrapping_mul(0x238F13AF);
@@ -55,12 +56,13 @@ pub fn hash_key(key: &[u8]) -> u32 {
     value
 }
 
-// hash-to-bucket lookup
+/// The directory index of the bucket for a hash: its top dir_bits bits.
 pub fn bucket_dir(dir_bits: u32, hash: u32) -> usize {
     (hash as usize) >> (HASH_BITS - dir_bits)
 }
 
-// derives hash and bucket metadata from key
+/// The hash of a key, the directory index of its bucket, and the bucket
+/// element where the search for it starts.
 pub fn key_loc(dir_bits: u32, bucket_elems: u32, key: &[u8]) -> (u32, usize, u32) {
     let hash = hash_key(key);
     let bucket = bucket_dir(dir_bits, hash);
@@ -69,6 +71,32 @@ pub fn key_loc(dir_bits: u32, bucket_elems: u32, key: &[u8]) -> (u32, usize, u32
     (hash, bucket, ofs)
 }
 
+// CRC-32 (IEEE 802.3, as used by zlib), for bucket checksums
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = match c & 1 {
+                1 => 0xEDB88320 ^ (c >> 1),
+                _ => c >> 1,
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+};
+
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, b| {
+        CRC_TABLE[((crc ^ *b as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -79,4 +107,10 @@ mod tests {
         assert_eq!(hash_key(b"hello\0"), 72084335);
         assert_eq!(hash_key(b""), 12345);
     }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(crc32(b""), 0);
+    }
 }
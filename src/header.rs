@@ -10,14 +10,32 @@
 
 use std::io::{self, Read, Write};
 
-use crate::avail::{AvailBlock, AvailElem};
-use crate::bucket::{Bucket, BucketElement};
-use crate::dir::build_dir_size;
+use crate::avail::AvailBlock;
+#[cfg(feature = "write")]
+use crate::avail::AvailElem;
+use crate::bucket::BucketElement;
+#[cfg(feature = "write")]
+use crate::hashutil::HASH_BITS;
+use crate::layout::{bucket_overhead, build_dir_size};
 use crate::magic::Magic;
-use crate::ser::{read32, read64, write32, write64, Alignment, Endian, Layout, Offset};
+#[cfg(feature = "write")]
+use crate::providers::Random;
+use crate::ser::{
+    read16, read32, read64, write16, write32, write64, Alignment, Endian, Layout, Offset,
+};
 use crate::{Error, Result};
 
-#[derive(Debug)]
+// Size of a database identifier.
+pub const ID_SIZE: usize = 16;
+
+const NUMSYNC_PAD_SIZE: usize = 24;
+
+// Splitting a bucket divides its elements between two, so it must hold at
+// least two.
+#[cfg(feature = "write")]
+const MIN_BUCKET_ELEMS: u32 = 2;
+
+#[derive(Clone, Debug)]
 pub struct Header {
     // on-disk gdbm database file header
     pub magic: Magic,
@@ -29,10 +47,22 @@ pub struct Header {
     pub bucket_elems: u32,
     pub next_block: u64,
     numsync: Option<u32>,
+    // identifier kept in the numsync header's padding, if set
+    id: Option<[u8; ID_SIZE]>,
+    // length above which keys are stored by digest, kept in the numsync
+    // header's padding of extended databases
+    digest_keys: Option<u32>,
+    // sealed read-only, kept in the numsync header's padding of extended
+    // databases
+    sealed: bool,
+    // version of the application's data, kept in the numsync header's
+    // padding; zero if none
+    user_version: u16,
 
     pub avail: AvailBlock,
 
     // following fields are calculated, not stored
+    #[cfg_attr(not(feature = "write"), allow(dead_code))]
     pub dirty: bool,
     pub layout: Layout,
 }
@@ -47,24 +77,79 @@ impl Header {
         }
     }
 
-    pub fn new(block_size: u32, layout: &Layout, dir_bits: u32, numsync: bool) -> Self {
-        let bucket_elems = (block_size - Bucket::sizeof(layout)) / BucketElement::sizeof(layout);
-        let avail_elems =
-            (block_size - Self::sizeof(layout, numsync, 0)) / AvailElem::sizeof(layout);
+    // bytes of each bucket other than its elements
+    fn bucket_overhead(layout: &Layout, magic: Magic) -> u32 {
+        bucket_overhead(layout, magic.is_extended())
+    }
+
+    #[cfg(feature = "write")]
+    pub fn new(
+        block_size: u32,
+        layout: &Layout,
+        dir_bits: u32,
+        numsync: bool,
+        extended: bool,
+    ) -> Self {
+        let magic = match extended {
+            true => Magic::extended(layout.endian, layout.offset),
+            false => Magic::new(layout.endian, layout.offset, numsync),
+        };
+        let overhead = Self::bucket_overhead(layout, magic);
+        let bucket_elems = block_size.saturating_sub(overhead) / BucketElement::sizeof(layout);
+        let avail_elems = block_size.saturating_sub(Self::sizeof(layout, magic.is_numsync(), 0))
+            / AvailElem::sizeof(layout);
         Header {
-            magic: Magic::new(layout.endian, layout.offset, numsync),
+            magic,
             block_sz: block_size,
             dir_ofs: block_size as u64,
             dir_sz: block_size,
             dir_bits,
-            bucket_sz: Bucket::sizeof(layout) + bucket_elems * BucketElement::sizeof(layout),
+            bucket_sz: overhead + bucket_elems * BucketElement::sizeof(layout),
             bucket_elems,
             next_block: block_size as u64 * 3,
             avail: AvailBlock::new(avail_elems, 0, vec![]),
             dirty: true,
             layout: *layout,
             numsync: None,
+            id: None,
+            digest_keys: None,
+            sealed: false,
+            user_version: 0,
+        }
+    }
+
+    // Check the geometry of a new header, so that a block size giving a
+    // database unable to grow is refused at create.
+    #[cfg(feature = "write")]
+    pub fn check_geometry(&self) -> Result<()> {
+        if self.bucket_elems < MIN_BUCKET_ELEMS {
+            return Err(Error::BadGeometryBucketElems {
+                block_size: self.block_sz,
+                elems: self.bucket_elems,
+                minimum: MIN_BUCKET_ELEMS,
+            });
+        }
+
+        let entry_size = match self.layout.offset {
+            Offset::Small => 4,
+            Offset::LFS => 8,
+        };
+        if self.dir_bits >= HASH_BITS || entry_size << self.dir_bits != self.dir_sz as u64 {
+            return Err(Error::BadGeometryDirectory {
+                block_size: self.block_sz,
+                bits: self.dir_bits,
+            });
+        }
+
+        if self.avail.sz == 0 {
+            return Err(Error::BadHeaderAvail {
+                elems: self.avail.sz,
+                size: AvailBlock::sizeof(&self.layout, self.avail.sz),
+                block_size: self.block_sz,
+            });
         }
+
+        Ok(())
     }
 
     pub fn from_reader(
@@ -86,10 +171,14 @@ impl Header {
             Offset::Small => read32(magic.endian(), reader)? as u64,
             Offset::LFS => read64(magic.endian(), reader)?,
         };
-        let numsync = magic
-            .is_numsync()
-            .then(|| read_numsync(magic.endian(), reader))
-            .transpose()?;
+        let (numsync, id, digest_keys, sealed, user_version) = match magic.is_numsync() {
+            true => read_numsync(magic.endian(), reader).map(
+                |(numsync, id, digest_keys, sealed, user_version)| {
+                    (Some(numsync), id, digest_keys, sealed, user_version)
+                },
+            )?,
+            false => (None, None, None, false, 0),
+        };
 
         let layout = Layout {
             offset: magic.offset(),
@@ -133,17 +222,18 @@ impl Header {
             });
         }
 
-        if bucket_sz < Bucket::sizeof(&layout) + BucketElement::sizeof(&layout) {
+        let overhead = Self::bucket_overhead(&layout, magic);
+        if bucket_sz < overhead + BucketElement::sizeof(&layout) {
             return Err(Error::BadHeaderBucketSize {
                 size: bucket_sz,
-                minimum: Bucket::sizeof(&layout) + BucketElement::sizeof(&layout),
+                minimum: overhead + BucketElement::sizeof(&layout),
             });
         }
 
-        if bucket_elems != (bucket_sz - Bucket::sizeof(&layout)) / BucketElement::sizeof(&layout) {
+        if bucket_elems != (bucket_sz - overhead) / BucketElement::sizeof(&layout) {
             return Err(Error::BadHeaderBucketElems {
                 elems: bucket_elems,
-                expected: (bucket_sz - Bucket::sizeof(&layout)) / BucketElement::sizeof(&layout),
+                expected: (bucket_sz - overhead) / BucketElement::sizeof(&layout),
             });
         }
 
@@ -161,6 +251,15 @@ impl Header {
             }
         })?;
 
+        if let Some(i) = avail.overlapping() {
+            return Err(Error::BadAvailOverlap {
+                block_offset: Self::sizeof(&layout, magic.is_numsync(), 0) as u64,
+                elem: i,
+                offset: avail.elems[i].addr,
+                size: avail.elems[i].sz,
+            });
+        }
+
         if avail.sz == 0 || block_sz < Self::sizeof(&layout, magic.is_numsync(), avail.sz) {
             return Err(Error::BadHeaderAvail {
                 elems: avail.sz,
@@ -189,6 +288,10 @@ impl Header {
             dirty: false,
             layout,
             numsync,
+            id,
+            digest_keys,
+            sealed,
+            user_version,
         })
     }
 
@@ -215,7 +318,15 @@ impl Header {
         }
 
         if self.magic.is_numsync() {
-            write_numsync(layout.endian, writer, self.numsync.unwrap_or(0))?
+            write_numsync(
+                layout.endian,
+                writer,
+                self.numsync.unwrap_or(0),
+                self.id,
+                self.digest_keys(),
+                self.sealed(),
+                self.user_version,
+            )?
         }
 
         self.avail.serialize(layout, writer)?;
@@ -223,10 +334,86 @@ impl Header {
         Ok(())
     }
 
+    // the sync counter, for numsync databases
+    pub fn numsync(&self) -> Option<u32> {
+        self.magic.is_numsync().then(|| self.numsync.unwrap_or(0))
+    }
+
+    // the database identifier, for numsync databases given one on creation
+    pub fn id(&self) -> Option<[u8; ID_SIZE]> {
+        self.id.filter(|_| self.magic.is_numsync())
+    }
+
+    // give a numsync database a new random identifier
+    #[cfg(feature = "write")]
+    pub fn set_random_id(&mut self, random: &dyn Random) {
+        if self.magic.is_numsync() {
+            self.id = Some(random_id(random));
+            self.dirty = true;
+        }
+    }
+
+    // length above which keys are stored by digest, for extended databases
+    // created with digest keys
+    pub fn digest_keys(&self) -> Option<u32> {
+        self.digest_keys.filter(|_| self.magic.is_extended())
+    }
+
+    #[cfg(feature = "write")]
+    pub fn set_digest_keys(&mut self, threshold: Option<u32>) {
+        if self.magic.is_extended() {
+            self.digest_keys = threshold;
+            self.dirty = true;
+        }
+    }
+
+    // whether an extended database is sealed read-only
+    pub fn sealed(&self) -> bool {
+        self.sealed && self.magic.is_extended()
+    }
+
+    #[cfg(feature = "write")]
+    pub fn set_sealed(&mut self, sealed: bool) {
+        if self.magic.is_extended() {
+            self.sealed = sealed;
+            self.dirty = true;
+        }
+    }
+
+    // version of the application's data, for numsync databases; zero if
+    // none was set
+    pub fn user_version(&self) -> u16 {
+        match self.magic.is_numsync() {
+            true => self.user_version,
+            false => 0,
+        }
+    }
+
+    #[cfg(feature = "write")]
+    pub fn set_user_version(&mut self, version: u16) {
+        if self.magic.is_numsync() {
+            self.user_version = version;
+            self.dirty = true;
+        }
+    }
+
+    // take over the identifier, sync counter and user version of the
+    // database this one replaces, as when compacting
+    #[cfg(feature = "write")]
+    pub fn adopt_identity(&mut self, other: &Header) {
+        if self.magic.is_numsync() {
+            self.numsync = other.numsync;
+            self.id = other.id;
+            self.user_version = other.user_version;
+            self.dirty = true;
+        }
+    }
+
+    #[cfg(feature = "write")]
     pub fn increment_numsync(&mut self) {
         if self.magic.is_numsync() {
             self.numsync = match self.numsync {
-                Some(n) => Some(n + 1),
+                Some(n) => Some(n.wrapping_add(1)),
                 None => Some(0),
             };
 
@@ -236,42 +423,105 @@ impl Header {
 
     // convert_numsync converts the header to numsync and retuns a list of
     // offset/length pairs that need to be freed (because avail is shortened).
+    // Extended databases always keep the numsync header.
+    #[cfg(feature = "write")]
     pub fn convert_numsync(&mut self, use_numsync: bool) -> Vec<(u64, u32)> {
+        if self.magic.is_extended() {
+            return Vec::new();
+        }
+
         let new_avail_sz = (self.block_sz - Self::sizeof(&self.layout, use_numsync, 0))
             / AvailElem::sizeof(&self.layout);
 
         self.magic = Magic::new(self.magic.endian(), self.magic.offset(), use_numsync);
         self.numsync = None;
+        self.id = None;
         self.dirty = true;
         self.avail.resize(new_avail_sz)
     }
 
+    // whether each bucket is followed by a checksum
+    pub fn checksums(&self) -> bool {
+        self.magic.is_extended()
+    }
+
+    #[cfg(feature = "write")]
     pub fn allocate(&mut self, size: u32) -> Option<(u64, u32)> {
         self.avail.remove_elem(size).inspect(|_| self.dirty = true)
     }
 
+    #[cfg(feature = "write")]
     pub fn free(&mut self, offset: u64, length: u32) {
         self.avail.insert_elem(offset, length);
         self.dirty = true;
     }
 }
 
-fn read_numsync(endian: Endian, reader: &mut impl Read) -> Result<u32> {
-    (0..8)
-        .map(|_| read32(endian, reader).map_err(Error::Io))
-        .collect::<Result<Vec<_>>>()
-        .and_then(|ext| match ext[0] {
-            0 => Ok(ext[1]),
-            v => Err(Error::BadNumsyncVersion { version: v }),
-        })
+// The numsync header: version, sync counter and 24 bytes of padding.  The
+// first 16 bytes of padding hold the database identifier; all zero if none.
+// The next 4 hold the digest key threshold; zero if none.  The next byte
+// holds flags, and the last 2 the user version; zero if none.
+type Numsync = (u32, Option<[u8; ID_SIZE]>, Option<u32>, bool, u16);
+
+const FLAGS_OFFSET: usize = ID_SIZE + 4;
+const FLAG_SEALED: u8 = 0x01;
+const USER_VERSION_OFFSET: usize = NUMSYNC_PAD_SIZE - 2;
+
+fn read_numsync(endian: Endian, reader: &mut impl Read) -> Result<Numsync> {
+    let version = read32(endian, reader)?;
+    if version != 0 {
+        return Err(Error::BadNumsyncVersion { version });
+    }
+    let numsync = read32(endian, reader)?;
+
+    let mut pad = [0; NUMSYNC_PAD_SIZE];
+    reader.read_exact(&mut pad)?;
+    let id: [u8; ID_SIZE] = pad[..ID_SIZE].try_into().unwrap();
+    let digest_keys = read32(endian, &mut &pad[ID_SIZE..])?;
+    let flags = pad[FLAGS_OFFSET];
+    let user_version = read16(endian, &mut &pad[USER_VERSION_OFFSET..])?;
+
+    Ok((
+        numsync,
+        (id != [0; ID_SIZE]).then_some(id),
+        (digest_keys != 0).then_some(digest_keys),
+        flags & FLAG_SEALED != 0,
+        user_version,
+    ))
 }
 
-fn write_numsync(endian: Endian, writer: &mut impl Write, numsync: u32) -> io::Result<()> {
+fn write_numsync(
+    endian: Endian,
+    writer: &mut impl Write,
+    numsync: u32,
+    id: Option<[u8; ID_SIZE]>,
+    digest_keys: Option<u32>,
+    sealed: bool,
+    user_version: u16,
+) -> io::Result<()> {
     write32(endian, writer, 0)?;
     write32(endian, writer, numsync)?;
-    write64(endian, writer, 0)?;
-    write64(endian, writer, 0)?;
-    write64(endian, writer, 0)?;
+
+    let mut pad = [0; NUMSYNC_PAD_SIZE];
+    pad[..ID_SIZE].copy_from_slice(&id.unwrap_or_default());
+    write32(endian, &mut &mut pad[ID_SIZE..], digest_keys.unwrap_or(0))?;
+    if sealed {
+        pad[FLAGS_OFFSET] |= FLAG_SEALED;
+    }
+    write16(endian, &mut &mut pad[USER_VERSION_OFFSET..], user_version)?;
+    writer.write_all(&pad)?;
 
     Ok(())
 }
+
+// A random version 4 UUID.
+#[cfg(feature = "write")]
+fn random_id(random: &dyn Random) -> [u8; ID_SIZE] {
+    let mut id = [0; ID_SIZE];
+    id.chunks_mut(8)
+        .for_each(|chunk| chunk.copy_from_slice(&random.next_u64().to_le_bytes()));
+    id[6] = (id[6] & 0x0f) | 0x40;
+    id[8] = (id[8] & 0x3f) | 0x80;
+
+    id
+}
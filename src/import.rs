@@ -1,13 +1,25 @@
 use std::io::{self, BufRead, BufReader, ErrorKind, Read};
 
+#[cfg(feature = "ascii-dump")]
 use base64::Engine;
 
 use crate::ser::Alignment;
 
+#[cfg(feature = "ascii-dump")]
+// Dumps moved between systems may have CRLF line endings.
+fn trim_cr(mut line: String) -> String {
+    if line.ends_with('\r') {
+        line.pop();
+    }
+    line
+}
+
+#[cfg(feature = "ascii-dump")]
 pub struct ASCIIImportIterator<'a> {
     buf_reader: BufReader<&'a mut dyn Read>,
 }
 
+#[cfg(feature = "ascii-dump")]
 impl<'a> ASCIIImportIterator<'a> {
     pub fn new(reader: &'a mut dyn Read) -> io::Result<Self> {
         let mut buf_reader = BufReader::new(reader);
@@ -19,15 +31,12 @@ impl<'a> ASCIIImportIterator<'a> {
     fn read_header(buf_reader: &mut BufReader<&'a mut dyn Read>) -> io::Result<Vec<String>> {
         buf_reader
             .lines()
-            .map(|line| match line {
-                Ok(s) if s.as_str().starts_with('#') => Ok(s),
-                Ok(s) => Err(io::Error::new(
-                    ErrorKind::Other,
-                    format!("bad header line: {}", s),
-                )),
+            .map(|line| match line.map(trim_cr) {
+                Ok(s) if s.starts_with('#') || s.trim().is_empty() => Ok(s),
+                Ok(s) => Err(io::Error::other(format!("bad header line: {}", s))),
                 Err(e) => Err(e),
             })
-            .take_while(|l| !l.as_ref().is_ok_and(|s| s == "# End of header"))
+            .take_while(|l| !l.as_ref().is_ok_and(|s| s.trim_end() == "# End of header"))
             .collect()
     }
 
@@ -36,7 +45,8 @@ impl<'a> ASCIIImportIterator<'a> {
             .by_ref()
             .lines()
             .next()
-            .unwrap_or(Err(io::Error::new(ErrorKind::Other, "end of input")))
+            .unwrap_or(Err(io::Error::other("end of input")))
+            .map(trim_cr)
     }
 
     fn read_base64(&mut self, length: usize) -> io::Result<Vec<u8>> {
@@ -54,40 +64,42 @@ impl<'a> ASCIIImportIterator<'a> {
 
         // read past line ending
         self.read_line().and_then(|l| {
-            l.is_empty()
+            l.trim()
+                .is_empty()
                 .then_some(())
-                .ok_or_else(|| io::Error::new(ErrorKind::Other, "unexpected data"))
+                .ok_or_else(|| io::Error::other("unexpected data"))
         })?;
 
         base64::prelude::BASE64_STANDARD
             .decode(bytes)
-            .map_err(|e| io::Error::new(ErrorKind::Other, format!("bad base64: {}", e)))
+            .map_err(|e| io::Error::other(format!("bad base64: {}", e)))
             .and_then(|decoded| {
                 (decoded.len() == length)
                     .then_some(decoded)
-                    .ok_or_else(|| io::Error::new(ErrorKind::Other, "length mismatch"))
+                    .ok_or_else(|| io::Error::other("length mismatch"))
             })
     }
 
     fn read_datum(&mut self) -> io::Result<Option<Vec<u8>>> {
-        let line = self.read_line()?;
-        match line.split_once('=') {
-            Some(("#:count", _)) => Ok(None),
-            Some(("#:len", length)) => length
-                .parse::<usize>()
-                .map_err(|e| {
-                    io::Error::new(ErrorKind::Other, format!("bad line ({}): {}", line, e))
-                })
-                .and_then(|length| self.read_base64(length))
-                .map(Some),
-            _ => Err(io::Error::new(
-                ErrorKind::Other,
-                format!("bad data ({})", line),
-            )),
+        loop {
+            let line = self.read_line()?;
+            return match line.split_once('=') {
+                Some(("#:count", _)) => Ok(None),
+                Some(("#:len", length)) => length
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|e| io::Error::other(format!("bad line ({}): {}", line, e)))
+                    .and_then(|length| self.read_base64(length))
+                    .map(Some),
+                // blank lines, comments and unknown directives
+                _ if line.starts_with('#') || line.trim().is_empty() => continue,
+                _ => Err(io::Error::other(format!("bad data ({})", line))),
+            };
         }
     }
 }
 
+#[cfg(feature = "ascii-dump")]
 impl<'a> Iterator for ASCIIImportIterator<'a> {
     type Item = io::Result<(Vec<u8>, Vec<u8>)>;
 
@@ -95,7 +107,7 @@ impl<'a> Iterator for ASCIIImportIterator<'a> {
         match self.read_datum() {
             Ok(None) => None,
             Ok(Some(key)) => match self.read_datum() {
-                Ok(None) => Some(Err(io::Error::new(ErrorKind::Other, "end of file"))),
+                Ok(None) => Some(Err(io::Error::other("end of file"))),
                 Ok(Some(value)) => Some(Ok((key, value))),
                 Err(e) => Some(Err(e)),
             },
@@ -113,9 +125,13 @@ impl<'a> BinaryImportIterator<'a> {
     pub fn new(alignment: Alignment, reader: &'a mut dyn Read) -> io::Result<Self> {
         let mut buf_reader = BufReader::new(reader);
 
-        // skip 4 header lines
-        let mut line = String::new();
-        (0..4).try_for_each(|_| buf_reader.read_line(&mut line).map(|_| ()))?;
+        // skip 4 header lines, absent from legacy (version 0) dumps.  Those
+        // start with a big-endian key length, whose first byte is never '!'
+        // for keys of sane size.
+        if buf_reader.fill_buf()?.first() == Some(&b'!') {
+            let mut line = String::new();
+            (0..4).try_for_each(|_| buf_reader.read_line(&mut line).map(|_| ()))?;
+        }
 
         Ok(Self {
             alignment,
@@ -135,12 +151,13 @@ impl<'a> BinaryImportIterator<'a> {
             .collect::<io::Result<Vec<_>>>()
             .and_then(|buf| match (self.alignment, buf.len()) {
                 (_, 0) => Ok(None),
-                (Alignment::Align32, 4) => {
-                    Ok(Some(u32::from_be_bytes(buf.try_into().unwrap()) as usize))
-                }
-                (Alignment::Align64, 8) => {
-                    Ok(Some(u64::from_be_bytes(buf.try_into().unwrap()) as usize))
-                }
+                // a length of all ones ends the records of an indexed dump
+                (Alignment::Align32, 4) => Ok(Some(u32::from_be_bytes(buf.try_into().unwrap()))
+                    .filter(|length| *length != u32::MAX)
+                    .map(|length| length as usize)),
+                (Alignment::Align64, 8) => Ok(Some(u64::from_be_bytes(buf.try_into().unwrap()))
+                    .filter(|length| *length != u64::MAX)
+                    .map(|length| length as usize)),
                 _ => Err(io::Error::new(ErrorKind::UnexpectedEof, "partial read")),
             })?;
 
@@ -162,7 +179,7 @@ impl<'a> Iterator for BinaryImportIterator<'a> {
         match self.read_datum() {
             Ok(None) => None,
             Ok(Some(key)) => match self.read_datum() {
-                Ok(None) => Some(Err(io::Error::new(ErrorKind::Other, "end of file"))),
+                Ok(None) => Some(Err(io::Error::other("end of file"))),
                 Ok(Some(value)) => Some(Ok((key, value))),
                 Err(e) => Some(Err(e)),
             },
@@ -171,7 +188,7 @@ impl<'a> Iterator for BinaryImportIterator<'a> {
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "ascii-dump"))]
 mod test {
     use super::*;
 
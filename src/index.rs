@@ -0,0 +1,202 @@
+//
+// index.rs -- secondary index maintenance
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+use crate::bytes::{Bytes, BytesRef};
+use crate::{Error, Gdbm, ReadWrite, Result};
+
+/// Derives the index key of a record from its key and value, or None if the
+/// record should not be indexed.
+pub type IndexKeyFn = Box<dyn Fn(&[u8], &[u8]) -> Option<Vec<u8>> + Send + Sync>;
+
+/// A secondary index: a second database mapping index keys, derived from
+/// each record of a primary database, back to the primary keys.
+///
+/// Each index record holds the list of primary keys sharing an index key,
+/// each prefixed by its big-endian u32 length.
+pub struct Index {
+    db: Gdbm<ReadWrite>,
+    key_fn: IndexKeyFn,
+}
+
+fn decode_keys(data: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut keys = Vec::new();
+    let mut data = data;
+
+    while !data.is_empty() {
+        if data.len() < 4 {
+            return Err(Error::BadIndexEntry);
+        }
+        let (length, rest) = data.split_at(4);
+        let length = u32::from_be_bytes(length.try_into().unwrap()) as usize;
+        if rest.len() < length {
+            return Err(Error::BadIndexEntry);
+        }
+        let (key, rest) = rest.split_at(length);
+        keys.push(key.to_vec());
+        data = rest;
+    }
+
+    Ok(keys)
+}
+
+fn encode_keys(keys: &[Vec<u8>]) -> Vec<u8> {
+    keys.iter().fold(Vec::new(), |mut data, key| {
+        data.extend_from_slice(&(key.len() as u32).to_be_bytes());
+        data.extend_from_slice(key);
+        data
+    })
+}
+
+impl Index {
+    pub fn new(db: Gdbm<ReadWrite>, key_fn: IndexKeyFn) -> Index {
+        Index { db, key_fn }
+    }
+
+    /// Primary keys of the records with the given index key.
+    pub fn primary_keys<'a, K: Into<BytesRef<'a>>>(
+        &mut self,
+        index_key: K,
+    ) -> Result<Vec<Vec<u8>>> {
+        match self.db.get::<_, Vec<u8>>(index_key)? {
+            Some(data) => decode_keys(&data),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn add(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        let Some(index_key) = (self.key_fn)(key, value) else {
+            return Ok(());
+        };
+
+        let mut keys = self.primary_keys(&index_key)?;
+        if !keys.iter().any(|k| k == key) {
+            keys.push(key.to_vec());
+            self.db.insert(index_key, encode_keys(&keys))?;
+        }
+
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        let Some(index_key) = (self.key_fn)(key, value) else {
+            return Ok(());
+        };
+
+        let mut keys = self.primary_keys(&index_key)?;
+        keys.retain(|k| k != key);
+        match keys.is_empty() {
+            true => self.db.remove(&index_key).map(|_| ()),
+            false => self.db.insert(index_key, encode_keys(&keys)).map(|_| ()),
+        }
+    }
+
+    pub fn sync(&mut self) -> Result<()> {
+        self.db.sync()
+    }
+
+    pub fn into_inner(self) -> Gdbm<ReadWrite> {
+        self.db
+    }
+}
+
+/// A database whose secondary indexes are updated on every insert and remove.
+pub struct Indexed {
+    db: Gdbm<ReadWrite>,
+    indexes: Vec<Index>,
+}
+
+impl Indexed {
+    pub fn new(db: Gdbm<ReadWrite>) -> Indexed {
+        Indexed {
+            db,
+            indexes: Vec::new(),
+        }
+    }
+
+    /// Add an index, first indexing every existing record.  Returns the
+    /// index number used for lookups.
+    pub fn add_index(&mut self, mut index: Index) -> Result<usize> {
+        self.db
+            .iter::<Vec<u8>, Vec<u8>>()
+            .try_for_each(|kv| kv.and_then(|(key, value)| index.add(&key, &value)))?;
+
+        self.indexes.push(index);
+
+        Ok(self.indexes.len() - 1)
+    }
+
+    pub fn index(&mut self, index: usize) -> &mut Index {
+        &mut self.indexes[index]
+    }
+
+    pub fn get<'a, K: Into<BytesRef<'a>>, V: From<Bytes>>(&mut self, key: K) -> Result<Option<V>> {
+        self.db.get(key)
+    }
+
+    /// Records with the given index key, looked up in the numbered index.
+    pub fn lookup<'a, K: Into<BytesRef<'a>>>(
+        &mut self,
+        index: usize,
+        index_key: K,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.indexes[index]
+            .primary_keys(index_key)?
+            .into_iter()
+            .filter_map(|key| match self.db.get::<_, Vec<u8>>(&key) {
+                Ok(Some(value)) => Some(Ok((key, value))),
+                Ok(None) => None,
+                Err(e) => Some(Err(e)),
+            })
+            .collect()
+    }
+
+    pub fn insert<K: Into<Bytes>, V: Into<Bytes>>(
+        &mut self,
+        key: K,
+        value: V,
+    ) -> Result<Option<Vec<u8>>> {
+        let key = key.into().into_vec();
+        let value = value.into().into_vec();
+
+        let old_value = self.db.insert(key.clone(), value.clone())?;
+
+        self.indexes.iter_mut().try_for_each(|index| {
+            if let Some(old_value) = &old_value {
+                index.remove(&key, old_value)?;
+            }
+            index.add(&key, &value)
+        })?;
+
+        Ok(old_value)
+    }
+
+    pub fn remove<'a, K: Into<BytesRef<'a>>>(&mut self, key: K) -> Result<Option<Vec<u8>>> {
+        let key = key.into();
+        let old_value = self.db.remove(key.as_ref())?;
+
+        if let Some(old_value) = &old_value {
+            self.indexes
+                .iter_mut()
+                .try_for_each(|index| index.remove(key.as_ref(), old_value))?;
+        }
+
+        Ok(old_value)
+    }
+
+    /// Sync the database and all of its indexes.
+    pub fn sync(&mut self) -> Result<()> {
+        self.db.sync()?;
+        self.indexes.iter_mut().try_for_each(|index| index.sync())
+    }
+
+    pub fn into_inner(self) -> (Gdbm<ReadWrite>, Vec<Index>) {
+        (self.db, self.indexes)
+    }
+}
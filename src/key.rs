@@ -0,0 +1,223 @@
+//
+// key.rs -- composite key encoding
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+use crate::{Error, Result};
+
+/// Start building a composite key from a sequence of fields.
+///
+/// Integers are encoded big-endian, with the sign bit of signed integers
+/// flipped, so keys built from the same field types compare in field order.
+/// Strings and byte strings are prefixed by their big-endian u32 length, so
+/// no two different field sequences produce the same key.
+pub fn key_builder() -> KeyBuilder {
+    KeyBuilder::default()
+}
+
+/// Start decoding the fields of a composite key, in the order they were
+/// added to its builder.
+pub fn key_reader(key: &[u8]) -> KeyReader<'_> {
+    KeyReader { data: key }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct KeyBuilder {
+    key: Vec<u8>,
+}
+
+impl KeyBuilder {
+    pub fn u8(mut self, value: u8) -> Self {
+        self.key.push(value);
+        self
+    }
+
+    pub fn u16(mut self, value: u16) -> Self {
+        self.key.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn u32(mut self, value: u32) -> Self {
+        self.key.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn u64(mut self, value: u64) -> Self {
+        self.key.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn i32(self, value: i32) -> Self {
+        self.u32(value as u32 ^ (1 << 31))
+    }
+
+    pub fn i64(self, value: i64) -> Self {
+        self.u64(value as u64 ^ (1 << 63))
+    }
+
+    pub fn bytes(mut self, value: &[u8]) -> Self {
+        self.key
+            .extend_from_slice(&(value.len() as u32).to_be_bytes());
+        self.key.extend_from_slice(value);
+        self
+    }
+
+    pub fn str(self, value: &str) -> Self {
+        self.bytes(value.as_bytes())
+    }
+
+    pub fn build(self) -> Vec<u8> {
+        self.key
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct KeyReader<'a> {
+    data: &'a [u8],
+}
+
+impl KeyReader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        if self.data.len() < N {
+            return Err(Error::BadCompositeKey);
+        }
+
+        let (field, rest) = self.data.split_at(N);
+        self.data = rest;
+
+        Ok(field.try_into().unwrap())
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        self.take::<1>().map(|b| b[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16> {
+        self.take().map(u16::from_be_bytes)
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        self.take().map(u32::from_be_bytes)
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        self.take().map(u64::from_be_bytes)
+    }
+
+    pub fn i32(&mut self) -> Result<i32> {
+        self.u32().map(|value| (value ^ (1 << 31)) as i32)
+    }
+
+    pub fn i64(&mut self) -> Result<i64> {
+        self.u64().map(|value| (value ^ (1 << 63)) as i64)
+    }
+
+    pub fn bytes(&mut self) -> Result<Vec<u8>> {
+        let length = self.u32()? as usize;
+        if self.data.len() < length {
+            return Err(Error::BadCompositeKey);
+        }
+
+        let (field, rest) = self.data.split_at(length);
+        self.data = rest;
+
+        Ok(field.to_vec())
+    }
+
+    pub fn str(&mut self) -> Result<String> {
+        self.bytes()
+            .and_then(|bytes| String::from_utf8(bytes).map_err(|_| Error::BadCompositeKey))
+    }
+
+    /// Check that every field of the key has been read.
+    pub fn finish(self) -> Result<()> {
+        match self.data.is_empty() {
+            true => Ok(()),
+            false => Err(Error::BadCompositeKey),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let key = key_builder()
+            .u8(7)
+            .u16(0x1234)
+            .str("user")
+            .i32(-5)
+            .u64(u64::MAX)
+            .bytes(&[0, 1, 2])
+            .i64(i64::MIN)
+            .u32(42)
+            .build();
+
+        let mut reader = key_reader(&key);
+        assert_eq!(reader.u8().unwrap(), 7);
+        assert_eq!(reader.u16().unwrap(), 0x1234);
+        assert_eq!(reader.str().unwrap(), "user");
+        assert_eq!(reader.i32().unwrap(), -5);
+        assert_eq!(reader.u64().unwrap(), u64::MAX);
+        assert_eq!(reader.bytes().unwrap(), vec![0, 1, 2]);
+        assert_eq!(reader.i64().unwrap(), i64::MIN);
+        assert_eq!(reader.u32().unwrap(), 42);
+        reader.finish().unwrap();
+    }
+
+    #[test]
+    fn no_collisions() {
+        // the same concatenated bytes, split differently
+        assert_ne!(
+            key_builder().str("ab").str("c").build(),
+            key_builder().str("a").str("bc").build()
+        );
+        assert_ne!(
+            key_builder().str("").str("a").build(),
+            key_builder().str("a").str("").build()
+        );
+    }
+
+    #[test]
+    fn integer_order() {
+        let signed = [i64::MIN, -1000, -1, 0, 1, 1000, i64::MAX];
+        let keys = signed
+            .iter()
+            .map(|n| key_builder().i64(*n).u32(0).build())
+            .collect::<Vec<_>>();
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+
+        let keys = [0u32, 1, 255, 256, u32::MAX]
+            .iter()
+            .map(|n| key_builder().u32(*n).build())
+            .collect::<Vec<_>>();
+        assert!(keys.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn bad_keys() {
+        // truncated integer and string
+        assert!(matches!(
+            key_reader(&[0, 0, 1]).u32(),
+            Err(Error::BadCompositeKey)
+        ));
+        let key = key_builder().str("hello").build();
+        assert!(matches!(
+            key_reader(&key[..6]).str(),
+            Err(Error::BadCompositeKey)
+        ));
+
+        // trailing fields
+        let key = key_builder().u8(1).u8(2).build();
+        let mut reader = key_reader(&key);
+        reader.u8().unwrap();
+        assert!(matches!(reader.finish(), Err(Error::BadCompositeKey)));
+    }
+}
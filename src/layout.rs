@@ -0,0 +1,189 @@
+//
+// layout.rs -- block size and file layout planning
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+//! File layout arithmetic: the sizes of the directory and buckets of a
+//! database with a given layout and block size, and estimates of file size.
+
+use crate::bucket::{Bucket, BucketElement};
+use crate::hashutil::HASH_BITS;
+use crate::ser::{Alignment, Endian, Layout, Offset};
+
+const MIN_BLOCK_SIZE: u32 = 512;
+const MAX_BLOCK_SIZE: u32 = 65536;
+
+// Buckets split when full, leaving them about ln 2 full on average.
+const BUCKET_FILL: f64 = 0.69;
+
+// Beyond this many buckets a larger block size is preferred, to keep the
+// directory small enough to stay in memory comfortably.
+const MAX_BUCKETS: f64 = 32768.0;
+
+// A block of fresh file space should hold several records.
+const RECORDS_PER_BLOCK: usize = 4;
+
+/// Initial directory size in bytes and directory bits for a requested block
+/// size.  The directory size is also the actual block size of a new
+/// database: the requested size rounded up to a power of two, at least 512.
+pub fn build_dir_size(offset: Offset, block_sz: u32) -> (u32, u32) {
+    let block_sz = block_sz.max(MIN_BLOCK_SIZE);
+
+    let mut dir_size = 8 * match offset {
+        Offset::Small => 4,
+        Offset::LFS => 8,
+    };
+    let mut dir_bits = 3;
+
+    while dir_size < block_sz && dir_bits < HASH_BITS - 3 {
+        dir_size <<= 1;
+        dir_bits += 1;
+    }
+
+    (dir_size, dir_bits)
+}
+
+/// Bytes of each bucket other than its elements.
+pub fn bucket_overhead(layout: &Layout, extended: bool) -> u32 {
+    match extended {
+        true => Bucket::sizeof(layout) + Bucket::CHECKSUM_SIZE,
+        false => Bucket::sizeof(layout),
+    }
+}
+
+/// Number of records each bucket holds.
+pub fn bucket_capacity(layout: &Layout, block_size: u32, extended: bool) -> u32 {
+    (block_size - bucket_overhead(layout, extended)) / BucketElement::sizeof(layout)
+}
+
+/// Bytes of each bucket on disk.
+pub fn bucket_size(layout: &Layout, block_size: u32, extended: bool) -> u32 {
+    bucket_overhead(layout, extended)
+        + bucket_capacity(layout, block_size, extended) * BucketElement::sizeof(layout)
+}
+
+/// Estimated number of buckets holding record_count records.
+pub fn expected_buckets(
+    layout: &Layout,
+    block_size: u32,
+    extended: bool,
+    record_count: usize,
+) -> usize {
+    let capacity = bucket_capacity(layout, block_size, extended) as f64 * BUCKET_FILL;
+    ((record_count as f64 / capacity).ceil() as usize).max(1)
+}
+
+/// Estimated size in bytes of a database file holding record_count records
+/// with the given average key and value sizes: a header block, the
+/// directory, the buckets and the records, without free space.
+pub fn expected_file_size(
+    layout: &Layout,
+    block_size: u32,
+    extended: bool,
+    record_count: usize,
+    avg_key: usize,
+    avg_value: usize,
+) -> u64 {
+    let buckets = expected_buckets(layout, block_size, extended, record_count) as u64;
+    let dir_entry = match layout.offset {
+        Offset::Small => 4,
+        Offset::LFS => 8,
+    };
+    let dir_size = (buckets.next_power_of_two() * dir_entry).max(block_size as u64);
+
+    block_size as u64
+        + dir_size
+        + buckets * bucket_size(layout, block_size, extended) as u64
+        + (record_count * (avg_key + avg_value)) as u64
+}
+
+/// Recommend a block size for a database of record_count records, with the
+/// given average key and value sizes, created with the default layout.
+///
+/// The recommendation is the smallest power of two block size from 512 to
+/// 65536 bytes that holds several average records, and whose buckets hold
+/// enough records to keep the directory small.
+pub fn recommend_block_size(record_count: usize, avg_key: usize, avg_value: usize) -> u32 {
+    let layout = Layout {
+        alignment: Alignment::Align64,
+        endian: Endian::Little,
+        offset: Offset::LFS,
+    };
+    let record_size = avg_key + avg_value;
+
+    (MIN_BLOCK_SIZE.ilog2()..=MAX_BLOCK_SIZE.ilog2())
+        .map(|bits| 1 << bits)
+        .find(|block_size| {
+            let buckets = expected_buckets(&layout, *block_size, false, record_count);
+            record_size * RECORDS_PER_BLOCK <= *block_size as usize && buckets as f64 <= MAX_BUCKETS
+        })
+        .unwrap_or(MAX_BLOCK_SIZE)
+}
+
+/// How well a database's block size suits its records, from
+/// [`Gdbm::layout_efficiency`](crate::Gdbm::layout_efficiency).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LayoutEfficiency {
+    pub block_size: u32,
+    pub records: usize,
+    pub buckets: usize,
+    pub avg_key: usize,
+    pub avg_value: usize,
+    /// Fraction of bucket elements in use, from 0.0 to 1.0.
+    pub bucket_fill: f64,
+    /// Block size to use when rewriting the database, from
+    /// [`recommend_block_size`].
+    pub recommended_block_size: u32,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn recommendations() {
+        // small records, few of them
+        assert_eq!(recommend_block_size(0, 0, 0), 512);
+        assert_eq!(recommend_block_size(100, 10, 20), 512);
+
+        // large records need blocks holding several of them
+        assert_eq!(recommend_block_size(100, 10, 1000), 4096);
+        assert_eq!(recommend_block_size(100, 10, 1_000_000), MAX_BLOCK_SIZE);
+
+        // many records need larger buckets
+        let small = recommend_block_size(100_000, 10, 20);
+        let large = recommend_block_size(10_000_000, 10, 20);
+        assert!(small < large);
+        assert!(large.is_power_of_two());
+    }
+
+    #[test]
+    fn sizes() {
+        let layout = Layout {
+            alignment: Alignment::Align64,
+            endian: Endian::Little,
+            offset: Offset::LFS,
+        };
+
+        assert_eq!(build_dir_size(Offset::LFS, 0), (512, 6));
+        assert_eq!(build_dir_size(Offset::Small, 4000), (4096, 10));
+        assert_eq!(build_dir_size(Offset::LFS, 4097), (8192, 10));
+
+        // 112 byte bucket header, 24 byte elements
+        assert_eq!(bucket_capacity(&layout, 4096, false), 166);
+        assert_eq!(bucket_size(&layout, 4096, false), 112 + 166 * 24);
+        assert_eq!(bucket_capacity(&layout, 4096, true), 165);
+        assert_eq!(bucket_size(&layout, 4096, true), 116 + 165 * 24);
+
+        assert_eq!(expected_buckets(&layout, 4096, false, 0), 1);
+        assert_eq!(
+            expected_file_size(&layout, 4096, false, 0, 0, 0),
+            2 * 4096 + bucket_size(&layout, 4096, false) as u64
+        );
+    }
+}
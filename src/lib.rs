@@ -8,41 +8,125 @@
 // file in the root directory of this project.
 // SPDX-License-Identifier: MIT
 
+#[cfg(feature = "ascii-dump")]
 extern crate base64;
 
+#[cfg(feature = "ascii-dump")]
 use base64::Engine;
 use std::any::Any;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::{self, Debug, Display, Formatter};
 use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::iter::FusedIterator;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
+mod anonymize;
 mod avail;
 mod bucket;
 mod bytes;
+#[cfg(feature = "write")]
+mod cache;
+#[cfg(feature = "write")]
+mod changelog;
+mod codec;
+mod datum;
+mod digest;
 mod dir;
+mod dump;
+#[cfg(feature = "write")]
+mod entry;
 mod error;
+mod event;
+pub mod hash;
 mod hashutil;
 mod header;
+#[cfg(feature = "write")]
 mod import;
+#[cfg(feature = "write")]
+mod index;
+mod key;
+pub mod layout;
 mod magic;
 mod options;
+#[cfg(feature = "write")]
+mod overlay;
+mod pool;
+mod providers;
+mod quarantine;
+mod records;
 mod ser;
+#[cfg(feature = "testutil")]
+mod simulation;
+mod stats;
+mod storage;
+mod sys;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+mod uring;
+#[cfg(feature = "watch")]
+mod watch;
 
 use avail::AvailBlock;
 use bucket::{Bucket, BucketCache, BucketElement};
 use bytes::{Bytes, BytesRef};
-use dir::{build_dir_size, Directory};
+#[cfg(feature = "write")]
+pub use cache::{BoundedCache, CacheLimits};
+#[cfg(feature = "write")]
+use changelog::Changelog;
+pub use codec::{Codec, Codecs};
+pub use datum::Datum;
+use dir::Directory;
+use dump::DumpIndex;
+pub use dump::{restore_metadata, DumpReader};
+#[cfg(feature = "write")]
+pub use entry::Entry;
 pub use error::Error;
-use hashutil::{bucket_dir, key_loc, PartialKey};
+pub use event::{Event, Operation};
+use hashutil::{bucket_dir, crc32, key_loc, PartialKey};
+#[cfg(feature = "write")]
+use hashutil::{hash_key, HASH_BITS};
 use header::Header;
-use import::{ASCIIImportIterator, BinaryImportIterator};
+#[cfg(all(feature = "ascii-dump", feature = "write"))]
+use import::ASCIIImportIterator;
+#[cfg(feature = "write")]
+use import::BinaryImportIterator;
+#[cfg(feature = "write")]
+pub use index::{Index, IndexKeyFn, Indexed};
+pub use key::{key_builder, key_reader, KeyBuilder, KeyReader};
+#[cfg(any(feature = "diagnostic", feature = "write"))]
+use layout::build_dir_size;
+pub use layout::{recommend_block_size, LayoutEfficiency};
 pub use magic::Magic;
-pub use options::{BlockSize, ConvertOptions, Create, OpenOptions};
-use ser::{write32, write64};
+pub use options::{
+    Anonymize, AsciiDumpOptions, AvailPolicy, BinDumpVersion, BlockSize, CompactOptions,
+    ConvertOptions, Create, DumpField, DumpVersion, ExportOptions, ImportOptions, InsertMode,
+    KeyNormalizer, KeyOptions, OpenOptions, RecordCacheOptions,
+};
+#[cfg(feature = "write")]
+pub use overlay::Overlay;
+pub use pool::{PooledHandle, ReadPool};
+pub use providers::{
+    Clock, ManualClock, Providers, Random, SeededRandom, SystemClock, SystemRandom,
+};
+pub use quarantine::QuarantinedBucket;
+use records::RecordCache;
+use ser::{read32, write32, write64};
 pub use ser::{Alignment, Endian, Layout, Offset};
+#[cfg(feature = "testutil")]
+pub use simulation::Simulation;
+use stats::Counters;
+pub use stats::{OpStats, RecordCacheStats, WriteAmplification};
 use std::fs::File;
+use storage::Storage;
+use sys::Advice;
+#[cfg(feature = "watch")]
+pub use watch::Watcher;
 
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", feature = "write"))]
 use std::os::linux::fs::MetadataExt;
-#[cfg(target_os = "macos")]
+#[cfg(all(target_os = "macos", feature = "write"))]
 use std::os::macos::fs::MetadataExt;
 
 // Our claimed GDBM lib version compatibility.  Appears in dump files.
@@ -50,6 +134,25 @@ const COMPAT_GDBM_VERSION: &str = "1.23";
 
 const IGNORE_SMALL: usize = 4;
 
+// directory entries sampled by len_estimate
+const LEN_ESTIMATE_SAMPLES: usize = 64;
+
+// random picks of random_key before counting every record instead
+const RANDOM_PICKS: usize = 64;
+
+// rereads of a header or directory that fails validation while the file is
+// changing, and the delay before the first, doubling each time
+const METADATA_RETRIES: u32 = 4;
+const METADATA_RETRY_DELAY: Duration = Duration::from_millis(10);
+
+// keys looked up in the rebuilt database by compact()
+#[cfg(feature = "write")]
+const COMPACT_SPOT_CHECKS: usize = 16;
+
+// records imported per allocation
+#[cfg(feature = "write")]
+const IMPORT_BATCH: usize = 256;
+
 pub const DEFAULT_CACHESIZE: usize = 4 * 1024 * 1024;
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -61,7 +164,41 @@ pub enum ExportBinMode {
     Exp64,
 }
 
+/// An approximate record count, from [`Gdbm::len_estimate`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LenEstimate {
+    /// Estimated number of records.
+    pub count: usize,
+    /// The record count is within this many records of the estimate, with
+    /// about 95% confidence.  Zero when the count is exact.
+    pub error: usize,
+}
+
+/// What [`Gdbm::export_changes_since`] found besides the records it wrote.
+#[cfg(feature = "write")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExportedChanges {
+    /// Keys removed since the generation given, sorted.
+    pub removed: Vec<Vec<u8>>,
+    /// Generation the changes bring a copy up to, to export the changes
+    /// since next time.
+    pub generation: u64,
+}
+
+/// What [`Gdbm::insert_detailed`] did.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InsertOutcome {
+    /// The value replaced, if the key was present.
+    pub replaced: Option<Vec<u8>>,
+    /// Whether a bucket was split to make room for the record.
+    pub caused_split: bool,
+    /// File offset the record was written at.
+    pub new_offset: u64,
+}
+
+// only ever Clean without the write feature
 #[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(not(feature = "write"), allow(dead_code))]
 enum WriteState {
     #[default]
     Clean,
@@ -71,19 +208,37 @@ enum WriteState {
 
 #[derive(Copy, Clone, Debug, Default)]
 pub struct ReadOnly;
+// settings and state of a writable handle, read only by the write feature
 #[derive(Copy, Clone, Debug, Default)]
+#[cfg_attr(not(feature = "write"), allow(dead_code))]
 pub struct ReadWrite {
     sync: bool,
     state: WriteState,
+    readonly_fs: bool,
+    punch_holes: bool,
+    preallocate: Option<u64>,
+    // end of the file space reserved by preallocation
+    reserved: u64,
+    on_drop_error: Option<fn(&Error)>,
+    strict_drop: bool,
+    closed: bool,
+    max_file_size: Option<u64>,
+    avail_policy: AvailPolicy,
+    convert_endian: Option<Endian>,
+    shadow_metadata: bool,
+    // space reserved by allocate_many(), not yet given to records
+    extent: Option<(u64, u32)>,
 }
 
+pub type EventHook = Box<dyn FnMut(&Event) + Send>;
+
 pub trait CacheBucket {
     fn cache_bucket(&mut self, offset: u64, bucket: Bucket) -> Result<()>;
 }
 
 // read and return file data stored at (ofs,total_size)
 // todo:  use Read+Seek traits rather than File
-fn read_ofs(f: &mut std::fs::File, ofs: u64, total_size: usize) -> io::Result<Vec<u8>> {
+fn read_ofs(f: &mut (impl Read + Seek), ofs: u64, total_size: usize) -> io::Result<Vec<u8>> {
     let mut data: Vec<u8> = vec![0; total_size];
 
     f.seek(SeekFrom::Start(ofs))?;
@@ -92,13 +247,309 @@ fn read_ofs(f: &mut std::fs::File, ofs: u64, total_size: usize) -> io::Result<Ve
     Ok(data)
 }
 
-// #[derive(Debug)]
+// read and validate header and directory, assuming the given alignment
+fn read_metadata(
+    f: &mut (impl Read + Seek),
+    file_size: u64,
+    alignment: Alignment,
+) -> Result<(Header, Directory)> {
+    f.seek(SeekFrom::Start(0))?;
+    let header = Header::from_reader(Some(alignment), file_size, f)?;
+
+    f.seek(SeekFrom::Start(header.dir_ofs))?;
+    let dir = Directory::from_reader(&header.layout, header.dir_sz, f)?;
+
+    // ensure all bucket offsets are reasonable
+    if !dir.validate(header.block_sz as u64, header.next_block, header.block_sz) {
+        return Err(Error::BadDirectory {
+            offset: header.dir_ofs,
+            length: header.dir_sz,
+        });
+    }
+
+    Ok((header, dir))
+}
+
+// read the header and directory, detecting the alignment if not given.
+// Alignment isn't recorded in the file, so the usual alignment for the magic
+// is tried first, falling back to the alternative if the header, directory
+// or first bucket don't make sense.
+fn detect_metadata(
+    f: &mut File,
+    file_size: u64,
+    alignment: Option<Alignment>,
+) -> Result<(Header, Directory)> {
+    match alignment {
+        Some(alignment) => read_metadata(f, file_size, alignment),
+        None => {
+            f.seek(SeekFrom::Start(0))?;
+            let preferred = Magic::from_reader(f)?.default_alignment();
+            let alternate = match preferred {
+                Alignment::Align32 => Alignment::Align64,
+                Alignment::Align64 => Alignment::Align32,
+            };
+
+            read_metadata(f, file_size, preferred)
+                .and_then(|(header, dir)| {
+                    read_bucket(f, &header, dir.dir[0]).map(|_| (header, dir))
+                })
+                .or_else(|e| {
+                    read_metadata(f, file_size, alternate)
+                        .and_then(|(header, dir)| {
+                            read_bucket(f, &header, dir.dir[0]).map(|_| (header, dir))
+                        })
+                        .map_err(|_| e)
+                })
+        }
+    }
+}
+
+// detect_metadata(), tolerating a concurrent writer without locking.  A
+// writer, such as C GDBM, may leave the header and directory briefly
+// inconsistent, so failed reads are retried while the file keeps changing.
+// Errors in a file that changed between reads are reported as
+// TransientInconsistency; errors in an unchanging file are returned as is.
+fn read_metadata_retrying(
+    f: &mut File,
+    file_size: u64,
+    alignment: Option<Alignment>,
+    clock: &dyn Clock,
+) -> Result<(Header, Directory)> {
+    let version = |f: &File| {
+        f.metadata()
+            .map(|metadata| (metadata.len(), metadata.modified().ok()))
+    };
+
+    let mut last = version(f)?;
+    let mut file_size = file_size;
+    let mut changed = false;
+    let mut attempt = 0;
+    loop {
+        let e = match detect_metadata(f, file_size, alignment) {
+            Ok(metadata) => return Ok(metadata),
+            Err(Error::Io(e)) if e.kind() != io::ErrorKind::UnexpectedEof => {
+                return Err(Error::Io(e))
+            }
+            Err(e) => e,
+        };
+
+        if attempt == METADATA_RETRIES {
+            return match changed {
+                true => Err(Error::TransientInconsistency(Box::new(e))),
+                false => Err(e),
+            };
+        }
+
+        clock.sleep(METADATA_RETRY_DELAY * (1 << attempt));
+        let current = version(f)?;
+        if current != last {
+            changed = true;
+            file_size = current.0;
+            last = current;
+        } else if !changed {
+            return Err(e);
+        }
+        attempt += 1;
+    }
+}
+
+// serialize a bucket, followed by its checksum in extended databases
+fn serialize_bucket(header: &Header, bucket: &Bucket, buffer: &mut Vec<u8>) -> io::Result<()> {
+    bucket.serialize(&header.layout, buffer)?;
+    if header.checksums() {
+        let checksum = crc32(buffer);
+        write32(header.layout.endian, buffer, checksum)?;
+    }
+
+    Ok(())
+}
+
+// elements of bucket, with their offsets, that may hold the record for a
+// key at the location given by locate()
+fn candidates(
+    bucket: &Bucket,
+    key: &[u8],
+    (key_hash, _, elem_ofs): (u32, usize, u32),
+) -> Vec<(usize, BucketElement)> {
+    let key_start = PartialKey::new(key);
+
+    (0..bucket.tab.len())
+        .map(|index| (index + elem_ofs as usize) % bucket.tab.len())
+        .map(|offset| (offset, bucket.tab[offset]))
+        .take_while(|(_, elem)| elem.is_occupied())
+        .filter(|(_, elem)| {
+            elem.hash == key_hash
+                && elem.key_size == key.len() as u32
+                && elem.key_start == key_start
+        })
+        .collect()
+}
+
+// write the offsets of the cached buckets, one per line
+fn save_hot_buckets(path: &Path, bucket_cache: &BucketCache) -> io::Result<()> {
+    let offsets = bucket_cache
+        .offsets()
+        .iter()
+        .map(|offset| format!("{offset}\n"))
+        .collect::<String>();
+
+    std::fs::write(path, offsets)
+}
+
+// API: put new_db in place of the database at live_path, as when building
+// a database offline then swapping it in.  new_db is synced to disk and
+// renamed over live_path, so opening live_path gives either the old database
+// or the whole new one, and the directory is synced so the swap survives a
+// crash.  Handles open on the old database keep reading it.  Both paths must
+// be on the same filesystem.
+#[cfg(feature = "write")]
+pub fn replace_file<P: AsRef<Path>>(live_path: P, mut new_db: Gdbm<ReadWrite>) -> Result<()> {
+    new_db.sync()?;
+    new_db.f.sync_all()?;
+    new_db.read_write.closed = true;
+    let new_path = PathBuf::from(&new_db.pathname);
+    drop(new_db);
+
+    rename_over(&new_path, live_path.as_ref(), true).map_err(Error::Io)
+}
+
+// rename the synced file at new_path over live_path, then sync the
+// directories of both if sync_dirs, so the rename survives a crash
+#[cfg(feature = "write")]
+fn rename_over(new_path: &Path, live_path: &Path, sync_dirs: bool) -> io::Result<()> {
+    std::fs::rename(new_path, live_path)?;
+    if sync_dirs {
+        sys::sync_parent(live_path)?;
+        if new_path.parent() != live_path.parent() {
+            sys::sync_parent(new_path)?;
+        }
+    }
+
+    Ok(())
+}
+
+// storage of the database file f, through the simulated disk of providers
+// if any
+#[cfg(feature = "testutil")]
+fn storage(f: File, providers: &Providers) -> Storage {
+    Storage::new(f).with_simulation(providers.simulation())
+}
+
+#[cfg(not(feature = "testutil"))]
+fn storage(f: File, _providers: &Providers) -> Storage {
+    Storage::new(f)
+}
+
+// whether metadata are of the same file
+#[cfg(unix)]
+fn same_file(a: &std::fs::Metadata, b: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    (a.dev(), a.ino()) == (b.dev(), b.ino())
+}
+
+#[cfg(not(unix))]
+fn same_file(_a: &std::fs::Metadata, _b: &std::fs::Metadata) -> bool {
+    true
+}
+
+// read and validate the bucket stored at offset
+fn read_bucket(f: &mut (impl Read + Seek), header: &Header, offset: u64) -> Result<Bucket> {
+    f.seek(SeekFrom::Start(offset))?;
+    let mut data = vec![0; header.bucket_sz as usize];
+    f.read_exact(&mut data)?;
+
+    decode_bucket(header, offset, &data)
+}
+
+// decode and validate the bucket read from offset
+fn decode_bucket(header: &Header, offset: u64, data: &[u8]) -> Result<Bucket> {
+    let bucket = match header.checksums() {
+        true => {
+            let (data, checksum) = data.split_at(data.len() - Bucket::CHECKSUM_SIZE as usize);
+            if crc32(data) != read32(header.layout.endian, &mut &checksum[..])? {
+                return Err(Error::BadBucketChecksum { offset });
+            }
+
+            Bucket::from_reader(header.bucket_elems, &header.layout, &mut &data[..])?
+        }
+        false => Bucket::from_reader(header.bucket_elems, &header.layout, &mut &data[..])?,
+    };
+
+    if bucket.count > header.bucket_elems || bucket.bits > header.dir_bits {
+        return Err(Error::BadBucket {
+            offset,
+            elems: bucket.count,
+            bits: bucket.bits,
+            max_elems: header.bucket_elems,
+            dir_bits: header.dir_bits,
+        });
+    }
+
+    // free space must lie in the data area, clear of other free space and
+    // of the bucket's records
+    let overlaps = |start: u64, size: u64, other: (u64, u64)| {
+        start < other.0 + other.1 && other.0 < start + size
+    };
+    let records = bucket
+        .tab
+        .iter()
+        .filter(|elem| elem.is_occupied())
+        .map(|elem| (elem.data_ofs, elem.key_size as u64 + elem.data_size as u64));
+    bucket.avail.iter().enumerate().try_for_each(|(i, elem)| {
+        let (start, size) = (elem.addr, elem.sz as u64);
+        let bad = start < header.block_sz as u64
+            || start + size > header.next_block
+            || bucket.avail[..i]
+                .iter()
+                .any(|other| overlaps(start, size, (other.addr, other.sz as u64)))
+            || records.clone().any(|record| overlaps(start, size, record));
+
+        match bad {
+            true => Err(Error::BadBucketAvail {
+                offset,
+                elem: i,
+                avail_offset: elem.addr,
+                size: elem.sz,
+            }),
+            false => Ok(()),
+        }
+    })?;
+
+    Ok(bucket)
+}
+
 pub struct Gdbm<R: 'static> {
     pathname: String,
-    f: std::fs::File,
+    f: Storage,
     header: Header,
     dir: Directory,
     bucket_cache: BucketCache,
+    event_hook: Option<EventHook>,
+    // number of records, once counted
+    record_count: Option<usize>,
+    // reusable buffer for serializing metadata
+    #[cfg_attr(not(feature = "write"), allow(dead_code))]
+    scratch: Vec<u8>,
+    keys: KeyOptions,
+    // offsets of damaged buckets, in quarantine mode
+    quarantine: Option<BTreeSet<u64>>,
+    // end of the time allowed for the current time-bounded operation
+    deadline: Option<Instant>,
+    // operations taking this long are reported to the event hook
+    slow_threshold: Option<Duration>,
+    // running totals of buckets read and split; bytes are counted by f
+    counters: Counters,
+    // stats of the last timed operation
+    last_op: Option<OpStats>,
+    // file the offsets of cached buckets are saved to on drop
+    hot_buckets: Option<PathBuf>,
+    codecs: Codecs,
+    record_cache: Option<RecordCache>,
+    providers: Providers,
+    // keys changed through the handle, with the changelog write option
+    #[cfg(feature = "write")]
+    changelog: Option<Changelog>,
 
     read_write: R,
 }
@@ -113,6 +564,7 @@ impl CacheBucket for Gdbm<ReadOnly> {
 }
 
 // cache_bucket for ReadWrite variant needs to write dirty displaced buckets.
+#[cfg(feature = "write")]
 impl CacheBucket for Gdbm<ReadWrite> {
     fn cache_bucket(&mut self, offset: u64, bucket: Bucket) -> Result<()> {
         if let Some((evicted_offset, evicted_bucket)) = self.bucket_cache.insert(offset, bucket) {
@@ -130,10 +582,21 @@ where
 {
     // API: open database file, read and validate header
     pub fn open<P: AsRef<std::path::Path>>(
+        f: File,
+        path: P,
+        alignment: Option<Alignment>,
+        cachesize: Option<usize>,
+    ) -> Result<Gdbm<R>> {
+        Self::open_with(f, path, alignment, cachesize, Providers::default())
+    }
+
+    // open(), with the clock and random numbers to use
+    fn open_with<P: AsRef<std::path::Path>>(
         mut f: File,
         path: P,
         alignment: Option<Alignment>,
         cachesize: Option<usize>,
+        providers: Providers,
     ) -> Result<Gdbm<R>> {
         let metadata = f.metadata()?;
 
@@ -141,18 +604,8 @@ where
             return Err(Error::EmptyFile(f));
         }
 
-        let header = Header::from_reader(alignment, metadata.len(), &mut f)?;
-
-        f.seek(SeekFrom::Start(header.dir_ofs))?;
-        let dir = Directory::from_reader(&header.layout, header.dir_sz, &mut f)?;
-
-        // ensure all bucket offsets are reasonable
-        if !dir.validate(header.block_sz as u64, header.next_block, header.block_sz) {
-            return Err(Error::BadDirectory {
-                offset: header.dir_ofs,
-                length: header.dir_sz,
-            });
-        }
+        let (header, dir) =
+            read_metadata_retrying(&mut f, metadata.len(), alignment, providers.clock())?;
 
         let bucket_cache = {
             let cache_buckets = {
@@ -165,24 +618,211 @@ where
 
         Ok(Gdbm {
             pathname: path.as_ref().to_string_lossy().to_string(),
-            f,
+            f: storage(f, &providers),
             header,
             dir,
             bucket_cache,
+            event_hook: None,
+            record_count: None,
+            scratch: Vec::new(),
+            keys: KeyOptions::default(),
+            quarantine: None,
+            deadline: None,
+            slow_threshold: None,
+            counters: Counters::default(),
+            last_op: None,
+            hot_buckets: None,
+            codecs: Codecs::default(),
+            record_cache: None,
+            providers,
+            #[cfg(feature = "write")]
+            changelog: None,
             read_write: R::default(),
         })
     }
 
-    fn export_ascii_header(&self, outf: &mut std::fs::File) -> io::Result<()> {
+    // API: set or clear the hook called on bucket splits, directory
+    // extension, avail block pushes and slow operations
+    pub fn set_event_hook(&mut self, hook: Option<EventHook>) {
+        self.event_hook = hook;
+    }
+
+    fn emit(&mut self, event: Event) {
+        if let Some(hook) = self.event_hook.as_mut() {
+            hook(&event);
+        }
+    }
+
+    // API: report get, insert, remove and sync operations taking at least
+    // threshold to the event hook, or stop reporting them (None)
+    pub fn set_slow_threshold(&mut self, threshold: Option<Duration>) {
+        self.slow_threshold = threshold;
+    }
+
+    // API: check the database can be read, for service readiness and
+    // liveness probes, returning the time taken.  Reads and validates the
+    // header, the first directory entry and its bucket from the file rather
+    // than the caches: three small reads, usually served by the page cache,
+    // whatever the size of the database.
+    pub fn ping(&mut self) -> Result<Duration> {
+        let start = self.providers.clock().now();
+
+        // Between syncs of a writer the file grows past the next block in
+        // the header, which is only damage to open().
+        let alignment = Some(self.header.layout.alignment);
+        let file_size = self.f.file_size()?;
+        self.f.seek(SeekFrom::Start(0))?;
+        let header = match Header::from_reader(alignment, file_size, &mut self.f) {
+            Err(Error::BadHeaderNextBlock { next_block, .. }) => {
+                self.f.seek(SeekFrom::Start(0))?;
+                Header::from_reader(alignment, next_block, &mut self.f)?
+            }
+            header => header?,
+        };
+
+        let entry_size = match header.layout.offset {
+            Offset::Small => 4,
+            Offset::LFS => 8,
+        };
+        self.f.seek(SeekFrom::Start(header.dir_ofs))?;
+        let dir = Directory::from_reader(&header.layout, entry_size, &mut self.f)?;
+        read_bucket(&mut self.f, &header, dir.dir[0])?;
+
+        Ok(self.providers.clock().now() - start)
+    }
+
+    // API: I/O done by the last get, insert, remove or sync
+    pub fn last_op_stats(&self) -> Option<OpStats> {
+        self.last_op
+    }
+
+    fn counters(&self) -> Counters {
+        Counters {
+            bytes_read: self.f.bytes_read(),
+            bytes_written: self.f.bytes_written(),
+            ..self.counters
+        }
+    }
+
+    // run op, recording its stats and reporting it to the event hook if it
+    // is slow
+    fn timed<T>(&mut self, operation: Operation, op: impl FnOnce(&mut Self) -> T) -> T {
+        let start = self.providers.clock().now();
+        let before = self.counters();
+        let result = op(self);
+        let elapsed = self.providers.clock().now() - start;
+        let stats = OpStats::new(operation, elapsed, &before, &self.counters());
+        self.last_op = Some(stats);
+
+        if self
+            .slow_threshold
+            .is_some_and(|threshold| stats.elapsed >= threshold)
+        {
+            self.emit(Event::SlowOperation {
+                operation,
+                elapsed: stats.elapsed,
+                bucket_offset: self.bucket_cache.current_bucket_offset(),
+                buckets_read: stats.buckets_read,
+                bytes_read: stats.bytes_read,
+            });
+        }
+
+        result
+    }
+
+    fn set_open_options<W>(&mut self, options: &OpenOptions<W>) -> Result<()> {
+        self.keys = options.keys;
+        self.codecs = options.codecs.clone();
+        self.providers = options.providers.clone();
+        self.record_cache = options.record_cache.as_ref().map(RecordCache::new);
+        self.quarantine = options.quarantine.then(BTreeSet::new);
+        self.f.set_fadvise(options.fadvise);
+        self.f.set_direct(options.direct).map_err(Error::Io)?;
+
+        if options.verify_directory {
+            self.verify_directory()?;
+        }
+
+        if let Some(path) = options.warm_from.as_ref() {
+            self.warm(path)?;
+            self.hot_buckets = Some(path.clone());
+        }
+
+        Ok(())
+    }
+
+    // API: save the offsets of the cached buckets, most recently used first,
+    // for OpenOptions::warm_from to prefetch
+    pub fn save_hot_buckets<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        save_hot_buckets(path.as_ref(), &self.bucket_cache).map_err(Error::Io)
+    }
+
+    // prefetch the saved buckets that are still in the directory
+    fn warm(&mut self, path: &Path) -> Result<()> {
+        let saved = match std::fs::read_to_string(path) {
+            Ok(saved) => saved,
+            // nothing saved yet
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(Error::Io(e)),
+        };
+        let offsets = saved
+            .lines()
+            .filter_map(|line| line.parse::<u64>().ok())
+            .collect::<HashSet<_>>();
+
+        let bucket_dirs = self
+            .dir
+            .dir
+            .iter()
+            .enumerate()
+            .filter(|(_, offset)| offsets.contains(offset))
+            .map(|(bucket_dir, _)| bucket_dir)
+            .collect::<Vec<_>>();
+
+        self.load_buckets(bucket_dirs.into_iter())
+    }
+
+    #[cfg(feature = "ascii-dump")]
+    fn export_ascii_header(
+        &self,
+        outf: &mut std::fs::File,
+        options: &ExportOptions,
+    ) -> io::Result<()> {
         // TODO: add ctime() to "created by" output line
         writeln!(outf, "# GDBM dump file created by {}", COMPAT_GDBM_VERSION)?;
-        writeln!(outf, "#:version=1.1")?;
-        writeln!(outf, "#:file={}", self.pathname)?;
-        writeln!(outf, "#:format=standard")?;
+        match options.version {
+            DumpVersion::V1_0 => writeln!(outf, "#:version=1.0")?,
+            DumpVersion::V1_1 => writeln!(outf, "#:version=1.1")?,
+        }
+        options
+            .ascii
+            .fields
+            .iter()
+            .try_for_each(|field| match (field, options.version) {
+                (DumpField::File, _) => writeln!(outf, "#:file={}", self.pathname),
+                (DumpField::Format, DumpVersion::V1_0) => Ok(()),
+                (DumpField::Format, DumpVersion::V1_1) => match self.header.magic.is_numsync() {
+                    true => writeln!(outf, "#:format=numsync"),
+                    false => writeln!(outf, "#:format=standard"),
+                },
+                (DumpField::Owner, _) => {
+                    use std::os::unix::fs::MetadataExt;
+
+                    let metadata = self.f.file().metadata()?;
+                    writeln!(
+                        outf,
+                        "#:uid={},gid={},mode={:03o}",
+                        metadata.uid(),
+                        metadata.gid(),
+                        metadata.mode() & 0o777
+                    )
+                }
+            })?;
         writeln!(outf, "# End of header")?;
         Ok(())
     }
 
+    #[cfg(feature = "ascii-dump")]
     fn export_ascii_datum(outf: &mut std::fs::File, bindata: Vec<u8>) -> io::Result<()> {
         const MAX_DUMP_LINE_LEN: usize = 76;
 
@@ -203,9 +843,22 @@ where
         Ok(())
     }
 
-    fn export_ascii_records(&mut self, outf: &mut std::fs::File) -> Result<usize> {
+    #[cfg(feature = "ascii-dump")]
+    fn export_ascii_records(
+        &mut self,
+        outf: &mut std::fs::File,
+        anonymize: Option<&Anonymize>,
+        mut filter: impl FnMut(&[u8], &[u8]) -> bool,
+    ) -> Result<usize> {
+        let mut anonymizer = anonymize::Anonymizer::new(anonymize);
         self.iter().try_fold(0, |count, kv| {
-            kv.and_then(|(key, value)| {
+            kv.and_then(|(key, value): (Vec<u8>, Vec<u8>)| {
+                if !filter(&key, &value) {
+                    return Ok(count);
+                }
+
+                let (key, value) = anonymizer.record(key, value)?;
+
                 Self::export_ascii_datum(outf, key)
                     .and_then(|_| Self::export_ascii_datum(outf, value))
                     .map(|_| count + 1)
@@ -214,6 +867,7 @@ where
         })
     }
 
+    #[cfg(feature = "ascii-dump")]
     fn export_ascii_footer(&self, outf: &mut std::fs::File, n_written: usize) -> io::Result<()> {
         writeln!(outf, "#:count={}", n_written)?;
         writeln!(outf, "# End of data")?;
@@ -221,14 +875,55 @@ where
     }
 
     // API: export database to ASCII dump file
+    #[cfg(feature = "ascii-dump")]
     pub fn export_ascii(&mut self, outf: &mut std::fs::File) -> Result<()> {
-        self.export_ascii_header(outf)
+        self.export_ascii_with(outf, &ExportOptions::default())
+    }
+
+    // API: export database to ASCII dump file, in the given format version
+    #[cfg(feature = "ascii-dump")]
+    pub fn export_ascii_with(
+        &mut self,
+        outf: &mut std::fs::File,
+        options: &ExportOptions,
+    ) -> Result<()> {
+        self.export_ascii_filtered_with(outf, options, |_, _| true)
+    }
+
+    // API: export the records for which filter(key, value) is true to ASCII
+    // dump file
+    #[cfg(feature = "ascii-dump")]
+    pub fn export_ascii_filtered(
+        &mut self,
+        outf: &mut std::fs::File,
+        filter: impl FnMut(&[u8], &[u8]) -> bool,
+    ) -> Result<()> {
+        self.export_ascii_filtered_with(outf, &ExportOptions::default(), filter)
+    }
+
+    #[cfg(feature = "ascii-dump")]
+    pub fn export_ascii_filtered_with(
+        &mut self,
+        outf: &mut std::fs::File,
+        options: &ExportOptions,
+        filter: impl FnMut(&[u8], &[u8]) -> bool,
+    ) -> Result<()> {
+        self.export_ascii_header(outf, options)
             .map_err(Error::Io)
-            .and_then(|_| self.export_ascii_records(outf))
+            .and_then(|_| self.export_ascii_records(outf, options.anonymize.as_ref(), filter))
             .and_then(|n_written| self.export_ascii_footer(outf, n_written).map_err(Error::Io))
+            .map(|_| self.f.advise(Advice::DontNeed, 0, 0))
     }
 
-    fn export_bin_header(&self, outf: &mut std::fs::File) -> io::Result<()> {
+    fn export_bin_header(
+        &self,
+        outf: &mut std::fs::File,
+        options: &ExportOptions,
+    ) -> io::Result<()> {
+        if options.bin_version == BinDumpVersion::V0 {
+            return Ok(());
+        }
+
         write!(
             outf,
             "!\r\n! GDBM FLAT FILE DUMP -- THIS IS NOT A TEXT FILE\r\n"
@@ -254,9 +949,37 @@ where
         Ok(())
     }
 
-    fn export_bin_records(&mut self, outf: &mut std::fs::File, alignment: Alignment) -> Result<()> {
+    // write the records, adding each to index if given, with the offset of
+    // its value from start
+    fn export_bin_records(
+        &mut self,
+        outf: &mut std::fs::File,
+        alignment: Alignment,
+        start: u64,
+        mut index: Option<&mut DumpIndex>,
+        anonymize: Option<&Anonymize>,
+        mut filter: impl FnMut(&[u8], &[u8]) -> bool,
+    ) -> Result<()> {
+        let length_size = match alignment {
+            Alignment::Align32 => 4,
+            Alignment::Align64 => 8,
+        };
+
+        let mut anonymizer = anonymize::Anonymizer::new(anonymize);
         self.iter().try_for_each(|kv| {
-            kv.and_then(|(key, value)| {
+            kv.and_then(|(key, value): (Vec<u8>, Vec<u8>)| {
+                if !filter(&key, &value) {
+                    return Ok(());
+                }
+
+                let (key, value) = anonymizer.record(key, value)?;
+
+                if let Some(index) = index.as_mut() {
+                    let offset = outf.stream_position()? - start;
+                    let value_offset = offset + 2 * length_size + key.len() as u64;
+                    index.push((key.clone(), value_offset, value.len() as u64));
+                }
+
                 Self::export_bin_datum(outf, alignment, key)
                     .and_then(|_| Self::export_bin_datum(outf, alignment, value))
                     .map_err(Error::Io)
@@ -264,39 +987,195 @@ where
         })
     }
 
+    // API: write the structure of the database -- header, directory,
+    // buckets and avail blocks, but no records -- for restore_metadata, as
+    // for a bug report.  The leading key bytes kept in bucket elements are
+    // zeroed, so no key or value is disclosed.
+    pub fn export_metadata(&mut self, outf: &mut impl Write) -> Result<()> {
+        let mut blocks = Vec::new();
+
+        let mut buffer = Vec::new();
+        self.header.serialize(&mut buffer)?;
+        blocks.push((0, buffer));
+
+        let mut buffer = Vec::new();
+        self.dir.serialize(&self.header.layout, &mut buffer)?;
+        blocks.push((self.header.dir_ofs, buffer));
+
+        for bucket_dir in self.buckets() {
+            let bucket = self.cache_load_bucket(bucket_dir)?;
+            let mut copy = Bucket::new(bucket.bits, 0, bucket.avail.clone(), vec![]);
+            copy.count = bucket.count;
+            copy.tab = bucket
+                .tab
+                .iter()
+                .map(|elem| BucketElement {
+                    key_start: PartialKey::default(),
+                    ..*elem
+                })
+                .collect();
+
+            let mut buffer = Vec::new();
+            serialize_bucket(&self.header, &copy, &mut buffer)?;
+            blocks.push((self.dir.dir[bucket_dir], buffer));
+        }
+
+        // the avail block chain, stopping at any loop
+        let mut visited = HashSet::new();
+        let mut next_block = self.header.avail.next_block;
+        while next_block != 0 && visited.insert(next_block) {
+            self.f.seek(SeekFrom::Start(next_block))?;
+            let block = AvailBlock::from_reader(&self.header.layout, &mut self.f)?;
+
+            let mut buffer = Vec::new();
+            block.serialize(&self.header.layout, &mut buffer)?;
+            blocks.push((next_block, buffer));
+            next_block = block.next_block;
+        }
+
+        let file_size = self.f.file_size()?.max(self.header.next_block);
+        dump::write_metadata(outf, file_size, blocks).map_err(Error::Io)
+    }
+
     // API: export database to binary dump file
     pub fn export_bin(&mut self, outf: &mut std::fs::File, mode: ExportBinMode) -> Result<()> {
+        self.export_bin_with(outf, mode, &ExportOptions::default())
+    }
+
+    // API: export database to binary dump file, in the given format version
+    pub fn export_bin_with(
+        &mut self,
+        outf: &mut std::fs::File,
+        mode: ExportBinMode,
+        options: &ExportOptions,
+    ) -> Result<()> {
+        self.export_bin_filtered_with(outf, mode, options, |_, _| true)
+    }
+
+    // API: export the records for which filter(key, value) is true to binary
+    // dump file
+    pub fn export_bin_filtered(
+        &mut self,
+        outf: &mut std::fs::File,
+        mode: ExportBinMode,
+        filter: impl FnMut(&[u8], &[u8]) -> bool,
+    ) -> Result<()> {
+        self.export_bin_filtered_with(outf, mode, &ExportOptions::default(), filter)
+    }
+
+    pub fn export_bin_filtered_with(
+        &mut self,
+        outf: &mut std::fs::File,
+        mode: ExportBinMode,
+        options: &ExportOptions,
+        filter: impl FnMut(&[u8], &[u8]) -> bool,
+    ) -> Result<()> {
         let alignment = match mode {
             ExportBinMode::ExpNative => self.header.layout.alignment,
             ExportBinMode::Exp32 => Alignment::Align32,
             ExportBinMode::Exp64 => Alignment::Align64,
         };
 
-        self.export_bin_header(outf)
+        let start = outf.stream_position()?;
+        let mut index = options.bin_index.then(DumpIndex::new);
+
+        self.export_bin_header(outf, options)
             .map_err(Error::Io)
-            .and_then(|_| self.export_bin_records(outf, alignment))
+            .and_then(|_| {
+                self.export_bin_records(
+                    outf,
+                    alignment,
+                    start,
+                    index.as_mut(),
+                    options.anonymize.as_ref(),
+                    filter,
+                )
+            })
+            .and_then(|_| match index {
+                Some(index) => dump::write_index(outf, alignment, start, index).map_err(Error::Io),
+                None => Ok(()),
+            })
+            .map(|_| self.f.advise(Advice::DontNeed, 0, 0))
+    }
+
+    // API: copy the records into one new database per path, in the same
+    // format as this one.  Each record goes to the database numbered
+    // shard(key) modulo the number of paths.
+    #[cfg(feature = "write")]
+    pub fn split_into<P: AsRef<std::path::Path>>(
+        &mut self,
+        paths: &[P],
+        mut shard: impl FnMut(&[u8]) -> usize,
+    ) -> Result<Vec<Gdbm<ReadWrite>>> {
+        if paths.is_empty() {
+            return Err(Error::Io(io::ErrorKind::InvalidInput.into()));
+        }
+
+        let options = OpenOptions {
+            alignment: Some(self.header.layout.alignment),
+            keys: self.keys,
+            ..OpenOptions::new()
+        }
+        .write()
+        .create()
+        .newdb(true)
+        .offset(Some(self.header.layout.offset))
+        .endian(Some(self.header.layout.endian))
+        .numsync(self.header.magic.is_numsync())
+        .extended(self.header.magic.is_extended())
+        .digest_keys(self.header.digest_keys())
+        .block_size(BlockSize::Roughly(self.header.block_sz));
+
+        let mut shards = paths
+            .iter()
+            .map(|path| options.open(path))
+            .collect::<Result<Vec<_>>>()?;
+
+        let n_shards = shards.len();
+        self.iter::<Vec<u8>, Vec<u8>>().try_for_each(|kv| {
+            kv.and_then(|(key, value)| {
+                shards[shard(&key) % n_shards]
+                    .insert(key, value)
+                    .map(|_| ())
+            })
+        })?;
+        self.f.advise(Advice::DontNeed, 0, 0);
+
+        shards.iter_mut().try_for_each(|db| db.sync())?;
+
+        Ok(shards)
+    }
+
+    // API: split_into(), choosing each record's database by its key hash
+    #[cfg(feature = "write")]
+    pub fn split_by_hash<P: AsRef<std::path::Path>>(
+        &mut self,
+        paths: &[P],
+    ) -> Result<Vec<Gdbm<ReadWrite>>> {
+        self.split_into(paths, |key| hash_key(key) as usize)
     }
 
     // read bucket into bucket cache.
     fn cache_load_bucket(&mut self, bucket_dir: usize) -> Result<&Bucket> {
         let offset = self.dir.dir[bucket_dir];
 
-        if !self.bucket_cache.contains(offset) {
-            self.f.seek(SeekFrom::Start(offset))?;
-            let bucket =
-                Bucket::from_reader(self.header.bucket_elems, &self.header.layout, &mut self.f)?;
-
-            if bucket.count > self.header.bucket_elems || bucket.bits > self.header.dir_bits {
-                return Err(Error::BadBucket {
-                    offset,
-                    elems: bucket.count,
-                    bits: bucket.bits,
-                    max_elems: self.header.bucket_elems,
-                    dir_bits: self.header.dir_bits,
-                });
-            }
+        if self
+            .quarantine
+            .as_ref()
+            .is_some_and(|q| q.contains(&offset))
+        {
+            return Err(Error::Quarantined { offset });
+        }
 
+        if !self.bucket_cache.contains(offset) {
+            self.check_deadline()?;
+            let bucket = read_bucket(&mut self.f, &self.header, offset)
+                .map_err(|e| self.replaced_or(e))
+                .map_err(|e| self.note_damage(e))?;
+            self.counters.buckets_read += 1;
             self.cache_bucket(offset, bucket)?;
+        } else {
+            self.counters.bucket_hits += 1;
         }
 
         self.bucket_cache.set_current(offset);
@@ -304,6 +1183,16 @@ where
         Ok(self.bucket_cache.current_bucket().unwrap())
     }
 
+    // the first of the run of directory entries sharing a bucket with
+    // bucket_dir
+    fn bucket_start(&self, bucket_dir: usize) -> usize {
+        let offset = self.dir.dir[bucket_dir];
+        self.dir.dir[..bucket_dir]
+            .iter()
+            .rposition(|entry| *entry != offset)
+            .map_or(0, |before| before + 1)
+    }
+
     // since one bucket dir entry may duplicate another,
     // this function returns the next non-dup bucket dir
     fn next_bucket_dir(&self, bucket_dir_in: usize) -> usize {
@@ -322,100 +1211,874 @@ where
         bucket_dir
     }
 
-    // API: count entries in database
-    #[allow(clippy::len_without_is_empty)]
-    pub fn len(&mut self) -> Result<usize> {
-        let mut len: usize = 0;
-        let mut cur_dir: usize = 0;
-        let dir_max_elem = self.dir.dir.len();
-        while cur_dir < dir_max_elem {
-            len += self.cache_load_bucket(cur_dir)?.count as usize;
-            cur_dir = self.next_bucket_dir(cur_dir);
-        }
+    // e, or FileReplaced if e may have come of the file being truncated, or
+    // replaced at its path, since open: a read past its end, or a damaged
+    // bucket.
+    fn replaced_or(&self, e: Error) -> Error {
+        let suspect = match &e {
+            Error::Io(e) => e.kind() == io::ErrorKind::UnexpectedEof,
+            e => e.damaged_bucket().is_some(),
+        };
 
-        Ok(len)
+        match suspect && self.file_replaced() {
+            true => Error::FileReplaced,
+            false => e,
+        }
     }
 
-    // API: get an iterator over values
-    pub fn values<V: From<Bytes>>(&mut self) -> impl std::iter::Iterator<Item = Result<V>> + '_ {
-        GDBMIterator::<R>::new(self, KeyOrValue::Value)
-            .map(|data| data.map(|(_, value)| Bytes::from(value).into()))
-    }
+    // whether the open file was truncated, or is no longer the file at its
+    // path
+    fn file_replaced(&self) -> bool {
+        let Ok(metadata) = self.f.file().metadata() else {
+            return false;
+        };
 
-    // API: get an iterator over keys
-    pub fn keys<K: From<Bytes>>(&mut self) -> impl std::iter::Iterator<Item = Result<K>> + '_ {
-        GDBMIterator::<R>::new(self, KeyOrValue::Key)
-            .map(|data| data.map(|(key, _)| Bytes::from(key).into()))
+        self.f.truncated().unwrap_or(false)
+            || self.path().is_ok_and(|path| {
+                std::fs::metadata(path).map_or(true, |current| !same_file(&current, &metadata))
+            })
     }
 
-    // API: get an iterator
-    pub fn iter<K: From<Bytes>, V: From<Bytes>>(
-        &mut self,
-    ) -> impl std::iter::Iterator<Item = Result<(K, V)>> + '_ {
-        GDBMIterator::<R>::new(self, KeyOrValue::Both).map(|data| {
-            data.map(|(key, value)| (Bytes::from(key).into(), Bytes::from(value).into()))
-        })
+    // the path the database was opened at, unless opened from a file
+    fn path(&self) -> Result<&Path> {
+        match self.pathname.is_empty() {
+            true => Err(Error::Io(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "database opened from a file, without a path",
+            ))),
+            false => Ok(Path::new(&self.pathname)),
+        }
     }
 
-    // API: does key exist?
-    pub fn contains_key<'a, K: Into<BytesRef<'a>>>(&mut self, key: K) -> Result<bool> {
-        self.int_get(key.into().as_ref())
-            .map(|result| result.is_some())
+    // read length bytes at offset of the file
+    fn read_at(&mut self, offset: u64, length: usize) -> Result<Vec<u8>> {
+        read_ofs(&mut self.f, offset, length).map_err(|e| self.replaced_or(Error::Io(e)))
     }
 
-    // retrieve record data, and element offset in bucket, for given key
-    fn int_get(&mut self, key: &[u8]) -> Result<Option<(usize, Vec<u8>)>> {
-        let (key_hash, bucket_dir, elem_ofs) =
-            key_loc(self.header.dir_bits, self.header.bucket_elems, key);
-        let key_start = PartialKey::new(key);
+    // In quarantine mode, set aside the bucket e reports damaged.  Returns e.
+    fn note_damage(&mut self, e: Error) -> Error {
+        if let (Some(quarantine), Some(offset)) = (&mut self.quarantine, e.damaged_bucket()) {
+            if quarantine.insert(offset) {
+                // the count may include records of the bucket
+                self.record_count = None;
+            }
+        }
 
-        let bucket = self.cache_load_bucket(bucket_dir)?;
+        e
+    }
 
-        let bucket_entries = (0..bucket.tab.len())
-            .map(|index| ((index + elem_ofs as usize) % bucket.tab.len()))
-            .map(|offset| (offset, bucket.tab[offset]))
-            .take_while(|(_, elem)| elem.is_occupied())
-            .filter(|(_, elem)| {
-                elem.hash == key_hash
-                    && elem.key_size == key.len() as u32
-                    && elem.key_start == key_start
+    // In quarantine mode, turn failure from a quarantined bucket into None,
+    // so the bucket can be skipped.
+    fn skip_quarantined<T>(&mut self, result: Result<T>) -> Result<Option<T>> {
+        match result.map_err(|e| self.note_damage(e)) {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::Quarantined { .. }) if self.quarantine.is_some() => Ok(None),
+            Err(e) => match (&self.quarantine, e.damaged_bucket()) {
+                (Some(quarantine), Some(offset)) if quarantine.contains(&offset) => Ok(None),
+                _ => Err(e),
+            },
+        }
+    }
+
+    // API: buckets set aside as damaged in quarantine mode, with the key
+    // hashes of the records they hold
+    pub fn quarantined(&self) -> Vec<QuarantinedBucket> {
+        let Some(quarantine) = &self.quarantine else {
+            return Vec::new();
+        };
+
+        quarantine
+            .iter()
+            .filter_map(|offset| {
+                let start = self.dir.dir.iter().position(|o| o == offset)?;
+                let length = self.dir.dir[start..]
+                    .iter()
+                    .take_while(|o| *o == offset)
+                    .count();
+                Some(QuarantinedBucket::new(
+                    *offset,
+                    self.header.dir_bits,
+                    start,
+                    length,
+                ))
             })
-            .collect::<Vec<_>>();
+            .collect()
+    }
+
+    // API: whether the record for a key would be in a quarantined bucket
+    pub fn is_quarantined<'a, K: Into<BytesRef<'a>>>(&self, key: K) -> bool {
+        let key = key.into();
+        let (_, bucket_dir, _) = self.locate(&self.normalized(key.as_ref()));
+        self.quarantine
+            .as_ref()
+            .is_some_and(|q| q.contains(&self.dir.dir[bucket_dir]))
+    }
+
+    // API: count entries in database.  The count is kept up to date after
+    // the first call, so only that call reads every bucket.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&mut self) -> Result<usize> {
+        if let Some(len) = self.record_count {
+            return Ok(len);
+        }
+
+        let mut len: usize = 0;
+        let mut cur_dir: usize = 0;
+        let dir_max_elem = self.dir.dir.len();
+        while cur_dir < dir_max_elem {
+            let count = self
+                .cache_load_bucket(cur_dir)
+                .map(|bucket| bucket.count as usize);
+            len += self.skip_quarantined(count)?.unwrap_or(0);
+            cur_dir = self.next_bucket_dir(cur_dir);
+        }
+
+        self.record_count = Some(len);
+
+        Ok(len)
+    }
+
+    // API: estimate the number of records from the buckets of a sample of
+    // directory entries, evenly spaced.  Exact if the count is already
+    // known, or the directory is no larger than the sample.
+    pub fn len_estimate(&mut self) -> Result<LenEstimate> {
+        let dir_len = self.dir.dir.len();
+        if self.record_count.is_some() || dir_len <= LEN_ESTIMATE_SAMPLES {
+            return self.len().map(|count| LenEstimate { count, error: 0 });
+        }
+
+        // Each directory entry gets an equal share of its bucket's records,
+        // so the record count is the sum of the shares of all entries.
+        let dir_bits = self.header.dir_bits;
+        let step = dir_len / LEN_ESTIMATE_SAMPLES;
+        let mut shares = Vec::with_capacity(LEN_ESTIMATE_SAMPLES);
+        for bucket_dir in (0..LEN_ESTIMATE_SAMPLES).map(|n| n * step) {
+            let share = self
+                .cache_load_bucket(bucket_dir)
+                .map(|bucket| bucket.count as f64 / (1u64 << (dir_bits - bucket.bits)) as f64);
+            shares.push(self.skip_quarantined(share)?.unwrap_or(0.0));
+        }
+
+        let samples = shares.len() as f64;
+        let mean = shares.iter().sum::<f64>() / samples;
+        let variance = shares.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (samples - 1.0);
+        let correction = 1.0 - samples / dir_len as f64;
+        let std_error = dir_len as f64 * (variance / samples * correction).sqrt();
+
+        Ok(LenEstimate {
+            count: (mean * dir_len as f64).round() as usize,
+            error: (2.0 * std_error).ceil() as usize,
+        })
+    }
+
+    // API: compare record sizes with the block size, and recommend a block
+    // size for rewriting the database.  Reads every bucket, but no records.
+    pub fn layout_efficiency(&mut self) -> Result<LayoutEfficiency> {
+        let (mut records, mut buckets, mut key_bytes, mut value_bytes) = (0, 0, 0, 0);
+        let mut cur_dir: usize = 0;
+        let dir_max_elem = self.dir.dir.len();
+        while cur_dir < dir_max_elem {
+            let bucket = self.cache_load_bucket(cur_dir)?;
+            buckets += 1;
+            records += bucket.count as usize;
+            bucket
+                .tab
+                .iter()
+                .filter(|elem| elem.is_occupied())
+                .for_each(|elem| {
+                    key_bytes += elem.key_size as usize;
+                    value_bytes += elem.data_size as usize;
+                });
+            cur_dir = self.next_bucket_dir(cur_dir);
+        }
+
+        let avg_key = key_bytes.checked_div(records).unwrap_or(0);
+        let avg_value = value_bytes.checked_div(records).unwrap_or(0);
+
+        Ok(LayoutEfficiency {
+            block_size: self.header.block_sz,
+            records,
+            buckets,
+            avg_key,
+            avg_value,
+            bucket_fill: records as f64 / (buckets * self.header.bucket_elems as usize) as f64,
+            recommended_block_size: recommend_block_size(records, avg_key, avg_value),
+        })
+    }
 
-        let data_entries = bucket_entries
+    // Check each bucket is pointed to by an aligned run of directory entries
+    // matching its bits, returning the runs as (start, length).
+    fn verify_directory(&mut self) -> Result<Vec<(usize, usize)>> {
+        let runs = self.dir.runs().map_err(|index| Error::BadDirectoryEntry {
+            index,
+            offset: self.dir.dir[index],
+        })?;
+
+        runs.iter().try_for_each(|(start, length)| {
+            let offset = self.dir.dir[*start];
+            let expected_bits = self.header.dir_bits - length.ilog2();
+            let result = self.cache_load_bucket(*start).and_then(|bucket| {
+                match bucket.bits == expected_bits {
+                    true => Ok(()),
+                    false => Err(Error::BadBucketBits {
+                        offset,
+                        bits: bucket.bits,
+                        expected_bits,
+                    }),
+                }
+            });
+            self.skip_quarantined(result).map(|_| ())
+        })?;
+
+        Ok(runs)
+    }
+
+    // API: check the directory against the buckets, and that every record is
+    // in the bucket its hash selects.  Reads every bucket, but no records.
+    // In quarantine mode damaged buckets are set aside, not reported.
+    pub fn verify(&mut self) -> Result<()> {
+        let dir_bits = self.header.dir_bits;
+
+        self.verify_directory()?
             .into_iter()
+            .try_for_each(|(start, length)| {
+                let offset = self.dir.dir[start];
+                let result = self.cache_load_bucket(start).and_then(|bucket| {
+                    bucket
+                        .tab
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, elem)| elem.is_occupied())
+                        .try_for_each(|(i, elem)| {
+                            match (start..start + length).contains(&bucket_dir(dir_bits, elem.hash))
+                            {
+                                true => Ok(()),
+                                false => Err(Error::BadBucketElement { offset, elem: i }),
+                            }
+                        })
+                });
+                self.skip_quarantined(result).map(|_| ())
+            })?;
+
+        self.f.advise(Advice::DontNeed, 0, 0);
+
+        Ok(())
+    }
+
+    // API: get an iterator over values.  Iteration ends after the first
+    // error, as do the key and record iterators.
+    pub fn values<V: From<Bytes>>(&mut self) -> impl FusedIterator<Item = Result<V>> + '_ {
+        GDBMIterator::<R>::new(self, KeyOrValue::Value)
+            .map(|data| data.map(|(_, value)| Bytes::from(value).into()))
+    }
+
+    // API: get an iterator over keys
+    pub fn keys<K: From<Bytes>>(&mut self) -> impl FusedIterator<Item = Result<K>> + '_ {
+        GDBMIterator::<R>::new(self, KeyOrValue::Key)
+            .map(|data| data.map(|(key, _)| Bytes::from(key).into()))
+    }
+
+    // API: get an iterator
+    pub fn iter<K: From<Bytes>, V: From<Bytes>>(
+        &mut self,
+    ) -> impl FusedIterator<Item = Result<(K, V)>> + '_ {
+        GDBMIterator::<R>::new(self, KeyOrValue::Both).map(|data| {
+            data.map(|(key, value)| (Bytes::from(key).into(), Bytes::from(value).into()))
+        })
+    }
+
+    // API: all keys, in iteration order, stopping at the first error
+    pub fn collect_keys<K: From<Bytes>>(&mut self) -> Result<Vec<K>> {
+        let mut keys = Vec::with_capacity(self.len()?);
+        self.keys()
+            .try_for_each(|key| key.map(|key| keys.push(key)))?;
+
+        Ok(keys)
+    }
+
+    // API: all keys and values, in iteration order, stopping at the first
+    // error
+    pub fn collect_entries<K: From<Bytes>, V: From<Bytes>>(&mut self) -> Result<Vec<(K, V)>> {
+        let mut entries = Vec::with_capacity(self.len()?);
+        self.iter()
+            .try_for_each(|entry| entry.map(|entry| entries.push(entry)))?;
+
+        Ok(entries)
+    }
+
+    // API: the directory index of the bucket holding key, or that would
+    // hold it, for iter_bucket
+    pub fn bucket_of<'a, K: Into<BytesRef<'a>>>(&self, key: K) -> usize {
+        let (_, bucket_dir, _) = self.locate(&self.normalized(key.into().as_ref()));
+        self.bucket_start(bucket_dir)
+    }
+
+    // API: the directory index of each bucket, in directory order, for
+    // processing a database one bucket at a time with iter_bucket
+    pub fn buckets(&self) -> Vec<usize> {
+        std::iter::successors(Some(0), |bucket_dir| {
+            Some(self.next_bucket_dir(*bucket_dir)).filter(|next| *next < self.dir.dir.len())
+        })
+        .collect()
+    }
+
+    // API: get an iterator over the records of the bucket at a directory
+    // index, in bucket order.  The bucket is read once.
+    pub fn iter_bucket<K: From<Bytes>, V: From<Bytes>>(
+        &mut self,
+        bucket_dir: usize,
+    ) -> Result<impl Iterator<Item = Result<(K, V)>> + '_> {
+        if bucket_dir >= self.dir.dir.len() {
+            return Err(Error::Io(io::ErrorKind::InvalidInput.into()));
+        }
+
+        let elems = self.occupied_elems(bucket_dir)?;
+
+        Ok(elems.into_iter().map(|elem| {
+            self.read_record(elem)
+                .map(|(key, value)| (Bytes::from(key).into(), Bytes::from(value).into()))
+        }))
+    }
+
+    // API: split the directory into at most n ranges holding roughly equal
+    // numbers of buckets, for processing a database in parallel with
+    // iter_range.  Each bucket belongs to the range holding its first
+    // directory entry, so the ranges share no records.
+    pub fn partition_ranges(&self, n: usize) -> Vec<Range<usize>> {
+        let buckets = self.buckets();
+        let n = n.clamp(1, buckets.len());
+
+        (0..n)
+            .map(|i| {
+                let start = buckets[i * buckets.len() / n];
+                let end = buckets
+                    .get((i + 1) * buckets.len() / n)
+                    .copied()
+                    .unwrap_or(self.dir.dir.len());
+                start..end
+            })
+            .collect()
+    }
+
+    // API: get an iterator over the records of the buckets whose first
+    // directory entry is in range, as given by partition_ranges.  Handles
+    // given ranges of one handle see the same buckets if no writer has
+    // split a bucket in between.  Iteration ends after the first error.
+    pub fn iter_range<K: From<Bytes>, V: From<Bytes>>(
+        &mut self,
+        range: Range<usize>,
+    ) -> Result<impl Iterator<Item = Result<(K, V)>> + '_> {
+        if range.start > range.end || range.end > self.dir.dir.len() {
+            return Err(Error::Io(io::ErrorKind::InvalidInput.into()));
+        }
+
+        let mut buckets = self
+            .buckets()
+            .into_iter()
+            .filter(move |bucket_dir| range.contains(bucket_dir));
+        let mut elems = Vec::new().into_iter();
+        let mut failed = false;
+
+        Ok(std::iter::from_fn(move || {
+            while !failed {
+                let result = match elems.next() {
+                    Some(elem) => self.read_record(elem),
+                    None => match self.occupied_elems(buckets.next()?) {
+                        Ok(bucket_elems) => {
+                            elems = bucket_elems.into_iter();
+                            continue;
+                        }
+                        Err(e) => Err(e),
+                    },
+                };
+                failed = result.is_err();
+
+                return Some(
+                    result.map(|(key, value)| (Bytes::from(key).into(), Bytes::from(value).into())),
+                );
+            }
+
+            None
+        }))
+    }
+
+    // the occupied elements of the bucket at a directory index
+    fn occupied_elems(&mut self, bucket_dir: usize) -> Result<Vec<BucketElement>> {
+        Ok(self
+            .cache_load_bucket(bucket_dir)?
+            .tab
+            .iter()
+            .filter(|elem| elem.is_occupied())
+            .copied()
+            .collect())
+    }
+
+    // the key and value of the record of a bucket element
+    fn read_record(&mut self, elem: BucketElement) -> Result<(Vec<u8>, Vec<u8>)> {
+        let key_size = elem.key_size as usize;
+        let mut data = self.read_at(elem.data_ofs, key_size + elem.data_size as usize)?;
+        let value = self.codecs.decode(data.split_off(key_size))?;
+        let (mut key, value) = self.unpack_record(data, value)?;
+        if self.keys.c_string {
+            key.pop_if(|b| *b == 0);
+        }
+
+        Ok((key, value))
+    }
+
+    // API: does key exist?
+    pub fn contains_key<'a, K: Into<BytesRef<'a>>>(&mut self, key: K) -> Result<bool> {
+        self.lookup(key.into().as_ref())
+            .map(|result| result.is_some())
+    }
+
+    // the key as stored, after any normalization and C string terminator
+    fn normalized<'k>(&self, key: &'k [u8]) -> Cow<'k, [u8]> {
+        let key = match self.keys.normalize {
+            Some(normalize) => Cow::Owned(normalize(key)),
+            None => Cow::Borrowed(key),
+        };
+
+        match self.keys.c_string {
+            true => {
+                let mut key = key.into_owned();
+                key.push(0);
+                Cow::Owned(key)
+            }
+            false => key,
+        }
+    }
+
+    // whether to fall back to the key as given when its normalized form
+    // isn't found
+    fn probe_unnormalized(&self, key: &[u8], normalized: &[u8]) -> bool {
+        self.keys.probe_unnormalized && key != normalized
+    }
+
+    // retrieve record data for a key, normalizing it as configured
+    fn lookup(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let normalized = self.normalized(key);
+        match self.cached_get(&normalized)? {
+            None if self.probe_unnormalized(key, &normalized) => self.cached_get(key),
+            result => Ok(result),
+        }
+    }
+
+    // retrieve record data for a key, from the record cache if enabled
+    fn cached_get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let now = self.providers.clock().now();
+        if let Some(data) = self
+            .record_cache
+            .as_mut()
+            .and_then(|cache| cache.get(key, now))
+        {
+            return Ok(Some(data));
+        }
+
+        let data = self.int_get(key, self.locate(key))?.map(|(_, data)| data);
+        if let (Some(cache), Some(data)) = (self.record_cache.as_mut(), data.as_ref()) {
+            cache.insert(key, data, now);
+        }
+
+        Ok(data)
+    }
+
+    // API: record cache activity, if the record cache is enabled
+    pub fn record_cache_stats(&self) -> Option<RecordCacheStats> {
+        self.record_cache.as_ref().map(RecordCache::stats)
+    }
+
+    // hash, bucket directory index and starting bucket element of a key
+    fn locate(&self, key: &[u8]) -> (u32, usize, u32) {
+        match self.digest(key) {
+            Some(digest) => key_loc(self.header.dir_bits, self.header.bucket_elems, &digest),
+            None => key_loc(self.header.dir_bits, self.header.bucket_elems, key),
+        }
+    }
+
+    // the digest key a key is stored under, if it is stored by digest
+    fn digest(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.header
+            .digest_keys()
+            .filter(|threshold| digest::is_large(key, *threshold))
+            .map(|_| digest::digest_key(key))
+    }
+
+    // the key and value of a record, unpacked if stored by digest
+    fn unpack_record(&self, key: Vec<u8>, value: Vec<u8>) -> Result<(Vec<u8>, Vec<u8>)> {
+        match self.header.digest_keys().is_some() && digest::is_digest_key(&key) {
+            true => digest::unpack(value),
+            false => Ok((key, value)),
+        }
+    }
+
+    // retrieve record data, and element offset in bucket, for a key at the
+    // location given by locate(), leaving its bucket current
+    fn int_get(
+        &mut self,
+        key: &[u8],
+        (key_hash, bucket_dir, elem_ofs): (u32, usize, u32),
+    ) -> Result<Option<(usize, Vec<u8>)>> {
+        let digest = self.digest(key);
+        let stored = digest.as_deref().unwrap_or(key);
+
+        let bucket = self.cache_load_bucket(bucket_dir)?;
+        let bucket_entries = candidates(bucket, stored, (key_hash, bucket_dir, elem_ofs));
+
+        // compare just the keys of candidates, reading only the matching value
+        let found = bucket_entries
+            .into_iter()
+            .map(|(offset, elem)| {
+                self.check_deadline()?;
+                self.read_at(elem.data_ofs, elem.key_size as usize)
+                    .map(|data| (data == stored).then_some((offset, elem)))
+            })
+            .find_map(|candidate| candidate.transpose())
+            .transpose()?;
+
+        self.check_deadline()?;
+        let result = found
             .map(|(offset, elem)| {
-                read_ofs(
-                    &mut self.f,
-                    elem.data_ofs,
-                    (elem.key_size + elem.data_size) as usize,
+                self.read_at(
+                    elem.data_ofs + elem.key_size as u64,
+                    elem.data_size as usize,
                 )
+                .and_then(|data| self.codecs.decode(data))
                 .map(|data| (offset, data))
             })
-            .collect::<io::Result<Vec<_>>>()?;
+            .transpose()?;
 
-        let result = data_entries
-            .into_iter()
-            .filter(|(_, data)| data[..key.len()] == *key)
-            .map(|(offset, data)| (offset, data[key.len()..].to_vec()))
-            .next();
+        // a digest key may, rarely, be shared by another key
+        match (digest, result) {
+            (Some(_), Some((offset, data))) => {
+                let (stored_key, data) = digest::unpack(data)?;
+                Ok((stored_key == key).then_some((offset, data)))
+            }
+            (_, result) => Ok(result),
+        }
+    }
 
-        Ok(result)
+    // API: Fetch record value, given a key as bytes
+    pub fn get_raw(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.timed(Operation::Get, |db| db.lookup(key))
     }
 
     // API: Fetch record value, given a key
     pub fn get<'a, K: Into<BytesRef<'a>>, V: From<Bytes>>(&mut self, key: K) -> Result<Option<V>> {
-        let get_opt = self.int_get(key.into().as_ref())?;
+        let key = key.into();
+        let get_opt = self.timed(Operation::Get, |db| db.lookup(key.as_ref()))?;
         match get_opt {
             None => Ok(None),
-            Some(data) => Ok(Some(Bytes::from(data.1).into())),
+            Some(data) => Ok(Some(Bytes::from(data).into())),
         }
     }
 
+    // API: Fetch record value, failing with KeyNotFound if the key is missing
+    pub fn get_required<'a, K: Into<BytesRef<'a>>, V: From<Bytes>>(&mut self, key: K) -> Result<V> {
+        let key = key.into();
+        self.get(key.as_ref())?
+            .ok_or_else(|| Error::KeyNotFound(key.as_ref().into()))
+    }
+
+    // API: Fetch record value, failing with TimedOut if the deadline passes
+    // before a bucket or record read.
+    pub fn get_with_deadline<'a, K: Into<BytesRef<'a>>, V: From<Bytes>>(
+        &mut self,
+        key: K,
+        deadline: Instant,
+    ) -> Result<Option<V>> {
+        self.deadline = Some(deadline);
+        let result = self.get(key);
+        self.deadline = None;
+
+        result
+    }
+
+    // fail with TimedOut once the deadline of a time-bounded operation passes
+    fn check_deadline(&self) -> Result<()> {
+        match self
+            .deadline
+            .is_some_and(|deadline| self.providers.clock().now() >= deadline)
+        {
+            true => Err(Error::TimedOut),
+            false => Ok(()),
+        }
+    }
+
+    // API: Fetch record value, failing with TimedOut if it takes longer
+    // than timeout
+    pub fn get_with_timeout<'a, K: Into<BytesRef<'a>>, V: From<Bytes>>(
+        &mut self,
+        key: K,
+        timeout: Duration,
+    ) -> Result<Option<V>> {
+        let deadline = self.providers.clock().now() + timeout;
+        self.get_with_deadline(key, deadline)
+    }
+
+    // API: Fetch the values of many keys.  Uncached buckets are read
+    // together, then the candidate records of every key, in file order.
+    // Keys are taken bucket by bucket, so each bucket is loaded once however
+    // few the cache holds.
+    pub fn get_many<'a, K: Into<BytesRef<'a>>, V: From<Bytes>>(
+        &mut self,
+        keys: impl IntoIterator<Item = K>,
+    ) -> Result<Vec<Option<V>>> {
+        let keys = keys.into_iter().map(Into::into).collect::<Vec<BytesRef>>();
+        if self.keys.probe_unnormalized || self.header.digest_keys().is_some() {
+            return keys.iter().map(|key| self.get(key.as_ref())).collect();
+        }
+
+        self.timed(Operation::Get, |db| db.int_get_many(&keys))
+    }
+
+    fn int_get_many<V: From<Bytes>>(&mut self, keys: &[BytesRef]) -> Result<Vec<Option<V>>> {
+        let keys = keys
+            .iter()
+            .map(|key| self.normalized(key.as_ref()).into_owned())
+            .collect::<Vec<_>>();
+        let locs = keys.iter().map(|key| self.locate(key)).collect::<Vec<_>>();
+
+        self.load_buckets(locs.iter().map(|(_, bucket_dir, _)| *bucket_dir))?;
+
+        let mut by_bucket = (0..keys.len()).collect::<Vec<_>>();
+        by_bucket.sort_by_key(|n| self.dir.dir[locs[*n].1]);
+        let mut elems = vec![Vec::new(); keys.len()];
+        by_bucket.into_iter().try_for_each(|n| {
+            self.cache_load_bucket(locs[n].1)
+                .map(|bucket| elems[n] = candidates(bucket, &keys[n], locs[n]))
+        })?;
+
+        // read each candidate record whole, key then value
+        let mut records = elems
+            .iter()
+            .flatten()
+            .map(|(_, elem)| vec![0; elem.key_size as usize + elem.data_size as usize])
+            .collect::<Vec<_>>();
+        let mut reads = elems
+            .iter()
+            .flatten()
+            .zip(records.iter_mut())
+            .map(|((_, elem), record)| (elem.data_ofs, record.as_mut_slice()))
+            .collect::<Vec<_>>();
+        reads.sort_by_key(|(offset, _)| *offset);
+        self.f
+            .read_many(&mut reads)
+            .map_err(|e| self.replaced_or(Error::Io(e)))?;
+
+        let mut records = records.into_iter();
+        keys.iter()
+            .zip(elems)
+            .map(|(key, elems)| {
+                records
+                    .by_ref()
+                    .take(elems.len())
+                    .collect::<Vec<_>>()
+                    .into_iter()
+                    .find(|record| record.starts_with(key))
+                    .map(|record| {
+                        self.codecs
+                            .decode(record[key.len()..].to_vec())
+                            .map(|value| Bytes::from(value).into())
+                    })
+                    .transpose()
+            })
+            .collect()
+    }
+
+    // API: a key chosen uniformly at random from all records, with random
+    // numbers from rng, or None if the database is empty
+    pub fn random_key<K: From<Bytes>>(
+        &mut self,
+        rng: &mut impl FnMut() -> u64,
+    ) -> Result<Option<K>> {
+        let Some(elem) = self.random_element(rng)? else {
+            return Ok(None);
+        };
+
+        let mut key = self.read_at(elem.data_ofs, elem.key_size as usize)?;
+        if self.header.digest_keys().is_some() && digest::is_digest_key(&key) {
+            let value = self.read_at(
+                elem.data_ofs + elem.key_size as u64,
+                elem.data_size as usize,
+            )?;
+            (key, _) = digest::unpack(self.codecs.decode(value)?)?;
+        }
+        if self.keys.c_string {
+            key.pop_if(|b| *b == 0);
+        }
+
+        Ok(Some(Bytes::from(key).into()))
+    }
+
+    // API: n keys chosen independently and uniformly at random, so a key
+    // may be chosen more than once.  Empty if the database is empty.
+    pub fn sample<K: From<Bytes>>(
+        &mut self,
+        n: usize,
+        rng: &mut impl FnMut() -> u64,
+    ) -> Result<Vec<K>> {
+        let mut keys = Vec::with_capacity(n);
+        while keys.len() < n {
+            match self.random_key(rng)? {
+                Some(key) => keys.push(key),
+                None => break,
+            }
+        }
+
+        Ok(keys)
+    }
+
+    // Pick a random directory entry and bucket element until the element is
+    // occupied.  A bucket with more directory entries is more likely to be
+    // picked, so picks are kept with probability one in its number of
+    // entries, leaving every record equally likely.  If picks keep failing,
+    // as in a nearly empty database, count the records of every bucket.
+    fn random_element(&mut self, rng: &mut impl FnMut() -> u64) -> Result<Option<BucketElement>> {
+        let dir_bits = self.header.dir_bits;
+        let dir_len = self.dir.dir.len() as u64;
+        let elems = self.header.bucket_elems as u64;
+
+        for _ in 0..RANDOM_PICKS {
+            let bucket_dir = (rng() % dir_len) as usize;
+            let slot = (rng() % elems) as usize;
+            let pick = self
+                .cache_load_bucket(bucket_dir)
+                .map(|bucket| (bucket.bits, bucket.tab[slot]));
+            let Some((bits, elem)) = self.skip_quarantined(pick)? else {
+                continue;
+            };
+
+            let entries = 1u64 << (dir_bits - bits);
+            if elem.is_occupied() && rng().is_multiple_of(entries) {
+                return Ok(Some(elem));
+            }
+        }
+
+        let mut counts = Vec::new();
+        let mut cur_dir = 0;
+        while cur_dir < self.dir.dir.len() {
+            let count = self
+                .cache_load_bucket(cur_dir)
+                .map(|bucket| bucket.count as u64);
+            counts.push((cur_dir, self.skip_quarantined(count)?.unwrap_or(0)));
+            cur_dir = self.next_bucket_dir(cur_dir);
+        }
+
+        let total = counts.iter().map(|(_, count)| count).sum::<u64>();
+        if total == 0 {
+            return Ok(None);
+        }
+
+        let mut n = rng() % total;
+        let (bucket_dir, _) = counts
+            .into_iter()
+            .find(|(_, count)| match n < *count {
+                true => true,
+                false => {
+                    n -= count;
+                    false
+                }
+            })
+            .unwrap();
+
+        let bucket = self.cache_load_bucket(bucket_dir)?;
+        Ok(bucket
+            .tab
+            .iter()
+            .filter(|elem| elem.is_occupied())
+            .nth(n as usize)
+            .copied())
+    }
+
+    // read the uncached buckets of some directory entries together, and
+    // cache them
+    fn load_buckets(&mut self, bucket_dirs: impl Iterator<Item = usize>) -> Result<()> {
+        let mut offsets = bucket_dirs
+            .map(|bucket_dir| self.dir.dir[bucket_dir])
+            .filter(|offset| {
+                !self.bucket_cache.contains(*offset)
+                    && !self.quarantine.as_ref().is_some_and(|q| q.contains(offset))
+            })
+            .collect::<Vec<_>>();
+        offsets.sort();
+        offsets.dedup();
+        // buckets beyond the cache size would be evicted before use
+        offsets.truncate(self.bucket_cache.cachesize());
+
+        let mut data = vec![vec![0; self.header.bucket_sz as usize]; offsets.len()];
+        let mut reads = offsets
+            .iter()
+            .copied()
+            .zip(data.iter_mut().map(|data| data.as_mut_slice()))
+            .collect::<Vec<_>>();
+        self.f
+            .read_many(&mut reads)
+            .map_err(|e| self.replaced_or(Error::Io(e)))?;
+        self.counters.buckets_read += offsets.len() as u64;
+
+        offsets
+            .into_iter()
+            .zip(data)
+            .try_for_each(|(offset, data)| {
+                let bucket = decode_bucket(&self.header, offset, &data);
+                match self.skip_quarantined(bucket)? {
+                    Some(bucket) => self.cache_bucket(offset, bucket),
+                    None => Ok(()),
+                }
+            })
+    }
+
     pub fn magic(&self) -> Magic {
         self.header.magic
     }
 
+    // API: alignment in use, either as requested or as detected at open.
+    pub fn alignment(&self) -> Alignment {
+        self.header.layout.alignment
+    }
+
+    // API: the random identifier given to the database on creation, kept
+    // by copies of the file, so copies of one database can be recognised
+    pub fn uuid(&self) -> Option<[u8; 16]> {
+        self.header.id()
+    }
+
+    // API: a number that grows whenever the database changes through this
+    // handle or is synced: the sync count (zero without numsync) plus the
+    // number of records stored or removed, and of syncs, since open.  Equal
+    // generations of one handle mean unchanged contents.
+    pub fn generation(&self) -> u64 {
+        self.header.numsync().unwrap_or(0) as u64 + self.counters.updates + self.counters.syncs
+    }
+
+    // API: has the database been sealed read-only by seal()?
+    pub fn is_sealed(&self) -> bool {
+        self.header.sealed()
+    }
+
+    // API: version of the application's data set by set_user_version(), or
+    // zero if none
+    pub fn user_version(&self) -> u16 {
+        self.header.user_version()
+    }
+
+    // refuse a database whose user version isn't the one expected, on open
+    fn check_user_version(&self, expected: Option<u16>) -> Result<()> {
+        match expected {
+            Some(expected) if self.user_version() != expected => Err(Error::VersionMismatch {
+                found: self.user_version(),
+                expected,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    // API: number of times the database has been synced, if it keeps count
+    pub fn numsync(&self) -> Option<u32> {
+        self.header.numsync()
+    }
+
     #[cfg(feature = "diagnostic")]
     pub fn show_header(&self, w: &mut impl Write) -> io::Result<()> {
         let (dir_sz, dir_bits) = build_dir_size(self.header.layout.offset, self.header.block_sz);
@@ -446,8 +2109,153 @@ where
 
         Ok(())
     }
+
+    #[cfg(feature = "diagnostic")]
+    pub fn show_cache(&self, w: &mut impl Write) -> io::Result<()> {
+        let hits = self.counters.bucket_hits;
+        let misses = self.counters.buckets_read;
+
+        writeln!(w, "cache-size {}", self.bucket_cache.cachesize())?;
+        writeln!(w, "cached-buckets {}", self.bucket_cache.offsets().len())?;
+        writeln!(w, "dirty-buckets {}", self.bucket_cache.dirty_list().len())?;
+        writeln!(w, "cache-hits {}", hits)?;
+        writeln!(w, "cache-misses {}", misses)?;
+        match hits + misses {
+            0 => writeln!(w, "hit-rate -")?,
+            lookups => writeln!(w, "hit-rate {:.3}", hits as f64 / lookups as f64)?,
+        }
+
+        Ok(())
+    }
+
+    // API: report the bucket of a directory entry: its bits, free space and
+    // occupied elements
+    #[cfg(feature = "diagnostic")]
+    pub fn show_bucket(&mut self, bucket_dir: usize, w: &mut impl Write) -> Result<()> {
+        if bucket_dir >= self.dir.dir.len() {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no such directory entry",
+            )));
+        }
+
+        let offset = self.dir.dir[bucket_dir];
+        let bucket = self.cache_load_bucket(bucket_dir)?;
+
+        writeln!(w, "offset {}", offset)?;
+        writeln!(w, "bits {}", bucket.bits)?;
+        writeln!(w, "count {}", bucket.count)?;
+        writeln!(w, "avail-count {}", bucket.avail.len())?;
+        for elem in &bucket.avail {
+            writeln!(w, "avail {} {}", elem.addr, elem.sz)?;
+        }
+        for (n, elem) in bucket.tab.iter().enumerate() {
+            if elem.is_occupied() {
+                writeln!(
+                    w,
+                    "{}: hash {:#010x} offset {} key-size {} data-size {}",
+                    n, elem.hash, elem.data_ofs, elem.key_size, elem.data_size
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // API: report the free space listed in the header
+    #[cfg(feature = "diagnostic")]
+    pub fn show_avail(&self, w: &mut impl Write) -> io::Result<()> {
+        writeln!(w, "size {}", self.header.avail.sz)?;
+        writeln!(w, "count {}", self.header.avail.elems.len())?;
+        writeln!(w, "next-block {}", self.header.avail.next_block)?;
+        for elem in &self.header.avail.elems {
+            writeln!(w, "{} {}", elem.addr, elem.sz)?;
+        }
+
+        Ok(())
+    }
+
+    // API: header, cache and directory reports together, for bug reports
+    #[cfg(feature = "diagnostic")]
+    pub fn show_all(&self, w: &mut impl Write) -> io::Result<()> {
+        writeln!(w, "[header]")?;
+        self.show_header(w)?;
+        writeln!(w, "[cache]")?;
+        self.show_cache(w)?;
+        writeln!(w, "[directory]")?;
+        self.show_directory(w)
+    }
 }
 
+impl Gdbm<ReadOnly> {
+    // API: can this handle modify the database?
+    pub fn is_writable(&self) -> bool {
+        false
+    }
+
+    // API: take up changes made to the database file by others since it was
+    // opened: reread the header and directory and drop cached buckets and
+    // records.  A file replaced by rename is reopened.
+    pub fn refresh(&mut self) -> Result<()> {
+        let mut f = File::open(self.path()?)?;
+        let metadata = f.metadata()?;
+        let replaced = !same_file(&metadata, &self.f.file().metadata()?);
+        let alignment = (!replaced).then_some(self.header.layout.alignment);
+        let (header, dir) =
+            read_metadata_retrying(&mut f, metadata.len(), alignment, self.providers.clock())?;
+
+        self.f.reload(replaced.then_some(f))?;
+        self.header = header;
+        self.dir = dir;
+        self.bucket_cache = BucketCache::new(self.bucket_cache.cachesize(), None);
+        if let Some(record_cache) = self.record_cache.as_mut() {
+            record_cache.clear();
+        }
+        self.record_count = None;
+
+        Ok(())
+    }
+
+    // API: another reader of the same open file, sharing nothing else: it
+    // starts with this handle's header and directory and empty caches,
+    // without the checks of an open
+    pub fn try_clone(&self) -> Result<Gdbm<ReadOnly>> {
+        Ok(Gdbm {
+            pathname: self.pathname.clone(),
+            f: self.f.try_clone()?,
+            header: self.header.clone(),
+            dir: self.dir.clone(),
+            bucket_cache: BucketCache::new(self.bucket_cache.cachesize(), None),
+            event_hook: None,
+            record_count: self.record_count,
+            scratch: Vec::new(),
+            keys: self.keys,
+            quarantine: self.quarantine.clone(),
+            deadline: None,
+            slow_threshold: self.slow_threshold,
+            counters: Counters::default(),
+            last_op: None,
+            hot_buckets: None,
+            codecs: self.codecs.clone(),
+            record_cache: self
+                .record_cache
+                .as_ref()
+                .map(|record_cache| RecordCache::new(&record_cache.options())),
+            providers: self.providers.clone(),
+            #[cfg(feature = "write")]
+            changelog: None,
+            read_write: ReadOnly,
+        })
+    }
+
+    // API: watch the database file for changes, to refresh on
+    #[cfg(feature = "watch")]
+    pub fn watch(&self) -> Result<Watcher> {
+        Ok(Watcher::new(self.path()?)?)
+    }
+}
+
+#[cfg(feature = "write")]
 impl Gdbm<ReadWrite> {
     // API: open database file, read and validate header
     pub fn create<P: AsRef<std::path::Path>>(
@@ -476,11 +2284,26 @@ impl Gdbm<ReadWrite> {
             }
         }
 
-        let header = Header::new(
+        let mut header = Header::new(
             block_size,
             &layout,
             dir_bits,
             !open_options.write.create.no_numsync,
+            open_options.write.create.extended,
+        );
+        header.check_geometry()?;
+        if open_options.write.create.identity {
+            header.set_random_id(open_options.providers.random());
+        }
+        if let Some(version) = open_options.user_version {
+            header.set_user_version(version);
+        }
+        header.set_digest_keys(
+            open_options
+                .write
+                .create
+                .digest_keys
+                .filter(|threshold| *threshold > 0),
         );
         let bucket = Bucket::new(0, header.bucket_elems as usize, vec![], vec![]);
         let bucket_offset = header.next_block - block_size as u64;
@@ -495,41 +2318,229 @@ impl Gdbm<ReadWrite> {
             BucketCache::new(cache_buckets, Some((bucket_offset, bucket)))
         };
 
-        let mut db = Gdbm {
-            pathname: path.as_ref().to_string_lossy().to_string(),
-            f,
-            header,
-            dir,
-            bucket_cache,
-            read_write: ReadWrite {
-                sync: open_options.write.sync,
-                state: WriteState::Dirty,
-            },
+        let mut db = Gdbm {
+            pathname: path.as_ref().to_string_lossy().to_string(),
+            f: storage(f, &open_options.providers),
+            header,
+            dir,
+            bucket_cache,
+            event_hook: None,
+            record_count: Some(0),
+            scratch: Vec::new(),
+            keys: KeyOptions::default(),
+            quarantine: None,
+            deadline: None,
+            slow_threshold: None,
+            counters: Counters::default(),
+            last_op: None,
+            hot_buckets: None,
+            codecs: Codecs::default(),
+            record_cache: None,
+            providers: open_options.providers.clone(),
+            // created empty, so no change predates the log
+            changelog: open_options.write.changelog.then(|| Changelog::new(0)),
+            read_write: ReadWrite {
+                sync: open_options.write.sync,
+                state: WriteState::Dirty,
+                readonly_fs: false,
+                punch_holes: open_options.write.punch_holes,
+                preallocate: open_options.write.preallocate,
+                reserved: 0,
+                on_drop_error: open_options.write.on_drop_error,
+                strict_drop: open_options.write.strict_drop,
+                closed: false,
+                max_file_size: open_options.write.max_file_size,
+                avail_policy: open_options.write.avail_policy,
+                convert_endian: open_options.write.convert_endian,
+                shadow_metadata: open_options.write.shadow_metadata,
+                extent: None,
+            },
+        };
+
+        db.reserve(db.header.next_block)?;
+
+        if db.read_write.sync {
+            db.sync()?;
+        }
+
+        Ok(db)
+    }
+
+    pub fn set_sync(&mut self, sync: bool) {
+        self.read_write.sync = sync;
+    }
+
+    // API: bytes written to the file since open, against key and value
+    // bytes inserted
+    pub fn write_amplification(&self) -> WriteAmplification {
+        WriteAmplification {
+            bytes_written: self.f.bytes_written(),
+            bytes_inserted: self.counters.bytes_inserted,
+        }
+    }
+
+    // API: write the records stored through this handle since generation,
+    // from generation() or a previous export, as a binary dump.  A copy of
+    // the database at that generation is brought up to date by importing
+    // the dump with import_bin() and removing the keys returned.  Needs the
+    // changelog write option, and a generation of this handle, no earlier
+    // than its open; otherwise Error::ChangesUnavailable.
+    pub fn export_changes_since(
+        &mut self,
+        generation: u64,
+        outf: &mut std::fs::File,
+        mode: ExportBinMode,
+    ) -> Result<ExportedChanges> {
+        let current = self.generation();
+        let changed = self
+            .changelog
+            .as_ref()
+            .and_then(|changelog| changelog.since(generation, current))
+            .ok_or(Error::ChangesUnavailable { generation })?;
+
+        let alignment = match mode {
+            ExportBinMode::ExpNative => self.header.layout.alignment,
+            ExportBinMode::Exp32 => Alignment::Align32,
+            ExportBinMode::Exp64 => Alignment::Align64,
+        };
+        self.export_bin_header(outf, &ExportOptions::default())?;
+
+        let mut removed = Vec::new();
+        for mut key in changed {
+            let value = self.int_get(&key, self.locate(&key))?;
+            // keys as iteration returns them
+            if self.keys.c_string {
+                key.pop_if(|b| *b == 0);
+            }
+            match value {
+                Some((_, value)) => Self::export_bin_datum(outf, alignment, key)
+                    .and_then(|_| Self::export_bin_datum(outf, alignment, value))?,
+                None => removed.push(key),
+            }
+        }
+
+        Ok(ExportedChanges {
+            removed,
+            generation: current,
+        })
+    }
+
+    fn set_write_options<C>(&mut self, write: &options::Write<C>) {
+        self.read_write.sync = write.sync;
+        self.read_write.punch_holes = write.punch_holes;
+        self.read_write.preallocate = write.preallocate;
+        self.read_write.reserved = self.header.next_block;
+        self.read_write.on_drop_error = write.on_drop_error;
+        self.read_write.strict_drop = write.strict_drop;
+        self.read_write.max_file_size = write.max_file_size;
+        self.read_write.convert_endian = write.convert_endian;
+        self.read_write.shadow_metadata = write.shadow_metadata;
+        self.changelog = write.changelog.then(|| Changelog::new(self.generation()));
+        self.read_write.avail_policy = AvailPolicy {
+            bucket_slots: write.avail_policy.bucket_slots.min(Bucket::AVAIL),
+            ..write.avail_policy
         };
+    }
 
-        if db.read_write.sync {
-            db.sync()?;
+    // API: can this handle modify the database?  False once the filesystem
+    // has refused a write as readonly.
+    pub fn is_writable(&self) -> bool {
+        !self.read_write.readonly_fs
+    }
+
+    fn check_writable(&self) -> Result<()> {
+        match self.read_write.readonly_fs {
+            true => Err(Error::WriteToReadonly),
+            false => Ok(()),
         }
+    }
 
-        Ok(db)
+    // Once the filesystem reports readonly, fail all later writes up front.
+    fn note_readonly_fs(&mut self, e: Error) -> Error {
+        match e {
+            Error::Io(e) => match Error::from_write(e) {
+                Error::WriteToReadonly => {
+                    self.read_write.readonly_fs = true;
+                    Error::WriteToReadonly
+                }
+                e => e,
+            },
+            Error::ShortWrite { error, .. }
+                if error.kind() == io::ErrorKind::ReadOnlyFilesystem =>
+            {
+                self.read_write.readonly_fs = true;
+                Error::WriteToReadonly
+            }
+            e => e,
+        }
     }
 
-    pub fn set_sync(&mut self, sync: bool) {
+    // Run a bulk update with the per-record syncs of sync mode suspended,
+    // syncing once at the end instead.
+    fn batch<T>(&mut self, update: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let sync = std::mem::replace(&mut self.read_write.sync, false);
+        let result = update(self);
         self.read_write.sync = sync;
+
+        match sync {
+            true => {
+                let synced = self.sync();
+                result.and_then(|value| synced.map(|_| value))
+            }
+            false => result,
+        }
     }
 
+    #[cfg(feature = "ascii-dump")]
     pub fn import_ascii(&mut self, reader: &mut impl Read) -> Result<()> {
+        self.import_ascii_with(reader, &ImportOptions::default())
+    }
+
+    #[cfg(feature = "ascii-dump")]
+    pub fn import_ascii_with(
+        &mut self,
+        reader: &mut impl Read,
+        options: &ImportOptions,
+    ) -> Result<()> {
+        self.import_ascii_mapped(reader, options, |key, value| Some((key, value)))
+    }
+
+    // API: import an ASCII dump, storing map(key, value) in place of each
+    // record, or dropping the record if it returns None
+    #[cfg(feature = "ascii-dump")]
+    pub fn import_ascii_mapped(
+        &mut self,
+        reader: &mut impl Read,
+        options: &ImportOptions,
+        map: impl FnMut(Vec<u8>, Vec<u8>) -> Option<(Vec<u8>, Vec<u8>)>,
+    ) -> Result<()> {
         ASCIIImportIterator::new(reader)
             .map_err(Error::Io)
-            .and_then(|mut lines| {
-                lines.try_for_each(|l| {
-                    let (key, value) = l.map_err(Error::Io)?;
-                    self.insert(key, value).map(|_| ())
-                })
-            })
+            .and_then(|lines| self.import_records(lines, options, map))
     }
 
     pub fn import_bin(&mut self, reader: &mut impl Read, mode: ExportBinMode) -> Result<()> {
+        self.import_bin_with(reader, mode, &ImportOptions::default())
+    }
+
+    pub fn import_bin_with(
+        &mut self,
+        reader: &mut impl Read,
+        mode: ExportBinMode,
+        options: &ImportOptions,
+    ) -> Result<()> {
+        self.import_bin_mapped(reader, mode, options, |key, value| Some((key, value)))
+    }
+
+    // API: import a binary dump, storing map(key, value) in place of each
+    // record, or dropping the record if it returns None
+    pub fn import_bin_mapped(
+        &mut self,
+        reader: &mut impl Read,
+        mode: ExportBinMode,
+        options: &ImportOptions,
+        map: impl FnMut(Vec<u8>, Vec<u8>) -> Option<(Vec<u8>, Vec<u8>)>,
+    ) -> Result<()> {
         let alignment = match mode {
             ExportBinMode::ExpNative => self.header.layout.alignment,
             ExportBinMode::Exp32 => Alignment::Align32,
@@ -538,56 +2549,153 @@ impl Gdbm<ReadWrite> {
 
         BinaryImportIterator::new(alignment, reader)
             .map_err(Error::Io)
-            .and_then(|mut lines| {
-                lines.try_for_each(|l| {
-                    let (key, value) = l.map_err(Error::Io)?;
-                    self.insert(key, value).map(|_| ())
+            .and_then(|lines| self.import_records(lines, options, map))
+    }
+
+    // Store imported records IMPORT_BATCH at a time, allocating the space
+    // for each batch at once.
+    fn import_records(
+        &mut self,
+        records: impl Iterator<Item = io::Result<(Vec<u8>, Vec<u8>)>>,
+        options: &ImportOptions,
+        mut map: impl FnMut(Vec<u8>, Vec<u8>) -> Option<(Vec<u8>, Vec<u8>)>,
+    ) -> Result<()> {
+        let records = records.filter_map(|record| match record {
+            Ok((key, value)) => map(key, value).map(Ok),
+            Err(e) => Some(Err(Error::Io(e))),
+        });
+
+        self.store_many(records, options.mode)
+    }
+
+    // Store records in batches, allocating space for each batch at once,
+    // and syncing, if the database syncs on update, only at the end.
+    fn store_many(
+        &mut self,
+        records: impl Iterator<Item = Result<(Vec<u8>, Vec<u8>)>>,
+        mode: InsertMode,
+    ) -> Result<()> {
+        let mut records = records.fuse();
+
+        self.batch(|db| loop {
+            let batch = records
+                .by_ref()
+                .take(IMPORT_BATCH)
+                .collect::<Result<Vec<_>>>()?;
+            if batch.is_empty() {
+                return Ok(());
+            }
+
+            // sized as stored, which with codecs means encoding each record
+            // here as well as when storing it
+            let sizes = batch
+                .iter()
+                .map(|(key, value)| {
+                    db.encode_record(db.normalized(key).into_owned(), value.clone())
+                        .map(|(key, data)| (key.len() + data.len()) as u32)
                 })
-            })
+                .collect::<Result<Vec<_>>>()?;
+            db.allocate_many(sizes)?;
+            let stored = batch
+                .into_iter()
+                .try_for_each(|(key, value)| db.store(key, value, mode).map(|_| ()));
+            let released = db.release_extent();
+            stored.and(released)?;
+        })
     }
 
-    // virtually allocate N blocks of data, at end of db file (no I/O)
-    fn extend(&mut self, size: u32) -> io::Result<(u64, u32)> {
+    // virtually allocate N blocks of data, at end of db file (no I/O unless
+    // preallocating)
+    fn extend(&mut self, size: u32) -> Result<(u64, u32)> {
         let offset = self.header.next_block;
         let length = match size % self.header.block_sz {
             0 => size / self.header.block_sz,
             _ => size / self.header.block_sz + 1,
         } * self.header.block_sz;
 
+        // small offsets are stored as 32 bits; refuse to grow past them
+        if self.header.layout.offset == Offset::Small && offset + length as u64 > u32::MAX as u64 {
+            return Err(Error::OffsetOverflow {
+                offset,
+                length,
+                maximum: u32::MAX as u64,
+            });
+        }
+
+        if let Some(maximum) = self.read_write.max_file_size {
+            if offset + length as u64 > maximum {
+                return Err(Error::DatabaseFull {
+                    size: offset + length as u64,
+                    maximum,
+                });
+            }
+        }
+
+        self.reserve(offset + length as u64)?;
+
         self.header.next_block += length as u64;
         self.header.dirty = true;
 
         Ok((offset, length))
     }
 
+    // When preallocating, reserve file space in whole chunks up to end.
+    fn reserve(&mut self, end: u64) -> io::Result<()> {
+        match self.read_write.preallocate {
+            Some(chunk) if end > self.read_write.reserved => {
+                let start = self.read_write.reserved;
+                let length = (end - start).next_multiple_of(chunk.max(1));
+                sys::preallocate(self.f.file(), start, length)?;
+                self.read_write.reserved = start + length;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
     // Free list is full.  Split in half, and store 1/2 in new list block.
-    fn push_avail_block(&mut self) -> io::Result<()> {
-        let (header_elems, new_elems) = avail::partition_elems(&self.header.avail.elems);
+    // On failure the header list is left whole.
+    fn push_avail_block(&mut self) -> Result<()> {
+        let avail = self.header.avail.clone();
+        let (header_elems, new_elems) = avail::partition_elems(&avail.elems);
+        let pushed = new_elems.len();
+        let block = AvailBlock::new(new_elems.len() as u32, avail.next_block, new_elems);
+
+        // Halve the header list before allocating the new block, so what the
+        // allocation leaves over fits there rather than pushing again.
+        // Unlinked meanwhile, the chain isn't popped into the header.
+        self.header.avail = AvailBlock::new(avail.sz, 0, header_elems);
+        self.header.dirty = true;
 
         // write extension block to storage (immediately)
-        let new_blk_ofs = {
-            let block = AvailBlock::new(
-                new_elems.len() as u32,
-                self.header.avail.next_block,
-                new_elems,
-            );
-            let offset = self.allocate_record(block.extent(&self.header.layout))?;
-            let mut buffer = Vec::with_capacity(self.header.block_sz as usize);
-            block.serialize(&self.header.layout, &mut buffer)?;
-            self.f.seek(SeekFrom::Start(offset))?;
-            self.f.write_all(&buffer)?;
-
-            offset
+        let new_blk_ofs = self
+            .allocate_record(block.extent(&self.header.layout))
+            .and_then(|offset| {
+                self.write_serialized(offset, |db, buffer| {
+                    block.serialize(&db.header.layout, buffer)
+                })
+                .map(|_| offset)
+                .map_err(Error::Io)
+            });
+        let new_blk_ofs = match new_blk_ofs {
+            Ok(offset) => offset,
+            Err(e) => {
+                self.header.avail = avail;
+                return Err(e);
+            }
         };
+        self.header.avail.next_block = new_blk_ofs;
 
-        self.header.avail = AvailBlock::new(self.header.avail.sz, new_blk_ofs, header_elems);
-        self.header.dirty = true;
+        self.emit(Event::AvailBlockPushed {
+            offset: new_blk_ofs,
+            elems: pushed,
+        });
 
         Ok(())
     }
 
     // pops a block of the avail block list into the header block, only if it can accommodate it
-    fn pop_avail_block(&mut self) -> io::Result<()> {
+    fn pop_avail_block(&mut self) -> Result<()> {
         let next_addr = self.header.avail.next_block;
 
         let next = {
@@ -595,7 +2703,24 @@ impl Gdbm<ReadWrite> {
             AvailBlock::from_reader(&self.header.layout, &mut self.f)?
         };
 
+        // check the block, then the block against the header's free space
+        let overlap = |block: &AvailBlock| {
+            block.overlapping().map(|i| Error::BadAvailOverlap {
+                block_offset: next_addr,
+                elem: i,
+                offset: block.elems[i].addr,
+                size: block.elems[i].sz,
+            })
+        };
+        if let Some(e) = overlap(&next) {
+            return Err(e);
+        }
+
         if let Some(block) = self.header.avail.merge(&next) {
+            if let Some(e) = overlap(&block) {
+                return Err(e);
+            }
+
             self.header.avail = block;
             self.header.dirty = true;
 
@@ -607,15 +2732,21 @@ impl Gdbm<ReadWrite> {
     }
 
     // Add (addr,sz) to db-wide free list
-    fn free_record(&mut self, addr: u64, sz: u32) -> io::Result<()> {
+    fn free_record(&mut self, addr: u64, sz: u32) -> Result<()> {
         // simply forget elements too small to worry about
-        if (sz as usize) <= IGNORE_SMALL {
+        if sz <= self.read_write.avail_policy.ignore_small {
             return Ok(());
         }
 
+        if self.read_write.punch_holes {
+            self.punch_hole(addr, sz)?;
+        }
+
         // smaller items go into bucket avail list
         let bucket = self.bucket_cache.current_bucket().unwrap();
-        if sz < self.header.block_sz && (bucket.avail.len() as u32) < Bucket::AVAIL {
+        if sz < self.header.block_sz
+            && (bucket.avail.len() as u32) < self.read_write.avail_policy.bucket_slots
+        {
             self.bucket_cache
                 .current_bucket_mut()
                 .unwrap()
@@ -624,7 +2755,13 @@ impl Gdbm<ReadWrite> {
             // larger items go into the header avail list
             // (and also when bucket avail list is full)
             if self.header.avail.elems.len() == self.header.avail.sz as usize {
-                self.push_avail_block()?;
+                match self.push_avail_block() {
+                    Ok(()) => (),
+                    // with no room left for another avail block, forget
+                    // the space, so freeing never runs out of it
+                    Err(Error::DatabaseFull { .. } | Error::OffsetOverflow { .. }) => return Ok(()),
+                    Err(e) => return Err(e),
+                }
             }
 
             self.header.free(addr, sz);
@@ -633,29 +2770,62 @@ impl Gdbm<ReadWrite> {
         Ok(())
     }
 
-    fn write_bucket(&mut self, bucket: &Bucket, offset: u64) -> io::Result<()> {
-        let mut buffer = Vec::with_capacity(self.header.block_sz as usize);
-        bucket.serialize(&self.header.layout, &mut buffer)?;
-        self.f.seek(SeekFrom::Start(offset))?;
-        self.f.write_all(&buffer)?;
+    // release storage for the whole blocks within a freed extent
+    fn punch_hole(&mut self, addr: u64, sz: u32) -> io::Result<()> {
+        let block_sz = self.header.block_sz as u64;
+        let start = addr.next_multiple_of(block_sz);
+        let end = (addr + sz as u64) / block_sz * block_sz;
+
+        match end > start {
+            true => self
+                .f
+                .flush()
+                .and_then(|_| sys::punch_hole(self.f.file(), start, end - start)),
+            false => Ok(()),
+        }
+    }
 
-        Ok(())
+    // serialize into the scratch buffer, then write it at offset
+    fn write_serialized(
+        &mut self,
+        offset: u64,
+        serialize: impl FnOnce(&Self, &mut Vec<u8>) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let mut buffer = std::mem::take(&mut self.scratch);
+        buffer.clear();
+
+        let result =
+            serialize(self, &mut buffer).and_then(|_| self.f.write_all_at(offset, &buffer));
+
+        self.scratch = buffer;
+
+        result
+    }
+
+    fn write_bucket(&mut self, bucket: &Bucket, offset: u64) -> io::Result<()> {
+        self.write_serialized(offset, |db, buffer| {
+            serialize_bucket(&db.header, bucket, buffer)
+        })
     }
 
     // write out any cached, not-yet-written metadata and data to storage
     fn write_buckets(&mut self) -> io::Result<()> {
-        self.bucket_cache
+        let mut buffer = std::mem::take(&mut self.scratch);
+
+        let result = self
+            .bucket_cache
             .dirty_list()
             .iter()
             .try_for_each(|(offset, bucket)| {
                 // Can't use self.write_bucket() here. We have a borrow in bucket list.
-                let mut buffer = Vec::with_capacity(self.header.block_sz as usize);
-                bucket
-                    .serialize(&self.header.layout, &mut buffer)
-                    .and_then(|_| self.f.seek(SeekFrom::Start(*offset)))
-                    .and_then(|_| self.f.write_all(&buffer))
-            })
-            .map(|_| self.bucket_cache.clear_dirty())
+                buffer.clear();
+                serialize_bucket(&self.header, bucket, &mut buffer)?;
+                self.f.write_all_at(*offset, &buffer)
+            });
+
+        self.scratch = buffer;
+
+        result.map(|_| self.bucket_cache.clear_dirty())
     }
 
     // write out any cached, not-yet-written metadata and data to storage
@@ -664,10 +2834,9 @@ impl Gdbm<ReadWrite> {
             return Ok(());
         }
 
-        let mut buffer = Vec::with_capacity(self.dir.extent(&self.header.layout) as usize);
-        self.dir.serialize(&self.header.layout, &mut buffer)?;
-        self.f.seek(SeekFrom::Start(self.header.dir_ofs))?;
-        self.f.write_all(&buffer)?;
+        self.write_serialized(self.header.dir_ofs, |db, buffer| {
+            db.dir.serialize(&db.header.layout, buffer)
+        })?;
 
         self.dir.dirty = false;
 
@@ -680,10 +2849,13 @@ impl Gdbm<ReadWrite> {
             return Ok(());
         }
 
-        let mut buffer = Vec::with_capacity(self.header.block_sz as usize);
-        self.header.serialize(&mut buffer)?;
-        self.f.seek(SeekFrom::Start(0))?;
-        self.f.write_all(&buffer)?;
+        // free space allocated at the end of the file may never have been
+        // written; the file must still cover everything the header claims
+        if self.f.file_size()? < self.header.next_block {
+            self.f.set_len(self.header.next_block)?;
+        }
+
+        self.write_serialized(0, |db, buffer| db.header.serialize(buffer))?;
 
         self.header.dirty = false;
 
@@ -696,6 +2868,13 @@ impl Gdbm<ReadWrite> {
 
         self.write_buckets()?;
         self.write_dir()?;
+
+        // with shadow metadata, everything the new header points at must be
+        // durable before the header replaces the old one
+        if self.read_write.shadow_metadata && self.header.dirty {
+            self.f.sync_data()?;
+        }
+
         self.write_header()?;
 
         self.read_write.state = WriteState::Clean;
@@ -703,34 +2882,224 @@ impl Gdbm<ReadWrite> {
         Ok(())
     }
 
+    // move a changed directory to freshly allocated space, leaving the copy
+    // the header on disk points at untouched
+    fn shadow_dir(&mut self) -> Result<()> {
+        if !self.dir.dirty {
+            return Ok(());
+        }
+
+        let (old_offset, size) = (self.header.dir_ofs, self.header.dir_sz);
+        let offset = self.allocate_record(size)?;
+
+        // the old directory is still live on disk until the header is
+        // written, so its space must not be punched out yet
+        let punch_holes = std::mem::replace(&mut self.read_write.punch_holes, false);
+        let result = self.free_record(old_offset, size);
+        self.read_write.punch_holes = punch_holes;
+        result?;
+
+        self.header.dir_ofs = offset;
+        self.header.dirty = true;
+
+        Ok(())
+    }
+
     // API: ensure database is flushed to stable storage
     pub fn sync(&mut self) -> Result<()> {
+        self.timed(Operation::Sync, |db| {
+            db.check_consistent()?;
+            match db.read_write.state {
+                WriteState::Clean => Ok(()),
+                WriteState::Inconsistent => Err(Error::Inconsistent),
+                WriteState::Dirty => {
+                    db.check_writable()?;
+                    db.header.increment_numsync();
+                    if db.read_write.shadow_metadata {
+                        db.shadow_dir()?;
+                    }
+                    db.write_dirty()
+                        .and_then(|_| db.f.sync_data())
+                        .map_err(|e| db.note_readonly_fs(Error::Io(e)))?;
+                    db.counters.syncs += 1;
+
+                    Ok(())
+                }
+            }
+        })
+    }
+
+    // API: seal an extended database read-only, then close it.  Later
+    // read-write opens fail with Error::Sealed unless force_unseal is set.
+    pub fn seal(mut self) -> Result<()> {
+        self.check_writable()?;
+        if !self.header.magic.is_extended() {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "only extended databases can be sealed",
+            )));
+        }
+
+        self.header.set_sealed(true);
+        self.read_write.state = WriteState::Dirty;
+        self.close()
+    }
+
+    // API: record the version of the application's data, for
+    // OpenOptions::expect_user_version to check on open.  Kept in the
+    // numsync header.
+    pub fn set_user_version(&mut self, version: u16) -> Result<()> {
+        self.check_writable()?;
+        if !self.header.magic.is_numsync() {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "only numsync databases have a user version",
+            )));
+        }
+
+        if version != self.header.user_version() {
+            self.header.set_user_version(version);
+            self.read_write.state = WriteState::Dirty;
+        }
+
+        Ok(())
+    }
+
+    // refuse a sealed database, or unseal it if forced, on open
+    fn check_sealed(&mut self, force_unseal: bool) -> Result<()> {
+        match (self.header.sealed(), force_unseal) {
+            (false, _) => Ok(()),
+            (true, false) => {
+                self.read_write.closed = true;
+                Err(Error::Sealed)
+            }
+            (true, true) => {
+                self.header.set_sealed(false);
+                self.read_write.state = WriteState::Dirty;
+                Ok(())
+            }
+        }
+    }
+
+    // API: sync and close the database, returning any error that dropping
+    // it would have ignored
+    pub fn close(mut self) -> Result<()> {
+        let result = self.sync();
+        self.read_write.closed = true;
+
+        result
+    }
+
+    // Fail if an update failed partway, or a record write deferred by
+    // write_behind() failed: either leaves the file inconsistent with the
+    // cached metadata.
+    fn check_consistent(&mut self) -> Result<()> {
+        if self.f.take_write_failure() {
+            self.read_write.state = WriteState::Inconsistent;
+        }
+
         match self.read_write.state {
-            WriteState::Clean => Ok(()),
             WriteState::Inconsistent => Err(Error::Inconsistent),
-            WriteState::Dirty => {
-                self.header.increment_numsync();
-                self.write_dirty()
-                    .and_then(|_| self.f.sync_data())
-                    .map_err(Error::Io)
+            _ => Ok(()),
+        }
+    }
+
+    // Sync a dropped database, reporting rather than ignoring failure.
+    fn drop_sync(&mut self) {
+        if self.read_write.closed {
+            return;
+        }
+
+        if self.read_write.strict_drop && !std::thread::panicking() {
+            debug_assert!(
+                self.read_write.state == WriteState::Clean,
+                "database {} dropped with unsynced changes",
+                self.pathname
+            );
+        }
+
+        if let Err(e) = self.sync() {
+            if let Some(on_drop_error) = self.read_write.on_drop_error {
+                on_drop_error(&e);
             }
         }
     }
 
-    fn int_remove(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
-        let get_opt = self.int_get(key)?;
+    // API: bump the numsync counter and sync, without changing any data
+    pub fn touch_numsync(&mut self) -> Result<()> {
+        self.check_writable()?;
 
-        if get_opt.is_none() {
-            return Ok(None);
+        if self.read_write.state == WriteState::Clean {
+            self.read_write.state = WriteState::Dirty;
+        }
+
+        self.sync()
+    }
+
+    // API: sync, then read the header and directory back from storage and
+    // check they match what was written
+    pub fn sync_and_verify(&mut self) -> Result<()> {
+        self.sync()?;
+
+        let mut header = Vec::with_capacity(self.header.block_sz as usize);
+        self.header.serialize(&mut header)?;
+        let mut dir = Vec::with_capacity(self.dir.extent(&self.header.layout) as usize);
+        self.dir.serialize(&self.header.layout, &mut dir)?;
+
+        [(0, header), (self.header.dir_ofs, dir)]
+            .into_iter()
+            .try_for_each(|(offset, written)| {
+                // make sure the data is read from disk, not the page cache
+                let _ = sys::fadvise(
+                    self.f.file(),
+                    offset,
+                    written.len() as u64,
+                    Advice::DontNeed,
+                );
+
+                match read_ofs(&mut self.f, offset, written.len())? == written {
+                    true => Ok(()),
+                    false => Err(Error::SyncMismatch {
+                        offset,
+                        length: written.len() as u32,
+                    }),
+                }
+            })?;
+
+        // and that it still passes validation
+        let file_size = self.f.file_size()?;
+        read_metadata(&mut self.f, file_size, self.header.layout.alignment).map(|_| ())
+    }
+
+    fn int_remove(&mut self, key: &[u8], loc: (u32, usize, u32)) -> Result<Option<Vec<u8>>> {
+        self.check_writable()?;
+        if let Some(cache) = self.record_cache.as_mut() {
+            cache.remove(key);
         }
 
-        if self.read_write.state == WriteState::Inconsistent {
-            return Err(Error::Inconsistent);
+        match self.int_get(key, loc)? {
+            Some((elem_ofs, data)) => self.remove_current(elem_ofs).map(|_| {
+                self.log_change(key);
+                Some(data)
+            }),
+            None => Ok(None),
         }
+    }
 
-        self.read_write.state = WriteState::Inconsistent;
+    // note key as changed by the update just made, in the changelog if any
+    fn log_change(&mut self, key: &[u8]) {
+        let generation = self.generation();
+        if let Some(changelog) = self.changelog.as_mut() {
+            changelog.record(key, generation);
+        }
+    }
 
-        let (elem_ofs, data) = get_opt.unwrap();
+    // remove the record at element elem_ofs of the current bucket, as found
+    // by int_get()
+    fn remove_current(&mut self, elem_ofs: usize) -> Result<()> {
+        self.check_consistent()?;
+
+        self.read_write.state = WriteState::Inconsistent;
 
         let elem = self
             .bucket_cache
@@ -738,70 +3107,274 @@ impl Gdbm<ReadWrite> {
             .unwrap()
             .remove(elem_ofs);
 
-        // release record bytes to available-space pool
-        self.free_record(elem.data_ofs, elem.key_size + elem.data_size)?;
+        // release record bytes to available-space pool
+        let result = self.free_record(elem.data_ofs, elem.key_size + elem.data_size);
+        self.record_count = self.record_count.map(|count| count - 1);
+
+        self.end_update(result)
+    }
+
+    // remove the record for a key, normalizing it as configured
+    fn remove_key(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let normalized = self.normalized(key);
+        match self.int_remove(&normalized, self.locate(&normalized))? {
+            None if self.probe_unnormalized(key, &normalized) => {
+                self.int_remove(key, self.locate(key))
+            }
+            result => Ok(result),
+        }
+    }
+
+    // API: remove a key/value pair from db, given a key as bytes
+    pub fn remove_raw(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.remove(key)
+    }
+
+    // API: remove a key/value pair from db, given a key
+    pub fn remove<'a, K: Into<BytesRef<'a>>>(&mut self, key: K) -> Result<Option<Vec<u8>>> {
+        self.timed(Operation::Remove, |db| {
+            db.remove_key(key.into().as_ref())
+                .and_then(|old_value| {
+                    if old_value.is_some() && db.read_write.sync {
+                        db.sync()?;
+                    }
+
+                    Ok(old_value)
+                })
+                .map_err(|e| db.note_readonly_fs(e))
+        })
+    }
+
+    // API: remove a key/value pair, failing with KeyNotFound if the key is
+    // missing
+    pub fn remove_required<'a, K: Into<BytesRef<'a>>>(&mut self, key: K) -> Result<Vec<u8>> {
+        let key = key.into();
+        self.remove(key.as_ref())?
+            .ok_or_else(|| Error::KeyNotFound(key.as_ref().into()))
+    }
+
+    // API: replace the value of an existing key, returning the old value,
+    // or fail with KeyNotFound, storing nothing, if the key is missing
+    pub fn update_required<K: Into<Bytes>, V: Into<Bytes>>(
+        &mut self,
+        key: K,
+        value: V,
+    ) -> Result<Vec<u8>> {
+        let key = key.into();
+        let value = value.into().into_vec();
+        let mut old = None;
+        self.entry(key.as_ref())?
+            .and_modify(|stored| old = Some(std::mem::replace(stored, value)))?;
+
+        old.ok_or_else(|| Error::KeyNotFound(key.into()))
+    }
+
+    fn allocate_record(&mut self, size: u32) -> Result<u64> {
+        let (offset, length) = match self
+            .bucket_cache
+            .current_bucket_mut()
+            .unwrap()
+            .allocate(size)
+        {
+            Some(block) => block,
+            None => {
+                // refill a header avail list at most half full from the
+                // avail block chain, as GDBM does
+                if self.header.avail.elems.len() as u32 <= self.header.avail.sz / 2
+                    && self.header.avail.next_block != 0
+                {
+                    self.pop_avail_block()?;
+                }
+
+                match self.header.allocate(size) {
+                    Some(block) => block,
+                    None => self.extend(size)?,
+                }
+            }
+        };
+
+        self.free_record(offset + size as u64, length - size)?;
+
+        Ok(offset)
+    }
+
+    // Reserve one extent large enough for records of the given sizes.
+    // Records are then carved from it in turn by allocate(), saving an
+    // allocation, and the avail list churn that comes with it, per record.
+    fn allocate_many(&mut self, sizes: impl IntoIterator<Item = u32>) -> Result<()> {
+        self.release_extent()?;
+
+        let size = sizes.into_iter().map(u64::from).sum::<u64>();
+        if let Ok(size @ 1..) = u32::try_from(size) {
+            self.read_write.extent = Some((self.allocate_record(size)?, size));
+        }
+
+        Ok(())
+    }
+
+    // allocate space for a record, from the extent reserved by
+    // allocate_many() while it lasts
+    fn allocate(&mut self, size: u32) -> Result<u64> {
+        match self.read_write.extent {
+            Some((offset, length)) if size <= length => {
+                self.read_write.extent = match length - size {
+                    0 => None,
+                    rest => Some((offset + size as u64, rest)),
+                };
+                Ok(offset)
+            }
+            _ => self.allocate_record(size),
+        }
+    }
+
+    // free whatever records didn't use of the extent from allocate_many()
+    fn release_extent(&mut self) -> Result<()> {
+        match self.read_write.extent.take() {
+            Some((offset, length)) => self.free_record(offset, length),
+            None => Ok(()),
+        }
+    }
+
+    // store a record, returning the file offset of its key and value
+    fn int_insert(&mut self, key: Vec<u8>, data: Vec<u8>, loc: (u32, usize, u32)) -> Result<u64> {
+        self.store_replacing(key, data, loc, None)
+    }
+
+    // Store a record as int_insert() does, in place of the record at element
+    // replaced of the current bucket, if given.  The old record is freed
+    // only once the new one is stored, and put back should storing fail.
+    fn store_replacing(
+        &mut self,
+        key: Vec<u8>,
+        data: Vec<u8>,
+        loc: (u32, usize, u32),
+        replaced: Option<usize>,
+    ) -> Result<u64> {
+        self.check_writable()?;
+        if let Some(cache) = self.record_cache.as_mut() {
+            cache.remove(&key);
+        }
+        self.counters.bytes_inserted += (key.len() + data.len()) as u64;
+        let logged = self.changelog.is_some().then(|| key.clone());
+        let (key, data) = self.encode_record(key, data)?;
+
+        self.check_consistent()?;
 
-        self.read_write.state = WriteState::Dirty;
+        self.read_write.state = WriteState::Inconsistent;
 
-        Ok(Some(data))
-    }
+        let old = replaced.map(|elem_ofs| {
+            self.bucket_cache
+                .current_bucket_mut()
+                .unwrap()
+                .remove(elem_ofs)
+        });
+        let result = match (self.store_record(key, data, loc), old) {
+            (Ok(offset), Some(elem)) => self
+                .free_record(elem.data_ofs, elem.key_size + elem.data_size)
+                .map(|_| offset),
+            (Err(e), Some(elem)) => self.restore_element(elem).and(Err(e)),
+            (result, None) => result,
+        };
+        self.record_count = match result {
+            Ok(_) if old.is_some() => self.record_count,
+            Ok(_) => self.record_count.map(|count| count + 1),
+            Err(_) => None,
+        };
 
-    // API: remove a key/value pair from db, given a key
-    pub fn remove<'a, K: Into<BytesRef<'a>>>(&mut self, key: K) -> Result<Option<Vec<u8>>> {
-        self.int_remove(key.into().as_ref()).and_then(|old_value| {
-            if old_value.is_some() && self.read_write.sync {
-                self.sync()?;
+        self.end_update(result).inspect(|_| {
+            if let Some(key) = logged {
+                self.log_change(&key);
             }
-
-            Ok(old_value)
         })
     }
 
-    fn allocate_record(&mut self, size: u32) -> io::Result<u64> {
-        let (offset, length) = match self
-            .bucket_cache
-            .current_bucket_mut()
-            .unwrap()
-            .allocate(size)
-        {
-            Some(block) => block,
-            None => {
-                if self.header.avail.elems.len() as u32 > self.header.avail.sz / 2 {
-                    self.pop_avail_block()?;
-                }
-
-                match self.header.allocate(size) {
-                    Some(block) => block,
-                    None => self.extend(size)?,
-                }
-            }
+    // The key and data of a record as stored: the key, or its digest with
+    // the key packed into the data, and the data encoded by the codecs.
+    fn encode_record(&self, key: Vec<u8>, data: Vec<u8>) -> Result<(Vec<u8>, Vec<u8>)> {
+        let (key, data) = match self.digest(&key) {
+            Some(digest) => (digest, digest::pack(&key, &data)),
+            None => (key, data),
         };
 
-        self.free_record(offset + size as u64, length - size)?;
-
-        Ok(offset)
+        Ok((key, self.codecs.encode(data)?))
     }
 
-    fn int_insert(&mut self, key: Vec<u8>, data: Vec<u8>) -> Result<()> {
-        if self.read_write.state == WriteState::Inconsistent {
-            return Err(Error::Inconsistent);
+    // put a bucket element removed by store_replacing() back in its bucket,
+    // which has room for it still
+    fn restore_element(&mut self, elem: BucketElement) -> Result<()> {
+        let bucket_dir_in = bucket_dir(self.header.dir_bits, elem.hash);
+        if self.bucket_cache.current_bucket_offset() != Some(self.dir.dir[bucket_dir_in]) {
+            self.cache_load_bucket(bucket_dir_in)?;
         }
 
-        self.read_write.state = WriteState::Inconsistent;
+        self.bucket_cache.current_bucket_mut().unwrap().insert(elem);
 
-        let offset = self.allocate_record((key.len() + data.len()) as u32)?;
+        Ok(())
+    }
 
-        self.f
-            .seek(SeekFrom::Start(offset))
-            .and_then(|_| self.f.write_all(&key))
-            .and_then(|_| self.f.write_all(&data))?;
+    // Record the outcome of an update.  Running out of space, or of hash
+    // bits, leaves the records as they were, so only other errors leave the
+    // database inconsistent.
+    fn end_update<T>(&mut self, result: Result<T>) -> Result<T> {
+        match result {
+            Ok(_)
+            | Err(Error::DatabaseFull { .. })
+            | Err(Error::OffsetOverflow { .. })
+            | Err(Error::HashSaturation { .. }) => {
+                self.read_write.state = WriteState::Dirty;
+            }
+            Err(_) => (),
+        }
+        if result.is_ok() {
+            self.counters.updates += 1;
+        }
+
+        result
+    }
 
-        let bucket_elem = BucketElement::new(&key, &data, offset);
-        self.cache_load_bucket(bucket_dir(self.header.dir_bits, bucket_elem.hash))?;
+    // Store a record at the location given by locate().  Its bucket is
+    // usually current already, from looking the key up.  Returns the offset
+    // the record was written at.
+    fn store_record(
+        &mut self,
+        key: Vec<u8>,
+        data: Vec<u8>,
+        (key_hash, bucket_dir_in, _): (u32, usize, u32),
+    ) -> Result<u64> {
+        let offset = self.allocate((key.len() + data.len()) as u32)?;
+
+        // key and value in one write
+        let mut buffer = std::mem::take(&mut self.scratch);
+        buffer.clear();
+        buffer.extend_from_slice(&key);
+        buffer.extend_from_slice(&data);
+        let result = self.f.write_behind(offset, &buffer);
+        self.scratch = buffer;
+        result?;
+
+        let bucket_elem = BucketElement::new(&key, &data, offset, key_hash);
+        if self.bucket_cache.current_bucket_offset() != Some(self.dir.dir[bucket_dir_in]) {
+            self.cache_load_bucket(bucket_dir_in)?;
+        }
 
+        // Each split uses one more bit of hash, so this ends once the bucket
+        // is split on every bit: its keys then all share the hash of this
+        // one, and no split can make room.
+        // The record is freed again if no room can be made for it.
         while self.bucket_cache.current_bucket().unwrap().count == self.header.bucket_elems {
-            self.split_bucket()?;
-            self.cache_load_bucket(bucket_dir(self.header.dir_bits, bucket_elem.hash))?;
+            let split = match self.bucket_cache.current_bucket().unwrap().bits {
+                HASH_BITS => Err(Error::HashSaturation {
+                    hash: bucket_elem.hash,
+                }),
+                _ => self.split_bucket().and_then(|_| {
+                    self.cache_load_bucket(bucket_dir(self.header.dir_bits, bucket_elem.hash))
+                        .map(|_| ())
+                }),
+            };
+            if let Err(e) = split {
+                self.free_record(offset, (key.len() + data.len()) as u32)?;
+                return Err(e);
+            }
         }
 
         self.bucket_cache
@@ -809,9 +3382,12 @@ impl Gdbm<ReadWrite> {
             .unwrap()
             .insert(bucket_elem);
 
-        self.read_write.state = WriteState::Dirty;
+        Ok(offset)
+    }
 
-        Ok(())
+    // API: insert or replace a record, given key and value as bytes
+    pub fn insert_raw(&mut self, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.insert(key, value)
     }
 
     pub fn insert<K: Into<Bytes>, V: Into<Bytes>>(
@@ -819,19 +3395,65 @@ impl Gdbm<ReadWrite> {
         key: K,
         value: V,
     ) -> Result<Option<Vec<u8>>> {
-        let key = key.into();
-        self.int_remove(key.as_ref())
-            .and_then(|oldvalue| {
-                self.int_insert(key.into_vec(), value.into().into_vec())
-                    .map(|_| oldvalue)
+        self.timed(Operation::Insert, |db| {
+            db.replace(key.into(), value.into())
+                .map(|(oldvalue, _)| oldvalue)
+        })
+    }
+
+    // API: insert() reporting where the record went and whether storing it
+    // split a bucket
+    pub fn insert_detailed<K: Into<Bytes>, V: Into<Bytes>>(
+        &mut self,
+        key: K,
+        value: V,
+    ) -> Result<InsertOutcome> {
+        self.timed(Operation::Insert, |db| {
+            let splits = db.counters.splits;
+            db.replace(key.into(), value.into())
+                .map(|(replaced, new_offset)| InsertOutcome {
+                    replaced,
+                    caused_split: db.counters.splits != splits,
+                    new_offset,
+                })
+        })
+    }
+
+    // insert or replace a record, returning the value replaced and the
+    // offset of the new record
+    fn replace(&mut self, key: Bytes, value: Bytes) -> Result<(Option<Vec<u8>>, u64)> {
+        let normalized = match self.normalized(key.as_ref()) {
+            Cow::Owned(normalized) => Some(normalized),
+            Cow::Borrowed(_) => None,
+        };
+        let (key, given) = match normalized {
+            Some(normalized) => (normalized, Some(key)),
+            None => (key.into_vec(), None),
+        };
+        let loc = self.locate(&key);
+        self.check_writable()
+            .and_then(|_| match (self.int_get(&key, loc)?, given) {
+                (None, Some(given)) if self.probe_unnormalized(given.as_ref(), &key) => {
+                    if let Some(cache) = self.record_cache.as_mut() {
+                        cache.remove(given.as_ref());
+                    }
+                    self.int_get(given.as_ref(), self.locate(given.as_ref()))
+                }
+                (found, _) => Ok(found),
             })
-            .and_then(|oldvalue| {
+            .and_then(|found| {
+                let (replaced, oldvalue) = found.unzip();
+                self.store_replacing(key, value.into_vec(), loc, replaced)
+                    .map(|offset| (oldvalue, offset))
+            })
+            .and_then(|result| {
                 if self.read_write.sync {
                     self.sync()?;
                 }
 
-                Ok(oldvalue)
+                Ok(result)
             })
+            .map_err(|e| self.note_readonly_fs(e))
     }
 
     pub fn try_insert<K: Into<Bytes>, V: Into<Bytes>>(
@@ -840,10 +3462,17 @@ impl Gdbm<ReadWrite> {
         value: V,
     ) -> Result<(bool, Option<Vec<u8>>)> {
         let key = key.into();
-        self.get(key.as_ref()).and_then(|olddata| match olddata {
-            Some(_) => Ok((false, olddata)),
-            _ => self
-                .int_insert(key.into_vec(), value.into().into_vec())
+        self.lookup(key.as_ref())
+            .and_then(|olddata| match olddata {
+                Some(_) => Ok((false, olddata)),
+                _ => {
+                    let key = match self.normalized(key.as_ref()) {
+                        Cow::Owned(normalized) => normalized,
+                        Cow::Borrowed(_) => key.into_vec(),
+                    };
+                    let loc = self.locate(&key);
+                    self.int_insert(key, value.into().into_vec(), loc)
+                }
                 .map(|_| (true, None))
                 .and_then(|result| {
                     if self.read_write.sync {
@@ -852,10 +3481,136 @@ impl Gdbm<ReadWrite> {
 
                     Ok(result)
                 }),
-        })
+            })
+            .map_err(|e| self.note_readonly_fs(e))
+    }
+
+    // API: fetch the value of key, first inserting the value computed by
+    // default if the key is missing
+    pub fn get_or_insert_with<K, D, V, F>(&mut self, key: K, default: F) -> Result<V>
+    where
+        K: Into<Bytes>,
+        D: Into<Bytes>,
+        V: From<Bytes>,
+        F: FnOnce() -> D,
+    {
+        let key = key.into();
+        self.lookup(key.as_ref())
+            .and_then(|olddata| match olddata {
+                Some(data) => Ok(data),
+                None => {
+                    let key = match self.normalized(key.as_ref()) {
+                        Cow::Owned(normalized) => normalized,
+                        Cow::Borrowed(_) => key.into_vec(),
+                    };
+                    let loc = self.locate(&key);
+                    let data = default().into().into_vec();
+                    self.int_insert(key, data.clone(), loc).and_then(|_| {
+                        if self.read_write.sync {
+                            self.sync()?;
+                        }
+
+                        Ok(data)
+                    })
+                }
+            })
+            .map(|data| Bytes::from(data).into())
+            .map_err(|e| self.note_readonly_fs(e))
+    }
+
+    // API: the entry for key, to read its value and then modify it, or
+    // insert one, without looking the key up again
+    pub fn entry<K: Into<Bytes>>(&mut self, key: K) -> Result<Entry<'_>> {
+        Entry::new(self, key.into())
+    }
+
+    // Replace the record at element elem_ofs of the current bucket, found by
+    // int_get() under found_key, with a record for key at loc.  Returns the
+    // element of the new record in the current bucket.
+    fn replace_found(
+        &mut self,
+        found_key: &[u8],
+        elem_ofs: usize,
+        key: Vec<u8>,
+        value: Vec<u8>,
+        loc: (u32, usize, u32),
+    ) -> Result<usize> {
+        self.check_writable()
+            .and_then(|_| {
+                if let Some(cache) = self.record_cache.as_mut() {
+                    cache.remove(found_key);
+                }
+                self.store_replacing(key, value, loc, Some(elem_ofs))
+            })
+            .and_then(|offset| {
+                // store_record() leaves the record's bucket current
+                let elem_ofs = self
+                    .bucket_cache
+                    .current_bucket()
+                    .unwrap()
+                    .tab
+                    .iter()
+                    .position(|elem| elem.is_occupied() && elem.data_ofs == offset)
+                    .unwrap();
+                if self.read_write.sync {
+                    self.sync()?;
+                }
+
+                Ok(elem_ofs)
+            })
+            .map_err(|e| self.note_readonly_fs(e))
+    }
+
+    // insert a record for a key int_get() didn't find at loc
+    fn insert_vacant(
+        &mut self,
+        key: Vec<u8>,
+        value: Vec<u8>,
+        loc: (u32, usize, u32),
+    ) -> Result<()> {
+        self.int_insert(key, value, loc)
+            .and_then(|_| {
+                if self.read_write.sync {
+                    self.sync()?;
+                }
+
+                Ok(())
+            })
+            .map_err(|e| self.note_readonly_fs(e))
+    }
+
+    // API: insert or replace many records, as insert() does each one, but
+    // allocating their space in batches and syncing, if the database syncs
+    // on update, once at the end
+    pub fn insert_many<K: Into<Bytes>, V: Into<Bytes>>(
+        &mut self,
+        records: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<()> {
+        let records = records
+            .into_iter()
+            .map(|(key, value)| Ok((key.into().into_vec(), value.into().into_vec())));
+        self.store_many(records, InsertMode::Replace)
+    }
+
+    // API: store a record, treating an existing key as gdbm_store() does for
+    // the corresponding flag.  Returns whether the record was stored.
+    pub fn store<K: Into<Bytes>, V: Into<Bytes>>(
+        &mut self,
+        key: K,
+        value: V,
+        mode: InsertMode,
+    ) -> Result<bool> {
+        match mode {
+            InsertMode::Replace => self.insert(key, value).map(|_| true),
+            InsertMode::Insert => self.try_insert(key, value).map(|(stored, _)| stored),
+            InsertMode::ErrorIfExists => match self.try_insert(key, value)? {
+                (true, _) => Ok(true),
+                (false, _) => Err(Error::KeyExists),
+            },
+        }
     }
 
-    fn split_bucket(&mut self) -> io::Result<()> {
+    fn split_bucket(&mut self) -> Result<()> {
         if self.bucket_cache.current_bucket().unwrap().bits == self.header.dir_bits {
             self.extend_directory()?;
         }
@@ -889,15 +3644,57 @@ impl Gdbm<ReadWrite> {
             new_bucket_offset,
         );
 
+        self.counters.splits += 1;
+        self.emit(Event::BucketSplit {
+            offset: cur_bucket_offset,
+            new_offset: new_bucket_offset,
+            bits,
+        });
+
         Ok(())
     }
 
+    // API: split the bucket holding key, or that would hold it, extending
+    // the directory if needed
+    #[cfg(feature = "testutil")]
+    pub fn force_split<'a, K: Into<BytesRef<'a>>>(&mut self, key: K) -> Result<()> {
+        let (hash, bucket_dir, _) = self.locate(&self.normalized(key.into().as_ref()));
+        self.force(|db| {
+            if db.cache_load_bucket(bucket_dir)?.bits == HASH_BITS {
+                return Err(Error::HashSaturation { hash });
+            }
+
+            db.split_bucket()
+        })
+    }
+
+    // API: double the directory, without splitting any bucket
+    #[cfg(feature = "testutil")]
+    pub fn force_extend_directory(&mut self) -> Result<()> {
+        self.force(|db| db.extend_directory())
+    }
+
+    // make a structural change that isn't an update
+    #[cfg(feature = "testutil")]
+    fn force(&mut self, change: impl FnOnce(&mut Self) -> Result<()>) -> Result<()> {
+        self.check_writable()?;
+        self.check_consistent()?;
+
+        self.read_write.state = WriteState::Inconsistent;
+        let result = change(self);
+        if result.is_ok() {
+            self.read_write.state = WriteState::Dirty;
+        }
+
+        result
+    }
+
     // Extends the directory by duplicating each bucket offset.
     // Old storage is freed and new storage is allocated.
     // The maximum number of hash_bits represented by each element is increased by 1.
     // The header is updated with new offset, size and bits.
     // Both the directory and header are marked dirty, but not written.
-    fn extend_directory(&mut self) -> io::Result<()> {
+    fn extend_directory(&mut self) -> Result<()> {
         let directory = self.dir.extend();
         let size = directory.extent(&self.header.layout);
         let offset = self.allocate_record(size)?;
@@ -910,14 +3707,20 @@ impl Gdbm<ReadWrite> {
 
         self.dir = directory;
 
+        self.emit(Event::DirectoryExtended {
+            bits: self.header.dir_bits,
+            offset,
+            size,
+        });
+
         Ok(())
     }
 
     // API: convert
     pub fn convert(&mut self, options: &ConvertOptions) -> Result<()> {
-        if self.read_write.state == WriteState::Inconsistent {
-            return Err(Error::Inconsistent);
-        }
+        self.check_writable()?;
+
+        self.check_consistent()?;
 
         self.read_write.state = WriteState::Inconsistent;
 
@@ -925,19 +3728,201 @@ impl Gdbm<ReadWrite> {
             .convert_numsync(options.numsync)
             .into_iter()
             .try_for_each(|(offset, length)| self.free_record(offset, length))
-            .map_err(Error::Io)?;
+            .map_err(|e| self.note_readonly_fs(e))?;
 
         self.read_write.state = WriteState::Dirty;
 
         Ok(())
     }
+
+    // API: rebuild the database without free space, and in the byte order
+    // given to convert_on_write(), in a new file at the database path plus
+    // ".compact", then rename it over the database.  The new file is checked
+    // first: its record count must match, as must the values of some keys
+    // chosen at random.  Otherwise the database is left unchanged and
+    // Error::CompactMismatch returned.  Records of quarantined buckets are
+    // not copied.
+    pub fn compact(&mut self) -> Result<()> {
+        self.compact_with(&CompactOptions::default())
+    }
+
+    // API: compact(), optionally keeping the file times and syncing the
+    // directory
+    pub fn compact_with(&mut self, options: &CompactOptions) -> Result<()> {
+        self.check_writable()?;
+
+        self.check_consistent()?;
+
+        self.path()?;
+        let metadata = self.f.file().metadata()?;
+        let times = std::fs::FileTimes::new()
+            .set_accessed(metadata.accessed()?)
+            .set_modified(metadata.modified()?);
+
+        let path = format!("{}.compact", self.pathname);
+        let result = self
+            .compact_into(&path)
+            .and_then(|_| self.swap_in(&path, &metadata, options.fsync_dir));
+        if result.is_err() {
+            let _ = std::fs::remove_file(&path);
+        }
+
+        result.and_then(|_| match options.preserve_times {
+            true => self
+                .f
+                .file()
+                .set_times(times)
+                .and_then(|_| self.f.sync_all())
+                .map_err(Error::Io),
+            false => Ok(()),
+        })
+    }
+
+    // copy the records into a new database at path and check it
+    fn compact_into(&mut self, path: &str) -> Result<()> {
+        let options = OpenOptions {
+            alignment: Some(self.header.layout.alignment),
+            keys: self.keys,
+            ..OpenOptions::new()
+        }
+        .write()
+        .create()
+        .newdb(true)
+        .offset(Some(self.header.layout.offset))
+        .endian(Some(
+            self.read_write
+                .convert_endian
+                .unwrap_or(self.header.layout.endian),
+        ))
+        .numsync(self.header.magic.is_numsync())
+        .extended(self.header.magic.is_extended())
+        .digest_keys(self.header.digest_keys())
+        .block_size(BlockSize::Roughly(self.header.block_sz));
+
+        let mut db = options.open(path)?;
+        db.codecs = self.codecs.clone();
+        self.iter::<Vec<u8>, Vec<u8>>()
+            .try_for_each(|kv| kv.and_then(|(key, value)| db.insert(key, value).map(|_| ())))?;
+        db.header.adopt_identity(&self.header);
+        db.close()?;
+
+        // read the new database back from disk
+        let mut db = OpenOptions {
+            alignment: Some(self.header.layout.alignment),
+            keys: self.keys,
+            ..OpenOptions::new()
+        }
+        .open(path)?;
+        db.codecs = self.codecs.clone();
+
+        if db.len()? != self.len()? {
+            return Err(Error::CompactMismatch { key: None });
+        }
+
+        let providers = self.providers.clone();
+        let mut rng = || providers.random().next_u64();
+        self.sample::<Vec<u8>>(COMPACT_SPOT_CHECKS, &mut rng)?
+            .into_iter()
+            .try_for_each(|key| {
+                match self.get::<_, Vec<u8>>(key.as_slice())? == db.get(key.as_slice())? {
+                    true => Ok(()),
+                    false => Err(Error::CompactMismatch {
+                        key: Some(key.into()),
+                    }),
+                }
+            })
+    }
+
+    // Put the database file at path in place of the database file, with the
+    // owner and permissions of metadata, then reopen it and reload the
+    // header and directory.  The file is synced and renamed over the
+    // database, so a crash leaves either the old database or the whole new
+    // one.
+    fn swap_in(&mut self, path: &str, metadata: &std::fs::Metadata, sync_dir: bool) -> Result<()> {
+        let compacted = File::open(path)?;
+        sys::copy_owner(&compacted, metadata)?;
+        compacted.set_permissions(metadata.permissions())?;
+        compacted.sync_all()?;
+        drop(compacted);
+
+        let live_path = PathBuf::from(&self.pathname);
+        rename_over(Path::new(path), &live_path, sync_dir)?;
+
+        // the handle's file is no longer the database until reopened
+        self.read_write.state = WriteState::Inconsistent;
+
+        let mut f = File::options().read(true).write(true).open(&live_path)?;
+        let size = f.metadata()?.len();
+        let (header, dir) = read_metadata(&mut f, size, self.header.layout.alignment)?;
+        self.f.reload(Some(f))?;
+        self.bucket_cache = BucketCache::new(self.bucket_cache.cachesize(), None);
+        self.header = header;
+        self.dir = dir;
+        if let Some(quarantine) = self.quarantine.as_mut() {
+            quarantine.clear();
+        }
+        self.read_write.reserved = self.header.next_block;
+        self.read_write.state = WriteState::Clean;
+
+        Ok(())
+    }
+}
+
+impl<R> Gdbm<R> {
+    // the write state of a writable handle
+    fn write_state(&self) -> Option<WriteState> {
+        let db: &dyn Any = self as &dyn Any;
+        db.downcast_ref::<Gdbm<ReadWrite>>()
+            .map(|db| db.read_write.state)
+    }
+}
+
+impl<R> Debug for Gdbm<R> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Gdbm")
+            .field("path", &self.pathname)
+            .field("magic", &self.header.magic)
+            .field("layout", &self.header.layout)
+            .field("block_size", &self.header.block_sz)
+            .field("dir_bits", &self.header.dir_bits)
+            .field("next_block", &self.header.next_block)
+            .field("records", &self.record_count)
+            .field("cached_buckets", &self.bucket_cache.offsets().len())
+            .field("write_state", &self.write_state())
+            .finish()
+    }
+}
+
+impl<R> Display for Gdbm<R> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}, block size {}",
+            self.pathname, self.header.magic, self.header.block_sz
+        )?;
+        match self.record_count {
+            Some(1) => write!(f, ", 1 record")?,
+            Some(count) => write!(f, ", {} records", count)?,
+            None => write!(f, ", records not counted")?,
+        }
+        match self.write_state() {
+            None => write!(f, ", read-only)"),
+            Some(WriteState::Clean) => write!(f, ")"),
+            Some(WriteState::Dirty) => write!(f, ", unsynced changes)"),
+            Some(WriteState::Inconsistent) => write!(f, ", inconsistent)"),
+        }
+    }
 }
 
 impl<R> Drop for Gdbm<R> {
     fn drop(&mut self) {
-        let db: &mut dyn Any = self as &mut dyn Any;
-        if let Some(db) = db.downcast_mut::<Gdbm<ReadWrite>>() {
-            let _ = db.sync();
+        #[cfg(feature = "write")]
+        if let Some(db) = (self as &mut dyn Any).downcast_mut::<Gdbm<ReadWrite>>() {
+            db.drop_sync();
+        }
+
+        if let Some(path) = self.hot_buckets.take() {
+            let _ = save_hot_buckets(&path, &self.bucket_cache);
         }
     }
 }
@@ -946,8 +3931,11 @@ struct GDBMIterator<'a, R: 'static> {
     key_or_value: KeyOrValue,
     db: &'a mut Gdbm<R>,
     slot: Option<Result<Slot>>,
+    // records not yet returned, if known
+    remaining: Option<usize>,
 }
 
+#[derive(Clone, Copy)]
 enum KeyOrValue {
     Key,
     Value,
@@ -985,15 +3973,45 @@ where
         }
     }
 
+    // on entering a bucket, hint that the following bucket will be read soon
+    fn advise_next_bucket(db: &Gdbm<R>, bucket: usize) {
+        let last = Slot {
+            bucket,
+            element: db.header.bucket_elems as usize,
+        };
+        if let Some(Slot { bucket, .. }) = Self::next_slot(db, last) {
+            db.f.advise(
+                Advice::WillNeed,
+                db.dir.dir[bucket],
+                db.header.bucket_sz as u64,
+            );
+        }
+    }
+
     fn next_occupied_slot(db: &mut Gdbm<R>, slot: Slot) -> Option<Result<Slot>> {
         let mut next_slot = Self::next_slot(db, slot);
         while let Some(slot) = next_slot {
+            if slot.element == 0 {
+                Self::advise_next_bucket(db, slot.bucket);
+            }
+
             let is_occupied = db
                 .cache_load_bucket(slot.bucket)
                 .map(|bucket| bucket.tab.get(slot.element).unwrap().is_occupied());
-            match is_occupied {
-                Ok(false) => (),
-                Ok(true) => return Some(Ok(slot)),
+            match db.skip_quarantined(is_occupied) {
+                Ok(Some(false)) => (),
+                Ok(Some(true)) => return Some(Ok(slot)),
+                // skip the rest of a quarantined bucket
+                Ok(None) => {
+                    next_slot = Self::next_slot(
+                        db,
+                        Slot {
+                            bucket: slot.bucket,
+                            element: db.header.bucket_elems as usize,
+                        },
+                    );
+                    continue;
+                }
                 Err(e) => return Some(Err(e)),
             }
             next_slot = Self::next_slot(db, slot);
@@ -1003,30 +4021,48 @@ where
     }
 
     fn new(db: &'a mut Gdbm<R>, key_or_value: KeyOrValue) -> GDBMIterator<'a, R> {
+        db.f.advise(Advice::Sequential, 0, 0);
+        Self::advise_next_bucket(db, 0);
+
+        // known only if already counted, to not read every bucket twice
+        let remaining = db.record_count;
+
         let slot = {
             let slot = Slot {
                 bucket: 0,
                 element: 0,
             };
-            match db.cache_load_bucket(0) {
-                Ok(bucket) => {
-                    if bucket.tab.first().unwrap().is_occupied() {
-                        Some(Ok(slot))
-                    } else {
-                        Self::next_occupied_slot(db, slot)
-                    }
-                }
+            let is_occupied = db
+                .cache_load_bucket(0)
+                .map(|bucket| bucket.tab.first().unwrap().is_occupied());
+            match db.skip_quarantined(is_occupied) {
+                Ok(Some(true)) => Some(Ok(slot)),
+                Ok(Some(false)) => Self::next_occupied_slot(db, slot),
+                Ok(None) => Self::next_occupied_slot(
+                    db,
+                    Slot {
+                        bucket: 0,
+                        element: db.header.bucket_elems as usize,
+                    },
+                ),
                 Err(e) => Some(Err(e)),
             }
         };
         Self {
             db,
             slot,
+            remaining,
             key_or_value,
         }
     }
 }
 
+impl<R> Drop for GDBMIterator<'_, R> {
+    fn drop(&mut self) {
+        self.db.f.advise(Advice::Normal, 0, 0);
+    }
+}
+
 impl<'a, R> Iterator for GDBMIterator<'a, R>
 where
     Gdbm<R>: CacheBucket,
@@ -1040,40 +4076,59 @@ where
             None => None,
             Some(Err(e)) => Some(Err(e)),
             Some(Ok(slot)) => {
-                let data = self
-                    .db
-                    .cache_load_bucket(slot.bucket)
-                    .map(|bucket| {
-                        bucket
-                            .tab
-                            .get(slot.element)
-                            .map(|e| (e.data_ofs, e.key_size as usize, e.data_size as usize))
-                            .unwrap()
-                    })
-                    .and_then(
-                        |(offset, key_length, data_length)| match self.key_or_value {
-                            KeyOrValue::Key => read_ofs(&mut self.db.f, offset, key_length)
-                                .map(|data| (data.to_vec(), vec![]))
-                                .map_err(Error::Io),
-                            KeyOrValue::Value => {
-                                read_ofs(&mut self.db.f, offset + key_length as u64, data_length)
-                                    .map(|data| (vec![], data.to_vec()))
-                                    .map_err(Error::Io)
-                            }
-                            KeyOrValue::Both => {
-                                read_ofs(&mut self.db.f, offset, key_length + data_length)
-                                    .map(|data| {
-                                        let (key, value) = data.split_at(key_length);
-                                        (key.to_vec(), value.to_vec())
-                                    })
-                                    .map_err(Error::Io)
-                            }
-                        },
-                    );
+                // records stored by digest hold their key with the value
+                let key_or_value = match self.db.header.digest_keys() {
+                    Some(_) => KeyOrValue::Both,
+                    None => self.key_or_value,
+                };
+                let data =
+                    self.db
+                        .cache_load_bucket(slot.bucket)
+                        .map(|bucket| {
+                            bucket
+                                .tab
+                                .get(slot.element)
+                                .map(|e| (e.data_ofs, e.key_size as usize, e.data_size as usize))
+                                .unwrap()
+                        })
+                        .and_then(|(offset, key_length, data_length)| match key_or_value {
+                            KeyOrValue::Key => self
+                                .db
+                                .read_at(offset, key_length)
+                                .map(|data| (data.to_vec(), vec![])),
+                            KeyOrValue::Value => self
+                                .db
+                                .read_at(offset + key_length as u64, data_length)
+                                .map(|data| (vec![], data.to_vec())),
+                            KeyOrValue::Both => self
+                                .db
+                                .read_at(offset, key_length + data_length)
+                                .map(|data| {
+                                    let (key, value) = data.split_at(key_length);
+                                    (key.to_vec(), value.to_vec())
+                                }),
+                        })
+                        .and_then(|(key, value)| match key_or_value {
+                            KeyOrValue::Key => Ok((key, value)),
+                            _ => self.db.codecs.decode(value).map(|value| (key, value)),
+                        })
+                        .and_then(|(key, value)| match key_or_value {
+                            KeyOrValue::Both => self.db.unpack_record(key, value),
+                            _ => Ok((key, value)),
+                        })
+                        .map(|(key, value)| match self.key_or_value {
+                            KeyOrValue::Key => (key, vec![]),
+                            KeyOrValue::Value => (vec![], value),
+                            KeyOrValue::Both => (key, value),
+                        });
 
                 match data {
-                    Ok(data) => {
+                    Ok(mut data) => {
+                        if self.db.keys.c_string {
+                            data.0.pop_if(|b| *b == 0);
+                        }
                         self.slot = Self::next_occupied_slot(self.db, slot);
+                        self.remaining = self.remaining.map(|n| n.saturating_sub(1));
                         Some(Ok(data))
                     }
                     Err(e) => Some(Err(e)),
@@ -1081,4 +4136,477 @@ where
             }
         }
     }
+
+    // Exact once the database has been counted, as the iterator borrows it
+    // mutably so no records can be added or removed during iteration.
+    // Iteration ends at the first error, which counts as one item.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match (&self.slot, self.remaining) {
+            (None, _) => (0, Some(0)),
+            (Some(Err(_)), _) => (1, Some(1)),
+            (Some(Ok(_)), Some(remaining)) => (remaining.max(1), Some(remaining.max(1))),
+            (Some(Ok(_)), None) => (1, None),
+        }
+    }
+}
+
+impl<R> FusedIterator for GDBMIterator<'_, R>
+where
+    Gdbm<R>: CacheBucket,
+    R: Default + 'static,
+{
+}
+
+#[cfg(all(test, feature = "write"))]
+mod test {
+    use super::*;
+    use avail::AvailElem;
+
+    #[test]
+    fn verify_corruption() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = OpenOptions::new()
+            .write()
+            .create()
+            .block_size(BlockSize::Exactly(512))
+            .open(dir.path().join("verify.db"))
+            .unwrap();
+        (0..1000).for_each(|n| {
+            db.insert(n.to_string(), n.to_string()).unwrap();
+        });
+        db.verify().unwrap();
+
+        // a bucket whose bits don't match its directory entries
+        db.cache_load_bucket(0).unwrap();
+        db.bucket_cache.current_bucket_mut().unwrap().bits += 1;
+        assert!(matches!(db.verify(), Err(Error::BadBucketBits { .. })));
+        db.bucket_cache.current_bucket_mut().unwrap().bits -= 1;
+
+        // a record hashed to another bucket
+        let flip_hash = |db: &mut Gdbm<ReadWrite>| {
+            let bucket = db.bucket_cache.current_bucket_mut().unwrap();
+            let elem = bucket
+                .tab
+                .iter_mut()
+                .find(|elem| elem.is_occupied())
+                .unwrap();
+            elem.hash ^= 1 << (hashutil::HASH_BITS - 1);
+        };
+        flip_hash(&mut db);
+        assert!(matches!(db.verify(), Err(Error::BadBucketElement { .. })));
+        db.cache_load_bucket(0).unwrap();
+        flip_hash(&mut db);
+        db.verify().unwrap();
+
+        // a bucket pointed to by two runs of entries
+        let last = *db.dir.dir.last().unwrap();
+        db.dir.dir[0] = last;
+        assert!(matches!(db.verify(), Err(Error::BadDirectoryEntry { .. })));
+    }
+
+    #[test]
+    fn allocate_many() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = OpenOptions::new()
+            .write()
+            .create()
+            .block_size(BlockSize::Exactly(512))
+            .open(dir.path().join("allocate.db"))
+            .unwrap();
+        db.insert("key".to_string(), "value".to_string()).unwrap();
+
+        db.allocate_many([100, 200, 300]).unwrap();
+        let first = db.allocate(100).unwrap();
+        assert_eq!(db.allocate(200).unwrap(), first + 100);
+        assert_eq!(db.allocate(300).unwrap(), first + 300);
+        assert_eq!(db.read_write.extent, None);
+
+        // records too large for what is left are allocated elsewhere, and
+        // the rest is freed for reuse
+        db.allocate_many([100, 100]).unwrap();
+        let first = db.allocate(100).unwrap();
+        let elsewhere = db.allocate(150).unwrap();
+        assert!(elsewhere < first || elsewhere >= first + 200);
+        assert_eq!(db.read_write.extent, Some((first + 100, 100)));
+        db.release_extent().unwrap();
+        assert_eq!(db.read_write.extent, None);
+        assert_eq!(db.allocate_record(100).unwrap(), first + 100);
+    }
+
+    #[test]
+    fn store_many_extent() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = OpenOptions::new()
+            .write()
+            .create()
+            .c_string_keys(true)
+            .open(dir.path().join("extent.db"))
+            .unwrap();
+
+        // free space early in the file, where records not fitting the
+        // extent would go
+        db.insert("hole".to_string(), vec![0u8; 10000]).unwrap();
+        db.insert("guard".to_string(), "guard".to_string()).unwrap();
+        db.remove("hole").unwrap();
+
+        let records = (0..200).map(|n| Ok((format!("key {n}").into_bytes(), vec![n as u8; 60])));
+        db.store_many(records, InsertMode::Replace).unwrap();
+
+        // sized with the NUL appended to each key, the records fill their
+        // extent end to end.  Directory entries share buckets, so the
+        // elements are deduplicated.
+        let records = (0..db.dir.dir.len())
+            .map(|bucket_dir| db.occupied_elems(bucket_dir))
+            .collect::<Result<Vec<_>>>()
+            .unwrap()
+            .into_iter()
+            .flatten()
+            .filter(|elem| elem.data_size == 60)
+            .map(|elem| (elem.data_ofs, elem.key_size + elem.data_size))
+            .collect::<BTreeSet<_>>();
+        assert_eq!(records.len(), 200);
+        assert!(records
+            .iter()
+            .zip(records.iter().skip(1))
+            .all(|((offset, size), (next, _))| offset + *size as u64 == *next));
+    }
+
+    #[test]
+    fn degenerate_geometry() {
+        let layout = Layout {
+            offset: Offset::LFS,
+            alignment: Alignment::Align64,
+            endian: Endian::Little,
+        };
+        let (block_size, dir_bits) = build_dir_size(layout.offset, 512);
+        Header::new(block_size, &layout, dir_bits, true, false)
+            .check_geometry()
+            .unwrap();
+
+        // blocks too small for two bucket elements
+        assert!(matches!(
+            Header::new(128, &layout, 4, true, false).check_geometry(),
+            Err(Error::BadGeometryBucketElems {
+                block_size: 128,
+                minimum: 2,
+                ..
+            })
+        ));
+
+        // a directory not filling its block, or with no bits left to split on
+        assert!(matches!(
+            Header::new(512, &layout, 5, true, false).check_geometry(),
+            Err(Error::BadGeometryDirectory {
+                block_size: 512,
+                bits: 5
+            })
+        ));
+        assert!(matches!(
+            Header::new(512, &layout, hashutil::HASH_BITS, true, false).check_geometry(),
+            Err(Error::BadGeometryDirectory { .. })
+        ));
+    }
+
+    #[test]
+    fn hash_saturation() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = OpenOptions::new()
+            .write()
+            .create()
+            .block_size(BlockSize::Exactly(512))
+            .open(dir.path().join("saturation.db"))
+            .unwrap();
+        let elems = db.header.bucket_elems as usize;
+        (0..elems).for_each(|n| {
+            db.insert(n.to_string(), n.to_string()).unwrap();
+        });
+        assert_eq!(db.dir.dir.iter().collect::<HashSet<_>>().len(), 1);
+
+        // the only bucket is full, and split on every bit already
+        db.bucket_cache.current_bucket_mut().unwrap().bits = HASH_BITS;
+        assert!(matches!(
+            db.insert("key".to_string(), "value".to_string()),
+            Err(Error::HashSaturation { hash }) if hash == hash_key(b"key")
+        ));
+
+        db.bucket_cache.current_bucket_mut().unwrap().bits = 0;
+        db.verify().unwrap();
+        assert_eq!(db.len().unwrap(), elems);
+        db.insert("key".to_string(), "value".to_string()).unwrap();
+        assert_eq!(db.len().unwrap(), elems + 1);
+    }
+
+    #[test]
+    fn quarantine() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("quarantine.db");
+        let mut db = OpenOptions::new()
+            .alignment(Some(Alignment::Align64))
+            .write()
+            .create()
+            .extended(true)
+            .block_size(BlockSize::Exactly(512))
+            .open(&path)
+            .unwrap();
+        (0..1000).for_each(|n| {
+            db.insert(n.to_string(), n.to_string()).unwrap();
+        });
+        let offset = db.dir.dir[0];
+        db.close().unwrap();
+
+        // damage the first bucket, so it fails its checksum
+        let mut data = std::fs::read(&path).unwrap();
+        data[offset as usize + 100] ^= 0xff;
+        std::fs::write(&path, data).unwrap();
+
+        let open = |quarantine| {
+            OpenOptions::new()
+                .alignment(Some(Alignment::Align64))
+                .quarantine(quarantine)
+                .open(&path)
+                .unwrap()
+        };
+
+        let mut db = open(false);
+        assert!(matches!(
+            db.verify(),
+            Err(Error::BadBucketChecksum { offset: o }) if o == offset
+        ));
+
+        let mut db = open(true);
+        db.verify().unwrap();
+        let quarantined = db.quarantined();
+        assert_eq!(quarantined.len(), 1);
+        assert_eq!(quarantined[0].offset, offset);
+        assert_eq!(*quarantined[0].hashes.start(), 0);
+
+        // records in other buckets are still served
+        let mut served = 0;
+        (0..1000).for_each(|n| {
+            let key = n.to_string();
+            match db.is_quarantined(&key) {
+                true => {
+                    assert!(matches!(
+                        db.get::<_, String>(&key),
+                        Err(Error::Quarantined { offset: o }) if o == offset
+                    ));
+                    assert!(quarantined[0].hashes.contains(&hash_key(key.as_bytes())));
+                }
+                false => {
+                    assert_eq!(db.get::<_, String>(&key).unwrap(), Some(key));
+                    served += 1;
+                }
+            }
+        });
+        assert!(served > 0 && served < 1000);
+
+        assert_eq!(db.len().unwrap(), served);
+        assert_eq!(
+            db.keys::<String>()
+                .collect::<Result<Vec<_>>>()
+                .unwrap()
+                .len(),
+            served
+        );
+    }
+
+    #[test]
+    fn bucket_avail_validation() {
+        let layout = Layout {
+            alignment: Alignment::Align64,
+            endian: Endian::Little,
+            offset: Offset::LFS,
+        };
+        let mut header = Header::new(512, &layout, 6, false, false);
+        header.next_block = 4096;
+
+        let read = |avail: &[(u64, u32)]| {
+            let avail = avail
+                .iter()
+                .map(|(addr, sz)| AvailElem {
+                    addr: *addr,
+                    sz: *sz,
+                })
+                .collect();
+            let record = BucketElement::new(b"key", b"value", 2000, hash_key(b"key"));
+            let bucket = Bucket::new(0, header.bucket_elems as usize, avail, vec![record]);
+            let mut buffer = Vec::new();
+            serialize_bucket(&header, &bucket, &mut buffer).unwrap();
+            read_bucket(&mut io::Cursor::new(buffer), &header, 0)
+        };
+
+        // valid free space is sorted by size
+        let bucket = read(&[(3000, 100), (1000, 50), (2008, 8)]).unwrap();
+        assert_eq!(
+            bucket.avail.iter().map(|elem| elem.sz).collect::<Vec<_>>(),
+            vec![8, 50, 100]
+        );
+
+        [
+            // in the header block
+            &[(100, 10)][..],
+            // past the end of the data area
+            &[(4090, 10)][..],
+            // overlapping free space
+            &[(1000, 100), (1050, 100)][..],
+            // overlapping the record at 2000..2008
+            &[(1990, 11)][..],
+        ]
+        .into_iter()
+        .for_each(|avail| {
+            assert!(matches!(
+                read(avail),
+                Err(Error::BadBucketAvail { offset: 0, .. })
+            ));
+        });
+    }
+
+    #[test]
+    fn readonly_filesystem() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = OpenOptions::new()
+            .write()
+            .create()
+            .open(dir.path().join("readonly_fs.db"))
+            .unwrap();
+
+        db.insert("key".to_string(), "value".to_string()).unwrap();
+        assert!(db.is_writable());
+
+        // an unrelated I/O error leaves the handle writable
+        let e = db.note_readonly_fs(Error::Io(io::Error::other("other")));
+        assert!(matches!(e, Error::Io(_)));
+        assert!(db.is_writable());
+
+        let e = db.note_readonly_fs(Error::Io(io::ErrorKind::ReadOnlyFilesystem.into()));
+        assert!(matches!(e, Error::WriteToReadonly));
+        assert!(!db.is_writable());
+
+        assert!(matches!(
+            db.insert("a".to_string(), "b".to_string()),
+            Err(Error::WriteToReadonly)
+        ));
+        assert!(matches!(db.remove("key"), Err(Error::WriteToReadonly)));
+        assert!(matches!(db.sync(), Err(Error::WriteToReadonly)));
+        assert_eq!(
+            db.get::<_, String>("key").unwrap(),
+            Some("value".to_string())
+        );
+    }
+
+    #[test]
+    fn drop_error() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static DROP_ERRORS: AtomicUsize = AtomicUsize::new(0);
+
+        let dir = tempfile::tempdir().unwrap();
+        let open = |name: &str| {
+            OpenOptions::new()
+                .write()
+                .create()
+                .on_drop_error(Some(|e| {
+                    assert!(matches!(e, Error::WriteToReadonly));
+                    DROP_ERRORS.fetch_add(1, Ordering::SeqCst);
+                }))
+                .open(dir.path().join(name))
+                .unwrap()
+        };
+
+        // clean drop: no report
+        let mut db = open("clean.db");
+        db.insert("key".to_string(), "value".to_string()).unwrap();
+        drop(db);
+        assert_eq!(DROP_ERRORS.load(Ordering::SeqCst), 0);
+
+        // failed sync on drop is reported
+        let mut db = open("failed.db");
+        db.insert("key".to_string(), "value".to_string()).unwrap();
+        db.note_readonly_fs(Error::Io(io::ErrorKind::ReadOnlyFilesystem.into()));
+        drop(db);
+        assert_eq!(DROP_ERRORS.load(Ordering::SeqCst), 1);
+
+        // close returns the error instead
+        let mut db = open("closed.db");
+        db.insert("key".to_string(), "value".to_string()).unwrap();
+        db.note_readonly_fs(Error::Io(io::ErrorKind::ReadOnlyFilesystem.into()));
+        assert!(matches!(db.close(), Err(Error::WriteToReadonly)));
+        assert_eq!(DROP_ERRORS.load(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "dropped with unsynced changes")]
+    fn strict_drop() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = OpenOptions::new()
+            .write()
+            .create()
+            .strict_drop(true)
+            .open(dir.path().join("strict.db"))
+            .unwrap();
+
+        db.insert("key".to_string(), "value".to_string()).unwrap();
+    }
+
+    #[test]
+    fn small_offset_overflow() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = OpenOptions::new()
+            .write()
+            .create()
+            .offset(Some(Offset::Small))
+            .block_size(BlockSize::Exactly(512))
+            .open(dir.path().join("small_offset.db"))
+            .unwrap();
+
+        // pretend the file has grown to just below 4GiB
+        let next_block = db.header.next_block;
+        db.header.next_block = u32::MAX as u64 - 1024;
+
+        match db.insert("key".to_string(), vec![0u8; 4096]) {
+            Err(Error::OffsetOverflow {
+                offset,
+                length,
+                maximum,
+            }) => {
+                assert_eq!(offset, u32::MAX as u64 - 1024);
+                assert_eq!(length, 4608);
+                assert_eq!(maximum, u32::MAX as u64);
+            }
+            result => panic!("expected OffsetOverflow, got {:?}", result),
+        }
+
+        // the failed insert left the database usable
+        db.header.next_block = next_block;
+        db.insert("key".to_string(), vec![0u8; 4096]).unwrap();
+        db.sync().unwrap();
+    }
+
+    #[test]
+    fn iterator_error_fuses() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = OpenOptions::new()
+            .write()
+            .create()
+            .open(dir.path().join("iterator_error.db"))
+            .unwrap();
+
+        (0..5).for_each(|n| {
+            db.insert(n.to_string(), "value".to_string()).unwrap();
+        });
+
+        // point the last record of the only bucket past the end of the file
+        let bucket = db.bucket_cache.current_bucket_mut().unwrap();
+        let last = bucket.tab.iter_mut().rfind(|elem| elem.is_occupied());
+        last.unwrap().data_ofs = u32::MAX as u64;
+
+        let mut iter = db.iter::<Vec<u8>, Vec<u8>>();
+        (0..4).for_each(|_| assert!(matches!(iter.next(), Some(Ok(_)))));
+        assert_eq!(iter.size_hint(), (1, Some(1)));
+        assert!(matches!(iter.next(), Some(Err(Error::Io(_)))));
+        assert_eq!(iter.size_hint(), (0, Some(0)));
+        assert!(iter.next().is_none());
+        assert!(iter.next().is_none());
+    }
 }
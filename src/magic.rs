@@ -1,5 +1,5 @@
 use std::fmt;
-use std::io::{self, ErrorKind};
+use std::io;
 
 use crate::ser::{Alignment, Endian, Offset};
 
@@ -13,6 +13,11 @@ const GDBM_MAGIC_BE_32: [u8; 4] = [0x13, 0x57, 0x9a, 0xcd];
 const GDBM_MAGIC_BE_64: [u8; 4] = [0x13, 0x57, 0x9a, 0xcf];
 const GDBM_NUMSYNC_MAGIC_BE_32: [u8; 4] = [0x13, 0x57, 0x9a, 0xd0];
 const GDBM_NUMSYNC_MAGIC_BE_64: [u8; 4] = [0x13, 0x57, 0x9a, 0xd1];
+// Extended flavor, not readable by C GDBM: numsync header and checksummed buckets.
+const GDBM_EXT_MAGIC_LE_32: [u8; 4] = [0xd8, 0x9a, 0x57, 0x13];
+const GDBM_EXT_MAGIC_LE_64: [u8; 4] = [0xd9, 0x9a, 0x57, 0x13];
+const GDBM_EXT_MAGIC_BE_32: [u8; 4] = [0x13, 0x57, 0x9a, 0xd8];
+const GDBM_EXT_MAGIC_BE_64: [u8; 4] = [0x13, 0x57, 0x9a, 0xd9];
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Magic {
@@ -26,6 +31,10 @@ pub enum Magic {
     BE32NS,
     LE64NS,
     BE64NS,
+    LE32X,
+    BE32X,
+    LE64X,
+    BE64X,
 }
 
 impl Magic {
@@ -42,6 +51,15 @@ impl Magic {
         }
     }
 
+    pub fn extended(endian: Endian, offset: Offset) -> Self {
+        match (endian, offset) {
+            (Endian::Little, Offset::Small) => Magic::LE32X,
+            (Endian::Little, Offset::LFS) => Magic::LE64X,
+            (Endian::Big, Offset::Small) => Magic::BE32X,
+            (Endian::Big, Offset::LFS) => Magic::BE64X,
+        }
+    }
+
     pub(super) fn from_reader(rdr: &mut impl std::io::Read) -> io::Result<Self> {
         let mut buf = [0u8; 4];
         rdr.read_exact(&mut buf)?;
@@ -56,23 +74,35 @@ impl Magic {
             GDBM_NUMSYNC_MAGIC_BE_32 => Ok(Magic::BE32NS),
             GDBM_NUMSYNC_MAGIC_LE_64 => Ok(Magic::LE64NS),
             GDBM_NUMSYNC_MAGIC_BE_64 => Ok(Magic::BE64NS),
-            _ => Err(io::Error::new(
-                ErrorKind::Other,
-                "Unknown/invalid magic number",
-            )),
+            GDBM_EXT_MAGIC_LE_32 => Ok(Magic::LE32X),
+            GDBM_EXT_MAGIC_BE_32 => Ok(Magic::BE32X),
+            GDBM_EXT_MAGIC_LE_64 => Ok(Magic::LE64X),
+            GDBM_EXT_MAGIC_BE_64 => Ok(Magic::BE64X),
+            _ => Err(io::Error::other("Unknown/invalid magic number")),
         }
     }
 
     pub fn endian(&self) -> Endian {
         match self {
-            Magic::LE | Magic::LE32 | Magic::LE64 | Magic::LE32NS | Magic::LE64NS => Endian::Little,
+            Magic::LE
+            | Magic::LE32
+            | Magic::LE64
+            | Magic::LE32NS
+            | Magic::LE64NS
+            | Magic::LE32X
+            | Magic::LE64X => Endian::Little,
             _ => Endian::Big,
         }
     }
 
     pub fn offset(&self) -> Offset {
         match self {
-            Magic::LE64 | Magic::BE64 | Magic::LE64NS | Magic::BE64NS => Offset::LFS,
+            Magic::LE64
+            | Magic::BE64
+            | Magic::LE64NS
+            | Magic::BE64NS
+            | Magic::LE64X
+            | Magic::BE64X => Offset::LFS,
             _ => Offset::Small,
         }
     }
@@ -81,12 +111,25 @@ impl Magic {
         matches!(
             self,
             Magic::BE64NS | Magic::LE64NS | Magic::BE32NS | Magic::LE32NS
+        ) || self.is_extended()
+    }
+
+    // extended flavor: numsync header, and a checksum after each bucket
+    pub fn is_extended(&self) -> bool {
+        matches!(
+            self,
+            Magic::BE64X | Magic::LE64X | Magic::BE32X | Magic::LE32X
         )
     }
 
     pub fn default_alignment(&self) -> Alignment {
         match self {
-            Magic::BE64 | Magic::LE64 | Magic::BE64NS | Magic::LE64NS => Alignment::Align64,
+            Magic::BE64
+            | Magic::LE64
+            | Magic::BE64NS
+            | Magic::LE64NS
+            | Magic::BE64X
+            | Magic::LE64X => Alignment::Align64,
             _ => Alignment::Align32,
         }
     }
@@ -103,6 +146,10 @@ impl Magic {
             Magic::BE64 => &GDBM_MAGIC_BE_64,
             Magic::BE32NS => &GDBM_NUMSYNC_MAGIC_BE_32,
             Magic::BE64NS => &GDBM_NUMSYNC_MAGIC_BE_64,
+            Magic::LE32X => &GDBM_EXT_MAGIC_LE_32,
+            Magic::LE64X => &GDBM_EXT_MAGIC_LE_64,
+            Magic::BE32X => &GDBM_EXT_MAGIC_BE_32,
+            Magic::BE64X => &GDBM_EXT_MAGIC_BE_64,
         }
     }
 }
@@ -120,6 +167,10 @@ impl fmt::Display for Magic {
             Magic::BE64 => "GDBM_MAGIC64_SWAP",
             Magic::BE32NS => "GDBM_NUMSYNC_MAGIC32_SWAP",
             Magic::BE64NS => "GDBM_NUMSYNC_MAGIC64_SWAP",
+            Magic::LE32X => "GDBM_EXT_MAGIC32",
+            Magic::LE64X => "GDBM_EXT_MAGIC64",
+            Magic::BE32X => "GDBM_EXT_MAGIC32_SWAP",
+            Magic::BE64X => "GDBM_EXT_MAGIC64_SWAP",
         };
         write!(f, "{}", name)
     }
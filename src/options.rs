@@ -8,7 +8,25 @@
 // file in the root directory of this project.
 // SPDX-License-Identifier: MIT
 
-use crate::{Alignment, Endian, Error, Gdbm, Offset, ReadOnly, ReadWrite, Result};
+use std::fmt::{self, Debug, Formatter};
+use std::fs::{File, TryLockError};
+#[cfg(unix)]
+use std::os::fd::{FromRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::codec::{Codec, Codecs};
+use crate::providers::{Clock, Providers, Random};
+#[cfg(feature = "testutil")]
+use crate::simulation::Simulation;
+#[cfg(feature = "write")]
+use crate::ReadWrite;
+use crate::{Alignment, Endian, Error, Gdbm, Offset, ReadOnly, Result};
+
+// first wait for a locked file, doubling on each retry up to the maximum
+const LOCK_RETRY_DELAY: Duration = Duration::from_millis(1);
+const LOCK_RETRY_MAX_DELAY: Duration = Duration::from_millis(100);
 
 #[derive(Copy, Clone, Debug, PartialOrd, PartialEq, Default)]
 pub enum BlockSize {
@@ -25,24 +43,166 @@ pub struct Create {
     pub no_numsync: bool,
     pub newdb: bool,
     pub block_size: BlockSize,
+    /// Create an extended database: numsync header and checksummed buckets.
+    /// Extended databases can't be opened by C GDBM.
+    pub extended: bool,
+    /// Give the database a random identifier, kept in the numsync header.
+    /// Ignored without numsync.
+    pub identity: bool,
+    /// Store keys longer than this many bytes by a digest, keeping the key
+    /// itself with the value, so large keys don't fill buckets or slow
+    /// lookups.  Ignored unless extended.
+    pub digest_keys: Option<u32>,
 }
 #[derive(Default, Copy, Clone, Debug)]
+#[cfg_attr(not(feature = "write"), allow(dead_code))]
 pub struct NotCreate;
 
+/// Settings of the record cache, for
+/// [`OpenOptions::record_cache`](OpenOptions::record_cache).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RecordCacheOptions {
+    /// Maximum total size of cached keys and values, in bytes.
+    pub max_bytes: usize,
+    /// Time each record stays cached, so changes by other handles are
+    /// seen eventually.  Unlimited if None.
+    pub ttl: Option<Duration>,
+}
+
 #[derive(Copy, Clone, Debug, Default)]
 pub struct NotWrite;
 #[derive(Copy, Clone, Debug, Default)]
+#[cfg_attr(not(feature = "write"), allow(dead_code))]
 pub struct Write<C> {
     pub sync: bool,
+    /// Release disk space behind large freed extents (Linux only).
+    pub punch_holes: bool,
+    /// Reserve file space in chunks of this many bytes as the database grows
+    /// (Linux only).
+    pub preallocate: Option<u64>,
+    /// Called with the error when the automatic sync on drop fails.
+    pub on_drop_error: Option<fn(&Error)>,
+    /// In debug builds, panic if the database is dropped with unsynced changes.
+    pub strict_drop: bool,
+    /// Refuse to grow the database file beyond this many bytes.
+    pub max_file_size: Option<u64>,
+    /// How freed space is tracked.
+    pub avail_policy: AvailPolicy,
+    /// Byte order for compact() to rewrite the database in.
+    pub convert_endian: Option<Endian>,
+    /// Write a changed directory to fresh space on sync, rather than over
+    /// the copy the header on disk points at.
+    pub shadow_metadata: bool,
+    /// Open a database sealed by [`Gdbm::seal`](crate::Gdbm::seal),
+    /// unsealing it.
+    pub force_unseal: bool,
+    /// Remember the keys stored or removed through the handle, for
+    /// [`Gdbm::export_changes_since`](crate::Gdbm::export_changes_since).
+    /// Holds every key changed since open in memory.
+    pub changelog: bool,
     pub create: C,
 }
 
+#[cfg(feature = "write")]
+impl<C> Write<C> {
+    // the same write options, with create options of another type
+    fn with_create<D>(self, create: D) -> Write<D> {
+        Write {
+            sync: self.sync,
+            punch_holes: self.punch_holes,
+            preallocate: self.preallocate,
+            on_drop_error: self.on_drop_error,
+            strict_drop: self.strict_drop,
+            max_file_size: self.max_file_size,
+            avail_policy: self.avail_policy,
+            convert_endian: self.convert_endian,
+            shadow_metadata: self.shadow_metadata,
+            force_unseal: self.force_unseal,
+            changelog: self.changelog,
+            create,
+        }
+    }
+}
+
+/// How freed space is tracked for reuse.  Every policy writes files C GDBM
+/// can read.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AvailPolicy {
+    /// Forget freed extents of this many bytes or fewer.
+    pub ignore_small: u32,
+    /// Number of the six avail slots of each bucket to use for extents
+    /// smaller than a block.  0 keeps all free space in the header avail
+    /// list, where any bucket can reuse it.
+    pub bucket_slots: u32,
+}
+
+impl Default for AvailPolicy {
+    fn default() -> Self {
+        AvailPolicy {
+            ignore_small: crate::IGNORE_SMALL as u32,
+            bucket_slots: crate::Bucket::AVAIL,
+        }
+    }
+}
+
+/// Rewrites a key into the form it is stored under.
+pub type KeyNormalizer = fn(&[u8]) -> Vec<u8>;
+
 #[derive(Copy, Clone, Debug, Default)]
+pub struct KeyOptions {
+    /// Rewrites each key given to lookups and updates, for example to
+    /// lowercase it or trim trailing NULs.  Keys are stored normalized.
+    pub normalize: Option<KeyNormalizer>,
+    /// When a normalized key isn't found, look the key up as given too, so
+    /// records stored before normalization remain reachable.
+    pub probe_unnormalized: bool,
+    /// Store keys NUL-terminated, as C programs using strlen(key) + 1 as the
+    /// key size do.  The NUL is appended on lookups and updates, and
+    /// stripped from keys returned by iteration.
+    pub c_string: bool,
+}
+
+#[derive(Clone, Debug, Default)]
 pub struct OpenOptions<W> {
     /// Override default alignement when opening a database.
     pub alignment: Option<Alignment>,
     /// Bytesize of in-memory bucket cache (defaults to DEFAULT_CACHESIZE)
     pub cachesize: Option<usize>,
+    /// Bypass the OS page cache with direct I/O (Linux only, with the
+    /// linux-fs feature).
+    pub direct: bool,
+    /// Hint the OS page cache about scans (Linux only), and drop the pages
+    /// read by full-database scans such as export.
+    pub fadvise: bool,
+    /// Key normalization applied to lookups and updates.
+    pub keys: KeyOptions,
+    /// Check on open that the directory agrees with the bits of every
+    /// bucket.  Reads every bucket.
+    pub verify_directory: bool,
+    /// Set aside damaged buckets found by reads or verify() instead of
+    /// failing every operation: records in other buckets stay available.
+    pub quarantine: bool,
+    /// Prefetch the buckets listed in this file, saved by
+    /// [`Gdbm::save_hot_buckets`](crate::Gdbm::save_hot_buckets), on open,
+    /// and save the buckets in use to it on close.
+    pub warm_from: Option<PathBuf>,
+    /// Transformations applied to values on write, and undone on read.
+    pub codecs: Codecs,
+    /// Cache values of recently read records, above the bucket cache.
+    pub record_cache: Option<RecordCacheOptions>,
+    /// Lock the file on open, shared by readers and exclusive to a writer,
+    /// waiting up to this long for other handles to release it.  Locks are
+    /// flock() locks, as taken by C GDBM.  Without a timeout the file isn't
+    /// locked.
+    pub lock_timeout: Option<Duration>,
+    /// Refuse a database whose user version, set by
+    /// [`Gdbm::set_user_version`](crate::Gdbm::set_user_version), differs,
+    /// with [`Error::VersionMismatch`](crate::Error::VersionMismatch).  A
+    /// database created by the open is given this version.
+    pub user_version: Option<u16>,
+    /// The clock and source of random numbers used by the database, so
+    /// that tests and simulations can make its behavior reproducible.
+    pub providers: Providers,
 
     pub write: W,
 }
@@ -61,71 +221,288 @@ impl<W> OpenOptions<W> {
     pub fn cachesize(self, cachesize: Option<usize>) -> OpenOptions<W> {
         OpenOptions { cachesize, ..self }
     }
-}
 
-impl OpenOptions<NotWrite> {
-    pub fn write(self) -> OpenOptions<Write<NotCreate>> {
+    pub fn direct(self, direct: bool) -> OpenOptions<W> {
+        OpenOptions { direct, ..self }
+    }
+
+    pub fn fadvise(self, fadvise: bool) -> OpenOptions<W> {
+        OpenOptions { fadvise, ..self }
+    }
+
+    pub fn verify_directory(self, verify_directory: bool) -> OpenOptions<W> {
+        OpenOptions {
+            verify_directory,
+            ..self
+        }
+    }
+
+    pub fn quarantine(self, quarantine: bool) -> OpenOptions<W> {
+        OpenOptions { quarantine, ..self }
+    }
+
+    pub fn warm_from<P: AsRef<Path>>(self, path: P) -> OpenOptions<W> {
+        OpenOptions {
+            warm_from: Some(path.as_ref().to_path_buf()),
+            ..self
+        }
+    }
+
+    /// Add a codec to the pipeline applied to values.
+    pub fn codec(mut self, codec: Box<dyn Codec>) -> OpenOptions<W> {
+        self.codecs.push(codec);
+        self
+    }
+
+    /// Read the time, and wait, with clock instead of the system clock.
+    pub fn clock(self, clock: Arc<dyn Clock>) -> OpenOptions<W> {
+        OpenOptions {
+            providers: self.providers.with_clock(clock),
+            ..self
+        }
+    }
+
+    /// Write the database file through a simulated disk, for crash and
+    /// fault testing.
+    #[cfg(feature = "testutil")]
+    pub fn simulation(self, simulation: Option<Simulation>) -> OpenOptions<W> {
+        OpenOptions {
+            providers: self.providers.with_simulation(simulation),
+            ..self
+        }
+    }
+
+    /// Take random numbers from random instead of the system.
+    pub fn random(self, random: Arc<dyn Random>) -> OpenOptions<W> {
+        OpenOptions {
+            providers: self.providers.with_random(random),
+            ..self
+        }
+    }
+
+    pub fn record_cache(self, record_cache: Option<RecordCacheOptions>) -> OpenOptions<W> {
+        OpenOptions {
+            record_cache,
+            ..self
+        }
+    }
+
+    pub fn lock_timeout(self, lock_timeout: Option<Duration>) -> OpenOptions<W> {
+        OpenOptions {
+            lock_timeout,
+            ..self
+        }
+    }
+
+    pub fn expect_user_version(self, user_version: Option<u16>) -> OpenOptions<W> {
+        OpenOptions {
+            user_version,
+            ..self
+        }
+    }
+
+    pub fn normalize_keys(self, normalize: Option<KeyNormalizer>) -> OpenOptions<W> {
+        OpenOptions {
+            keys: KeyOptions {
+                normalize,
+                ..self.keys
+            },
+            ..self
+        }
+    }
+
+    pub fn c_string_keys(self, c_string: bool) -> OpenOptions<W> {
+        OpenOptions {
+            keys: KeyOptions {
+                c_string,
+                ..self.keys
+            },
+            ..self
+        }
+    }
+
+    pub fn probe_unnormalized_keys(self, probe_unnormalized: bool) -> OpenOptions<W> {
+        OpenOptions {
+            keys: KeyOptions {
+                probe_unnormalized,
+                ..self.keys
+            },
+            ..self
+        }
+    }
+
+    // the same options, with write options of another type
+    #[cfg(feature = "write")]
+    fn retype<V>(self, write: V) -> OpenOptions<V> {
         OpenOptions {
             alignment: self.alignment,
             cachesize: self.cachesize,
-            write: Write {
-                sync: false,
-                create: NotCreate,
-            },
+            direct: self.direct,
+            fadvise: self.fadvise,
+            keys: self.keys,
+            verify_directory: self.verify_directory,
+            quarantine: self.quarantine,
+            warm_from: self.warm_from,
+            codecs: self.codecs,
+            record_cache: self.record_cache,
+            lock_timeout: self.lock_timeout,
+            user_version: self.user_version,
+            providers: self.providers,
+            write,
         }
     }
 }
 
+#[cfg(feature = "write")]
+impl OpenOptions<NotWrite> {
+    pub fn write(self) -> OpenOptions<Write<NotCreate>> {
+        self.retype(Write::default())
+    }
+}
+
+#[cfg(feature = "write")]
 impl<C> OpenOptions<Write<C>> {
     pub fn not_write(self) -> OpenOptions<NotWrite> {
+        self.retype(NotWrite)
+    }
+
+    pub fn sync(self, sync: bool) -> OpenOptions<Write<C>> {
         OpenOptions {
-            alignment: self.alignment,
-            cachesize: self.cachesize,
-            write: NotWrite,
+            write: Write { sync, ..self.write },
+            ..self
         }
     }
 
-    pub fn sync(self, sync: bool) -> OpenOptions<Write<C>> {
+    pub fn punch_holes(self, punch_holes: bool) -> OpenOptions<Write<C>> {
         OpenOptions {
-            alignment: self.alignment,
-            cachesize: self.cachesize,
             write: Write {
-                sync,
-                create: self.write.create,
+                punch_holes,
+                ..self.write
             },
+            ..self
         }
     }
-}
 
-impl OpenOptions<Write<NotCreate>> {
-    pub fn create(self) -> OpenOptions<Write<Create>> {
+    pub fn preallocate(self, preallocate: Option<u64>) -> OpenOptions<Write<C>> {
         OpenOptions {
-            alignment: self.alignment,
-            cachesize: self.cachesize,
             write: Write {
-                create: Create::default(),
-                sync: self.write.sync,
+                preallocate,
+                ..self.write
             },
+            ..self
         }
     }
-}
 
-impl OpenOptions<Write<Create>> {
-    pub fn not_create(self) -> OpenOptions<Write<NotCreate>> {
+    pub fn on_drop_error(self, on_drop_error: Option<fn(&Error)>) -> OpenOptions<Write<C>> {
         OpenOptions {
-            alignment: self.alignment,
-            cachesize: self.cachesize,
             write: Write {
-                create: NotCreate,
-                sync: self.write.sync,
+                on_drop_error,
+                ..self.write
+            },
+            ..self
+        }
+    }
+
+    pub fn strict_drop(self, strict_drop: bool) -> OpenOptions<Write<C>> {
+        OpenOptions {
+            write: Write {
+                strict_drop,
+                ..self.write
             },
+            ..self
         }
     }
 
+    pub fn changelog(self, changelog: bool) -> OpenOptions<Write<C>> {
+        OpenOptions {
+            write: Write {
+                changelog,
+                ..self.write
+            },
+            ..self
+        }
+    }
+
+    pub fn max_file_size(self, max_file_size: Option<u64>) -> OpenOptions<Write<C>> {
+        OpenOptions {
+            write: Write {
+                max_file_size,
+                ..self.write
+            },
+            ..self
+        }
+    }
+
+    pub fn avail_policy(self, avail_policy: AvailPolicy) -> OpenOptions<Write<C>> {
+        OpenOptions {
+            write: Write {
+                avail_policy,
+                ..self.write
+            },
+            ..self
+        }
+    }
+
+    /// Migrate the database to byte order endian, such as the host's, to
+    /// avoid byte swapping on every access.  The byte order of a file can't
+    /// change piecemeal, so the database is rewritten by
+    /// [`Gdbm::compact`](crate::Gdbm::compact).
+    pub fn convert_on_write(self, endian: Endian) -> OpenOptions<Write<C>> {
+        OpenOptions {
+            write: Write {
+                convert_endian: Some(endian),
+                ..self.write
+            },
+            ..self
+        }
+    }
+
+    /// On sync, write a changed directory to freshly allocated space, make
+    /// it durable, and only then write the header pointing at it.  The old
+    /// directory is left intact until the header is replaced, so a crash
+    /// leaves the header pointing at one complete directory or the other.
+    /// Buckets are still updated in place.
+    pub fn shadow_metadata(self, shadow_metadata: bool) -> OpenOptions<Write<C>> {
+        OpenOptions {
+            write: Write {
+                shadow_metadata,
+                ..self.write
+            },
+            ..self
+        }
+    }
+
+    /// Open a database sealed by [`Gdbm::seal`](crate::Gdbm::seal),
+    /// unsealing it, rather than failing with
+    /// [`Error::Sealed`](crate::Error::Sealed).
+    pub fn force_unseal(self, force_unseal: bool) -> OpenOptions<Write<C>> {
+        OpenOptions {
+            write: Write {
+                force_unseal,
+                ..self.write
+            },
+            ..self
+        }
+    }
+}
+
+#[cfg(feature = "write")]
+impl OpenOptions<Write<NotCreate>> {
+    pub fn create(self) -> OpenOptions<Write<Create>> {
+        let write = self.write.with_create(Create::default());
+        self.retype(write)
+    }
+}
+
+#[cfg(feature = "write")]
+impl OpenOptions<Write<Create>> {
+    pub fn not_create(self) -> OpenOptions<Write<NotCreate>> {
+        let write = self.write.with_create(NotCreate);
+        self.retype(write)
+    }
+
     pub fn offset(self, offset: Option<Offset>) -> OpenOptions<Write<Create>> {
         OpenOptions {
-            alignment: self.alignment,
-            cachesize: self.cachesize,
             write: Write {
                 create: Create {
                     offset,
@@ -133,13 +510,12 @@ impl OpenOptions<Write<Create>> {
                 },
                 ..self.write
             },
+            ..self
         }
     }
 
     pub fn endian(self, endian: Option<Endian>) -> OpenOptions<Write<Create>> {
         OpenOptions {
-            alignment: self.alignment,
-            cachesize: self.cachesize,
             write: Write {
                 create: Create {
                     endian,
@@ -147,13 +523,12 @@ impl OpenOptions<Write<Create>> {
                 },
                 ..self.write
             },
+            ..self
         }
     }
 
     pub fn numsync(self, numsync: bool) -> OpenOptions<Write<Create>> {
         OpenOptions {
-            alignment: self.alignment,
-            cachesize: self.cachesize,
             write: Write {
                 create: Create {
                     no_numsync: !numsync,
@@ -161,13 +536,51 @@ impl OpenOptions<Write<Create>> {
                 },
                 ..self.write
             },
+            ..self
+        }
+    }
+
+    pub fn extended(self, extended: bool) -> OpenOptions<Write<Create>> {
+        OpenOptions {
+            write: Write {
+                create: Create {
+                    extended,
+                    ..self.write.create
+                },
+                ..self.write
+            },
+            ..self
+        }
+    }
+
+    pub fn identity(self, identity: bool) -> OpenOptions<Write<Create>> {
+        OpenOptions {
+            write: Write {
+                create: Create {
+                    identity,
+                    ..self.write.create
+                },
+                ..self.write
+            },
+            ..self
+        }
+    }
+
+    pub fn digest_keys(self, digest_keys: Option<u32>) -> OpenOptions<Write<Create>> {
+        OpenOptions {
+            write: Write {
+                create: Create {
+                    digest_keys,
+                    ..self.write.create
+                },
+                ..self.write
+            },
+            ..self
         }
     }
 
     pub fn newdb(self, newdb: bool) -> OpenOptions<Write<Create>> {
         OpenOptions {
-            alignment: self.alignment,
-            cachesize: self.cachesize,
             write: Write {
                 create: Create {
                     newdb,
@@ -175,13 +588,12 @@ impl OpenOptions<Write<Create>> {
                 },
                 ..self.write
             },
+            ..self
         }
     }
 
     pub fn block_size(self, block_size: BlockSize) -> OpenOptions<Write<Create>> {
         OpenOptions {
-            alignment: self.alignment,
-            cachesize: self.cachesize,
             write: Write {
                 create: Create {
                     block_size,
@@ -189,6 +601,36 @@ impl OpenOptions<Write<Create>> {
                 },
                 ..self.write
             },
+            ..self
+        }
+    }
+}
+
+// Lock f, if given a timeout, retrying with growing delays until the lock is
+// free or the timeout passes.
+fn lock(f: File, exclusive: bool, timeout: Option<Duration>, clock: &dyn Clock) -> Result<File> {
+    let Some(timeout) = timeout else {
+        return Ok(f);
+    };
+
+    let deadline = clock.now() + timeout;
+    let mut delay = LOCK_RETRY_DELAY;
+    loop {
+        let locked = match exclusive {
+            true => f.try_lock(),
+            false => f.try_lock_shared(),
+        };
+        match locked {
+            Ok(()) => return Ok(f),
+            Err(TryLockError::Error(e)) => return Err(Error::Io(e)),
+            Err(TryLockError::WouldBlock) => {
+                let now = clock.now();
+                if now >= deadline {
+                    return Err(Error::LockTimeout);
+                }
+                clock.sleep(delay.min(deadline - now));
+                delay = (delay * 2).min(LOCK_RETRY_MAX_DELAY);
+            }
         }
     }
 }
@@ -199,56 +641,154 @@ impl OpenOptions<NotWrite> {
             .read(true)
             .open(path.as_ref())
             .map_err(Error::Io)
-            .and_then(|f| Gdbm::<ReadOnly>::open(f, path, self.alignment, self.cachesize))
+            .and_then(|f| self.open_file(f, path))
+    }
+
+    /// Open the database in a file already open for reading, as one passed
+    /// by a privileged process.  Without a path, the handle can't be
+    /// refreshed or watched.
+    #[allow(clippy::wrong_self_convention)]
+    pub fn from_file(&self, f: File) -> Result<Gdbm<ReadOnly>> {
+        self.open_file(f, "")
+    }
+
+    /// [`from_file`](Self::from_file) for a file descriptor.
+    ///
+    /// # Safety
+    ///
+    /// fd must be an open file descriptor, owned by nothing else: the
+    /// handle closes it.
+    #[cfg(unix)]
+    #[allow(clippy::wrong_self_convention)]
+    pub unsafe fn from_raw_fd(&self, fd: RawFd) -> Result<Gdbm<ReadOnly>> {
+        self.from_file(unsafe { File::from_raw_fd(fd) })
+    }
+
+    fn open_file<P: AsRef<std::path::Path>>(&self, f: File, path: P) -> Result<Gdbm<ReadOnly>> {
+        lock(f, false, self.lock_timeout, self.providers.clock())
+            .and_then(|f| {
+                Gdbm::<ReadOnly>::open_with(
+                    f,
+                    path,
+                    self.alignment,
+                    self.cachesize,
+                    self.providers.clone(),
+                )
+            })
+            .and_then(|db| db.check_user_version(self.user_version).map(|_| db))
+            .and_then(|mut db| db.set_open_options(self).map(|_| db))
     }
 }
 
+#[cfg(feature = "write")]
 impl OpenOptions<Write<NotCreate>> {
     pub fn open<P: AsRef<std::path::Path>>(&self, path: P) -> Result<Gdbm<ReadWrite>> {
         std::fs::OpenOptions::new()
             .read(true)
             .write(true)
             .open(path.as_ref())
-            .map_err(Error::Io)
-            .and_then(|f| Gdbm::<ReadWrite>::open(f, path, self.alignment, self.cachesize))
-            .map(|mut db| {
-                db.set_sync(self.write.sync);
-                db
+            .map_err(Error::from_write)
+            .and_then(|f| self.open_file(f, path))
+    }
+
+    /// Open the database in a file already open for reading and writing, as
+    /// one passed by a privileged process.  Without a path, the handle
+    /// can't be compacted.
+    #[allow(clippy::wrong_self_convention)]
+    pub fn from_file(&self, f: File) -> Result<Gdbm<ReadWrite>> {
+        self.open_file(f, "")
+    }
+
+    /// [`from_file`](Self::from_file) for a file descriptor.
+    ///
+    /// # Safety
+    ///
+    /// fd must be an open file descriptor, owned by nothing else: the
+    /// handle closes it.
+    #[cfg(unix)]
+    #[allow(clippy::wrong_self_convention)]
+    pub unsafe fn from_raw_fd(&self, fd: RawFd) -> Result<Gdbm<ReadWrite>> {
+        self.from_file(unsafe { File::from_raw_fd(fd) })
+    }
+
+    fn open_file<P: AsRef<std::path::Path>>(&self, f: File, path: P) -> Result<Gdbm<ReadWrite>> {
+        lock(f, true, self.lock_timeout, self.providers.clock())
+            .and_then(|f| {
+                Gdbm::<ReadWrite>::open_with(
+                    f,
+                    path,
+                    self.alignment,
+                    self.cachesize,
+                    self.providers.clone(),
+                )
+            })
+            .and_then(|db| db.check_user_version(self.user_version).map(|_| db))
+            .and_then(|mut db| db.check_sealed(self.write.force_unseal).map(|_| db))
+            .and_then(|mut db| {
+                db.set_write_options(&self.write);
+                db.set_open_options(self).map(|_| db)
             })
     }
 }
 
+#[cfg(feature = "write")]
 impl OpenOptions<Write<Create>> {
     pub fn open<P: AsRef<std::path::Path>>(&self, path: P) -> Result<Gdbm<ReadWrite>> {
-        if self.write.create.newdb {
-            std::fs::OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(path.as_ref())
-                .map_err(Error::Io)
-                .and_then(|f| Gdbm::create(f, path, self))
-        } else {
-            std::fs::OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true)
-                .truncate(false)
-                .open(path.as_ref())
-                .map_err(Error::Io)
-                .and_then(|f| {
-                    Gdbm::<ReadWrite>::open(f, path.as_ref(), self.alignment, self.cachesize)
-                        .or_else(|e| match e {
-                            Error::EmptyFile(f) => Gdbm::create(f, path, self),
-                            e => Err(e),
-                        })
-                })
-        }
-        .map(|mut db| {
-            db.set_sync(self.write.sync);
-            db
-        })
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path.as_ref())
+            .map_err(Error::from_write)
+            .and_then(|f| self.open_file(f, path))
+    }
+
+    /// Open, or create in an empty file, the database in a file already
+    /// open for reading and writing, as one passed by a privileged process.
+    /// Without a path, the handle can't be compacted.
+    #[allow(clippy::wrong_self_convention)]
+    pub fn from_file(&self, f: File) -> Result<Gdbm<ReadWrite>> {
+        self.open_file(f, "")
+    }
+
+    /// [`from_file`](Self::from_file) for a file descriptor.
+    ///
+    /// # Safety
+    ///
+    /// fd must be an open file descriptor, owned by nothing else: the
+    /// handle closes it.
+    #[cfg(unix)]
+    #[allow(clippy::wrong_self_convention)]
+    pub unsafe fn from_raw_fd(&self, fd: RawFd) -> Result<Gdbm<ReadWrite>> {
+        self.from_file(unsafe { File::from_raw_fd(fd) })
+    }
+
+    fn open_file<P: AsRef<std::path::Path>>(&self, f: File, path: P) -> Result<Gdbm<ReadWrite>> {
+        lock(f, true, self.lock_timeout, self.providers.clock())
+            .and_then(|f| match self.write.create.newdb {
+                true => {
+                    f.set_len(0).map_err(Error::from_write)?;
+                    Gdbm::create(f, path, self)
+                }
+                false => Gdbm::<ReadWrite>::open_with(
+                    f,
+                    path.as_ref(),
+                    self.alignment,
+                    self.cachesize,
+                    self.providers.clone(),
+                )
+                .and_then(|db| db.check_user_version(self.user_version).map(|_| db))
+                .and_then(|mut db| db.check_sealed(self.write.force_unseal).map(|_| db))
+                .or_else(|e| match e {
+                    Error::EmptyFile(f) => Gdbm::create(f, path, self),
+                    e => Err(e),
+                }),
+            })
+            .and_then(|mut db| {
+                db.set_write_options(&self.write);
+                db.set_open_options(self).map(|_| db)
+            })
     }
 }
 
@@ -256,3 +796,128 @@ impl OpenOptions<Write<Create>> {
 pub struct ConvertOptions {
     pub numsync: bool,
 }
+
+/// Settings of [`Gdbm::compact_with`](crate::Gdbm::compact_with).  The
+/// rebuilt database is renamed over the original file, given its owner and
+/// permissions first.  Other handles open on the database keep reading the
+/// original file.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct CompactOptions {
+    /// Restore the access and modification times the file had before.
+    pub preserve_times: bool,
+    /// Sync the directory holding the database once the rebuilt file is
+    /// renamed over it, so the rename survives a crash.
+    pub fsync_dir: bool,
+}
+
+/// How to store a record whose key already exists, as the flags to C GDBM's
+/// gdbm_store().
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum InsertMode {
+    /// Replace the existing record (GDBM_REPLACE).
+    #[default]
+    Replace,
+    /// Keep the existing record (GDBM_INSERT).
+    Insert,
+    /// Keep the existing record and fail with Error::KeyExists.
+    ErrorIfExists,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ImportOptions {
+    /// Handling of records whose key is already in the database.
+    pub mode: InsertMode,
+}
+
+/// Version of the ASCII dump format to write.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum DumpVersion {
+    /// Format 1.0, written by GNU gdbm_dump 1.11 to 1.20.
+    V1_0,
+    /// Format 1.1, written by GNU gdbm_dump 1.21 and later: adds the
+    /// database format (standard or numsync) to the header.
+    #[default]
+    V1_1,
+}
+
+/// Version of the binary (flat file) dump format to write.  Imports detect
+/// the version.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum BinDumpVersion {
+    /// Legacy format 0.0: records only, without a header, as read by the
+    /// oldest flat file tools.
+    V0,
+    /// Records after the "GDBM FLAT FILE DUMP" header, as written by GNU
+    /// gdbm_export.
+    #[default]
+    V1,
+}
+
+/// A field of the ASCII dump header.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DumpField {
+    /// `#:file=`, the database path.
+    File,
+    /// `#:format=`, standard or numsync.  Only in format 1.1.
+    Format,
+    /// `#:uid=,gid=,mode=`, the owner and permissions of the database file.
+    Owner,
+}
+
+/// The fields of the ASCII dump header, after the version, in the order
+/// written.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AsciiDumpOptions {
+    pub fields: &'static [DumpField],
+}
+
+impl AsciiDumpOptions {
+    /// The fields and order of GNU gdbm_dump 1.23.
+    pub const GNU_1_23: AsciiDumpOptions = AsciiDumpOptions {
+        fields: &[DumpField::File, DumpField::Format, DumpField::Owner],
+    };
+}
+
+impl Default for AsciiDumpOptions {
+    fn default() -> Self {
+        AsciiDumpOptions {
+            fields: &[DumpField::File, DumpField::Format],
+        }
+    }
+}
+
+/// Replace exported records by HMAC-SHA-256 digests of the originals under
+/// a secret, of the same sizes, so a dump reproduces the layout of a
+/// confidential database without its contents.  Equal records still map to
+/// equal digests, except that an anonymized key equal to one already
+/// exported is derived again, so short keys don't collide.
+#[derive(Copy, Clone, PartialEq)]
+pub struct Anonymize {
+    pub secret: [u8; 32],
+    /// Replace keys too, not just values.
+    pub keys: bool,
+}
+
+impl Debug for Anonymize {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Anonymize")
+            .field("secret", &"..")
+            .field("keys", &self.keys)
+            .finish()
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ExportOptions {
+    /// ASCII dump format version.
+    pub version: DumpVersion,
+    /// ASCII dump header fields.
+    pub ascii: AsciiDumpOptions,
+    /// Binary dump format version.
+    pub bin_version: BinDumpVersion,
+    /// End binary dumps with an index of their records, for
+    /// [`DumpReader`](crate::DumpReader).  Importers ignore the index.
+    pub bin_index: bool,
+    /// Replace records by digests.
+    pub anonymize: Option<Anonymize>,
+}
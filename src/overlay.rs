@@ -0,0 +1,127 @@
+//
+// overlay.rs -- writes staged in memory over a database
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+use std::collections::HashMap;
+
+use crate::bytes::{Bytes, BytesRef};
+use crate::{Gdbm, ReadWrite, Result};
+
+// the key as given, and its new value, or None if removed
+type Change = (Vec<u8>, Option<Vec<u8>>);
+
+/// Inserts and removes staged in memory over a database.  Reads see the
+/// staged changes first, then the database.  Nothing reaches the database
+/// until [`commit`](Overlay::commit); dropping the overlay, or calling
+/// [`discard`](Overlay::discard), forgets the changes.
+pub struct Overlay<'a> {
+    db: &'a mut Gdbm<ReadWrite>,
+    // by normalized key
+    changes: HashMap<Vec<u8>, Change>,
+}
+
+impl<'a> Overlay<'a> {
+    pub fn new(db: &'a mut Gdbm<ReadWrite>) -> Overlay<'a> {
+        Overlay {
+            db,
+            changes: HashMap::new(),
+        }
+    }
+
+    // the value of key as seen through the overlay
+    fn lookup(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        match self.changes.get(self.db.normalized(key).as_ref()) {
+            Some((_, value)) => Ok(value.clone()),
+            None => self.db.get(key),
+        }
+    }
+
+    pub fn get<'k, K: Into<BytesRef<'k>>, V: From<Bytes>>(&mut self, key: K) -> Result<Option<V>> {
+        self.lookup(key.into().as_ref())
+            .map(|value| value.map(|value| Bytes::from(value).into()))
+    }
+
+    pub fn contains_key<'k, K: Into<BytesRef<'k>>>(&mut self, key: K) -> Result<bool> {
+        let key = key.into();
+        match self.changes.get(self.db.normalized(key.as_ref()).as_ref()) {
+            Some((_, value)) => Ok(value.is_some()),
+            None => self.db.contains_key(key.as_ref()),
+        }
+    }
+
+    /// Stage an insert, returning the value it replaces.
+    pub fn insert<K: Into<Bytes>, V: Into<Bytes>>(
+        &mut self,
+        key: K,
+        value: V,
+    ) -> Result<Option<Vec<u8>>> {
+        self.stage(key.into().into_vec(), Some(value.into().into_vec()))
+    }
+
+    /// Stage a remove, returning the value removed.
+    pub fn remove<'k, K: Into<BytesRef<'k>>>(&mut self, key: K) -> Result<Option<Vec<u8>>> {
+        self.stage(key.into().as_ref().to_vec(), None)
+    }
+
+    fn stage(&mut self, key: Vec<u8>, value: Option<Vec<u8>>) -> Result<Option<Vec<u8>>> {
+        let old_value = self.lookup(&key)?;
+        let normalized = self.db.normalized(&key).into_owned();
+        self.changes.insert(normalized, (key, value));
+
+        Ok(old_value)
+    }
+
+    /// Number of keys inserted or removed.
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Apply the staged changes to the database, syncing once at the end in
+    /// sync mode.  Should a change fail, those already applied are undone,
+    /// leaving the database as it was.
+    pub fn commit(self) -> Result<()> {
+        let changes = self.changes;
+        self.db.batch(|db| {
+            // each change applied, with the value it replaced
+            let mut applied = Vec::new();
+            changes
+                .into_values()
+                .try_for_each(|(key, value)| {
+                    let old_value = apply(db, key.clone(), value)?;
+                    applied.push((key, old_value));
+                    Ok(())
+                })
+                .or_else(|e| {
+                    applied
+                        .into_iter()
+                        .rev()
+                        .try_for_each(|(key, old_value)| apply(db, key, old_value).map(|_| ()))
+                        .and(Err(e))
+                })
+        })
+    }
+
+    pub fn discard(self) {}
+}
+
+// set the value of key, or remove it if None, returning the value replaced
+fn apply(
+    db: &mut Gdbm<ReadWrite>,
+    key: Vec<u8>,
+    value: Option<Vec<u8>>,
+) -> Result<Option<Vec<u8>>> {
+    match value {
+        Some(value) => db.insert(key, value),
+        None => db.remove(key.as_slice()),
+    }
+}
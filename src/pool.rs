@@ -0,0 +1,121 @@
+//
+// pool.rs -- pool of read-only handles for multi-threaded readers
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::sync::{Condvar, Mutex};
+
+use crate::bytes::{Bytes, BytesRef};
+use crate::options::NotWrite;
+use crate::{Gdbm, OpenOptions, ReadOnly, Result};
+
+/// A fixed number of independent read-only handles on one database, shared
+/// between threads.  Each handle is used by one thread at a time; threads
+/// wanting a handle while all are in use wait for one to be returned.
+pub struct ReadPool {
+    handles: Mutex<Vec<Gdbm<ReadOnly>>>,
+    returned: Condvar,
+    size: usize,
+}
+
+/// A handle taken from a [`ReadPool`], returned to the pool on drop.
+pub struct PooledHandle<'a> {
+    pool: &'a ReadPool,
+    db: Option<Gdbm<ReadOnly>>,
+}
+
+impl ReadPool {
+    /// Open size handles on the database at path, each with options.
+    pub fn open<P: AsRef<Path>>(
+        options: &OpenOptions<NotWrite>,
+        path: P,
+        size: usize,
+    ) -> Result<ReadPool> {
+        let handles = (0..size)
+            .map(|_| options.open(path.as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(ReadPool {
+            handles: Mutex::new(handles),
+            returned: Condvar::new(),
+            size,
+        })
+    }
+
+    /// Number of handles owned by the pool.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Number of handles not currently in use.
+    pub fn idle(&self) -> usize {
+        self.handles.lock().unwrap().len()
+    }
+
+    /// Take a handle, waiting for one to be returned if all are in use.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pool has no handles.
+    pub fn handle(&self) -> PooledHandle<'_> {
+        assert!(self.size > 0, "empty read pool");
+
+        let mut handles = self
+            .returned
+            .wait_while(self.handles.lock().unwrap(), |handles| handles.is_empty())
+            .unwrap();
+
+        PooledHandle {
+            pool: self,
+            db: handles.pop(),
+        }
+    }
+
+    /// Take a handle if one is not in use.
+    pub fn try_handle(&self) -> Option<PooledHandle<'_>> {
+        self.handles.lock().unwrap().pop().map(|db| PooledHandle {
+            pool: self,
+            db: Some(db),
+        })
+    }
+
+    /// Fetch a record value with a pooled handle.
+    pub fn get<'a, K: Into<BytesRef<'a>>, V: From<Bytes>>(&self, key: K) -> Result<Option<V>> {
+        self.handle().get(key)
+    }
+
+    /// Check for a key with a pooled handle.
+    pub fn contains_key<'a, K: Into<BytesRef<'a>>>(&self, key: K) -> Result<bool> {
+        self.handle().contains_key(key)
+    }
+}
+
+impl Deref for PooledHandle<'_> {
+    type Target = Gdbm<ReadOnly>;
+
+    fn deref(&self) -> &Self::Target {
+        self.db.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledHandle<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.db.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledHandle<'_> {
+    fn drop(&mut self) {
+        if let Some(db) = self.db.take() {
+            self.pool.handles.lock().unwrap().push(db);
+            self.pool.returned.notify_one();
+        }
+    }
+}
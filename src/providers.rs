@@ -0,0 +1,220 @@
+//
+// providers.rs -- sources of time and randomness, replaceable for tests
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+use std::fmt::{Debug, Formatter};
+use std::hash::{BuildHasher, RandomState};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "testutil")]
+use crate::simulation::Simulation;
+
+/// The time, as read for timeouts, deadlines, operation timings and record
+/// cache expiry, and waiting, as for lock retries.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+    fn sleep(&self, duration: Duration);
+}
+
+/// Random numbers, as used for database identifiers and compaction spot
+/// checks.
+pub trait Random: Send + Sync {
+    fn next_u64(&self) -> u64;
+}
+
+/// The system clock.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// A clock that only moves when advanced, or slept on: sleeping advances
+/// it at once by the time slept.
+#[derive(Debug)]
+pub struct ManualClock {
+    start: Instant,
+    elapsed: Mutex<Duration>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        ManualClock {
+            start: Instant::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+
+    /// Time advanced since the clock was created.
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+/// Random numbers from the randomly keyed hasher of the standard library,
+/// the time and the process id.
+#[derive(Debug, Default)]
+pub struct SystemRandom {
+    counter: AtomicU64,
+}
+
+impl Random for SystemRandom {
+    fn next_u64(&self) -> u64 {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|t| t.as_nanos())
+            .unwrap_or_default();
+        let n = self.counter.fetch_add(1, Ordering::Relaxed);
+
+        RandomState::new().hash_one((n, nanos, std::process::id()))
+    }
+}
+
+/// A reproducible sequence of random numbers from a seed (SplitMix64).
+#[derive(Debug)]
+pub struct SeededRandom {
+    state: AtomicU64,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> Self {
+        SeededRandom {
+            state: AtomicU64::new(seed),
+        }
+    }
+}
+
+impl Random for SeededRandom {
+    fn next_u64(&self) -> u64 {
+        let mut z = self
+            .state
+            .fetch_add(0x9e3779b97f4a7c15, Ordering::Relaxed)
+            .wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// The clock and source of random numbers of a database, set with
+/// [`OpenOptions::clock`](crate::OpenOptions::clock) and
+/// [`OpenOptions::random`](crate::OpenOptions::random).  The system's by
+/// default.  With the testutil feature, also the simulated disk set with
+/// [`OpenOptions::simulation`](crate::OpenOptions::simulation).
+#[derive(Clone)]
+pub struct Providers {
+    clock: Arc<dyn Clock>,
+    #[cfg_attr(not(feature = "write"), allow(dead_code))]
+    random: Arc<dyn Random>,
+    #[cfg(feature = "testutil")]
+    simulation: Option<Simulation>,
+}
+
+impl Providers {
+    pub(crate) fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Providers { clock, ..self }
+    }
+
+    pub(crate) fn with_random(self, random: Arc<dyn Random>) -> Self {
+        Providers { random, ..self }
+    }
+
+    #[cfg(feature = "testutil")]
+    pub(crate) fn with_simulation(self, simulation: Option<Simulation>) -> Self {
+        Providers { simulation, ..self }
+    }
+
+    #[cfg(feature = "testutil")]
+    pub(crate) fn simulation(&self) -> Option<Simulation> {
+        self.simulation.clone()
+    }
+
+    pub(crate) fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    #[cfg(feature = "write")]
+    pub(crate) fn random(&self) -> &dyn Random {
+        self.random.as_ref()
+    }
+}
+
+impl Default for Providers {
+    fn default() -> Self {
+        Providers {
+            clock: Arc::new(SystemClock),
+            random: Arc::new(SystemRandom::default()),
+            #[cfg(feature = "testutil")]
+            simulation: None,
+        }
+    }
+}
+
+impl Debug for Providers {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "Providers")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn seeded_random() {
+        // reference values of SplitMix64 seeded with 0
+        let random = SeededRandom::new(0);
+        assert_eq!(random.next_u64(), 0xe220a8397b1dcdaf);
+        assert_eq!(random.next_u64(), 0x6e789e6aa1b965f4);
+
+        let (a, b) = (SeededRandom::new(7), SeededRandom::new(7));
+        assert!((0..10).all(|_| a.next_u64() == b.next_u64()));
+    }
+
+    #[test]
+    fn manual_clock() {
+        let clock = ManualClock::new();
+        let start = clock.now();
+        assert_eq!(clock.now(), start);
+
+        clock.advance(Duration::from_secs(1));
+        clock.sleep(Duration::from_secs(2));
+        assert_eq!(clock.now() - start, Duration::from_secs(3));
+        assert_eq!(clock.elapsed(), Duration::from_secs(3));
+    }
+}
@@ -0,0 +1,38 @@
+//
+// quarantine.rs -- damaged buckets set aside in quarantine mode
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+use std::ops::RangeInclusive;
+
+use crate::hashutil::HASH_BITS;
+
+/// A damaged bucket set aside in quarantine mode.  Records whose key hashes
+/// fall in its range can't be read or written; the rest of the database
+/// stays available.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuarantinedBucket {
+    /// Bucket file offset.
+    pub offset: u64,
+    /// Key hashes of the records the bucket holds.
+    pub hashes: RangeInclusive<u32>,
+}
+
+impl QuarantinedBucket {
+    // The bucket pointed to by length directory entries from start.
+    pub(crate) fn new(offset: u64, dir_bits: u32, start: usize, length: usize) -> Self {
+        let shift = HASH_BITS - dir_bits;
+        let first = (start as u64) << shift;
+        let end = ((start + length) as u64) << shift;
+
+        QuarantinedBucket {
+            offset,
+            hashes: first as u32..=(end - 1) as u32,
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//
+// records.rs -- cache of recently read records
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use crate::options::RecordCacheOptions;
+use crate::stats::RecordCacheStats;
+
+struct Entry {
+    value: Vec<u8>,
+    stamp: u64,
+    expires: Option<Instant>,
+}
+
+// Values of recently read records by key, within a byte budget.  Beyond the
+// budget the least recently used records are dropped.  Records are dropped
+// from the cache when stored or removed, and read again once their time to
+// live passes.
+pub struct RecordCache {
+    max_bytes: usize,
+    ttl: Option<Duration>,
+
+    entries: HashMap<Vec<u8>, Entry>,
+    // keys by stamp, least recently used first
+    order: BTreeMap<u64, Vec<u8>>,
+    next_stamp: u64,
+    bytes: usize,
+
+    hits: u64,
+    misses: u64,
+}
+
+impl RecordCache {
+    pub fn new(options: &RecordCacheOptions) -> Self {
+        RecordCache {
+            max_bytes: options.max_bytes,
+            ttl: options.ttl,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            next_stamp: 0,
+            bytes: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn options(&self) -> RecordCacheOptions {
+        RecordCacheOptions {
+            max_bytes: self.max_bytes,
+            ttl: self.ttl,
+        }
+    }
+
+    // the cached value of key, at time now
+    pub fn get(&mut self, key: &[u8], now: Instant) -> Option<Vec<u8>> {
+        match self.entries.get_mut(key) {
+            Some(entry) if entry.expires.is_none_or(|expires| now < expires) => {
+                self.order.remove(&entry.stamp);
+                entry.stamp = self.next_stamp;
+                self.next_stamp += 1;
+                self.order.insert(entry.stamp, key.to_vec());
+                self.hits += 1;
+
+                Some(entry.value.clone())
+            }
+            Some(_) => {
+                self.remove(key);
+                self.misses += 1;
+                None
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    // cache the value of key, read at time now
+    pub fn insert(&mut self, key: &[u8], value: &[u8], now: Instant) {
+        self.remove(key);
+
+        let size = key.len() + value.len();
+        if size > self.max_bytes {
+            return;
+        }
+
+        while self.bytes + size > self.max_bytes {
+            let (_, oldest) = self.order.pop_first().unwrap();
+            let entry = self.entries.remove(&oldest).unwrap();
+            self.bytes -= oldest.len() + entry.value.len();
+        }
+
+        let stamp = self.next_stamp;
+        self.next_stamp += 1;
+        self.order.insert(stamp, key.to_vec());
+        self.entries.insert(
+            key.to_vec(),
+            Entry {
+                value: value.to_vec(),
+                stamp,
+                expires: self.ttl.map(|ttl| now + ttl),
+            },
+        );
+        self.bytes += size;
+    }
+
+    pub fn remove(&mut self, key: &[u8]) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.stamp);
+            self.bytes -= key.len() + entry.value.len();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        self.bytes = 0;
+    }
+
+    pub fn stats(&self) -> RecordCacheStats {
+        RecordCacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            records: self.entries.len(),
+            bytes: self.bytes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn budget_and_ttl() {
+        let now = Instant::now();
+        let mut cache = RecordCache::new(&RecordCacheOptions {
+            max_bytes: 10,
+            ttl: None,
+        });
+
+        cache.insert(b"a", b"1234", now);
+        cache.insert(b"b", b"1234", now);
+        assert_eq!(cache.get(b"a", now), Some(b"1234".to_vec()));
+
+        // b is least recently used
+        cache.insert(b"c", b"1234", now);
+        assert_eq!(cache.get(b"b", now), None);
+        assert_eq!(cache.stats().bytes, 10);
+
+        // too large to cache
+        cache.insert(b"d", b"12345678901", now);
+        assert_eq!(cache.get(b"d", now), None);
+
+        cache.remove(b"a");
+        assert_eq!(cache.get(b"a", now), None);
+        assert_eq!(
+            cache.stats(),
+            RecordCacheStats {
+                hits: 1,
+                misses: 3,
+                records: 1,
+                bytes: 5,
+            }
+        );
+
+        let mut cache = RecordCache::new(&RecordCacheOptions {
+            max_bytes: 10,
+            ttl: Some(Duration::from_secs(1)),
+        });
+        cache.insert(b"a", b"1", now);
+        assert_eq!(cache.get(b"a", now), Some(b"1".to_vec()));
+        assert_eq!(cache.get(b"a", now + Duration::from_secs(1)), None);
+        assert_eq!(cache.stats().records, 0);
+    }
+}
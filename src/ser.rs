@@ -51,6 +51,15 @@ pub struct Layout {
     pub offset: Offset,
 }
 
+pub fn read16(endian: Endian, reader: &mut impl Read) -> io::Result<u16> {
+    let mut bytes = [0u8; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(match endian {
+        Endian::Little => u16::from_le_bytes(bytes),
+        Endian::Big => u16::from_be_bytes(bytes),
+    })
+}
+
 pub fn read32(endian: Endian, reader: &mut impl Read) -> io::Result<u32> {
     let mut bytes = [0u8; 4];
     reader.read_exact(&mut bytes)?;
@@ -69,6 +78,14 @@ pub fn read64(endian: Endian, reader: &mut impl Read) -> io::Result<u64> {
     })
 }
 
+pub fn write16(endian: Endian, writer: &mut impl Write, value: u16) -> io::Result<()> {
+    let bytes = match endian {
+        Endian::Little => value.to_le_bytes(),
+        Endian::Big => value.to_be_bytes(),
+    };
+    writer.write_all(&bytes)
+}
+
 pub fn write32(endian: Endian, writer: &mut impl Write, value: u32) -> io::Result<()> {
     let bytes = match endian {
         Endian::Little => value.to_le_bytes(),
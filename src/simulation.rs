@@ -0,0 +1,200 @@
+//
+// simulation.rs -- a simulated disk that delays, reorders and fails writes
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+use std::fmt::{Debug, Formatter};
+use std::fs::File;
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::sync::{Arc, Mutex};
+
+use crate::providers::{Random, SeededRandom};
+
+// A change to the file not yet made durable by a sync
+enum Change {
+    // the bytes written at offset, and the bytes they replaced
+    Write {
+        offset: u64,
+        old: Vec<u8>,
+        new: Vec<u8>,
+    },
+    // the length the file was set to, and its old length and lost tail
+    SetLen {
+        old_len: u64,
+        tail: Vec<u8>,
+        new_len: u64,
+    },
+}
+
+struct State {
+    random: SeededRandom,
+    // chance of each write failing, in millionths
+    failure_rate: u32,
+    // changes since the last sync, oldest first
+    volatile: Vec<Change>,
+    // length of the file at the last sync
+    durable_len: Option<u64>,
+    failures: u64,
+}
+
+impl State {
+    fn chance(&self, millionths: u32) -> bool {
+        self.random.next_u64() % 1_000_000 < millionths as u64
+    }
+}
+
+/// A simulated disk for testing, set with
+/// [`OpenOptions::simulation`](crate::OpenOptions::simulation).  Writes
+/// reach the database file at once, so the handle sees them, but count as
+/// lost in a crash until a sync: [`crash_image`](Simulation::crash_image)
+/// gives the file a crash could leave, with any subset of the writes since
+/// the last sync durable, in any order.  Writes can also be made to fail,
+/// part way.  The choices are made by a seed, so a failing run can be
+/// repeated.
+///
+/// Hole punching and preallocation aren't simulated.
+#[derive(Clone)]
+pub struct Simulation(Arc<Mutex<State>>);
+
+impl Simulation {
+    pub fn new(seed: u64) -> Self {
+        Simulation(Arc::new(Mutex::new(State {
+            random: SeededRandom::new(seed),
+            failure_rate: 0,
+            volatile: Vec::new(),
+            durable_len: None,
+            failures: 0,
+        })))
+    }
+
+    /// Fail this many in a million writes from now on.  A failed write may
+    /// have written part of its data.
+    pub fn set_failure_rate(&self, millionths: u32) {
+        self.0.lock().unwrap().failure_rate = millionths;
+    }
+
+    /// Number of writes failed so far.
+    pub fn failures(&self) -> u64 {
+        self.0.lock().unwrap().failures
+    }
+
+    /// Number of changes to the file since the last sync.
+    pub fn unsynced(&self) -> usize {
+        self.0.lock().unwrap().volatile.len()
+    }
+
+    /// The contents the database file could be left with by a crash now:
+    /// everything synced, and a random subset of the writes since.
+    pub fn crash_image(&self, f: &File) -> io::Result<Vec<u8>> {
+        let state = self.0.lock().unwrap();
+
+        let mut image = vec![0; f.metadata()?.len() as usize];
+        f.read_exact_at(&mut image, 0)?;
+
+        // undo the unsynced changes, newest first
+        state.volatile.iter().rev().for_each(|change| match change {
+            Change::Write { offset, old, new } => {
+                let offset = *offset as usize;
+                image[offset..offset + new.len()].fill(0);
+                image[offset..offset + old.len()].copy_from_slice(old);
+            }
+            Change::SetLen { old_len, tail, .. } => {
+                image.resize(*old_len as usize, 0);
+                let start = image.len() - tail.len();
+                image[start..].copy_from_slice(tail);
+            }
+        });
+        if let Some(durable_len) = state.durable_len {
+            image.truncate(durable_len as usize);
+        }
+
+        // then redo those that happen to have become durable
+        state
+            .volatile
+            .iter()
+            .filter(|_| state.chance(500_000))
+            .for_each(|change| match change {
+                Change::Write { offset, new, .. } => {
+                    let offset = *offset as usize;
+                    if image.len() < offset + new.len() {
+                        image.resize(offset + new.len(), 0);
+                    }
+                    image[offset..offset + new.len()].copy_from_slice(new);
+                }
+                Change::SetLen { new_len, .. } => image.resize(*new_len as usize, 0),
+            });
+
+        Ok(image)
+    }
+
+    // Write buf at offset, as a write that may fail.  Returns the bytes
+    // written.
+    pub(crate) fn write_at(&self, f: &File, buf: &[u8], offset: u64) -> io::Result<usize> {
+        let mut state = self.0.lock().unwrap();
+
+        let (length, error) = match state.chance(state.failure_rate) {
+            true => {
+                state.failures += 1;
+                let length = state.random.next_u64() as usize % (buf.len() + 1);
+                (length, Some(io::Error::other("simulated write failure")))
+            }
+            false => (buf.len(), None),
+        };
+
+        let file_len = f.metadata()?.len();
+        state.durable_len.get_or_insert(file_len);
+        let old_len = file_len.saturating_sub(offset).min(length as u64) as usize;
+        let mut old = vec![0; old_len];
+        f.read_exact_at(&mut old, offset)?;
+        f.write_all_at(&buf[..length], offset)?;
+        state.volatile.push(Change::Write {
+            offset,
+            old,
+            new: buf[..length].to_vec(),
+        });
+
+        match error {
+            Some(e) => Err(e),
+            None => Ok(length),
+        }
+    }
+
+    pub(crate) fn set_len(&self, f: &File, size: u64) -> io::Result<()> {
+        let mut state = self.0.lock().unwrap();
+
+        let old_len = f.metadata()?.len();
+        state.durable_len.get_or_insert(old_len);
+        let mut tail = vec![0; old_len.saturating_sub(size) as usize];
+        f.read_exact_at(&mut tail, size)?;
+        f.set_len(size)?;
+        state.volatile.push(Change::SetLen {
+            old_len,
+            tail,
+            new_len: size,
+        });
+
+        Ok(())
+    }
+
+    // everything written so far is durable
+    #[cfg(feature = "write")]
+    pub(crate) fn sync(&self, f: &File) -> io::Result<()> {
+        let mut state = self.0.lock().unwrap();
+        state.volatile.clear();
+        state.durable_len = Some(f.metadata()?.len());
+
+        Ok(())
+    }
+}
+
+impl Debug for Simulation {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "Simulation")
+    }
+}
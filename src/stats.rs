@@ -0,0 +1,97 @@
+//
+// stats.rs -- I/O accounting of database operations
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+use std::time::Duration;
+
+use crate::Operation;
+
+// Running totals of a database handle.  The counts of one operation are the
+// difference between the totals before and after it.
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct Counters {
+    // buckets found in the cache
+    pub bucket_hits: u64,
+    pub buckets_read: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub splits: u64,
+    // key and value bytes given to inserts
+    #[cfg_attr(not(feature = "write"), allow(dead_code))]
+    pub bytes_inserted: u64,
+    // records stored or removed
+    pub updates: u64,
+    // syncs that wrote changes
+    pub syncs: u64,
+}
+
+/// The work done by one get, insert, remove or sync, from
+/// [`Gdbm::last_op_stats`](crate::Gdbm::last_op_stats).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct OpStats {
+    pub operation: Operation,
+    /// Time taken.
+    pub elapsed: Duration,
+    /// Buckets read from the file rather than found in the cache.
+    pub buckets_read: u64,
+    /// Bytes read from the file.
+    pub bytes_read: u64,
+    /// Bytes written to the file.
+    pub bytes_written: u64,
+    /// Buckets split to make room for records.
+    pub splits: u64,
+}
+
+impl OpStats {
+    pub(crate) fn new(
+        operation: Operation,
+        elapsed: Duration,
+        before: &Counters,
+        after: &Counters,
+    ) -> Self {
+        OpStats {
+            operation,
+            elapsed,
+            buckets_read: after.buckets_read - before.buckets_read,
+            bytes_read: after.bytes_read - before.bytes_read,
+            bytes_written: after.bytes_written - before.bytes_written,
+            splits: after.splits - before.splits,
+        }
+    }
+}
+
+/// Bytes written to the file by a database handle, against the key and value
+/// bytes inserted through it, from
+/// [`Gdbm::write_amplification`](crate::Gdbm::write_amplification).
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct WriteAmplification {
+    pub bytes_written: u64,
+    pub bytes_inserted: u64,
+}
+
+impl WriteAmplification {
+    /// Bytes written per byte inserted, or None if nothing was inserted.
+    pub fn ratio(&self) -> Option<f64> {
+        (self.bytes_inserted > 0).then(|| self.bytes_written as f64 / self.bytes_inserted as f64)
+    }
+}
+
+/// Record cache activity, from
+/// [`Gdbm::record_cache_stats`](crate::Gdbm::record_cache_stats).
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct RecordCacheStats {
+    /// Lookups answered from the cache.
+    pub hits: u64,
+    /// Lookups that read the database.
+    pub misses: u64,
+    /// Records cached.
+    pub records: usize,
+    /// Total size of cached keys and values.
+    pub bytes: usize,
+}
@@ -0,0 +1,686 @@
+//
+// storage.rs -- database file access, buffered or direct
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::FileExt;
+
+#[cfg(feature = "testutil")]
+use crate::simulation::Simulation;
+use crate::sys::{self, Advice};
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+use crate::uring::Ring;
+
+// Direct I/O transfers must start at, and be a multiple of, this many bytes
+// in both the file and memory.  4096 satisfies any logical block size.
+const DIRECT_ALIGN: u64 = 4096;
+
+// Record data is buffered until the page it falls in is complete, in pages
+// of this many bytes.
+#[cfg(feature = "write")]
+const WRITE_BEHIND_PAGE: u64 = 4096;
+
+// The database file.  Normally reads and writes go straight to the file
+// (and the OS page cache).  In direct mode the file is opened O_DIRECT and
+// every access is widened to aligned blocks staged in an aligned buffer;
+// the bucket cache is then the only cache of database contents.
+//
+// Record data written with write_behind() collects in a buffer, written out
+// by the next other access to the file, so that the records stored in a
+// page reach the file in one write.
+//
+// The file position is kept here, and every access is made at an explicit
+// offset, so storages sharing a file descriptor (see try_clone) don't move
+// each other's position.
+pub struct Storage {
+    f: File,
+    direct: bool,
+    fadvise: bool,
+    // file position, and size tracked here in direct mode
+    pos: u64,
+    size: u64,
+    // total bytes read from and written to the file, in whole blocks in
+    // direct mode
+    bytes_read: u64,
+    bytes_written: u64,
+    // offset and data of buffered writes, within one page
+    pending: Option<(u64, Vec<u8>)>,
+    // whether writing out the buffer failed, until taken by
+    // take_write_failure()
+    pending_failed: bool,
+    // the largest size the file is known to have had: at open, or since
+    // extended by writes, or as set by set_len
+    known_size: u64,
+    // created on first use, None if io_uring is unavailable
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    ring: Option<Option<Ring>>,
+    // simulated disk the file's writes go through
+    #[cfg(feature = "testutil")]
+    simulation: Option<Simulation>,
+}
+
+// A write that stopped part way: of length bytes to be written at offset,
+// only the first written were.  Carried inside the io::Error of the failed
+// write, and reported as Error::ShortWrite.
+#[derive(Debug)]
+pub struct ShortWrite {
+    pub offset: u64,
+    pub length: usize,
+    pub written: usize,
+    pub error: io::Error,
+}
+
+impl Display for ShortWrite {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "wrote {} of {} bytes at offset {}: {}",
+            self.written, self.length, self.offset, self.error
+        )
+    }
+}
+
+impl std::error::Error for ShortWrite {}
+
+// An aligned, zeroed buffer of len bytes within backing.
+fn aligned_buffer(backing: &mut Vec<u8>, len: usize) -> &mut [u8] {
+    *backing = vec![0; len + DIRECT_ALIGN as usize];
+    let start = backing.as_ptr().align_offset(DIRECT_ALIGN as usize);
+    &mut backing[start..start + len]
+}
+
+fn align_down(offset: u64) -> u64 {
+    offset / DIRECT_ALIGN * DIRECT_ALIGN
+}
+
+fn align_up(offset: u64) -> u64 {
+    offset.next_multiple_of(DIRECT_ALIGN)
+}
+
+impl Storage {
+    pub fn new(f: File) -> Self {
+        let known_size = f.metadata().map_or(0, |metadata| metadata.len());
+        Storage {
+            f,
+            direct: false,
+            fadvise: false,
+            pos: 0,
+            size: 0,
+            bytes_read: 0,
+            bytes_written: 0,
+            pending: None,
+            pending_failed: false,
+            known_size,
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            ring: None,
+            #[cfg(feature = "testutil")]
+            simulation: None,
+        }
+    }
+
+    // Another storage of the same file, through a duplicate of its file
+    // descriptor, with its own position and counters.
+    pub fn try_clone(&self) -> io::Result<Storage> {
+        Ok(Storage {
+            f: self.f.try_clone()?,
+            direct: self.direct,
+            fadvise: self.fadvise,
+            pos: 0,
+            size: self.size,
+            bytes_read: 0,
+            bytes_written: 0,
+            pending: None,
+            pending_failed: false,
+            known_size: self.known_size,
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            ring: None,
+            #[cfg(feature = "testutil")]
+            simulation: self.simulation.clone(),
+        })
+    }
+
+    #[cfg(feature = "testutil")]
+    pub fn with_simulation(mut self, simulation: Option<Simulation>) -> Self {
+        self.simulation = simulation;
+        self
+    }
+
+    // write to the file, through the simulated disk if any
+    fn file_write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        #[cfg(feature = "testutil")]
+        if let Some(simulation) = &self.simulation {
+            return simulation.write_at(&self.f, buf, offset);
+        }
+
+        self.f.write_at(buf, offset)
+    }
+
+    fn file_set_len(&self, size: u64) -> io::Result<()> {
+        #[cfg(feature = "testutil")]
+        if let Some(simulation) = &self.simulation {
+            return simulation.set_len(&self.f, size);
+        }
+
+        self.f.set_len(size)
+    }
+
+    // the file's writes so far are durable
+    #[cfg(feature = "write")]
+    fn synced(&self) -> io::Result<()> {
+        #[cfg(feature = "testutil")]
+        if let Some(simulation) = &self.simulation {
+            return simulation.sync(&self.f);
+        }
+
+        Ok(())
+    }
+
+    pub fn file(&self) -> &File {
+        &self.f
+    }
+
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    // Switch between buffered and direct I/O.  Direct I/O is only available
+    // on Linux, with the linux-fs feature; otherwise the file stays buffered.
+    pub fn set_direct(&mut self, direct: bool) -> io::Result<()> {
+        self.flush_pending()?;
+
+        #[cfg(all(target_os = "linux", feature = "linux-fs"))]
+        if direct != self.direct {
+            use rustix::fs::{fcntl_getfl, fcntl_setfl, OFlags};
+
+            let flags = fcntl_getfl(&self.f)?;
+            let flags = match direct {
+                true => flags | OFlags::DIRECT,
+                false => flags - OFlags::DIRECT,
+            };
+
+            if direct {
+                self.size = self.f.metadata()?.len();
+            }
+
+            fcntl_setfl(&self.f, flags)?;
+            self.direct = direct;
+        }
+
+        #[cfg(not(all(target_os = "linux", feature = "linux-fs")))]
+        let _ = direct;
+
+        Ok(())
+    }
+
+    // Take up changes made to the file by others: its size, in direct mode,
+    // and the file itself if it was replaced.
+    pub fn reload(&mut self, f: Option<File>) -> io::Result<()> {
+        let direct = self.direct;
+        self.set_direct(false)?;
+        if let Some(f) = f {
+            self.known_size = f.metadata()?.len();
+            self.f = f;
+        }
+        self.set_direct(direct)
+    }
+
+    pub fn set_fadvise(&mut self, fadvise: bool) {
+        self.fadvise = fadvise;
+    }
+
+    // Hint the expected access pattern of (offset, length), or of the rest of
+    // the file when length is 0, if hints are enabled.  Hints are advisory, so
+    // failures are ignored.
+    pub fn advise(&self, advice: Advice, offset: u64, length: u64) {
+        if self.fadvise {
+            let _ = sys::fadvise(&self.f, offset, length, advice);
+        }
+    }
+
+    // whether the file is now shorter than it is known to have been, as
+    // when truncated by another process
+    pub fn truncated(&self) -> io::Result<bool> {
+        Ok(self.f.metadata()?.len() < self.known_size)
+    }
+
+    pub fn file_size(&self) -> io::Result<u64> {
+        let size = match self.direct {
+            true => self.size,
+            false => self.f.metadata()?.len(),
+        };
+
+        match &self.pending {
+            Some((offset, data)) => Ok(size.max(offset + data.len() as u64)),
+            None => Ok(size),
+        }
+    }
+
+    #[cfg(feature = "write")]
+    pub fn set_len(&mut self, size: u64) -> io::Result<()> {
+        self.flush_pending()?;
+        self.file_set_len(size)?;
+        self.size = size;
+        self.known_size = size;
+
+        Ok(())
+    }
+
+    // Write all of buf at offset.  The error of a failed write carries a
+    // ShortWrite recording how much was written.
+    #[cfg(feature = "write")]
+    pub fn write_all_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+        self.flush_pending()?;
+        self.write_unbuffered(offset, buf)
+    }
+
+    // write_all_at(), leaving any buffered data alone
+    fn write_unbuffered(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+        let mut written = 0;
+        let result = self.seek(SeekFrom::Start(offset)).and_then(|_| {
+            while written < buf.len() {
+                match self.write(&buf[written..]) {
+                    Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                    Ok(n) => written += n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(())
+        });
+        self.known_size = self.known_size.max(offset + written as u64);
+
+        result.map_err(|error| {
+            io::Error::new(
+                error.kind(),
+                ShortWrite {
+                    offset,
+                    length: buf.len(),
+                    written,
+                    error,
+                },
+            )
+        })
+    }
+
+    // Write buf at offset, buffering it while it continues the buffered
+    // writes within their page.  The buffer is written out once its page is
+    // full, or before any other access to the file.
+    #[cfg(feature = "write")]
+    pub fn write_behind(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+        let page_end = |offset: u64| (offset / WRITE_BEHIND_PAGE + 1) * WRITE_BEHIND_PAGE;
+        let end = offset + buf.len() as u64;
+
+        match &mut self.pending {
+            Some((start, data))
+                if *start + data.len() as u64 == offset && end <= page_end(*start) =>
+            {
+                data.extend_from_slice(buf);
+            }
+            _ if end > page_end(offset) => return self.write_all_at(offset, buf),
+            _ => {
+                self.flush_pending()?;
+                self.pending = Some((offset, buf.to_vec()));
+            }
+        }
+
+        match end == page_end(offset) {
+            true => self.flush_pending(),
+            false => Ok(()),
+        }
+    }
+
+    // Write out buffered data, keeping the file position of the access that
+    // caused it.  Should the write fail, the data stays buffered for the
+    // next attempt.
+    fn flush_pending(&mut self) -> io::Result<()> {
+        let Some((offset, data)) = self.pending.take() else {
+            return Ok(());
+        };
+
+        let result = self.stream_position().and_then(|pos| {
+            self.write_unbuffered(offset, &data)?;
+            self.seek(SeekFrom::Start(pos)).map(|_| ())
+        });
+        if result.is_err() {
+            self.pending = Some((offset, data));
+            self.pending_failed = true;
+        }
+
+        result
+    }
+
+    // whether writing out buffered data has failed since last asked
+    #[cfg(feature = "write")]
+    pub fn take_write_failure(&mut self) -> bool {
+        std::mem::take(&mut self.pending_failed)
+    }
+
+    #[cfg(feature = "write")]
+    pub fn sync_data(&mut self) -> io::Result<()> {
+        self.flush_pending()?;
+        self.f.sync_data()?;
+        self.synced()
+    }
+
+    #[cfg(feature = "write")]
+    pub fn sync_all(&mut self) -> io::Result<()> {
+        self.flush_pending()?;
+        self.f.sync_all()?;
+        self.synced()
+    }
+
+    // Fill each buffer from its file offset.  With the io-uring feature the
+    // reads are submitted together; otherwise, and in direct mode, they are
+    // made one by one.
+    pub fn read_many(&mut self, reads: &mut [(u64, &mut [u8])]) -> io::Result<()> {
+        self.flush_pending()?;
+
+        if self.direct {
+            return reads.iter_mut().try_for_each(|(offset, buf)| {
+                self.seek(SeekFrom::Start(*offset))?;
+                self.read_exact(buf)
+            });
+        }
+
+        self.bytes_read += reads.iter().map(|(_, buf)| buf.len() as u64).sum::<u64>();
+
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        if let Some(ring) = self.ring.get_or_insert_with(|| Ring::new().ok()) {
+            return ring.read_all(&self.f, reads);
+        }
+
+        reads
+            .iter_mut()
+            .try_for_each(|(offset, buf)| self.f.read_exact_at(buf, *offset))
+    }
+
+    // Read whole aligned blocks at offset into buf.  Reading stops early only
+    // at end of file, leaving the rest of buf zeroed.
+    fn read_blocks(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        let mut done = 0;
+        while done < buf.len() {
+            match self.f.read_at(&mut buf[done..], offset + done as u64) {
+                Ok(0) => break,
+                Ok(n) => done += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+
+            // a partial block can only be the end of the file
+            if !(done as u64).is_multiple_of(DIRECT_ALIGN) {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_blocks(&self, buf: &[u8], offset: u64) -> io::Result<()> {
+        let mut done = 0;
+        while done < buf.len() {
+            match self.file_write_at(&buf[done..], offset + done as u64) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => done += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Read for Storage {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.flush_pending()?;
+
+        if !self.direct {
+            let n = self.f.read_at(buf, self.pos)?;
+            self.pos += n as u64;
+            self.bytes_read += n as u64;
+            return Ok(n);
+        }
+
+        if buf.is_empty() || self.pos >= self.size {
+            return Ok(0);
+        }
+
+        let length = buf.len().min((self.size - self.pos) as usize);
+        let start = align_down(self.pos);
+        let end = align_up(self.pos + length as u64);
+
+        let mut backing = Vec::new();
+        let blocks = aligned_buffer(&mut backing, (end - start) as usize);
+        self.read_blocks(blocks, start)?;
+
+        let skip = (self.pos - start) as usize;
+        buf[..length].copy_from_slice(&blocks[skip..skip + length]);
+        self.pos += length as u64;
+        self.bytes_read += blocks.len() as u64;
+
+        Ok(length)
+    }
+}
+
+impl Write for Storage {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.flush_pending()?;
+
+        if !self.direct {
+            let n = self.file_write_at(buf, self.pos)?;
+            self.pos += n as u64;
+            self.bytes_written += n as u64;
+            return Ok(n);
+        }
+
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let start = align_down(self.pos);
+        let end = align_up(self.pos + buf.len() as u64);
+
+        let mut backing = Vec::new();
+        let blocks = aligned_buffer(&mut backing, (end - start) as usize);
+
+        // preserve the existing contents of partially overwritten blocks
+        if start < self.size {
+            self.read_blocks(blocks, start)?;
+        }
+
+        let skip = (self.pos - start) as usize;
+        blocks[skip..skip + buf.len()].copy_from_slice(buf);
+        self.write_blocks(blocks, start)?;
+        self.bytes_written += blocks.len() as u64;
+
+        self.pos += buf.len() as u64;
+
+        // whole-block writes may have run past the intended end of file
+        if self.pos > self.size {
+            self.size = self.pos;
+        }
+        if end > self.size {
+            self.file_set_len(self.size)?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_pending()?;
+        self.f.flush()
+    }
+}
+
+impl Drop for Storage {
+    // Databases write out buffered data when synced; this only covers
+    // handles dropped without syncing, which can't report errors.
+    fn drop(&mut self) {
+        let _ = self.flush_pending();
+    }
+}
+
+impl Seek for Storage {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let size = match self.direct {
+            true => self.size,
+            false => self.f.metadata()?.len(),
+        };
+
+        let new_pos = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => size.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        };
+
+        match new_pos {
+            Some(offset) => {
+                self.pos = offset;
+                Ok(offset)
+            }
+            None => Err(io::ErrorKind::InvalidInput.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[cfg(feature = "write")]
+    use crate::Error;
+
+    #[cfg(feature = "write")]
+    fn read_ofs(storage: &mut Storage, offset: u64, length: usize) -> Vec<u8> {
+        let mut data = vec![0; length];
+        storage.seek(SeekFrom::Start(offset)).unwrap();
+        storage.read_exact(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    #[cfg(feature = "write")]
+    fn short_write() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut storage = Storage::new(File::open(file.path()).unwrap());
+
+        let e = storage.write_all_at(10, b"abc").unwrap_err();
+        assert!(matches!(
+            Error::from(e),
+            Error::ShortWrite {
+                offset: 10,
+                length: 3,
+                written: 0,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "write")]
+    fn write_behind() {
+        let file = tempfile::tempfile().unwrap();
+        let mut storage = Storage::new(file.try_clone().unwrap());
+
+        // contiguous writes within a page are held back, but visible
+        storage.write_behind(4000, b"key").unwrap();
+        storage.write_behind(4003, b"value").unwrap();
+        assert_eq!(file.metadata().unwrap().len(), 0);
+        assert_eq!(storage.file_size().unwrap(), 4008);
+        assert_eq!(storage.bytes_written(), 0);
+        assert_eq!(read_ofs(&mut storage, 4000, 8), b"keyvalue");
+
+        // filling the page writes it out at once
+        storage.write_behind(4008, &[1; 88]).unwrap();
+        storage.write_behind(4096, &[2; 4]).unwrap();
+        assert_eq!(file.metadata().unwrap().len(), 4096);
+        assert_eq!(storage.bytes_written(), 96);
+
+        // as does dropping
+        drop(storage);
+        assert_eq!(file.metadata().unwrap().len(), 4100);
+    }
+
+    #[test]
+    fn read_many() {
+        let mut file = tempfile::tempfile().unwrap();
+        let data = (0..100000).map(|n| n as u8).collect::<Vec<_>>();
+        file.write_all(&data).unwrap();
+
+        // more reads than one batch, in no particular order
+        let extents = (0..200)
+            .map(|n| ((n * 7919) % 99000, 1 + n % 1000))
+            .collect::<Vec<_>>();
+        let mut buffers = extents
+            .iter()
+            .map(|(_, length)| vec![0; *length])
+            .collect::<Vec<_>>();
+
+        let mut storage = Storage::new(file);
+        let mut reads = extents
+            .iter()
+            .zip(buffers.iter_mut())
+            .map(|((offset, _), buf)| (*offset as u64, buf.as_mut_slice()))
+            .collect::<Vec<_>>();
+        storage.read_many(&mut reads).unwrap();
+
+        extents
+            .iter()
+            .zip(buffers)
+            .for_each(|((offset, length), buf)| assert_eq!(buf, data[*offset..offset + length]));
+
+        // reading past end of file fails
+        let mut buf = [0; 10];
+        assert!(storage.read_many(&mut [(99995, &mut buf[..])]).is_err());
+    }
+
+    #[cfg(all(target_os = "linux", feature = "linux-fs"))]
+    #[test]
+    fn direct_unaligned() {
+        let file = tempfile::tempfile().unwrap();
+        let mut storage = Storage::new(file.try_clone().unwrap());
+        storage.set_direct(true).unwrap();
+
+        // writes straddling block boundaries, including past end of file
+        let mut expected = vec![0u8; 10000];
+        [(0, 10), (4090, 20), (9000, 1000), (100, 5000)]
+            .into_iter()
+            .enumerate()
+            .for_each(|(i, (offset, length))| {
+                let data = vec![i as u8 + 1; length];
+                expected[offset..offset + length].copy_from_slice(&data);
+                storage.seek(SeekFrom::Start(offset as u64)).unwrap();
+                storage.write_all(&data).unwrap();
+            });
+
+        assert_eq!(storage.file_size().unwrap(), 10000);
+        assert_eq!(file.metadata().unwrap().len(), 10000);
+
+        let mut data = vec![0u8; 1234];
+        storage.seek(SeekFrom::Start(4000)).unwrap();
+        storage.read_exact(&mut data).unwrap();
+        assert_eq!(data, expected[4000..5234]);
+
+        // reads stop at end of file
+        storage.seek(SeekFrom::Start(9990)).unwrap();
+        assert!(storage.read_exact(&mut data).is_err());
+
+        // buffered access sees the same contents
+        storage.set_direct(false).unwrap();
+        let mut data = Vec::new();
+        storage.seek(SeekFrom::Start(0)).unwrap();
+        storage.read_to_end(&mut data).unwrap();
+        assert_eq!(data, expected);
+    }
+}
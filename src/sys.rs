@@ -0,0 +1,123 @@
+//
+// sys.rs -- platform-specific file space management and access hints
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+use std::fs::File;
+#[cfg(feature = "write")]
+use std::fs::Metadata;
+use std::io;
+#[cfg(feature = "write")]
+use std::path::Path;
+
+// Release the storage behind (offset, length) without changing the file
+// size.  Filesystems without hole support are silently left alone.
+#[cfg(feature = "write")]
+#[cfg(all(target_os = "linux", feature = "linux-fs"))]
+pub fn punch_hole(f: &File, offset: u64, length: u64) -> io::Result<()> {
+    use rustix::fs::{fallocate, FallocateFlags};
+
+    match fallocate(
+        f,
+        FallocateFlags::PUNCH_HOLE | FallocateFlags::KEEP_SIZE,
+        offset,
+        length,
+    ) {
+        Err(e) if e == rustix::io::Errno::OPNOTSUPP => Ok(()),
+        result => result.map_err(io::Error::from),
+    }
+}
+
+#[cfg(feature = "write")]
+#[cfg(not(all(target_os = "linux", feature = "linux-fs")))]
+pub fn punch_hole(_f: &File, _offset: u64, _length: u64) -> io::Result<()> {
+    Ok(())
+}
+
+// Reserve storage for (offset, length) without changing the file size, so
+// running out of space is reported here rather than by a later write.
+// Filesystems without preallocation support are silently left alone.
+#[cfg(feature = "write")]
+#[cfg(all(target_os = "linux", feature = "linux-fs"))]
+pub fn preallocate(f: &File, offset: u64, length: u64) -> io::Result<()> {
+    use rustix::fs::{fallocate, FallocateFlags};
+
+    match fallocate(f, FallocateFlags::KEEP_SIZE, offset, length) {
+        Err(e) if e == rustix::io::Errno::OPNOTSUPP => Ok(()),
+        result => result.map_err(io::Error::from),
+    }
+}
+
+#[cfg(feature = "write")]
+#[cfg(not(all(target_os = "linux", feature = "linux-fs")))]
+pub fn preallocate(_f: &File, _offset: u64, _length: u64) -> io::Result<()> {
+    Ok(())
+}
+
+// Give f the owner and group of the file metadata was read from, if it
+// hasn't them already.
+#[cfg(feature = "write")]
+#[cfg(unix)]
+pub fn copy_owner(f: &File, metadata: &Metadata) -> io::Result<()> {
+    use std::os::unix::fs::{fchown, MetadataExt};
+
+    let own = f.metadata()?;
+    match (own.uid(), own.gid()) == (metadata.uid(), metadata.gid()) {
+        true => Ok(()),
+        false => fchown(f, Some(metadata.uid()), Some(metadata.gid())),
+    }
+}
+
+#[cfg(feature = "write")]
+#[cfg(not(unix))]
+pub fn copy_owner(_f: &File, _metadata: &Metadata) -> io::Result<()> {
+    Ok(())
+}
+
+// Flush changes to the entries of the directory holding path, such as
+// files created, renamed or removed there.
+#[cfg(feature = "write")]
+pub fn sync_parent(path: &Path) -> io::Result<()> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    File::open(parent)?.sync_all()
+}
+
+// Expected access pattern for part of a file.
+#[derive(Copy, Clone, Debug)]
+pub enum Advice {
+    Normal,
+    Sequential,
+    WillNeed,
+    DontNeed,
+}
+
+// Pass an access pattern hint for (offset, length) to the OS page cache.  A
+// length of 0 extends to the end of the file.
+#[cfg(all(target_os = "linux", feature = "linux-fs"))]
+pub fn fadvise(f: &File, offset: u64, length: u64, advice: Advice) -> io::Result<()> {
+    use rustix::fs::Advice as Fadvice;
+
+    let advice = match advice {
+        Advice::Normal => Fadvice::Normal,
+        Advice::Sequential => Fadvice::Sequential,
+        Advice::WillNeed => Fadvice::WillNeed,
+        Advice::DontNeed => Fadvice::DontNeed,
+    };
+
+    rustix::fs::fadvise(f, offset, std::num::NonZeroU64::new(length), advice)
+        .map_err(io::Error::from)
+}
+
+#[cfg(not(all(target_os = "linux", feature = "linux-fs")))]
+pub fn fadvise(_f: &File, _offset: u64, _length: u64, _advice: Advice) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,204 @@
+//
+// uring.rs -- batched reads with io_uring (Linux, io-uring feature)
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+use std::ffi::c_void;
+use std::fs::File;
+use std::io;
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::os::unix::fs::FileExt;
+use std::ptr::null_mut;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use rustix::io_uring::{
+    io_uring_cqe, io_uring_enter, io_uring_params, io_uring_ptr, io_uring_setup, io_uring_sqe,
+    io_uring_user_data, IoringEnterFlags, IoringOp, IORING_OFF_CQ_RING, IORING_OFF_SQES,
+    IORING_OFF_SQ_RING,
+};
+use rustix::mm::{mmap, munmap, MapFlags, ProtFlags};
+
+const ENTRIES: u32 = 64;
+
+// A mapped region of the ring.
+struct Mapping {
+    ptr: *mut c_void,
+    len: usize,
+}
+
+impl Mapping {
+    fn new(fd: &OwnedFd, len: usize, offset: u64) -> io::Result<Self> {
+        // SAFETY: a fresh shared mapping of the ring, unmapped on drop
+        let ptr = unsafe {
+            mmap(
+                null_mut(),
+                len,
+                ProtFlags::READ | ProtFlags::WRITE,
+                MapFlags::SHARED | MapFlags::POPULATE,
+                fd,
+                offset,
+            )?
+        };
+
+        Ok(Mapping { ptr, len })
+    }
+
+    // SAFETY: offset must be a field of type T within the mapping
+    unsafe fn at<T>(&self, offset: u32) -> *mut T {
+        self.ptr.byte_add(offset as usize).cast()
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        // SAFETY: the region was mapped by Mapping::new
+        let _ = unsafe { munmap(self.ptr, self.len) };
+    }
+}
+
+// An io_uring instance for submitting reads of one file in batches.  The
+// submission queue is filled and drained within read_all, so entries are
+// never shared with the kernel across calls.
+pub struct Ring {
+    sq: Mapping,
+    cq: Mapping,
+    sqes: Mapping,
+    params: io_uring_params,
+    // must outlive the mappings
+    fd: OwnedFd,
+}
+
+// SAFETY: the ring is only used through &mut self
+unsafe impl Send for Ring {}
+unsafe impl Sync for Ring {}
+
+impl Ring {
+    pub fn new() -> io::Result<Self> {
+        let mut params = io_uring_params::default();
+        // SAFETY: params is a valid, zeroed parameter block
+        let fd = unsafe { io_uring_setup(ENTRIES, &mut params)? };
+
+        let sq_len = params.sq_off.array as usize + params.sq_entries as usize * 4;
+        let cq_len = params.cq_off.cqes as usize
+            + params.cq_entries as usize * std::mem::size_of::<io_uring_cqe>();
+        let sqes_len = params.sq_entries as usize * std::mem::size_of::<io_uring_sqe>();
+
+        Ok(Ring {
+            sq: Mapping::new(&fd, sq_len, IORING_OFF_SQ_RING)?,
+            cq: Mapping::new(&fd, cq_len, IORING_OFF_CQ_RING)?,
+            sqes: Mapping::new(&fd, sqes_len, IORING_OFF_SQES)?,
+            params,
+            fd,
+        })
+    }
+
+    // Read each buffer in full from its file offset, ENTRIES reads at a time.
+    pub fn read_all(&mut self, f: &File, reads: &mut [(u64, &mut [u8])]) -> io::Result<()> {
+        let entries = self.params.sq_entries as usize;
+        reads.chunks_mut(entries).try_for_each(|batch| {
+            self.submit(f, batch)?;
+            self.complete(f, batch)
+        })
+    }
+
+    fn submit(&mut self, f: &File, batch: &mut [(u64, &mut [u8])]) -> io::Result<()> {
+        let sq_off = self.params.sq_off;
+
+        // SAFETY: the offsets come from io_uring_setup, and the queue is
+        // empty, so every entry is ours until the kernel consumes them
+        unsafe {
+            let tail = &*self.sq.at::<AtomicU32>(sq_off.tail);
+            let mask = *self.sq.at::<u32>(sq_off.ring_mask);
+            let array = self.sq.at::<u32>(sq_off.array);
+            let sqes = self.sqes.at::<io_uring_sqe>(0);
+
+            let mut next = tail.load(Ordering::Relaxed);
+            batch.iter_mut().enumerate().for_each(|(i, (offset, buf))| {
+                let index = next & mask;
+                let mut sqe = io_uring_sqe {
+                    opcode: IoringOp::Read,
+                    fd: f.as_raw_fd(),
+                    user_data: io_uring_user_data::from_u64(i as u64),
+                    ..Default::default()
+                };
+                sqe.off_or_addr2.off = *offset;
+                sqe.addr_or_splice_off_in.addr = io_uring_ptr::new(buf.as_mut_ptr().cast());
+                sqe.len.len = buf.len() as u32;
+
+                sqes.add(index as usize).write(sqe);
+                array.add(index as usize).write(index);
+                next = next.wrapping_add(1);
+            });
+            tail.store(next, Ordering::Release);
+        }
+
+        let mut submitted = 0;
+        while submitted < batch.len() as u32 {
+            // SAFETY: the submitted buffers outlive the batch
+            match unsafe {
+                io_uring_enter(
+                    &self.fd,
+                    batch.len() as u32 - submitted,
+                    0,
+                    IoringEnterFlags::empty(),
+                )
+            } {
+                Ok(n) => submitted += n,
+                Err(rustix::io::Errno::INTR) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn complete(&mut self, f: &File, batch: &mut [(u64, &mut [u8])]) -> io::Result<()> {
+        let cq_off = self.params.cq_off;
+        let mut result = Ok(());
+        let mut done = 0;
+
+        while done < batch.len() {
+            // SAFETY: the offsets come from io_uring_setup; entries between
+            // head and tail are written by the kernel before tail advances
+            let reaped = unsafe {
+                let head = &*self.cq.at::<AtomicU32>(cq_off.head);
+                let tail = (*self.cq.at::<AtomicU32>(cq_off.tail)).load(Ordering::Acquire);
+                let mask = *self.cq.at::<u32>(cq_off.ring_mask);
+                let cqes = self.cq.at::<io_uring_cqe>(cq_off.cqes);
+
+                let start = head.load(Ordering::Relaxed);
+                let mut next = start;
+                while next != tail {
+                    let cqe = &*cqes.add((next & mask) as usize);
+                    let (offset, buf) = &mut batch[cqe.user_data.u64_() as usize];
+                    let read = match cqe.res {
+                        res if res < 0 => Err(io::Error::from_raw_os_error(-res)),
+                        // finish short reads synchronously
+                        res => f.read_exact_at(&mut buf[res as usize..], *offset + res as u64),
+                    };
+                    result = result.and(read);
+                    next = next.wrapping_add(1);
+                    done += 1;
+                }
+                head.store(next, Ordering::Release);
+
+                next != start
+            };
+
+            if !reaped && done < batch.len() {
+                // SAFETY: waits for completions only
+                match unsafe { io_uring_enter(&self.fd, 0, 1, IoringEnterFlags::GETEVENTS) } {
+                    Ok(_) | Err(rustix::io::Errno::INTR) => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+
+        result
+    }
+}
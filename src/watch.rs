@@ -0,0 +1,211 @@
+//
+// watch.rs -- notice changes to a database file on disk
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Notices changes to a database file, such as writes by another process or
+/// its replacement by rename, so a reader can take them up with
+/// [`refresh`](crate::Gdbm::refresh).
+///
+/// On Linux the watcher uses inotify on the directory holding the file, and
+/// its file descriptor can be registered with an event loop: it becomes
+/// readable on a change.  Elsewhere the file's size, modification time and
+/// inode are polled.
+pub struct Watcher {
+    #[cfg(target_os = "linux")]
+    inotify: std::os::fd::OwnedFd,
+    #[cfg(target_os = "linux")]
+    name: std::ffi::OsString,
+
+    #[cfg(not(target_os = "linux"))]
+    path: std::path::PathBuf,
+    #[cfg(not(target_os = "linux"))]
+    version: Option<Version>,
+}
+
+// interval between checks of the file when polling
+#[cfg(not(target_os = "linux"))]
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// what is polled to notice changes: the file's size, modification time and
+// inode, or None if it doesn't exist
+#[cfg(not(target_os = "linux"))]
+type Version = (u64, Option<std::time::SystemTime>, u64);
+
+#[cfg(not(target_os = "linux"))]
+fn version(path: &Path) -> Option<Version> {
+    #[cfg(unix)]
+    use std::os::unix::fs::MetadataExt;
+
+    std::fs::metadata(path).ok().map(|metadata| {
+        #[cfg(unix)]
+        let inode = metadata.ino();
+        #[cfg(not(unix))]
+        let inode = 0;
+
+        (metadata.len(), metadata.modified().ok(), inode)
+    })
+}
+
+impl Watcher {
+    #[cfg(target_os = "linux")]
+    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Watcher> {
+        use rustix::fs::inotify::{self, CreateFlags, WatchFlags};
+
+        let path = path.as_ref();
+        let name = path
+            .file_name()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?
+            .to_os_string();
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+
+        let inotify = inotify::init(CreateFlags::CLOEXEC | CreateFlags::NONBLOCK)?;
+        inotify::add_watch(
+            &inotify,
+            parent,
+            WatchFlags::MODIFY
+                | WatchFlags::CLOSE_WRITE
+                | WatchFlags::CREATE
+                | WatchFlags::MOVED_TO,
+        )?;
+
+        Ok(Watcher { inotify, name })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Watcher> {
+        let path = path.as_ref().to_path_buf();
+        let version = version(&path);
+
+        Ok(Watcher { path, version })
+    }
+
+    /// Whether the file changed since the watcher was created or last
+    /// reported a change, without blocking.
+    #[cfg(target_os = "linux")]
+    pub fn changed(&mut self) -> io::Result<bool> {
+        use rustix::fs::inotify::{ReadFlags, Reader};
+        use rustix::io::Errno;
+        use std::mem::MaybeUninit;
+        use std::os::unix::ffi::OsStrExt;
+
+        let mut buffer = [MaybeUninit::uninit(); 4096];
+        let mut reader = Reader::new(&self.inotify, &mut buffer);
+        let mut changed = false;
+        loop {
+            match reader.next() {
+                Ok(event) => {
+                    changed |= event.events().contains(ReadFlags::QUEUE_OVERFLOW)
+                        || event
+                            .file_name()
+                            .is_some_and(|name| name.to_bytes() == self.name.as_bytes());
+                }
+                Err(Errno::AGAIN) => return Ok(changed),
+                Err(Errno::INTR) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn changed(&mut self) -> io::Result<bool> {
+        let version = version(&self.path);
+        let changed = version != self.version;
+        self.version = version;
+
+        Ok(changed)
+    }
+
+    /// Wait up to timeout, or indefinitely if None, for the file to change.
+    /// Returns whether it changed.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> io::Result<bool> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            if self.changed()? {
+                return Ok(true);
+            }
+
+            let remaining =
+                deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            if remaining == Some(Duration::ZERO) {
+                return Ok(false);
+            }
+
+            self.sleep(remaining)?;
+        }
+    }
+
+    // block until there may be a change, or for at most timeout
+    #[cfg(target_os = "linux")]
+    fn sleep(&self, timeout: Option<Duration>) -> io::Result<()> {
+        use rustix::event::{poll, PollFd, PollFlags, Timespec};
+
+        let timeout = timeout
+            .map(Timespec::try_from)
+            .transpose()
+            .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+        match poll(
+            &mut [PollFd::new(&self.inotify, PollFlags::IN)],
+            timeout.as_ref(),
+        ) {
+            Err(rustix::io::Errno::INTR) => Ok(()),
+            result => result.map(|_| ()).map_err(io::Error::from),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn sleep(&self, timeout: Option<Duration>) -> io::Result<()> {
+        std::thread::sleep(timeout.map_or(POLL_INTERVAL, |timeout| timeout.min(POLL_INTERVAL)));
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl std::os::fd::AsFd for Watcher {
+    fn as_fd(&self) -> std::os::fd::BorrowedFd<'_> {
+        self.inotify.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn watch_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db");
+        std::fs::write(&path, b"one").unwrap();
+
+        let mut watcher = Watcher::new(&path).unwrap();
+        assert!(!watcher.changed().unwrap());
+        assert!(!watcher.wait(Some(Duration::from_millis(10))).unwrap());
+
+        // other files in the directory are ignored
+        std::fs::write(dir.path().join("other"), b"other").unwrap();
+        assert!(!watcher.wait(Some(Duration::from_millis(10))).unwrap());
+
+        std::fs::write(&path, b"two, longer").unwrap();
+        assert!(watcher.wait(Some(Duration::from_secs(5))).unwrap());
+        assert!(!watcher.changed().unwrap());
+
+        // replaced by rename
+        let new_path = dir.path().join("db.new");
+        std::fs::write(&new_path, b"three").unwrap();
+        let _ = watcher.changed().unwrap();
+        std::fs::rename(&new_path, &path).unwrap();
+        assert!(watcher.wait(Some(Duration::from_secs(5))).unwrap());
+    }
+}
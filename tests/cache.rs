@@ -0,0 +1,210 @@
+//
+// tests/cache.rs -- testing capacity-bounded cache databases
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+extern crate gdbm_native;
+
+use gdbm_native::{BoundedCache, CacheLimits, OpenOptions};
+use tempfile::NamedTempFile;
+
+fn open_cache(file: &NamedTempFile, limits: CacheLimits) -> BoundedCache {
+    OpenOptions::new()
+        .write()
+        .create()
+        .open(file.path())
+        .and_then(|db| BoundedCache::new(db, limits))
+        .unwrap()
+}
+
+#[test]
+fn cache_max_records() {
+    let file = NamedTempFile::new().unwrap();
+    let mut cache = open_cache(
+        &file,
+        CacheLimits {
+            max_records: Some(3),
+            ..Default::default()
+        },
+    );
+
+    ["a", "b", "c"].into_iter().for_each(|key| {
+        assert!(cache
+            .insert(key.to_string(), "value".to_string())
+            .unwrap()
+            .is_empty());
+    });
+
+    // "a" is used, so "b" is the least recently used
+    assert_eq!(
+        cache.get::<_, String>("a").unwrap(),
+        Some("value".to_string())
+    );
+    let evicted = cache.insert("d".to_string(), "value".to_string()).unwrap();
+    assert_eq!(evicted, vec![b"b".to_vec()]);
+    assert_eq!(cache.len(), 3);
+    assert!(!cache.contains_key("b"));
+    assert_eq!(cache.get::<_, String>("b").unwrap(), None);
+
+    // recency survives reopening
+    cache.into_inner().unwrap().close().unwrap();
+    let mut cache = open_cache(
+        &file,
+        CacheLimits {
+            max_records: Some(3),
+            ..Default::default()
+        },
+    );
+    let evicted = cache.insert("e".to_string(), "value".to_string()).unwrap();
+    assert_eq!(evicted, vec![b"c".to_vec()]);
+}
+
+#[test]
+fn cache_max_bytes() {
+    let file = NamedTempFile::new().unwrap();
+    let mut cache = open_cache(
+        &file,
+        CacheLimits {
+            max_bytes: Some(1000),
+            ..Default::default()
+        },
+    );
+
+    (0..100).for_each(|n| {
+        cache.insert(n, vec![0u8; 92]).unwrap();
+        assert!(cache.bytes() <= 1000);
+    });
+
+    // each record is 100 bytes
+    assert_eq!(cache.len(), 10);
+    assert_eq!(cache.bytes(), 1000);
+    (90..100).for_each(|n| assert!(cache.contains_key(&n)));
+
+    // tightening the limits on reopen evicts the oldest
+    cache.into_inner().unwrap().close().unwrap();
+    let cache = open_cache(
+        &file,
+        CacheLimits {
+            max_bytes: Some(500),
+            ..Default::default()
+        },
+    );
+    assert_eq!(cache.len(), 5);
+    (95..100).for_each(|n| assert!(cache.contains_key(&n)));
+}
+
+#[test]
+fn cache_remove() {
+    let file = NamedTempFile::new().unwrap();
+    let mut cache = open_cache(&file, CacheLimits::default());
+
+    cache
+        .insert("key".to_string(), "value".to_string())
+        .unwrap();
+    assert_eq!(cache.bytes(), 8);
+    assert_eq!(cache.remove("key").unwrap(), Some(b"value".to_vec()));
+    assert_eq!(cache.remove("key").unwrap(), None);
+    assert!(cache.is_empty());
+    assert_eq!(cache.bytes(), 0);
+}
+
+#[test]
+fn cache_sync_retry() {
+    use gdbm_native::Error;
+
+    let file = NamedTempFile::new().unwrap();
+    let mut cache = OpenOptions::new()
+        .write()
+        .create()
+        .max_file_size(Some(64 * 1024))
+        .open(file.path())
+        .and_then(|db| BoundedCache::new(db, CacheLimits::default()))
+        .unwrap();
+
+    let stored = (0..)
+        .find(|n| {
+            cache
+                .insert(format!("key {}", n), vec![0x5au8; 1000])
+                .is_err_and(|e| matches!(e, Error::DatabaseFull { .. }))
+        })
+        .unwrap();
+
+    // with no room to rewrite it, "key 0" stays touched
+    assert!(cache.get::<_, Vec<u8>>("key 0").unwrap().is_some());
+    assert!(matches!(cache.sync(), Err(Error::DatabaseFull { .. })));
+
+    // and is rewritten once there is
+    cache.remove("key 1").unwrap();
+    cache.sync().unwrap();
+    cache.into_inner().unwrap().close().unwrap();
+
+    let mut cache = open_cache(
+        &file,
+        CacheLimits {
+            max_records: Some(stored - 1),
+            ..Default::default()
+        },
+    );
+    let evicted = cache
+        .insert("key".to_string(), "value".to_string())
+        .unwrap();
+    assert_eq!(evicted, vec![b"key 2".to_vec()]);
+}
+
+#[test]
+fn cache_evict_retry() {
+    use gdbm_native::{Codec, Error, Result};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    // fails to read values back once broken
+    struct Breakable(Arc<AtomicBool>);
+
+    impl Codec for Breakable {
+        fn encode(&self, value: Vec<u8>) -> Result<Vec<u8>> {
+            Ok(value)
+        }
+
+        fn decode(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+            match self.0.load(Ordering::Relaxed) {
+                true => Err(Error::Io(std::io::ErrorKind::Other.into())),
+                false => Ok(data),
+            }
+        }
+    }
+
+    let file = NamedTempFile::new().unwrap();
+    let broken = Arc::new(AtomicBool::new(false));
+    let mut cache = OpenOptions::new()
+        .codec(Box::new(Breakable(broken.clone())))
+        .write()
+        .create()
+        .open(file.path())
+        .and_then(|db| {
+            BoundedCache::new(
+                db,
+                CacheLimits {
+                    max_records: Some(2),
+                    ..Default::default()
+                },
+            )
+        })
+        .unwrap();
+
+    cache.insert("a".to_string(), "value".to_string()).unwrap();
+    cache.insert("b".to_string(), "value".to_string()).unwrap();
+
+    // "a" can't be evicted yet, but is evicted first once it can
+    broken.store(true, Ordering::Relaxed);
+    assert!(cache.insert("c".to_string(), "value".to_string()).is_err());
+    broken.store(false, Ordering::Relaxed);
+    let evicted = cache.insert("d".to_string(), "value".to_string()).unwrap();
+    assert_eq!(evicted, vec![b"a".to_vec(), b"b".to_vec()]);
+    assert_eq!(cache.len(), 2);
+    assert!(!cache.contains_key("a"));
+}
@@ -0,0 +1,436 @@
+//
+// tests/compat.rs -- GNU GDBM byte-compatibility tests
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+// Databases written by this crate are compared byte-for-byte against
+// checked-in golden files, one per on-disk flavor.  Set GDBM_NATIVE_BLESS=1
+// to regenerate the golden files after an intentional format change.
+//
+// With the "gnu-compat" feature, databases are additionally round-tripped
+// through the system libgdbm (loaded at runtime) for the host's native flavor.
+
+extern crate gdbm_native;
+
+use std::path::PathBuf;
+
+use gdbm_native::{Alignment, BlockSize, Endian, Offset, OpenOptions};
+use tempfile::NamedTempFile;
+
+const RECORD_COUNT: usize = 64;
+
+#[derive(Copy, Clone, Debug)]
+struct Flavor {
+    endian: Endian,
+    offset: Offset,
+    alignment: Alignment,
+    numsync: bool,
+}
+
+impl Flavor {
+    fn all() -> Vec<Flavor> {
+        [Endian::Little, Endian::Big]
+            .into_iter()
+            .flat_map(|endian| {
+                [Offset::Small, Offset::LFS]
+                    .into_iter()
+                    .flat_map(move |offset| {
+                        [Alignment::Align32, Alignment::Align64]
+                            .into_iter()
+                            .flat_map(move |alignment| {
+                                [false, true].into_iter().map(move |numsync| Flavor {
+                                    endian,
+                                    offset,
+                                    alignment,
+                                    numsync,
+                                })
+                            })
+                    })
+            })
+            .collect()
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "{}{}-align{}{}",
+            match self.endian {
+                Endian::Little => "le",
+                Endian::Big => "be",
+            },
+            match self.offset {
+                Offset::Small => 32,
+                Offset::LFS => 64,
+            },
+            match self.alignment {
+                Alignment::Align32 => 32,
+                Alignment::Align64 => 64,
+            },
+            if self.numsync { "-numsync" } else { "" }
+        )
+    }
+
+    fn golden_path(&self) -> PathBuf {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("src/data/golden");
+        path.push(format!("{}.db", self.name()));
+        path
+    }
+}
+
+fn records() -> impl Iterator<Item = (String, String)> {
+    (0..RECORD_COUNT).map(|n| (format!("key {}", n), format!("value {}", n)))
+}
+
+// Keys removed after loading, to exercise the avail lists.
+fn is_removed(n: usize) -> bool {
+    n % 7 == 3
+}
+
+// Write the reference data set using a fixed block size, so the resulting
+// file is fully deterministic.
+fn write_db(flavor: &Flavor, path: &std::path::Path) -> Result<(), String> {
+    OpenOptions::new()
+        .alignment(Some(flavor.alignment))
+        .write()
+        .create()
+        .newdb(true)
+        .endian(Some(flavor.endian))
+        .offset(Some(flavor.offset))
+        .numsync(flavor.numsync)
+        .block_size(BlockSize::Exactly(512))
+        .open(path)
+        .and_then(|mut db| {
+            records().try_for_each(|(key, value)| db.insert(key, value).map(|_| ()))?;
+            (0..RECORD_COUNT)
+                .filter(|n| is_removed(*n))
+                .try_for_each(|n| db.remove(&format!("key {}", n)).map(|_| ()))?;
+            db.sync()
+        })
+        .map_err(|e| format!("{}: writing: {}", flavor.name(), e))
+}
+
+// Check path contains exactly the reference data set.
+fn check_db(flavor: &Flavor, path: &std::path::Path) -> Result<(), String> {
+    OpenOptions::new()
+        .alignment(Some(flavor.alignment))
+        .open(path)
+        .map_err(|e| e.to_string())
+        .and_then(|mut db| {
+            (db.magic().is_numsync() == flavor.numsync)
+                .then_some(())
+                .ok_or_else(|| format!("unexpected magic {}", db.magic()))?;
+
+            records().enumerate().try_for_each(|(n, (key, value))| {
+                let expected = (!is_removed(n)).then_some(value);
+                db.get::<_, String>(&key)
+                    .map_err(|e| e.to_string())
+                    .and_then(|got| {
+                        (got == expected)
+                            .then_some(())
+                            .ok_or_else(|| format!("key {:?}: got {:?}", key, got))
+                    })
+            })?;
+
+            let expected_len = (0..RECORD_COUNT).filter(|n| !is_removed(*n)).count();
+            db.len().map_err(|e| e.to_string()).and_then(|len| {
+                (len == expected_len)
+                    .then_some(())
+                    .ok_or_else(|| format!("expected {} records, got {}", expected_len, len))
+            })
+        })
+        .map_err(|e| format!("{}: checking: {}", flavor.name(), e))
+}
+
+#[test]
+fn golden_write() {
+    let bless = std::env::var_os("GDBM_NATIVE_BLESS").is_some();
+
+    Flavor::all()
+        .iter()
+        .try_for_each(|flavor| {
+            let tempfile = NamedTempFile::new().unwrap();
+            write_db(flavor, tempfile.path())?;
+            let written = std::fs::read(tempfile.path()).unwrap();
+
+            if bless {
+                std::fs::create_dir_all(flavor.golden_path().parent().unwrap()).unwrap();
+                std::fs::write(flavor.golden_path(), &written).unwrap();
+            }
+
+            let golden = std::fs::read(flavor.golden_path())
+                .map_err(|e| format!("{}: reading golden file: {}", flavor.name(), e))?;
+
+            match written.iter().zip(golden.iter()).position(|(a, b)| a != b) {
+                None if written.len() == golden.len() => Ok(()),
+                None => Err(format!(
+                    "{}: length {} differs from golden length {}",
+                    flavor.name(),
+                    written.len(),
+                    golden.len()
+                )),
+                Some(offset) => Err(format!(
+                    "{}: differs from golden file at offset {}",
+                    flavor.name(),
+                    offset
+                )),
+            }
+        })
+        .unwrap_or_else(|e: String| panic!("{}", e));
+}
+
+#[test]
+fn golden_read() {
+    Flavor::all()
+        .iter()
+        .try_for_each(|flavor| check_db(flavor, &flavor.golden_path()))
+        .unwrap_or_else(|e| panic!("{}", e));
+}
+
+#[cfg(feature = "gnu-compat")]
+mod libgdbm {
+    use std::ffi::{c_char, c_int, c_void, CString};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    pub const GDBM_READER: c_int = 0;
+    pub const GDBM_WRITER: c_int = 1;
+    pub const GDBM_NEWDB: c_int = 3;
+    pub const GDBM_NOLOCK: c_int = 0x40;
+    pub const GDBM_NUMSYNC: c_int = 0x2000;
+    const GDBM_REPLACE: c_int = 1;
+
+    #[repr(C)]
+    struct Datum {
+        dptr: *mut c_char,
+        dsize: c_int,
+    }
+
+    type OpenFn = unsafe extern "C" fn(
+        *const c_char,
+        c_int,
+        c_int,
+        c_int,
+        Option<unsafe extern "C" fn(*const c_char)>,
+    ) -> *mut c_void;
+    type StoreFn = unsafe extern "C" fn(*mut c_void, Datum, Datum, c_int) -> c_int;
+    type FetchFn = unsafe extern "C" fn(*mut c_void, Datum) -> Datum;
+    type DeleteFn = unsafe extern "C" fn(*mut c_void, Datum) -> c_int;
+    type CountFn = unsafe extern "C" fn(*mut c_void, *mut u64) -> c_int;
+    type CloseFn = unsafe extern "C" fn(*mut c_void) -> c_int;
+
+    pub struct Lib {
+        open: OpenFn,
+        store: StoreFn,
+        fetch: FetchFn,
+        delete: DeleteFn,
+        count: CountFn,
+        close: CloseFn,
+    }
+
+    impl Lib {
+        // Load the system libgdbm, if there is one.
+        pub fn load() -> Option<Lib> {
+            [
+                "libgdbm.so",
+                "libgdbm.so.6",
+                "libgdbm.dylib",
+                "libgdbm.6.dylib",
+            ]
+            .into_iter()
+            .find_map(|name| {
+                let name = CString::new(name).unwrap();
+                let handle = unsafe { libc::dlopen(name.as_ptr(), libc::RTLD_NOW) };
+                (!handle.is_null()).then_some(handle)
+            })
+            .and_then(|handle| {
+                let sym = |name: &str| {
+                    let name = CString::new(name).unwrap();
+                    let ptr = unsafe { libc::dlsym(handle, name.as_ptr()) };
+                    (!ptr.is_null()).then_some(ptr)
+                };
+
+                unsafe {
+                    Some(Lib {
+                        open: std::mem::transmute::<*mut c_void, OpenFn>(sym("gdbm_open")?),
+                        store: std::mem::transmute::<*mut c_void, StoreFn>(sym("gdbm_store")?),
+                        fetch: std::mem::transmute::<*mut c_void, FetchFn>(sym("gdbm_fetch")?),
+                        delete: std::mem::transmute::<*mut c_void, DeleteFn>(sym("gdbm_delete")?),
+                        count: std::mem::transmute::<*mut c_void, CountFn>(sym("gdbm_count")?),
+                        close: std::mem::transmute::<*mut c_void, CloseFn>(sym("gdbm_close")?),
+                    })
+                }
+            })
+        }
+
+        pub fn open<'a>(&'a self, path: &Path, flags: c_int) -> Option<Db<'a>> {
+            let path = CString::new(path.as_os_str().as_bytes()).unwrap();
+            let dbf = unsafe { (self.open)(path.as_ptr(), 512, flags | GDBM_NOLOCK, 0o644, None) };
+            (!dbf.is_null()).then_some(Db { lib: self, dbf })
+        }
+    }
+
+    pub struct Db<'a> {
+        lib: &'a Lib,
+        dbf: *mut c_void,
+    }
+
+    fn datum(bytes: &[u8]) -> Datum {
+        Datum {
+            dptr: bytes.as_ptr() as *mut c_char,
+            dsize: bytes.len() as c_int,
+        }
+    }
+
+    impl Db<'_> {
+        pub fn store(&mut self, key: &[u8], value: &[u8]) -> bool {
+            unsafe { (self.lib.store)(self.dbf, datum(key), datum(value), GDBM_REPLACE) == 0 }
+        }
+
+        pub fn delete(&mut self, key: &[u8]) -> bool {
+            unsafe { (self.lib.delete)(self.dbf, datum(key)) == 0 }
+        }
+
+        pub fn fetch(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+            unsafe {
+                let value = (self.lib.fetch)(self.dbf, datum(key));
+                (!value.dptr.is_null()).then(|| {
+                    let bytes =
+                        std::slice::from_raw_parts(value.dptr as *const u8, value.dsize as usize)
+                            .to_vec();
+                    libc::free(value.dptr as *mut c_void);
+                    bytes
+                })
+            }
+        }
+
+        pub fn count(&mut self) -> Option<u64> {
+            let mut count = 0;
+            unsafe { ((self.lib.count)(self.dbf, &mut count) == 0).then_some(count) }
+        }
+    }
+
+    impl Drop for Db<'_> {
+        fn drop(&mut self) {
+            unsafe { (self.lib.close)(self.dbf) };
+        }
+    }
+}
+
+#[cfg(feature = "gnu-compat")]
+fn native_flavors() -> Vec<Flavor> {
+    Flavor::all()
+        .into_iter()
+        .filter(|flavor| {
+            flavor.endian
+                == if cfg!(target_endian = "little") {
+                    Endian::Little
+                } else {
+                    Endian::Big
+                }
+                && flavor.offset == Offset::LFS
+                && flavor.alignment
+                    == if cfg!(target_pointer_width = "64") {
+                        Alignment::Align64
+                    } else {
+                        Alignment::Align32
+                    }
+        })
+        .collect()
+}
+
+#[cfg(feature = "gnu-compat")]
+#[test]
+fn libgdbm_reads_ours() {
+    let Some(lib) = libgdbm::Lib::load() else {
+        println!("libgdbm not found, skipping");
+        return;
+    };
+
+    native_flavors()
+        .iter()
+        .try_for_each(|flavor| {
+            let tempfile = NamedTempFile::new().unwrap();
+            write_db(flavor, tempfile.path())?;
+
+            let mut db = lib
+                .open(tempfile.path(), libgdbm::GDBM_READER)
+                .ok_or_else(|| format!("{}: libgdbm open failed", flavor.name()))?;
+
+            records().enumerate().try_for_each(|(n, (key, value))| {
+                let expected = (!is_removed(n)).then(|| value.into_bytes());
+                (db.fetch(key.as_bytes()) == expected)
+                    .then_some(())
+                    .ok_or_else(|| format!("{}: libgdbm fetch {:?} mismatch", flavor.name(), key))
+            })?;
+
+            let expected_len = (0..RECORD_COUNT).filter(|n| !is_removed(*n)).count() as u64;
+            (db.count() == Some(expected_len))
+                .then_some(())
+                .ok_or_else(|| format!("{}: libgdbm count mismatch", flavor.name()))
+        })
+        .unwrap_or_else(|e| panic!("{}", e));
+}
+
+#[cfg(feature = "gnu-compat")]
+#[test]
+fn ours_reads_libgdbm() {
+    let Some(lib) = libgdbm::Lib::load() else {
+        println!("libgdbm not found, skipping");
+        return;
+    };
+
+    native_flavors()
+        .iter()
+        .try_for_each(|flavor| {
+            let tempfile = NamedTempFile::new().unwrap();
+            {
+                let flags = libgdbm::GDBM_NEWDB
+                    | if flavor.numsync {
+                        libgdbm::GDBM_NUMSYNC
+                    } else {
+                        0
+                    };
+                let mut db = lib
+                    .open(tempfile.path(), flags)
+                    .ok_or_else(|| format!("{}: libgdbm create failed", flavor.name()))?;
+                records().try_for_each(|(key, value)| {
+                    db.store(key.as_bytes(), value.as_bytes())
+                        .then_some(())
+                        .ok_or_else(|| format!("{}: libgdbm store failed", flavor.name()))
+                })?;
+                (0..RECORD_COUNT)
+                    .filter(|n| is_removed(*n))
+                    .try_for_each(|n| {
+                        db.delete(format!("key {}", n).as_bytes())
+                            .then_some(())
+                            .ok_or_else(|| format!("{}: libgdbm delete failed", flavor.name()))
+                    })?;
+            }
+
+            check_db(flavor, tempfile.path())?;
+
+            // modify with this crate, then read back with libgdbm
+            OpenOptions::new()
+                .alignment(Some(flavor.alignment))
+                .write()
+                .open(tempfile.path())
+                .and_then(|mut db| {
+                    db.insert("added".to_string(), "by gdbm-native".to_string())
+                        .and_then(|_| db.sync())
+                })
+                .map_err(|e| format!("{}: updating: {}", flavor.name(), e))?;
+
+            let mut db = lib
+                .open(tempfile.path(), libgdbm::GDBM_WRITER)
+                .ok_or_else(|| format!("{}: libgdbm reopen failed", flavor.name()))?;
+            (db.fetch(b"added") == Some(b"by gdbm-native".to_vec()))
+                .then_some(())
+                .ok_or_else(|| format!("{}: libgdbm missed our update", flavor.name()))
+        })
+        .unwrap_or_else(|e| panic!("{}", e));
+}
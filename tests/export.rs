@@ -72,6 +72,7 @@ fn api_export_bin() {
 }
 
 #[test]
+#[cfg(feature = "ascii-dump")]
 fn api_export_ascii() {
     let tests = init_tests();
 
@@ -118,3 +119,766 @@ fn api_export_ascii() {
             .unwrap_or_else(|e| panic!("{}", e));
     }
 }
+
+#[test]
+fn api_export_fadvise() {
+    init_tests()
+        .into_iter()
+        .try_for_each(|test| {
+            // page cache hints must not change what is exported
+            let dumps = [false, true]
+                .into_iter()
+                .map(|fadvise| {
+                    let mut dump = Vec::new();
+                    let mut dumpfile = NamedTempFile::new().unwrap();
+                    OpenOptions::new()
+                        .alignment(test.alignment)
+                        .fadvise(fadvise)
+                        .open(&test.db_path)
+                        .and_then(|mut db| {
+                            db.export_bin(dumpfile.as_file_mut(), ExportBinMode::ExpNative)
+                        })
+                        .map_err(|e| e.to_string())?;
+                    std::io::Read::read_to_end(&mut dumpfile.reopen().unwrap(), &mut dump)
+                        .map_err(|e| e.to_string())?;
+                    Ok(dump)
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+
+            (dumps[0] == dumps[1])
+                .then_some(())
+                .ok_or_else(|| format!("{}: dumps differ", test.db_path))
+        })
+        .unwrap_or_else(|e| panic!("{}", e));
+}
+
+#[test]
+#[cfg(feature = "ascii-dump")]
+fn api_import_mode() {
+    use gdbm_native::{Error, ImportOptions, InsertMode};
+
+    // dump of {a: new, b: new}
+    let dumpfile = NamedTempFile::new().unwrap();
+    let source = NamedTempFile::new().unwrap();
+    OpenOptions::new()
+        .write()
+        .create()
+        .open(source.path())
+        .and_then(|mut db| {
+            db.insert("a".to_string(), "new".to_string())?;
+            db.insert("b".to_string(), "new".to_string())?;
+            db.export_ascii(&mut dumpfile.reopen().unwrap())
+        })
+        .unwrap();
+
+    // import into {a: old}
+    let import = |mode| {
+        let target = NamedTempFile::new().unwrap();
+        let mut db = OpenOptions::new()
+            .write()
+            .create()
+            .open(target.path())
+            .unwrap();
+        db.insert("a".to_string(), "old".to_string()).unwrap();
+        let result = db.import_ascii_with(&mut dumpfile.reopen().unwrap(), &ImportOptions { mode });
+        let a = db.get::<_, String>("a").unwrap();
+        (result, a)
+    };
+
+    let (result, a) = import(InsertMode::Replace);
+    assert!(result.is_ok());
+    assert_eq!(a, Some("new".to_string()));
+
+    let (result, a) = import(InsertMode::Insert);
+    assert!(result.is_ok());
+    assert_eq!(a, Some("old".to_string()));
+
+    let (result, a) = import(InsertMode::ErrorIfExists);
+    assert!(matches!(result, Err(Error::KeyExists)));
+    assert_eq!(a, Some("old".to_string()));
+}
+
+#[test]
+#[cfg(feature = "ascii-dump")]
+fn api_import_mapped() {
+    use gdbm_native::ImportOptions;
+
+    let source = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .open(source.path())
+        .unwrap();
+    db.insert("old:a".to_string(), "1".to_string()).unwrap();
+    db.insert("old:b".to_string(), "2".to_string()).unwrap();
+    db.insert("tmp:c".to_string(), "3".to_string()).unwrap();
+
+    let ascii = NamedTempFile::new().unwrap();
+    db.export_ascii(&mut ascii.reopen().unwrap()).unwrap();
+    let binary = NamedTempFile::new().unwrap();
+    db.export_bin(&mut binary.reopen().unwrap(), ExportBinMode::Exp64)
+        .unwrap();
+
+    // strip the prefix of old keys, drop temporary ones
+    let map = |key: Vec<u8>, value: Vec<u8>| {
+        key.strip_prefix(b"old:")
+            .map(|key| (key.to_vec(), [b"v", value.as_slice()].concat()))
+    };
+
+    [false, true].into_iter().for_each(|bin| {
+        let target = NamedTempFile::new().unwrap();
+        let mut db = OpenOptions::new()
+            .write()
+            .create()
+            .open(target.path())
+            .unwrap();
+        match bin {
+            false => {
+                db.import_ascii_mapped(&mut ascii.reopen().unwrap(), &ImportOptions::default(), map)
+            }
+            true => db.import_bin_mapped(
+                &mut binary.reopen().unwrap(),
+                ExportBinMode::Exp64,
+                &ImportOptions::default(),
+                map,
+            ),
+        }
+        .unwrap();
+
+        assert_eq!(db.len().unwrap(), 2);
+        assert_eq!(db.get::<_, String>("a").unwrap(), Some("v1".to_string()));
+        assert_eq!(db.get::<_, String>("b").unwrap(), Some("v2".to_string()));
+    });
+}
+
+#[test]
+#[cfg(feature = "ascii-dump")]
+fn api_import_sync_once() {
+    let dumpfile = NamedTempFile::new().unwrap();
+    let source = NamedTempFile::new().unwrap();
+    OpenOptions::new()
+        .write()
+        .create()
+        .open(source.path())
+        .and_then(|mut db| {
+            (0..100).try_for_each(|n| db.insert(n.to_string(), "value".to_string()).map(|_| ()))?;
+            db.export_ascii(&mut dumpfile.reopen().unwrap())
+        })
+        .unwrap();
+
+    let target = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .sync(true)
+        .create()
+        .numsync(true)
+        .open(target.path())
+        .unwrap();
+
+    // standalone updates still sync each time
+    let numsync = db.numsync().unwrap();
+    db.insert("key".to_string(), "value".to_string()).unwrap();
+    assert_eq!(db.numsync().unwrap(), numsync + 1);
+
+    // a sync mode import syncs once, at the end
+    db.import_ascii(&mut dumpfile.reopen().unwrap()).unwrap();
+    assert_eq!(db.numsync().unwrap(), numsync + 2);
+    assert_eq!(db.len().unwrap(), 101);
+}
+
+#[test]
+#[cfg(feature = "ascii-dump")]
+fn api_dump_versions() {
+    use gdbm_native::{DumpVersion, ExportOptions};
+
+    let expected = [
+        (b"key1".to_vec(), b"value1".to_vec()),
+        (b"key2".to_vec(), b"value2".to_vec()),
+        (b"long".to_vec(), vec![b'x'; 100]),
+        (b"empty".to_vec(), vec![]),
+    ];
+
+    // dumps written by GNU gdbm_dump, format 1.0 and 1.1
+    ["gdbm-1.18.dump", "gdbm-1.23.dump"]
+        .into_iter()
+        .for_each(|name| {
+            let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("src/data/dump")
+                .join(name);
+            let target = NamedTempFile::new().unwrap();
+            let mut db = OpenOptions::new()
+                .write()
+                .create()
+                .open(target.path())
+                .unwrap();
+            db.import_ascii(&mut std::fs::File::open(path).unwrap())
+                .unwrap();
+
+            assert_eq!(db.len().unwrap(), expected.len(), "{}", name);
+            expected.iter().for_each(|(key, value)| {
+                assert_eq!(db.get_raw(key).unwrap().as_ref(), Some(value), "{}", name);
+            });
+
+            // and written back in both versions
+            [
+                (DumpVersion::V1_0, "#:version=1.0", false),
+                (DumpVersion::V1_1, "#:version=1.1", true),
+            ]
+            .into_iter()
+            .for_each(|(version, version_line, has_format)| {
+                let dumpfile = NamedTempFile::new().unwrap();
+                db.export_ascii_with(
+                    &mut dumpfile.reopen().unwrap(),
+                    &ExportOptions {
+                        version,
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+
+                let dump = std::fs::read_to_string(dumpfile.path()).unwrap();
+                assert_eq!(dump.lines().nth(1), Some(version_line));
+                assert_eq!(dump.contains("#:format="), has_format);
+                assert!(dump
+                    .lines()
+                    .all(|line| line.len() <= 76 || line.starts_with('#')));
+
+                let copy = NamedTempFile::new().unwrap();
+                let mut copy = OpenOptions::new()
+                    .write()
+                    .create()
+                    .open(copy.path())
+                    .unwrap();
+                copy.import_ascii(&mut dumpfile.reopen().unwrap()).unwrap();
+                assert_eq!(copy.len().unwrap(), expected.len());
+            });
+        });
+}
+
+#[test]
+fn api_bin_dump_versions() {
+    use gdbm_native::{BinDumpVersion, ExportOptions};
+
+    init_tests().into_iter().for_each(|test| {
+        [
+            (BinDumpVersion::V0, ExportBinMode::Exp32),
+            (BinDumpVersion::V0, ExportBinMode::Exp64),
+            (BinDumpVersion::V1, ExportBinMode::Exp64),
+        ]
+        .into_iter()
+        .for_each(|(bin_version, mode)| {
+            let dumpfile = NamedTempFile::new().unwrap();
+            OpenOptions::new()
+                .alignment(test.alignment)
+                .open(&test.db_path)
+                .and_then(|mut db| {
+                    db.export_bin_with(
+                        &mut dumpfile.reopen().unwrap(),
+                        mode,
+                        &ExportOptions {
+                            bin_version,
+                            ..Default::default()
+                        },
+                    )
+                })
+                .unwrap();
+
+            // legacy dumps have no header
+            let dump = std::fs::read(dumpfile.path()).unwrap();
+            assert_eq!(
+                dump.starts_with(b"!\r\n! GDBM FLAT FILE DUMP"),
+                bin_version == BinDumpVersion::V1
+            );
+
+            // the version is detected on import
+            let importdb = NamedTempFile::new().unwrap();
+            let mut db = OpenOptions::new()
+                .write()
+                .create()
+                .open(importdb.path())
+                .unwrap();
+            db.import_bin(&mut dumpfile.reopen().unwrap(), mode)
+                .unwrap();
+
+            assert_eq!(db.len().unwrap(), test.metadata.data.len());
+            test.metadata.data.iter().for_each(|kv| {
+                assert_eq!(db.get(&kv[0]).unwrap(), Some(kv[1].clone()));
+            });
+        });
+    });
+}
+
+#[test]
+#[cfg(feature = "ascii-dump")]
+fn api_ascii_dump_fields() {
+    use gdbm_native::{AsciiDumpOptions, DumpField, DumpVersion, ExportOptions};
+
+    // names of the header directives, as in "#:uid=...,mode=..." -> "uid"
+    fn directives(dump: &str) -> Vec<String> {
+        dump.lines()
+            .skip(1)
+            .take_while(|line| *line != "# End of header")
+            .map(|line| {
+                line.trim_start_matches("#:")
+                    .split(['=', ','])
+                    .next()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    let target = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .open(target.path())
+        .unwrap();
+    db.insert(b"key1".to_vec(), b"value1".to_vec()).unwrap();
+
+    // the header as written by GNU gdbm_dump, format 1.0 and 1.1
+    [
+        ("gdbm-1.18.dump", DumpVersion::V1_0),
+        ("gdbm-1.23.dump", DumpVersion::V1_1),
+    ]
+    .into_iter()
+    .for_each(|(name, version)| {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/data/dump")
+            .join(name);
+        let golden = std::fs::read_to_string(path).unwrap();
+
+        let dumpfile = NamedTempFile::new().unwrap();
+        db.export_ascii_with(
+            &mut dumpfile.reopen().unwrap(),
+            &ExportOptions {
+                version,
+                ascii: AsciiDumpOptions::GNU_1_23,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let dump = std::fs::read_to_string(dumpfile.path()).unwrap();
+        assert_eq!(directives(&dump), directives(&golden), "{}", name);
+    });
+
+    // any order is written as asked, and read back
+    let dumpfile = NamedTempFile::new().unwrap();
+    db.export_ascii_with(
+        &mut dumpfile.reopen().unwrap(),
+        &ExportOptions {
+            ascii: AsciiDumpOptions {
+                fields: &[DumpField::Owner, DumpField::Format],
+            },
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let dump = std::fs::read_to_string(dumpfile.path()).unwrap();
+    assert_eq!(directives(&dump), ["version", "uid", "format"]);
+
+    let copy = NamedTempFile::new().unwrap();
+    let mut copy = OpenOptions::new()
+        .write()
+        .create()
+        .open(copy.path())
+        .unwrap();
+    copy.import_ascii(&mut dumpfile.reopen().unwrap()).unwrap();
+    assert_eq!(copy.get_raw(b"key1").unwrap(), Some(b"value1".to_vec()));
+}
+
+#[test]
+fn api_dump_reader() {
+    use gdbm_native::{DumpReader, ExportOptions};
+
+    init_tests().into_iter().for_each(|test| {
+        [ExportBinMode::Exp32, ExportBinMode::Exp64]
+            .into_iter()
+            .for_each(|mode| {
+                let dumpfile = NamedTempFile::new().unwrap();
+                let mut db = OpenOptions::new()
+                    .alignment(test.alignment)
+                    .open(&test.db_path)
+                    .unwrap();
+                db.export_bin_with(
+                    &mut dumpfile.reopen().unwrap(),
+                    mode,
+                    &ExportOptions {
+                        bin_index: true,
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+
+                let mut reader = DumpReader::new(dumpfile.reopen().unwrap()).unwrap();
+                assert_eq!(reader.len(), test.metadata.data.len());
+                test.metadata.data.iter().for_each(|kv| {
+                    assert!(reader.contains_key(&kv[0]));
+                    assert_eq!(
+                        reader.get::<_, String>(&kv[0]).unwrap(),
+                        Some(kv[1].clone())
+                    );
+                });
+                assert_eq!(reader.get::<_, Vec<u8>>("no such key").unwrap(), None);
+
+                // importers stop before the index
+                let importdb = NamedTempFile::new().unwrap();
+                let mut copy = OpenOptions::new()
+                    .write()
+                    .create()
+                    .open(importdb.path())
+                    .unwrap();
+                copy.import_bin(&mut dumpfile.reopen().unwrap(), mode)
+                    .unwrap();
+                assert_eq!(copy.len().unwrap(), test.metadata.data.len());
+
+                // a dump without an index can't be read
+                let plain = NamedTempFile::new().unwrap();
+                db.export_bin(&mut plain.reopen().unwrap(), mode).unwrap();
+                assert!(DumpReader::new(plain.reopen().unwrap()).is_err());
+            });
+    });
+}
+
+#[test]
+#[cfg(feature = "ascii-dump")]
+fn api_import_tolerant() {
+    // a GNU dump as transferred from Windows, with comments and blank lines
+    let path =
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src/data/dump/gdbm-1.23.dump");
+    let dump = std::fs::read_to_string(path).unwrap();
+    let dump = dump
+        .lines()
+        .flat_map(|line| match line {
+            "# End of header" => vec!["", "# End of header  ", "# records follow", ""],
+            "#:len=4" => vec!["", "# a key", "#:len=4"],
+            line => vec![line],
+        })
+        .map(|line| format!("{}\r\n", line))
+        .collect::<String>();
+
+    let target = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .open(target.path())
+        .unwrap();
+    db.import_ascii(&mut dump.as_bytes()).unwrap();
+
+    assert_eq!(db.len().unwrap(), 4);
+    assert_eq!(db.get_raw(b"key1").unwrap(), Some(b"value1".to_vec()));
+    assert_eq!(db.get_raw(b"long").unwrap(), Some(vec![b'x'; 100]));
+    assert_eq!(db.get_raw(b"empty").unwrap(), Some(vec![]));
+
+    // other stray lines are still rejected
+    let bad = dump.replace("# a key", "not a comment");
+    assert!(db.import_ascii(&mut bad.as_bytes()).is_err());
+}
+
+#[test]
+#[cfg(feature = "ascii-dump")]
+fn api_export_filtered() {
+    let source = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .open(source.path())
+        .unwrap();
+    (0..50).for_each(|n| {
+        db.insert(format!("user:{n}"), n.to_string()).unwrap();
+        db.insert(format!("group:{n}"), n.to_string()).unwrap();
+    });
+
+    let is_user = |key: &[u8], _: &[u8]| key.starts_with(b"user:");
+
+    let ascii = NamedTempFile::new().unwrap();
+    db.export_ascii_filtered(&mut ascii.reopen().unwrap(), is_user)
+        .unwrap();
+    let binary = NamedTempFile::new().unwrap();
+    db.export_bin_filtered(
+        &mut binary.reopen().unwrap(),
+        ExportBinMode::ExpNative,
+        is_user,
+    )
+    .unwrap();
+
+    let dump = std::fs::read_to_string(ascii.path()).unwrap();
+    assert!(dump.contains("#:count=50\n"));
+
+    [true, false].into_iter().for_each(|is_ascii| {
+        let target = NamedTempFile::new().unwrap();
+        let mut copy = OpenOptions::new()
+            .write()
+            .create()
+            .open(target.path())
+            .unwrap();
+        match is_ascii {
+            true => copy.import_ascii(&mut ascii.reopen().unwrap()),
+            false => copy.import_bin(&mut binary.reopen().unwrap(), ExportBinMode::ExpNative),
+        }
+        .unwrap();
+
+        assert_eq!(copy.len().unwrap(), 50);
+        assert!(copy
+            .keys::<String>()
+            .all(|key| key.unwrap().starts_with("user:")));
+    });
+}
+
+#[test]
+#[cfg(feature = "ascii-dump")]
+fn api_export_anonymized() {
+    use gdbm_native::{Anonymize, ExportOptions};
+
+    let source = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .open(source.path())
+        .unwrap();
+    (0..50).for_each(|n| {
+        db.insert(format!("customer:{n}"), format!("secret value {n}"))
+            .unwrap();
+    });
+
+    let options = |keys| ExportOptions {
+        anonymize: Some(Anonymize {
+            secret: [7; 32],
+            keys,
+        }),
+        ..Default::default()
+    };
+
+    let ascii = NamedTempFile::new().unwrap();
+    db.export_ascii_with(&mut ascii.reopen().unwrap(), &options(false))
+        .unwrap();
+    let binary = NamedTempFile::new().unwrap();
+    db.export_bin_with(
+        &mut binary.reopen().unwrap(),
+        ExportBinMode::ExpNative,
+        &options(true),
+    )
+    .unwrap();
+
+    let import = |is_ascii| {
+        let target = NamedTempFile::new().unwrap();
+        let mut copy = OpenOptions::new()
+            .write()
+            .create()
+            .open(target.path())
+            .unwrap();
+        match is_ascii {
+            true => copy.import_ascii(&mut ascii.reopen().unwrap()),
+            false => copy.import_bin(&mut binary.reopen().unwrap(), ExportBinMode::ExpNative),
+        }
+        .unwrap();
+        copy.iter::<Vec<u8>, Vec<u8>>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+    };
+
+    // values replaced, keys kept
+    let records = import(true);
+    assert_eq!(records.len(), 50);
+    records.iter().for_each(|(key, value)| {
+        let original = db.get::<_, Vec<u8>>(key.as_slice()).unwrap().unwrap();
+        assert_eq!(value.len(), original.len());
+        assert_ne!(*value, original);
+    });
+
+    // keys replaced too, sizes kept
+    let records = import(false);
+    assert_eq!(records.len(), 50);
+    let mut sizes = records
+        .iter()
+        .map(|(key, value)| (key.len(), value.len()))
+        .collect::<Vec<_>>();
+    let mut original_sizes = db
+        .iter::<Vec<u8>, Vec<u8>>()
+        .map(|kv| kv.map(|(key, value)| (key.len(), value.len())))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    sizes.sort();
+    original_sizes.sort();
+    assert_eq!(sizes, original_sizes);
+    assert!(records
+        .iter()
+        .all(|(key, _)| !db.contains_key(key.as_slice()).unwrap()));
+}
+
+#[test]
+fn api_export_anonymized_short_keys() {
+    use gdbm_native::{Anonymize, ExportOptions};
+
+    let source = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .open(source.path())
+        .unwrap();
+    (0..=u8::MAX).for_each(|b| {
+        db.insert([b].as_slice(), b"value".as_slice()).unwrap();
+    });
+
+    let binary = NamedTempFile::new().unwrap();
+    db.export_bin_with(
+        &mut binary.reopen().unwrap(),
+        ExportBinMode::ExpNative,
+        &ExportOptions {
+            anonymize: Some(Anonymize {
+                secret: [7; 32],
+                keys: true,
+            }),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    // every 1-byte key exported as a distinct 1-byte key
+    let target = NamedTempFile::new().unwrap();
+    let mut copy = OpenOptions::new()
+        .write()
+        .create()
+        .open(target.path())
+        .unwrap();
+    copy.import_bin(&mut binary.reopen().unwrap(), ExportBinMode::ExpNative)
+        .unwrap();
+    assert_eq!(copy.len().unwrap(), 256);
+    assert!(copy.keys::<Vec<u8>>().all(|key| key.unwrap().len() == 1));
+}
+
+#[test]
+fn api_export_changes_since() {
+    use gdbm_native::Error;
+
+    let source = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .changelog(true)
+        .open(source.path())
+        .unwrap();
+    (0..100).for_each(|n| {
+        db.insert(format!("key {n}"), format!("value {n}")).unwrap();
+    });
+
+    // a full copy, then the changes since
+    let backup = NamedTempFile::new().unwrap();
+    let mut copy = OpenOptions::new()
+        .write()
+        .create()
+        .open(backup.path())
+        .unwrap();
+    let full = NamedTempFile::new().unwrap();
+    db.export_bin(&mut full.reopen().unwrap(), ExportBinMode::ExpNative)
+        .unwrap();
+    copy.import_bin(&mut full.reopen().unwrap(), ExportBinMode::ExpNative)
+        .unwrap();
+    let generation = db.generation();
+
+    db.insert("key 0".to_string(), "replaced".to_string())
+        .unwrap();
+    db.insert("new".to_string(), "value".to_string()).unwrap();
+    db.remove("key 1").unwrap();
+    db.remove("key 2").unwrap();
+    db.insert("key 2".to_string(), "again".to_string()).unwrap();
+    db.insert("fleeting".to_string(), "value".to_string())
+        .unwrap();
+    db.remove("fleeting").unwrap();
+    db.sync().unwrap();
+
+    let diff = NamedTempFile::new().unwrap();
+    let changes = db
+        .export_changes_since(
+            generation,
+            &mut diff.reopen().unwrap(),
+            ExportBinMode::ExpNative,
+        )
+        .unwrap();
+    assert_eq!(changes.generation, db.generation());
+    assert_eq!(
+        changes.removed,
+        vec![b"fleeting".to_vec(), b"key 1".to_vec()]
+    );
+
+    // only the changed records are written
+    assert!(diff.as_file().metadata().unwrap().len() < 200);
+    copy.import_bin(&mut diff.reopen().unwrap(), ExportBinMode::ExpNative)
+        .unwrap();
+    changes.removed.iter().for_each(|key| {
+        copy.remove(key.as_slice()).unwrap();
+    });
+    let records = |db: &mut gdbm_native::Gdbm<_>| {
+        let mut records = db
+            .iter::<Vec<u8>, Vec<u8>>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        records.sort();
+        records
+    };
+    assert_eq!(records(&mut copy), records(&mut db));
+
+    // nothing changed since
+    let changes = db
+        .export_changes_since(
+            changes.generation,
+            &mut diff.reopen().unwrap(),
+            ExportBinMode::ExpNative,
+        )
+        .unwrap();
+    assert!(changes.removed.is_empty());
+
+    // unknown to the handle
+    assert!(matches!(
+        db.export_changes_since(
+            db.generation() + 1,
+            &mut diff.reopen().unwrap(),
+            ExportBinMode::ExpNative,
+        ),
+        Err(Error::ChangesUnavailable { .. })
+    ));
+    assert!(matches!(
+        copy.export_changes_since(0, &mut diff.reopen().unwrap(), ExportBinMode::ExpNative),
+        Err(Error::ChangesUnavailable { generation: 0 })
+    ));
+}
+
+#[test]
+fn api_export_metadata() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("meta.db");
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .extended(true)
+        .block_size(gdbm_native::BlockSize::Exactly(512))
+        .open(&path)
+        .unwrap();
+    (0..1000).for_each(|n| {
+        db.insert(format!("secret key {}", n), format!("secret value {}", n))
+            .unwrap();
+    });
+    (0..500).for_each(|n| {
+        db.remove(format!("secret key {}", n * 2).as_str()).unwrap();
+    });
+
+    db.sync().unwrap();
+
+    let mut dump = Vec::new();
+    db.export_metadata(&mut dump).unwrap();
+    assert!(!dump.windows(6).any(|window| window == b"secret"));
+
+    let restored = dir.path().join("restored.db");
+    gdbm_native::restore_metadata(&mut dump.as_slice(), &restored).unwrap();
+    assert_eq!(
+        std::fs::metadata(&restored).unwrap().len(),
+        std::fs::metadata(&path).unwrap().len()
+    );
+
+    let mut copy = OpenOptions::new().open(&restored).unwrap();
+    assert_eq!(copy.len().unwrap(), 500);
+    assert_eq!(copy.buckets(), db.buckets());
+
+    assert!(gdbm_native::restore_metadata(&mut &dump[..20], &restored).is_err());
+    assert!(gdbm_native::restore_metadata(&mut &b"not a dump"[..], &restored).is_err());
+}
@@ -0,0 +1,100 @@
+//
+// tests/index.rs -- testing secondary index maintenance
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+extern crate gdbm_native;
+
+use gdbm_native::{Gdbm, Index, Indexed, OpenOptions, ReadWrite};
+use tempfile::NamedTempFile;
+
+fn create(file: &NamedTempFile) -> Gdbm<ReadWrite> {
+    OpenOptions::new()
+        .write()
+        .create()
+        .open(file.path())
+        .unwrap()
+}
+
+// values look like "<city>,<name>"; index by city
+fn city_index(file: &NamedTempFile) -> Index {
+    Index::new(
+        create(file),
+        Box::new(|_, value| value.split(|b| *b == b',').next().map(|city| city.to_vec())),
+    )
+}
+
+fn lookup_keys(db: &mut Indexed, city: &str) -> Vec<String> {
+    let mut keys = db
+        .lookup(0, city)
+        .unwrap()
+        .into_iter()
+        .map(|(key, _)| String::from_utf8(key).unwrap())
+        .collect::<Vec<_>>();
+    keys.sort();
+    keys
+}
+
+#[test]
+fn index_maintained() {
+    let primary = NamedTempFile::new().unwrap();
+    let secondary = NamedTempFile::new().unwrap();
+
+    let mut db = Indexed::new(create(&primary));
+    assert_eq!(db.add_index(city_index(&secondary)).unwrap(), 0);
+
+    [("1", "paris,ann"), ("2", "oslo,bob"), ("3", "paris,cy")]
+        .into_iter()
+        .for_each(|(key, value)| {
+            db.insert(key.to_string(), value.to_string()).unwrap();
+        });
+    assert_eq!(lookup_keys(&mut db, "paris"), vec!["1", "3"]);
+    assert_eq!(lookup_keys(&mut db, "oslo"), vec!["2"]);
+
+    // moving a record moves its index entry
+    db.insert("3".to_string(), "oslo,cy".to_string()).unwrap();
+    assert_eq!(lookup_keys(&mut db, "paris"), vec!["1"]);
+    assert_eq!(lookup_keys(&mut db, "oslo"), vec!["2", "3"]);
+
+    // removing the last record for an index key removes the index record
+    db.remove("1").unwrap();
+    assert!(lookup_keys(&mut db, "paris").is_empty());
+    assert!(db.index(0).primary_keys("paris").unwrap().is_empty());
+
+    assert_eq!(
+        db.lookup(0, "oslo").unwrap().first(),
+        Some(&(b"2".to_vec(), b"oslo,bob".to_vec()))
+    );
+}
+
+#[test]
+fn index_existing_records() {
+    let primary = NamedTempFile::new().unwrap();
+    let secondary = NamedTempFile::new().unwrap();
+
+    let mut db = create(&primary);
+    (0..100).for_each(|n| {
+        let city = ["paris", "oslo", "rome"][n % 3];
+        db.insert(format!("{}", n), format!("{},name", city))
+            .unwrap();
+    });
+
+    let mut db = Indexed::new(db);
+    db.add_index(city_index(&secondary)).unwrap();
+    db.sync().unwrap();
+
+    assert_eq!(lookup_keys(&mut db, "paris").len(), 34);
+    assert_eq!(lookup_keys(&mut db, "oslo").len(), 33);
+    assert_eq!(lookup_keys(&mut db, "rome").len(), 33);
+
+    // adding an already populated index does not duplicate entries
+    let (db, mut indexes) = db.into_inner();
+    let mut db = Indexed::new(db);
+    db.add_index(indexes.remove(0)).unwrap();
+    assert_eq!(lookup_keys(&mut db, "rome").len(), 33);
+}
@@ -124,3 +124,64 @@ fn api_values() {
         })
         .unwrap_or_else(|e| panic!("{}", e));
 }
+
+#[test]
+fn api_iter_len() {
+    init_tests().into_iter().for_each(|test| {
+        let mut db = OpenOptions::new()
+            .alignment(test.alignment)
+            .open(&test.db_path)
+            .unwrap();
+
+        // not counted yet
+        let (lower, upper) = db.keys::<Vec<u8>>().size_hint();
+        assert!(lower <= test.n_records);
+        assert_eq!(upper, (test.n_records == 0).then_some(0));
+
+        db.len().unwrap();
+        let mut iter = db.iter::<Vec<u8>, Vec<u8>>();
+        assert_eq!(iter.size_hint(), (test.n_records, Some(test.n_records)));
+        if test.n_records > 0 {
+            iter.next().unwrap().unwrap();
+            let remaining = test.n_records - 1;
+            assert_eq!(iter.size_hint(), (remaining, Some(remaining)));
+        }
+        assert_eq!(iter.count(), test.n_records.saturating_sub(1));
+
+        assert_eq!(
+            db.values::<Vec<u8>>().size_hint(),
+            (test.n_records, Some(test.n_records))
+        );
+    });
+}
+
+#[test]
+fn api_collect() {
+    init_tests().into_iter().for_each(|test| {
+        let mut db = OpenOptions::new()
+            .alignment(test.alignment)
+            .open(&test.db_path)
+            .unwrap();
+
+        let keys = db.collect_keys::<String>().unwrap();
+        assert_eq!(keys.len(), test.metadata.data.len());
+        assert_eq!(
+            keys.into_iter().collect::<HashSet<_>>(),
+            test.metadata
+                .data
+                .iter()
+                .map(|kv| kv[0].clone())
+                .collect::<HashSet<_>>()
+        );
+
+        let entries = db.collect_entries::<String, String>().unwrap();
+        assert_eq!(
+            entries.into_iter().collect::<HashMap<_, _>>(),
+            test.metadata
+                .data
+                .iter()
+                .map(|kv| (kv[0].clone(), kv[1].clone()))
+                .collect::<HashMap<_, _>>()
+        );
+    });
+}
@@ -0,0 +1,109 @@
+//
+// tests/large.rs -- testing databases that grow past 4GiB
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+// These tests write several GiB of data; run them with
+// `cargo test --release --features large-tests --test large`.
+
+#![cfg(feature = "large-tests")]
+
+extern crate gdbm_native;
+
+use gdbm_native::{Error, Offset, OpenOptions};
+use tempfile::NamedTempFile;
+
+const VALUE_SIZE: usize = 256 * 1024 * 1024;
+const FOUR_GIB: u64 = 1 << 32;
+
+fn value(n: usize) -> Vec<u8> {
+    vec![n as u8; VALUE_SIZE]
+}
+
+fn check_value(db_value: Option<Vec<u8>>, n: usize) -> Result<(), String> {
+    let db_value = db_value.ok_or_else(|| format!("record {} missing", n))?;
+    (db_value == value(n))
+        .then_some(())
+        .ok_or_else(|| format!("record {} has wrong contents", n))
+}
+
+#[test]
+fn lfs_beyond_4gib() {
+    let tmpfile = NamedTempFile::new().unwrap();
+    let records = (FOUR_GIB as usize / VALUE_SIZE) + 1;
+
+    {
+        let mut db = OpenOptions::new()
+            .write()
+            .create()
+            .offset(Some(Offset::LFS))
+            .newdb(true)
+            .open(tmpfile.path())
+            .unwrap();
+
+        (0..records).for_each(|n| {
+            db.insert(n, value(n))
+                .unwrap_or_else(|e| panic!("insert {}: {}", n, e));
+        });
+
+        db.sync().unwrap();
+    }
+
+    let file_size = tmpfile.path().metadata().unwrap().len();
+    assert!(file_size > FOUR_GIB, "file size {}", file_size);
+
+    let mut db = OpenOptions::new().open(tmpfile.path()).unwrap();
+    assert_eq!(db.len().unwrap(), records);
+
+    (0..records)
+        .try_for_each(|n| {
+            db.get(&n)
+                .map_err(|e| e.to_string())
+                .and_then(|v| check_value(v, n))
+        })
+        .unwrap_or_else(|e| panic!("{}", e));
+}
+
+#[test]
+fn small_offset_stops_at_4gib() {
+    let tmpfile = NamedTempFile::new().unwrap();
+
+    let stored = {
+        let mut db = OpenOptions::new()
+            .write()
+            .create()
+            .offset(Some(Offset::Small))
+            .newdb(true)
+            .sync(true)
+            .open(tmpfile.path())
+            .unwrap();
+
+        (0..)
+            .find(|n| match db.insert(*n, value(*n)) {
+                Ok(_) => false,
+                Err(Error::OffsetOverflow { .. }) => true,
+                Err(e) => panic!("insert {}: {}", n, e),
+            })
+            .unwrap()
+    };
+
+    // everything stored before the overflow is intact
+    let file_size = tmpfile.path().metadata().unwrap().len();
+    assert!(file_size <= FOUR_GIB, "file size {}", file_size);
+
+    let mut db = OpenOptions::new().open(tmpfile.path()).unwrap();
+    assert_eq!(db.len().unwrap(), stored);
+
+    (0..stored)
+        .try_for_each(|n| {
+            db.get(&n)
+                .map_err(|e| e.to_string())
+                .and_then(|v| check_value(v, n))
+        })
+        .unwrap_or_else(|e| panic!("{}", e));
+}
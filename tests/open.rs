@@ -1,5 +1,8 @@
 extern crate gdbm_native;
 
+mod common;
+
+use common::init_tests;
 use gdbm_native::{
     Alignment::{Align32, Align64},
     BlockSize,
@@ -186,3 +189,468 @@ fn api_open_cachesize() {
         })
         .unwrap_or_else(|e| panic!("{}", e));
 }
+
+#[test]
+// Databases open without being told their alignment.
+fn api_open_detect_alignment() {
+    init_tests()
+        .into_iter()
+        .try_for_each(|test| {
+            OpenOptions::new()
+                .open(&test.db_path)
+                .map_err(|e| format!("opening: {}", e))
+                .and_then(|mut db| {
+                    let expected = test.alignment.unwrap_or(Align64);
+                    (db.alignment() == expected).then_some(()).ok_or_else(|| {
+                        format!("detected {:?}, expected {:?}", db.alignment(), expected)
+                    })?;
+
+                    db.len().map_err(|e| format!("len: {}", e)).and_then(|len| {
+                        (len == test.n_records).then_some(()).ok_or_else(|| {
+                            format!("expected {} records, got {}", test.n_records, len)
+                        })
+                    })
+                })
+                .map_err(|e| format!("[{}]: {}", test.db_path, e))
+        })
+        .unwrap_or_else(|e: String| panic!("{}", e));
+}
+
+#[test]
+// Extended databases checksum their buckets.
+fn api_open_extended() {
+    use gdbm_native::{ConvertOptions, Error};
+
+    let old_db = NamedTempFile::new().expect("creating a temporary file");
+
+    [
+        (LFS, Little, Magic::LE64X),
+        (LFS, Big, Magic::BE64X),
+        (Small, Little, Magic::LE32X),
+        (Small, Big, Magic::BE32X),
+    ]
+    .into_iter()
+    .for_each(|(offset, endian, expected_magic)| {
+        let mut db = OpenOptions::new()
+            .write()
+            .create()
+            .newdb(true)
+            .alignment(Some(Align64))
+            .offset(Some(offset))
+            .endian(Some(endian))
+            .numsync(false)
+            .extended(true)
+            .block_size(BlockSize::Exactly(512))
+            .open(old_db.path())
+            .unwrap();
+        (0..1000).for_each(|n| {
+            db.insert(n.to_string(), format!("value {n}")).unwrap();
+        });
+        // extended databases keep their numsync header
+        db.convert(&ConvertOptions { numsync: false }).unwrap();
+        db.sync().unwrap();
+        drop(db);
+
+        let mut db = OpenOptions::new()
+            .alignment(Some(Align64))
+            .open(old_db.path())
+            .unwrap();
+        assert_eq!(db.magic(), expected_magic);
+        assert_eq!(db.len().unwrap(), 1000);
+        (0..1000).for_each(|n| {
+            assert_eq!(db.get(&n.to_string()).unwrap(), Some(format!("value {n}")));
+        });
+    });
+
+    // a single bucket, following the header and directory blocks
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .newdb(true)
+        .extended(true)
+        .block_size(BlockSize::Exactly(512))
+        .open(old_db.path())
+        .unwrap();
+    db.insert("key".to_string(), "value".to_string()).unwrap();
+    db.sync().unwrap();
+    drop(db);
+
+    let mut data = std::fs::read(old_db.path()).unwrap();
+    data[1024 + 200] ^= 1;
+    std::fs::write(old_db.path(), data).unwrap();
+
+    assert!(matches!(
+        OpenOptions::new().open(old_db.path()),
+        Err(Error::BadBucketChecksum { offset: 1024 })
+    ));
+}
+
+#[test]
+fn api_open_expected_file_size() {
+    let layout = gdbm_native::Layout {
+        alignment: Align64,
+        endian: Little,
+        offset: LFS,
+    };
+    let tmp = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .alignment(Some(Align64))
+        .write()
+        .create()
+        .newdb(true)
+        .block_size(BlockSize::Exactly(4096))
+        .open(tmp.path())
+        .unwrap();
+    (0..10000).for_each(|n| {
+        db.insert(format!("key {n:06}"), format!("value {n:08}"))
+            .unwrap();
+    });
+    db.sync().unwrap();
+
+    let expected = gdbm_native::layout::expected_file_size(&layout, 4096, false, 10000, 10, 14);
+    let actual = tmp.as_file().metadata().unwrap().len();
+    assert!(actual > expected / 2 && actual < expected * 2);
+}
+
+#[test]
+fn api_open_transient_inconsistency() {
+    use gdbm_native::Error;
+    use std::io::Write;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .open(file.path())
+        .unwrap();
+    db.insert("key".to_string(), "value".to_string()).unwrap();
+    db.close().unwrap();
+
+    // cut short, so the directory is past the end of the file
+    let size = std::fs::metadata(file.path()).unwrap().len();
+    file.as_file().set_len(size / 2).unwrap();
+
+    // damage that doesn't change is reported as it is
+    let error = match OpenOptions::new().open(file.path()) {
+        Err(Error::TransientInconsistency(_)) | Ok(_) => panic!("expected damage"),
+        Err(e) => e.to_string(),
+    };
+
+    // damage in a file being written may pass
+    let writing = Arc::new(AtomicBool::new(true));
+    let writer = {
+        let writing = writing.clone();
+        let mut f = std::fs::OpenOptions::new()
+            .append(true)
+            .open(file.path())
+            .unwrap();
+        std::thread::spawn(move || {
+            while writing.load(Ordering::Relaxed) {
+                f.write_all(b"x").unwrap();
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+        })
+    };
+    let result = OpenOptions::new().open(file.path());
+    writing.store(false, Ordering::Relaxed);
+    writer.join().unwrap();
+
+    assert!(matches!(
+        result,
+        Err(Error::TransientInconsistency(e)) if e.to_string() == error
+    ));
+}
+
+#[test]
+fn api_open_lock_timeout() {
+    use gdbm_native::Error;
+    use std::time::{Duration, Instant};
+
+    let file = NamedTempFile::new().unwrap();
+    let timeout = Some(Duration::from_millis(50));
+    let mut writer = OpenOptions::new()
+        .lock_timeout(timeout)
+        .write()
+        .create()
+        .open(file.path())
+        .unwrap();
+    writer.sync().unwrap();
+
+    // a writer excludes others
+    let start = Instant::now();
+    assert!(matches!(
+        OpenOptions::new().lock_timeout(timeout).open(file.path()),
+        Err(Error::LockTimeout)
+    ));
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    assert!(matches!(
+        OpenOptions::new()
+            .lock_timeout(timeout)
+            .write()
+            .open(file.path()),
+        Err(Error::LockTimeout)
+    ));
+
+    // unless they don't lock
+    OpenOptions::new().open(file.path()).unwrap();
+    drop(writer);
+
+    // readers share the lock
+    let reader = OpenOptions::new()
+        .lock_timeout(timeout)
+        .open(file.path())
+        .unwrap();
+    OpenOptions::new()
+        .lock_timeout(timeout)
+        .open(file.path())
+        .unwrap();
+    assert!(matches!(
+        OpenOptions::new()
+            .lock_timeout(Some(Duration::ZERO))
+            .write()
+            .open(file.path()),
+        Err(Error::LockTimeout)
+    ));
+
+    // and a writer waits for them
+    let release = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(20));
+        drop(reader);
+    });
+    OpenOptions::new()
+        .lock_timeout(Some(Duration::from_secs(10)))
+        .write()
+        .open(file.path())
+        .unwrap();
+    release.join().unwrap();
+}
+
+#[test]
+fn api_open_refresh() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.db");
+    let mut writer = OpenOptions::new().write().create().open(&path).unwrap();
+    writer
+        .insert("color".to_string(), "red".to_string())
+        .unwrap();
+    writer.sync().unwrap();
+
+    let mut reader = OpenOptions::new().open(&path).unwrap();
+    #[cfg(feature = "watch")]
+    let mut watcher = reader.watch().unwrap();
+    assert_eq!(reader.get("color").unwrap(), Some("red".to_string()));
+
+    // changed in place
+    writer
+        .insert("color".to_string(), "green".to_string())
+        .unwrap();
+    (0..1000).for_each(|n| {
+        writer
+            .insert(format!("key {n}"), "value".to_string())
+            .unwrap();
+    });
+    writer.sync().unwrap();
+    #[cfg(feature = "watch")]
+    assert!(watcher
+        .wait(Some(std::time::Duration::from_secs(5)))
+        .unwrap());
+    reader.refresh().unwrap();
+    assert_eq!(reader.get("color").unwrap(), Some("green".to_string()));
+    assert_eq!(reader.len().unwrap(), 1001);
+    drop(writer);
+
+    // replaced by rename
+    let new_path = dir.path().join("config.db.new");
+    let mut replacement = OpenOptions::new().write().create().open(&new_path).unwrap();
+    replacement
+        .insert("color".to_string(), "blue".to_string())
+        .unwrap();
+    replacement.close().unwrap();
+    std::fs::rename(&new_path, &path).unwrap();
+    #[cfg(feature = "watch")]
+    assert!(watcher
+        .wait(Some(std::time::Duration::from_secs(5)))
+        .unwrap());
+    reader.refresh().unwrap();
+    assert_eq!(reader.get("color").unwrap(), Some("blue".to_string()));
+    assert_eq!(reader.len().unwrap(), 1);
+}
+
+#[test]
+fn api_open_from_file() {
+    use std::fs::File;
+    use std::os::fd::IntoRawFd;
+
+    let file = NamedTempFile::new().unwrap();
+    let f = File::options()
+        .read(true)
+        .write(true)
+        .open(file.path())
+        .unwrap();
+    let mut db = OpenOptions::new().write().create().from_file(f).unwrap();
+    db.insert("key".to_string(), "value".to_string()).unwrap();
+    assert!(db.compact().is_err());
+    db.close().unwrap();
+
+    let f = File::open(file.path()).unwrap();
+    let mut db = OpenOptions::new().from_file(f).unwrap();
+    assert_eq!(db.get("key").unwrap(), Some("value".to_string()));
+    assert!(db.refresh().is_err());
+
+    let fd = File::open(file.path()).unwrap().into_raw_fd();
+    let mut db = unsafe { OpenOptions::new().from_raw_fd(fd) }.unwrap();
+    assert_eq!(db.len().unwrap(), 1);
+}
+
+#[test]
+fn api_open_user_version() {
+    use gdbm_native::Error;
+
+    let file = NamedTempFile::new().unwrap();
+    let db = OpenOptions::new()
+        .expect_user_version(Some(3))
+        .write()
+        .create()
+        .open(file.path())
+        .unwrap();
+    assert_eq!(db.user_version(), 3);
+    db.close().unwrap();
+
+    let db = OpenOptions::new()
+        .expect_user_version(Some(3))
+        .open(file.path())
+        .unwrap();
+    assert_eq!(db.user_version(), 3);
+
+    assert!(matches!(
+        OpenOptions::new()
+            .expect_user_version(Some(4))
+            .open(file.path()),
+        Err(Error::VersionMismatch {
+            found: 3,
+            expected: 4
+        })
+    ));
+    assert!(matches!(
+        OpenOptions::new()
+            .expect_user_version(Some(4))
+            .write()
+            .create()
+            .open(file.path()),
+        Err(Error::VersionMismatch { .. })
+    ));
+
+    // the version survives updates and compaction
+    let mut db = OpenOptions::new().write().open(file.path()).unwrap();
+    db.set_user_version(4).unwrap();
+    db.insert("key".to_string(), "value".to_string()).unwrap();
+    db.compact().unwrap();
+    db.close().unwrap();
+
+    let db = OpenOptions::new()
+        .expect_user_version(Some(4))
+        .open(file.path())
+        .unwrap();
+    assert_eq!(db.user_version(), 4);
+
+    // databases without a numsync header have none
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .numsync(false)
+        .open(file.path())
+        .unwrap();
+    assert_eq!(db.user_version(), 0);
+    assert!(db.set_user_version(1).is_err());
+}
+
+#[test]
+fn api_open_try_clone() {
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .open(file.path())
+        .unwrap();
+    (0..1000).for_each(|n| {
+        db.insert(format!("key {n}"), format!("value {n}")).unwrap();
+    });
+    db.close().unwrap();
+
+    let mut db = OpenOptions::new().open(file.path()).unwrap();
+    let mut clone = db.try_clone().unwrap();
+
+    // reads through one handle don't disturb the other's
+    let keys = db.keys::<String>().map(|key| key.unwrap());
+    let clone_keys = clone.keys::<String>().map(|key| key.unwrap());
+    assert!(keys
+        .zip(clone_keys)
+        .all(|(key, clone_key)| key == clone_key));
+
+    let reader = std::thread::spawn(move || {
+        (0..1000)
+            .all(|n| clone.get(format!("key {n}").as_str()).unwrap() == Some(format!("value {n}")))
+    });
+    assert!((0..1000)
+        .all(|n| db.get(format!("key {n}").as_str()).unwrap() == Some(format!("value {n}"))));
+    assert!(reader.join().unwrap());
+}
+
+#[test]
+fn api_open_providers() {
+    use gdbm_native::{Clock, ManualClock, RecordCacheOptions, SeededRandom};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    // identifiers come from the random numbers given
+    let ids = (0..2)
+        .map(|_| {
+            let file = NamedTempFile::new().unwrap();
+            OpenOptions::new()
+                .random(Arc::new(SeededRandom::new(42)))
+                .write()
+                .create()
+                .identity(true)
+                .open(file.path())
+                .unwrap()
+                .uuid()
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(ids[0], ids[1]);
+
+    // cached records expire by the clock given
+    let file = NamedTempFile::new().unwrap();
+    let clock = Arc::new(ManualClock::new());
+    let mut db = OpenOptions::new()
+        .clock(clock.clone())
+        .record_cache(Some(RecordCacheOptions {
+            max_bytes: 1024,
+            ttl: Some(Duration::from_secs(60)),
+        }))
+        .write()
+        .create()
+        .open(file.path())
+        .unwrap();
+    db.insert("key".to_string(), "value".to_string()).unwrap();
+
+    let get = |db: &mut gdbm_native::Gdbm<gdbm_native::ReadWrite>| {
+        db.get::<_, String>("key").unwrap();
+        db.record_cache_stats().unwrap().hits
+    };
+    assert_eq!(get(&mut db), 0);
+    assert_eq!(get(&mut db), 1);
+    clock.advance(Duration::from_secs(59));
+    assert_eq!(get(&mut db), 2);
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(get(&mut db), 2);
+
+    // time-bounded operations use it too
+    let deadline = clock.now();
+    assert!(db
+        .get_with_deadline::<_, String>("other", deadline)
+        .is_err());
+}
@@ -111,3 +111,431 @@ fn api_len() {
         assert_eq!(res, testdb.n_records);
     }
 }
+
+#[test]
+fn api_layout_efficiency() {
+    let tests = init_tests();
+
+    for testdb in tests {
+        let mut db = OpenOptions::new()
+            .alignment(testdb.alignment)
+            .open(&testdb.db_path)
+            .unwrap();
+        let efficiency = db.layout_efficiency().unwrap();
+        assert_eq!(efficiency.records, testdb.n_records);
+        assert!(efficiency.buckets > 0);
+        assert!((0.0..=1.0).contains(&efficiency.bucket_fill));
+
+        let (key_bytes, value_bytes) = testdb
+            .metadata
+            .data
+            .iter()
+            .fold((0, 0), |(k, v), kv| (k + kv[0].len(), v + kv[1].len()));
+        assert_eq!(
+            efficiency.avg_key,
+            key_bytes.checked_div(testdb.n_records).unwrap_or(0)
+        );
+        assert_eq!(
+            efficiency.avg_value,
+            value_bytes.checked_div(testdb.n_records).unwrap_or(0)
+        );
+        assert_eq!(
+            efficiency.recommended_block_size,
+            gdbm_native::recommend_block_size(
+                efficiency.records,
+                efficiency.avg_key,
+                efficiency.avg_value
+            )
+        );
+    }
+}
+
+#[test]
+fn api_verify() {
+    let tests = init_tests();
+
+    for testdb in tests {
+        let mut db = OpenOptions::new()
+            .alignment(testdb.alignment)
+            .verify_directory(true)
+            .open(&testdb.db_path)
+            .unwrap();
+        db.verify().unwrap();
+    }
+}
+
+#[test]
+fn api_get_with_deadline() {
+    use gdbm_native::Error;
+    use std::time::{Duration, Instant};
+
+    let tests = init_tests();
+
+    for testdb in tests.into_iter().filter(|testdb| testdb.is_basic) {
+        let mut db = OpenOptions::new()
+            .alignment(testdb.alignment)
+            .open(&testdb.db_path)
+            .unwrap();
+
+        let (key, value) = (&testdb.metadata.data[0][0], &testdb.metadata.data[0][1]);
+        assert_eq!(
+            db.get_with_timeout::<_, String>(key, Duration::from_secs(60))
+                .unwrap(),
+            Some(value.clone())
+        );
+
+        // the record must be read, even with its bucket cached
+        assert!(matches!(
+            db.get_with_deadline::<_, String>(key, Instant::now()),
+            Err(Error::TimedOut)
+        ));
+
+        // the deadline applies to one lookup only
+        assert_eq!(db.get::<_, String>(key).unwrap(), Some(value.clone()));
+    }
+}
+
+#[test]
+fn api_read_pool() {
+    use gdbm_native::ReadPool;
+
+    let tests = init_tests();
+
+    for testdb in tests.into_iter().filter(|testdb| testdb.is_basic) {
+        let options = OpenOptions::new().alignment(testdb.alignment);
+        let pool = ReadPool::open(&options, &testdb.db_path, 2).unwrap();
+        assert_eq!(pool.size(), 2);
+
+        std::thread::scope(|scope| {
+            (0..4).for_each(|_| {
+                scope.spawn(|| {
+                    testdb.metadata.data.iter().for_each(|kv| {
+                        assert_eq!(
+                            pool.get::<_, String>(kv[0].as_str()).unwrap(),
+                            Some(kv[1].clone())
+                        );
+                    });
+                    assert!(!pool.contains_key("not a key").unwrap());
+                });
+            });
+        });
+        assert_eq!(pool.idle(), 2);
+
+        // handles are returned when dropped
+        let first = pool.handle();
+        let mut second = pool.try_handle().unwrap();
+        assert!(pool.try_handle().is_none());
+        assert_eq!(second.len().unwrap(), testdb.metadata.data.len());
+        drop(first);
+        assert!(pool.try_handle().is_some());
+    }
+}
+
+#[test]
+fn api_warm_from() {
+    use gdbm_native::Event;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    let tests = init_tests();
+
+    for testdb in tests.into_iter().filter(|testdb| testdb.is_basic) {
+        let dir = tempfile::tempdir().unwrap();
+        let hot = dir.path().join("hot");
+        let options = OpenOptions::new()
+            .alignment(testdb.alignment)
+            .warm_from(&hot);
+
+        // nothing saved yet
+        let mut db = options.clone().open(&testdb.db_path).unwrap();
+        testdb.metadata.data.iter().for_each(|kv| {
+            db.get::<_, String>(kv[0].as_str()).unwrap();
+        });
+        drop(db);
+        assert!(std::fs::read_to_string(&hot).unwrap().lines().count() > 1);
+
+        // the saved buckets are read on open
+        let mut db = options.open(&testdb.db_path).unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let hook_events = events.clone();
+        db.set_event_hook(Some(Box::new(move |event| {
+            hook_events.lock().unwrap().push(*event)
+        })));
+        db.set_slow_threshold(Some(Duration::ZERO));
+        db.get::<_, String>(testdb.metadata.data[0][0].as_str())
+            .unwrap();
+
+        assert!(matches!(
+            events.lock().unwrap()[0],
+            Event::SlowOperation {
+                buckets_read: 0,
+                ..
+            }
+        ));
+    }
+}
+
+#[test]
+fn api_random_key() {
+    use std::collections::HashSet;
+
+    // splitmix64
+    let mut state = 0x2545f4914f6cdd1d_u64;
+    let mut rng = move || {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let z = (state ^ (state >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        let z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    };
+
+    let tests = init_tests();
+
+    for testdb in tests.into_iter().filter(|testdb| testdb.is_basic) {
+        let mut db = OpenOptions::new()
+            .alignment(testdb.alignment)
+            .open(&testdb.db_path)
+            .unwrap();
+
+        let keys = testdb
+            .metadata
+            .data
+            .iter()
+            .map(|kv| kv[0].clone())
+            .collect::<HashSet<_>>();
+
+        let key = db.random_key::<String>(&mut rng).unwrap().unwrap();
+        assert!(keys.contains(&key));
+
+        let sample = db.sample::<String>(keys.len() * 4, &mut rng).unwrap();
+        assert_eq!(sample.len(), keys.len() * 4);
+        assert!(sample.iter().all(|key| keys.contains(key)));
+        // most keys are picked at least once
+        let picked = sample.iter().collect::<HashSet<_>>();
+        assert!(picked.len() > keys.len() / 2);
+    }
+
+    #[cfg(feature = "write")]
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = OpenOptions::new()
+            .write()
+            .create()
+            .open(dir.path().join("empty"))
+            .unwrap();
+        assert_eq!(db.random_key::<String>(&mut rng).unwrap(), None);
+        assert!(db.sample::<String>(10, &mut rng).unwrap().is_empty());
+    }
+}
+
+#[test]
+fn api_len_estimate() {
+    let tests = init_tests();
+
+    for testdb in tests.into_iter().filter(|testdb| testdb.is_basic) {
+        let mut db = OpenOptions::new()
+            .alignment(testdb.alignment)
+            .open(&testdb.db_path)
+            .unwrap();
+
+        let len = testdb.metadata.data.len();
+        let estimate = db.len_estimate().unwrap();
+        assert!(estimate.error > 0);
+        assert!(estimate.count.abs_diff(len) <= estimate.error);
+
+        // exact once counted
+        assert_eq!(db.len().unwrap(), len);
+        let estimate = db.len_estimate().unwrap();
+        assert_eq!((estimate.count, estimate.error), (len, 0));
+    }
+}
+
+#[test]
+fn api_iter_bucket() {
+    use std::collections::HashMap;
+
+    let tests = init_tests();
+
+    for testdb in tests.into_iter().filter(|testdb| testdb.is_basic) {
+        let mut db = OpenOptions::new()
+            .alignment(testdb.alignment)
+            .open(&testdb.db_path)
+            .unwrap();
+
+        let expected = testdb
+            .metadata
+            .data
+            .iter()
+            .map(|kv| (kv[0].clone(), kv[1].clone()))
+            .collect::<HashMap<_, _>>();
+
+        let buckets = db.buckets();
+        assert!(buckets.len() > 1);
+        assert_eq!(buckets[0], 0);
+
+        let mut seen = 0;
+        for bucket in buckets {
+            let records = db
+                .iter_bucket::<String, String>(bucket)
+                .unwrap()
+                .collect::<gdbm_native::Result<Vec<_>>>()
+                .unwrap();
+            for (key, value) in records {
+                assert_eq!(expected.get(&key), Some(&value));
+                assert_eq!(db.bucket_of(&key), bucket);
+                seen += 1;
+            }
+        }
+        assert_eq!(seen, expected.len());
+
+        assert!(db.iter_bucket::<String, String>(usize::MAX).is_err());
+    }
+}
+
+#[test]
+fn api_partition_ranges() {
+    use std::collections::HashMap;
+
+    let tests = init_tests();
+
+    for testdb in tests.into_iter().filter(|testdb| testdb.is_basic) {
+        let open = || {
+            OpenOptions::new()
+                .alignment(testdb.alignment)
+                .open(&testdb.db_path)
+                .unwrap()
+        };
+
+        let mut expected = testdb
+            .metadata
+            .data
+            .iter()
+            .map(|kv| (kv[0].clone(), kv[1].clone()))
+            .collect::<HashMap<_, _>>();
+
+        let db = open();
+        let ranges = db.partition_ranges(3);
+        assert_eq!(ranges.len(), 3.min(db.buckets().len()));
+        assert_eq!(ranges[0].start, 0);
+        assert!(ranges.windows(2).all(|pair| pair[0].end == pair[1].start));
+        assert_eq!(db.partition_ranges(0).len(), 1);
+        assert_eq!(db.partition_ranges(usize::MAX).len(), db.buckets().len());
+
+        // each range read by its own handle, every record exactly once
+        for range in ranges {
+            let mut db = open();
+            db.iter_range::<String, String>(range)
+                .unwrap()
+                .for_each(|kv| {
+                    let (key, value) = kv.unwrap();
+                    assert_eq!(expected.remove(&key), Some(value));
+                });
+        }
+        assert!(expected.is_empty());
+
+        let mut db = open();
+        assert!(db.iter_range::<String, String>(0..usize::MAX).is_err());
+    }
+}
+
+#[test]
+fn api_get_many() {
+    let tests = init_tests();
+
+    for testdb in tests.into_iter().filter(|testdb| testdb.is_basic) {
+        let mut db = OpenOptions::new()
+            .alignment(testdb.alignment)
+            .cachesize(Some(0))
+            .open(&testdb.db_path)
+            .unwrap();
+
+        let keys = testdb
+            .metadata
+            .data
+            .iter()
+            .map(|kv| kv[0].as_str())
+            .chain(["not a key"])
+            .collect::<Vec<_>>();
+        let values = db.get_many::<_, String>(keys.iter().copied()).unwrap();
+
+        assert_eq!(values.len(), keys.len());
+        testdb
+            .metadata
+            .data
+            .iter()
+            .zip(&values)
+            .for_each(|(kv, value)| assert_eq!(value.as_ref(), Some(&kv[1])));
+        assert_eq!(values.last().unwrap(), &None);
+    }
+}
+
+#[test]
+fn api_hash() {
+    use gdbm_native::hash::{bucket_dir, hash_key, key_loc, HASH_BITS};
+
+    let hash = hash_key(b"hello");
+    assert_eq!(hash, 1730502474);
+    assert_eq!(bucket_dir(HASH_BITS, hash), hash as usize);
+    assert_eq!(bucket_dir(0, hash), 0);
+    assert_eq!(
+        key_loc(10, 100, b"hello"),
+        (hash, bucket_dir(10, hash), hash % 100)
+    );
+}
+
+#[test]
+fn api_ping() {
+    init_tests().into_iter().for_each(|test| {
+        let mut db = OpenOptions::new()
+            .alignment(test.alignment)
+            .open(&test.db_path)
+            .unwrap();
+        db.ping().unwrap();
+    });
+
+    // unsynced changes don't matter, a truncated file does
+    #[cfg(feature = "write")]
+    {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut db = OpenOptions::new()
+            .write()
+            .create()
+            .open(file.path())
+            .unwrap();
+        db.sync().unwrap();
+        (0..1000).for_each(|n| {
+            db.insert(format!("key {}", n), format!("value {}", n))
+                .unwrap();
+        });
+        db.ping().unwrap();
+
+        file.as_file().set_len(512).unwrap();
+        assert!(db.ping().is_err());
+    }
+}
+
+#[test]
+#[cfg(feature = "write")]
+fn api_file_replaced() {
+    use gdbm_native::Error;
+
+    let file = tempfile::NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .open(file.path())
+        .unwrap();
+    (0..1000).for_each(|n| {
+        db.insert(format!("key {n}"), n.to_string()).unwrap();
+    });
+    db.close().unwrap();
+
+    let mut db = OpenOptions::new().open(file.path()).unwrap();
+    let size = std::fs::metadata(file.path()).unwrap().len();
+    file.as_file().set_len(size / 2).unwrap();
+
+    let errors = (0..1000)
+        .filter_map(|n| db.get::<_, String>(format!("key {n}").as_str()).err())
+        .collect::<Vec<_>>();
+    assert!(!errors.is_empty());
+    assert!(errors.iter().all(|e| matches!(e, Error::FileReplaced)));
+}
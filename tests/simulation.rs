@@ -0,0 +1,172 @@
+//
+// tests/simulation.rs -- crash and fault testing on a simulated disk
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+#![cfg(feature = "testutil")]
+
+extern crate gdbm_native;
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::Path;
+
+use gdbm_native::{BlockSize, OpenOptions, Random, SeededRandom, Simulation};
+
+type Model = BTreeMap<Vec<u8>, Vec<u8>>;
+
+// what a crash image holds, read back through a fresh handle
+fn contents(path: &Path) -> gdbm_native::Result<Model> {
+    let mut db = OpenOptions::new().open(path)?;
+    db.verify()?;
+    db.iter::<Vec<u8>, Vec<u8>>().collect()
+}
+
+// random inserts and removes of a few dozen keys, synced every ten
+// operations, until crash_at.  Returns the contents at the last sync, and
+// those of the file a crash would leave.
+fn crash(seed: u64, crash_at: usize) -> (Model, gdbm_native::Result<Model>, Vec<u8>) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("db");
+    let simulation = Simulation::new(seed);
+    let random = SeededRandom::new(seed);
+    let mut db = OpenOptions::new()
+        .simulation(Some(simulation.clone()))
+        .write()
+        .create()
+        .extended(true)
+        .shadow_metadata(true)
+        .block_size(BlockSize::Exactly(512))
+        .open(&path)
+        .unwrap();
+    db.sync().unwrap();
+
+    let mut model = Model::new();
+    let mut synced = Model::new();
+    for n in 0..=crash_at {
+        let key = format!("key {}", random.next_u64() % 60).into_bytes();
+        match random.next_u64() % 3 {
+            0 => {
+                db.remove(key.as_slice()).unwrap();
+                model.remove(&key);
+            }
+            _ => {
+                let value = vec![n as u8; (random.next_u64() % 40) as usize];
+                db.insert(key.clone(), value.clone()).unwrap();
+                model.insert(key, value);
+            }
+        }
+        if n % 10 == 9 {
+            db.sync().unwrap();
+            synced = model.clone();
+            assert_eq!(simulation.unsynced(), 0);
+        }
+    }
+
+    // the process dies: nothing more reaches the file
+    let image = simulation.crash_image(&File::open(&path).unwrap()).unwrap();
+    std::mem::forget(db);
+
+    let crash_path = dir.path().join("crash");
+    std::fs::write(&crash_path, &image).unwrap();
+    (synced, contents(&crash_path), image)
+}
+
+#[test]
+fn simulation_crash_after_sync() {
+    (0..20).for_each(|seed| {
+        let (synced, found, _) = crash(seed, 9 + 10 * (seed as usize % 8));
+        assert_eq!(found.unwrap(), synced, "seed {seed}");
+    });
+}
+
+#[test]
+fn simulation_crash_anywhere() {
+    // Updates between syncs are made in place, so a crash between them may
+    // leave a file that doesn't open, or holds some of the updates.  It
+    // must be reported as an error or read, never panic.
+    (0..100).for_each(|seed| {
+        let _ = crash(seed, (seed as usize * 7) % 100);
+    });
+}
+
+#[test]
+fn simulation_repeatable() {
+    let (_, _, image) = crash(42, 55);
+    assert_eq!(crash(42, 55).2, image);
+}
+
+#[test]
+fn simulation_write_failures() {
+    let tempfile = tempfile::NamedTempFile::new().unwrap();
+    let simulation = Simulation::new(7);
+    let mut db = OpenOptions::new()
+        .simulation(Some(simulation.clone()))
+        .write()
+        .create()
+        .open(tempfile.path())
+        .unwrap();
+    db.sync().unwrap();
+
+    // every failed write is reported, by the operation making it or a
+    // later sync
+    simulation.set_failure_rate(200_000);
+    let mut reported = 0;
+    let mut errors = 0;
+    (0..200).for_each(|n| {
+        let result = match n % 5 {
+            4 => db.sync(),
+            _ => db.insert(format!("key {n}"), vec![1u8; 100]).map(|_| ()),
+        };
+        match result {
+            Ok(()) if n % 5 == 4 => assert_eq!(simulation.failures(), reported, "op {n}"),
+            Ok(()) => {}
+            Err(_) => {
+                reported = simulation.failures();
+                errors += 1;
+            }
+        }
+    });
+    assert!(simulation.failures() > 0);
+    assert!(errors > 0);
+}
+
+#[test]
+fn simulation_deferred_write_failure() {
+    use gdbm_native::Error;
+
+    let tempfile = tempfile::NamedTempFile::new().unwrap();
+    let simulation = Simulation::new(7);
+    let mut db = OpenOptions::new()
+        .simulation(Some(simulation.clone()))
+        .write()
+        .create()
+        .open(tempfile.path())
+        .unwrap();
+    db.sync().unwrap();
+
+    // the record is buffered, and written out by the lookup
+    db.insert("key".to_string(), "deferred value".to_string())
+        .unwrap();
+    simulation.set_failure_rate(1_000_000);
+    assert!(db.get::<_, String>("key").is_err());
+    simulation.set_failure_rate(0);
+
+    // the handle no longer takes updates, but the record is kept buffered
+    assert!(matches!(
+        db.insert("other".to_string(), "value".to_string()),
+        Err(Error::Inconsistent)
+    ));
+    assert!(matches!(db.sync(), Err(Error::Inconsistent)));
+    drop(db);
+
+    let contents = std::fs::read(tempfile.path()).unwrap();
+    assert!(contents
+        .windows(b"deferred value".len())
+        .any(|window| window == b"deferred value"));
+}
@@ -0,0 +1,75 @@
+//
+// tests/split.rs -- testing splitting databases into shards
+//
+// Copyright (c) 2019-2024 Jeff Garzik
+//
+// This file is part of the gdbm-native software project covered under
+// the MIT License.  For the full license text, please see the LICENSE
+// file in the root directory of this project.
+// SPDX-License-Identifier: MIT
+
+extern crate gdbm_native;
+
+mod common;
+
+use common::init_tests;
+use gdbm_native::OpenOptions;
+
+#[test]
+fn api_split_into() {
+    let dir = tempfile::tempdir().unwrap();
+
+    init_tests().into_iter().for_each(|test| {
+        let paths = (0..3)
+            .map(|n| dir.path().join(format!("shard{n}.db")))
+            .collect::<Vec<_>>();
+
+        let mut db = OpenOptions::new()
+            .alignment(test.alignment)
+            .open(&test.db_path)
+            .unwrap();
+        let mut shards = db.split_into(&paths, |key| key.len()).unwrap();
+
+        let mut total = 0;
+        shards.iter_mut().enumerate().for_each(|(n, shard)| {
+            assert_eq!(shard.magic(), db.magic());
+            shard.iter::<Vec<u8>, Vec<u8>>().for_each(|kv| {
+                let (key, value) = kv.unwrap();
+                assert_eq!(key.len() % 3, n);
+                assert_eq!(db.get_raw(&key).unwrap(), Some(value));
+                total += 1;
+            });
+        });
+        assert_eq!(total, test.n_records);
+    });
+}
+
+#[test]
+fn api_split_by_hash() {
+    let dir = tempfile::tempdir().unwrap();
+    let paths = (0..4)
+        .map(|n| dir.path().join(format!("shard{n}.db")))
+        .collect::<Vec<_>>();
+
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .open(dir.path().join("source.db"))
+        .unwrap();
+    (0..1000).for_each(|n| {
+        db.insert(n.to_string(), n.to_string()).unwrap();
+    });
+
+    let shards = db.split_by_hash(&paths).unwrap();
+    drop(shards);
+
+    // every shard gets a share, and each record is in exactly one shard
+    let lens = paths
+        .iter()
+        .map(|path| OpenOptions::new().open(path).unwrap().len().unwrap())
+        .collect::<Vec<_>>();
+    assert!(lens.iter().all(|len| *len > 0));
+    assert_eq!(lens.iter().sum::<usize>(), 1000);
+
+    assert!(db.split_by_hash::<&str>(&[]).is_err());
+}
@@ -135,3 +135,1790 @@ fn api_insert() {
         .map_err(|e: String| println!("{}", e))
         .unwrap()
 }
+
+#[cfg(all(target_os = "linux", feature = "linux-fs"))]
+#[test]
+fn api_punch_holes() {
+    use std::os::unix::fs::MetadataExt;
+
+    let file = NamedTempFile::new().unwrap();
+    let blocks = || fs::metadata(file.path()).unwrap().blocks();
+
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .punch_holes(true)
+        .open(file.path())
+        .unwrap();
+
+    let value = vec![0x5au8; 4 * 1024 * 1024];
+    db.insert("key".to_string(), value).unwrap();
+    db.insert("small".to_string(), "value".to_string()).unwrap();
+    db.sync().unwrap();
+    let full = blocks();
+
+    db.remove("key").unwrap();
+    db.sync().unwrap();
+    let punched = blocks();
+
+    // at least 3 of the 4MiB released (blocks are 512 bytes)
+    assert!(
+        full - punched >= 3 * 2048,
+        "blocks before {} after {}",
+        full,
+        punched
+    );
+
+    // remaining data is intact and the freed space is reusable
+    assert_eq!(
+        db.get::<_, String>("small").unwrap(),
+        Some("value".to_string())
+    );
+    db.insert("key".to_string(), vec![0xa5u8; 1024 * 1024])
+        .unwrap();
+    assert_eq!(
+        db.get::<_, Vec<u8>>("key").unwrap(),
+        Some(vec![0xa5u8; 1024 * 1024])
+    );
+}
+
+#[cfg(all(target_os = "linux", feature = "linux-fs"))]
+#[test]
+fn api_preallocate() {
+    use std::os::unix::fs::MetadataExt;
+
+    const CHUNK: u64 = 1024 * 1024;
+
+    let file = NamedTempFile::new().unwrap();
+    let allocated = || fs::metadata(file.path()).unwrap().blocks() * 512;
+
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .preallocate(Some(CHUNK))
+        .open(file.path())
+        .unwrap();
+
+    // the first chunk is reserved at creation, beyond the file size
+    assert!(allocated() >= CHUNK, "allocated {}", allocated());
+    assert!(fs::metadata(file.path()).unwrap().len() < CHUNK);
+
+    // growing past it reserves another chunk
+    db.insert("key".to_string(), vec![0x5au8; CHUNK as usize])
+        .unwrap();
+    db.sync().unwrap();
+    assert!(allocated() >= 2 * CHUNK, "allocated {}", allocated());
+    assert_eq!(
+        db.get::<_, Vec<u8>>("key").unwrap(),
+        Some(vec![0x5au8; CHUNK as usize])
+    );
+}
+
+#[test]
+fn api_direct_io() {
+    let file = NamedTempFile::new().unwrap();
+
+    let mut db = OpenOptions::new()
+        .direct(true)
+        .write()
+        .create()
+        .open(file.path())
+        .unwrap();
+
+    (0..2000).for_each(|n| {
+        db.insert(format!("key {}", n), format!("value {}", n))
+            .unwrap();
+    });
+    (0..2000).step_by(3).for_each(|n| {
+        db.remove(&format!("key {}", n)).unwrap();
+    });
+    db.sync().unwrap();
+    drop(db);
+
+    let mut db = OpenOptions::new().direct(true).open(file.path()).unwrap();
+    assert_eq!(db.len().unwrap(), 1333);
+    (0..2000)
+        .try_for_each(|n| {
+            let key = format!("key {}", n);
+            let expected = (n % 3 != 0).then(|| format!("value {}", n));
+            match db.get::<_, String>(&key) {
+                Ok(value) if value == expected => Ok(()),
+                Ok(value) => Err(format!("key \"{}\": got {:?}", key, value)),
+                Err(e) => Err(format!("key \"{}\": {}", key, e)),
+            }
+        })
+        .unwrap_or_else(|e| panic!("{}", e));
+}
+
+#[test]
+fn api_sync_and_verify() {
+    use gdbm_native::Error;
+    use std::io::{Seek, SeekFrom, Write};
+
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .open(file.path())
+        .unwrap();
+
+    (0..1000).for_each(|n| {
+        db.insert(format!("key {}", n), format!("value {}", n))
+            .unwrap();
+    });
+    db.sync_and_verify().unwrap();
+
+    // corrupt the numsync counter behind the database's back
+    let mut f = fs::OpenOptions::new()
+        .write(true)
+        .open(file.path())
+        .unwrap();
+    f.seek(SeekFrom::Start(44)).unwrap();
+    f.write_all(&[0xff]).unwrap();
+    f.sync_all().unwrap();
+
+    match db.sync_and_verify() {
+        Err(Error::SyncMismatch { offset: 0, .. }) => (),
+        result => panic!("expected header mismatch, got {:?}", result),
+    }
+}
+
+#[test]
+fn api_numsync() {
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .open(file.path())
+        .unwrap();
+
+    db.insert("key".to_string(), "value".to_string()).unwrap();
+    db.sync().unwrap();
+    let numsync = db.numsync().unwrap();
+
+    db.touch_numsync().unwrap();
+    db.touch_numsync().unwrap();
+    assert_eq!(db.numsync(), Some(numsync + 2));
+    drop(db);
+
+    // the bumped counter is on disk and the data is untouched
+    let mut db = OpenOptions::new().open(file.path()).unwrap();
+    assert_eq!(db.numsync(), Some(numsync + 2));
+    assert_eq!(
+        db.get::<_, String>("key").unwrap(),
+        Some("value".to_string())
+    );
+
+    // databases without numsync have no counter
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .numsync(false)
+        .open(file.path())
+        .unwrap();
+    db.touch_numsync().unwrap();
+    assert_eq!(db.numsync(), None);
+}
+
+#[test]
+fn api_uuid() {
+    let create = |path: &std::path::Path, identity: bool| {
+        OpenOptions::new()
+            .write()
+            .create()
+            .identity(identity)
+            .open(path)
+            .unwrap()
+    };
+
+    let file = NamedTempFile::new().unwrap();
+    let mut db = create(file.path(), true);
+    let uuid = db.uuid().unwrap();
+    assert_eq!(uuid[6] >> 4, 4);
+    db.insert("key".to_string(), "value".to_string()).unwrap();
+    db.sync().unwrap();
+    drop(db);
+
+    // copies keep the identifier
+    let copy = NamedTempFile::new().unwrap();
+    fs::copy(file.path(), copy.path()).unwrap();
+    let db = OpenOptions::new().open(copy.path()).unwrap();
+    assert_eq!(db.uuid(), Some(uuid));
+
+    // other databases get their own
+    let other = NamedTempFile::new().unwrap();
+    assert_ne!(create(other.path(), true).uuid(), Some(uuid));
+
+    let plain = NamedTempFile::new().unwrap();
+    assert_eq!(create(plain.path(), false).uuid(), None);
+}
+
+#[test]
+fn api_generation() {
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .open(file.path())
+        .unwrap();
+
+    let mut generation = db.generation();
+    let mut changed = |db: &gdbm_native::Gdbm<_>| {
+        let changed = db.generation() > generation;
+        generation = db.generation();
+        changed
+    };
+
+    db.insert("key".to_string(), "value".to_string()).unwrap();
+    assert!(changed(&db));
+    db.get::<_, String>("key").unwrap();
+    assert!(!changed(&db));
+    db.sync().unwrap();
+    assert!(changed(&db));
+    db.remove("missing").unwrap();
+    assert!(!changed(&db));
+    db.remove("key").unwrap();
+    assert!(changed(&db));
+}
+
+#[test]
+fn api_record_cache() {
+    use gdbm_native::{RecordCacheOptions, RecordCacheStats};
+    use std::time::Duration;
+
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .record_cache(Some(RecordCacheOptions {
+            max_bytes: 1024,
+            ttl: None,
+        }))
+        .write()
+        .create()
+        .open(file.path())
+        .unwrap();
+
+    db.insert("key".to_string(), "1".to_string()).unwrap();
+    (0..3).for_each(|_| {
+        assert_eq!(db.get::<_, String>("key").unwrap(), Some("1".to_string()));
+    });
+
+    // repeated gets skip the record read
+    assert_eq!(db.last_op_stats().unwrap().bytes_read, 0);
+    assert_eq!(
+        db.record_cache_stats(),
+        Some(RecordCacheStats {
+            hits: 2,
+            misses: 1,
+            records: 1,
+            bytes: 4,
+        })
+    );
+
+    // updates are seen
+    db.insert("key".to_string(), "2".to_string()).unwrap();
+    assert_eq!(db.get::<_, String>("key").unwrap(), Some("2".to_string()));
+    db.remove("key").unwrap();
+    assert_eq!(db.get::<_, String>("key").unwrap(), None);
+    drop(db);
+
+    // expired records are read again
+    let mut db = OpenOptions::new()
+        .record_cache(Some(RecordCacheOptions {
+            max_bytes: 1024,
+            ttl: Some(Duration::ZERO),
+        }))
+        .write()
+        .open(file.path())
+        .unwrap();
+    db.insert("key".to_string(), "3".to_string()).unwrap();
+    db.get::<_, String>("key").unwrap();
+    db.get::<_, String>("key").unwrap();
+    assert_eq!(db.record_cache_stats().unwrap().hits, 0);
+
+    assert_eq!(
+        OpenOptions::new()
+            .open(file.path())
+            .unwrap()
+            .record_cache_stats(),
+        None
+    );
+}
+
+#[test]
+fn api_event_hook() {
+    use gdbm_native::{BlockSize, Event};
+    use std::sync::{Arc, Mutex};
+
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .block_size(BlockSize::Exactly(512))
+        .open(file.path())
+        .unwrap();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let hook_events = events.clone();
+    db.set_event_hook(Some(Box::new(move |event| {
+        hook_events.lock().unwrap().push(*event)
+    })));
+
+    // large records are freed to the header avail list, overflowing it
+    (0..1000).for_each(|n| {
+        db.insert(format!("key {}", n), vec![0u8; 600]).unwrap();
+    });
+    (0..1000).step_by(2).for_each(|n| {
+        db.remove(&format!("key {}", n)).unwrap();
+    });
+
+    let events = events.lock().unwrap();
+    let count = |f: fn(&Event) -> bool| events.iter().filter(|e| f(e)).count();
+    assert!(count(|e| matches!(e, Event::BucketSplit { .. })) > 0);
+    assert!(count(|e| matches!(e, Event::AvailBlockPushed { .. })) > 0);
+
+    // directory bits grow one at a time
+    events
+        .iter()
+        .filter_map(|e| match e {
+            Event::DirectoryExtended { bits, .. } => Some(*bits),
+            _ => None,
+        })
+        .reduce(|prev, bits| {
+            assert_eq!(bits, prev + 1);
+            bits
+        })
+        .expect("no directory extension");
+}
+
+#[test]
+fn api_event_hook_state() {
+    use std::cell::Cell;
+    use std::sync::mpsc;
+
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .open(file.path())
+        .unwrap();
+
+    // the hook is only called through the handle, so may keep state in a
+    // Cell
+    let (sender, receiver) = mpsc::channel();
+    let slow = Cell::new(0);
+    db.set_event_hook(Some(Box::new(move |_| {
+        slow.set(slow.get() + 1);
+        sender.send(slow.get()).unwrap();
+    })));
+    db.set_slow_threshold(Some(std::time::Duration::ZERO));
+    db.insert("key".to_string(), "value".to_string()).unwrap();
+    db.get::<_, String>("key").unwrap();
+    drop(db);
+
+    let counts = receiver.try_iter().collect::<Vec<_>>();
+    assert!(counts.len() >= 2);
+    assert!(counts.iter().copied().eq(1..=counts.len()));
+}
+
+#[test]
+fn api_slow_operations() {
+    use gdbm_native::{Event, Operation};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .open(file.path())
+        .unwrap();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let hook_events = events.clone();
+    db.set_event_hook(Some(Box::new(move |event| {
+        hook_events.lock().unwrap().push(*event)
+    })));
+
+    // nothing is reported without a threshold
+    db.insert("key".to_string(), "value".to_string()).unwrap();
+    assert!(events.lock().unwrap().is_empty());
+
+    // every operation takes at least no time
+    db.set_slow_threshold(Some(Duration::ZERO));
+    db.get::<_, String>("key").unwrap();
+    db.remove("key").unwrap();
+    db.sync().unwrap();
+
+    let events = events.lock().unwrap();
+    let operations = events
+        .iter()
+        .filter_map(|e| match e {
+            Event::SlowOperation { operation, .. } => Some(*operation),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        operations,
+        vec![Operation::Get, Operation::Remove, Operation::Sync]
+    );
+
+    // the bucket was cached, but the record was read from the file
+    match events[0] {
+        Event::SlowOperation {
+            bucket_offset,
+            buckets_read,
+            bytes_read,
+            ..
+        } => {
+            assert!(bucket_offset.is_some());
+            assert_eq!(buckets_read, 0);
+            assert_eq!(bytes_read, "key".len() as u64 + "value".len() as u64);
+        }
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn api_last_op_stats() {
+    use gdbm_native::{BlockSize, Operation};
+
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .block_size(BlockSize::Exactly(512))
+        .open(file.path())
+        .unwrap();
+    assert_eq!(db.last_op_stats(), None);
+
+    // small buckets split soon
+    let splits = (0..100)
+        .map(|n| {
+            db.insert(format!("key {n}"), format!("value {n}")).unwrap();
+            let stats = db.last_op_stats().unwrap();
+            assert_eq!(stats.operation, Operation::Insert);
+            stats.splits
+        })
+        .sum::<u64>();
+    assert!(splits > 0);
+
+    db.sync().unwrap();
+    let stats = db.last_op_stats().unwrap();
+    assert_eq!(stats.operation, Operation::Sync);
+    assert!(stats.bytes_written > 0);
+    assert_eq!(stats.bytes_read, 0);
+
+    db.get::<_, String>("key 0").unwrap();
+    let stats = db.last_op_stats().unwrap();
+    assert_eq!(stats.operation, Operation::Get);
+    assert_eq!(
+        stats.bytes_read,
+        "key 0".len() as u64 + "value 0".len() as u64
+    );
+    assert_eq!((stats.bytes_written, stats.splits), (0, 0));
+}
+
+#[test]
+fn api_write_amplification() {
+    let ratio = |sync: bool| {
+        let file = NamedTempFile::new().unwrap();
+        let mut db = OpenOptions::new()
+            .write()
+            .sync(sync)
+            .create()
+            .open(file.path())
+            .unwrap();
+        assert_eq!(db.write_amplification().ratio(), None);
+
+        (0..200).for_each(|n| {
+            db.insert(format!("key {n}"), format!("value {n}")).unwrap();
+        });
+        db.sync().unwrap();
+
+        let amplification = db.write_amplification();
+        assert!(amplification.bytes_inserted > 0);
+        amplification.ratio().unwrap()
+    };
+
+    // syncing every insert rewrites the header, directory and bucket
+    let (unsynced, synced) = (ratio(false), ratio(true));
+    assert!(unsynced > 1.0);
+    assert!(synced > unsynced * 10.0);
+}
+
+#[test]
+fn api_codec() {
+    use gdbm_native::{Codec, Error, Result};
+
+    // prefixes values with a tag
+    struct Tag(&'static [u8]);
+
+    impl Codec for Tag {
+        fn encode(&self, value: Vec<u8>) -> Result<Vec<u8>> {
+            Ok([self.0, &value].concat())
+        }
+
+        fn decode(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+            data.strip_prefix(self.0)
+                .map(<[u8]>::to_vec)
+                .ok_or_else(|| Error::Codec("missing tag".to_string()))
+        }
+    }
+
+    let file = NamedTempFile::new().unwrap();
+    let options = OpenOptions::new()
+        .codec(Box::new(Tag(b"inner:")))
+        .codec(Box::new(Tag(b"outer:")));
+
+    let mut db = options.clone().write().create().open(file.path()).unwrap();
+    db.insert("key".to_string(), "value".to_string()).unwrap();
+    assert_eq!(
+        db.insert("key".to_string(), "new value".to_string())
+            .unwrap(),
+        Some(b"value".to_vec())
+    );
+    db.insert("other".to_string(), "value".to_string()).unwrap();
+    assert_eq!(
+        db.get::<_, String>("key").unwrap(),
+        Some("new value".to_string())
+    );
+    assert_eq!(
+        db.get_many::<_, String>(["key", "other"]).unwrap(),
+        vec![Some("new value".to_string()), Some("value".to_string())]
+    );
+    assert_eq!(db.remove("other").unwrap(), Some(b"value".to_vec()));
+    db.close().unwrap();
+
+    // values are stored encoded by each codec in turn
+    let mut db = OpenOptions::new().open(file.path()).unwrap();
+    assert_eq!(
+        db.get::<_, String>("key").unwrap(),
+        Some("outer:inner:new value".to_string())
+    );
+
+    let mut db = options.open(file.path()).unwrap();
+    let values = db.values::<String>().collect::<Result<Vec<_>>>().unwrap();
+    assert_eq!(values, vec!["new value".to_string()]);
+
+    // data the codecs can't decode is an error
+    let mut db = OpenOptions::new()
+        .codec(Box::new(Tag(b"other:")))
+        .open(file.path())
+        .unwrap();
+    assert!(matches!(db.get::<_, String>("key"), Err(Error::Codec(_))));
+}
+
+#[test]
+fn api_max_file_size() {
+    use gdbm_native::Error;
+
+    const MAX_FILE_SIZE: u64 = 64 * 1024;
+
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .max_file_size(Some(MAX_FILE_SIZE))
+        .open(file.path())
+        .unwrap();
+
+    let value = vec![0x5au8; 1000];
+    let stored = (0..)
+        .find(|n| match db.insert(format!("key {}", n), value.clone()) {
+            Ok(_) => false,
+            Err(Error::DatabaseFull { size, maximum }) => {
+                assert!(size > maximum);
+                assert_eq!(maximum, MAX_FILE_SIZE);
+                true
+            }
+            Err(e) => panic!("insert {}: {}", n, e),
+        })
+        .unwrap();
+    assert!(stored > 0);
+
+    // the database is still usable: freed space can be reused
+    db.remove(&"key 0".to_string()).unwrap();
+    db.insert("key 0".to_string(), value.clone()).unwrap();
+    db.sync().unwrap();
+    assert_eq!(db.len().unwrap(), stored);
+    assert!(fs::metadata(file.path()).unwrap().len() <= MAX_FILE_SIZE);
+}
+
+#[test]
+fn api_max_file_size_replace() {
+    use gdbm_native::Error;
+
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .max_file_size(Some(64 * 1024))
+        .open(file.path())
+        .unwrap();
+
+    let value = vec![0x5au8; 1000];
+    let stored = (0..)
+        .find(|n| {
+            db.insert(format!("key {}", n), value.clone())
+                .is_err_and(|e| matches!(e, Error::DatabaseFull { .. }))
+        })
+        .unwrap();
+
+    // a replacing insert with no room for its record keeps the old one
+    let result = db.insert("key 0".to_string(), vec![0xa5u8; 20000]);
+    assert!(matches!(result, Err(Error::DatabaseFull { .. })));
+    assert_eq!(db.get::<_, Vec<u8>>("key 0").unwrap(), Some(value.clone()));
+    assert_eq!(db.len().unwrap(), stored);
+
+    db.sync().unwrap();
+    drop(db);
+
+    let mut db = OpenOptions::new().open(file.path()).unwrap();
+    assert_eq!(db.get::<_, Vec<u8>>("key 0").unwrap(), Some(value));
+    assert_eq!(db.len().unwrap(), stored);
+}
+
+#[test]
+fn api_store() {
+    use gdbm_native::{Error, InsertMode};
+
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .open(file.path())
+        .unwrap();
+
+    let get = |db: &mut gdbm_native::Gdbm<_>| db.get::<_, String>("key").unwrap();
+
+    assert!(db
+        .store("key".to_string(), "1".to_string(), InsertMode::Insert)
+        .unwrap());
+    assert!(!db
+        .store("key".to_string(), "2".to_string(), InsertMode::Insert)
+        .unwrap());
+    assert_eq!(get(&mut db), Some("1".to_string()));
+
+    assert!(matches!(
+        db.store(
+            "key".to_string(),
+            "3".to_string(),
+            InsertMode::ErrorIfExists
+        ),
+        Err(Error::KeyExists)
+    ));
+    assert_eq!(get(&mut db), Some("1".to_string()));
+
+    assert!(db
+        .store("key".to_string(), "4".to_string(), InsertMode::Replace)
+        .unwrap());
+    assert_eq!(get(&mut db), Some("4".to_string()));
+
+    db.remove("key").unwrap();
+    assert!(db
+        .store(
+            "key".to_string(),
+            "5".to_string(),
+            InsertMode::ErrorIfExists
+        )
+        .unwrap());
+    assert_eq!(get(&mut db), Some("5".to_string()));
+}
+
+#[test]
+fn api_required() {
+    use gdbm_native::Error;
+
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .open(file.path())
+        .unwrap();
+
+    assert!(matches!(
+        db.get_required::<_, String>("key"),
+        Err(Error::KeyNotFound(key)) if key.as_bytes() == b"key"
+    ));
+    assert!(matches!(
+        db.update_required("key".to_string(), "1".to_string()),
+        Err(Error::KeyNotFound(_))
+    ));
+    assert_eq!(db.len().unwrap(), 0);
+
+    db.insert("key".to_string(), "1".to_string()).unwrap();
+    assert_eq!(
+        db.update_required("key".to_string(), "2".to_string())
+            .unwrap(),
+        b"1"
+    );
+    assert_eq!(db.get_required::<_, String>("key").unwrap(), "2");
+    assert_eq!(db.remove_required("key").unwrap(), b"2");
+    assert!(matches!(
+        db.remove_required("key"),
+        Err(Error::KeyNotFound(_))
+    ));
+}
+
+#[test]
+fn api_datum() {
+    use gdbm_native::{Datum, Error};
+
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .open(file.path())
+        .unwrap();
+
+    db.insert(vec![0xffu8, 0x00], "binary".to_string()).unwrap();
+    let records = db
+        .iter::<Datum, Datum>()
+        .map(|kv| kv.map(|(key, value)| format!("{key} {value}")))
+        .collect::<gdbm_native::Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(records, vec!["0xff00 \"binary\"".to_string()]);
+
+    let e = db.get_required::<_, Datum>(&vec![0xfeu8]).unwrap_err();
+    assert!(matches!(e, Error::KeyNotFound(_)));
+    assert!(e.to_string().contains("0xfe"));
+}
+
+#[test]
+fn api_debug_display() {
+    use gdbm_native::BlockSize;
+
+    let file = NamedTempFile::new().unwrap();
+    let path = file.path().to_string_lossy().to_string();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .block_size(BlockSize::Exactly(512))
+        .open(file.path())
+        .unwrap();
+
+    db.insert("key".to_string(), "value".to_string()).unwrap();
+    let debug = format!("{:?}", db);
+    assert!(debug.starts_with("Gdbm {"));
+    assert!(debug.contains(&path));
+    assert!(debug.contains("write_state: Some(Dirty)"));
+    assert!(db.to_string().ends_with("unsynced changes)"));
+
+    db.sync().unwrap();
+    db.len().unwrap();
+    assert_eq!(
+        db.to_string(),
+        format!("{} (GDBM_NUMSYNC_MAGIC64, block size 512, 1 record)", path)
+    );
+    drop(db);
+
+    let db = OpenOptions::new().open(file.path()).unwrap();
+    assert!(format!("{:?}", db).contains("write_state: None"));
+    assert!(db.to_string().ends_with("records not counted, read-only)"));
+}
+
+#[test]
+fn api_get_or_insert_with() {
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .open(file.path())
+        .unwrap();
+
+    let value: String = db
+        .get_or_insert_with("key".to_string(), || "1".to_string())
+        .unwrap();
+    assert_eq!(value, "1");
+
+    // an existing value is returned without computing the default
+    let value: String = db
+        .get_or_insert_with("key".to_string(), || -> String { unreachable!() })
+        .unwrap();
+    assert_eq!(value, "1");
+    assert_eq!(db.len().unwrap(), 1);
+}
+
+#[test]
+fn api_entry() {
+    use gdbm_native::BlockSize;
+
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .block_size(BlockSize::Exactly(512))
+        .open(file.path())
+        .unwrap();
+
+    // counts, over enough keys to split buckets
+    (0..300).for_each(|n| {
+        let _: Vec<u8> = db
+            .entry(format!("key {}", n % 100))
+            .unwrap()
+            .and_modify(|count| count[0] += 1)
+            .unwrap()
+            .or_insert(vec![1u8])
+            .unwrap();
+    });
+    assert_eq!(db.len().unwrap(), 100);
+    assert!((0..100).all(|n| { db.get(format!("key {n}").as_bytes()).unwrap() == Some(vec![3u8]) }));
+
+    let entry = db.entry("key 5".to_string()).unwrap();
+    assert!(entry.is_occupied());
+    assert_eq!(entry.value(), Some(&[3u8][..]));
+
+    // modified twice, through one lookup
+    let value: Vec<u8> = entry
+        .and_modify(|value| value.push(4))
+        .unwrap()
+        .and_modify(|value| value.push(5))
+        .unwrap()
+        .or_insert_with(|| -> Vec<u8> { unreachable!() })
+        .unwrap();
+    assert_eq!(value, vec![3, 4, 5]);
+
+    // a missing key is inserted, and not modified
+    let entry = db.entry("new".to_string()).unwrap();
+    assert!(!entry.is_occupied());
+    assert_eq!(entry.value(), None);
+    let value: String = entry
+        .and_modify(|_| unreachable!())
+        .unwrap()
+        .or_insert_with(|| "inserted".to_string())
+        .unwrap();
+    assert_eq!(value, "inserted");
+    assert_eq!(db.len().unwrap(), 101);
+    db.close().unwrap();
+
+    let mut db = OpenOptions::new().open(file.path()).unwrap();
+    db.verify().unwrap();
+    assert_eq!(db.get("key 5").unwrap(), Some(vec![3u8, 4, 5]));
+    assert_eq!(db.get("new").unwrap(), Some(b"inserted".to_vec()));
+}
+
+#[test]
+fn api_entry_replace() {
+    use gdbm_native::{BlockSize, Error};
+
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .block_size(BlockSize::Exactly(512))
+        .open(file.path())
+        .unwrap();
+    (0..1000).for_each(|n| {
+        db.insert(format!("KEY {}", n), n.to_string()).unwrap();
+    });
+    db.close().unwrap();
+
+    // moving records to their normalized keys, among other inserts, splits
+    // buckets and extends the directory between modifications of an entry
+    let mut db = OpenOptions::new()
+        .normalize_keys(Some(|key: &[u8]| key.to_ascii_lowercase()))
+        .probe_unnormalized_keys(true)
+        .write()
+        .open(file.path())
+        .unwrap();
+    (0..1000).for_each(|n| {
+        db.insert(format!("more {}", n), n.to_string()).unwrap();
+        let value: String = db
+            .entry(format!("KEY {}", n))
+            .unwrap()
+            .and_modify(|value| value.push(b'a'))
+            .unwrap()
+            .and_modify(|value| value.push(b'b'))
+            .unwrap()
+            .or_insert_with(|| -> String { unreachable!() })
+            .unwrap();
+        assert_eq!(value, format!("{n}ab"));
+    });
+    assert_eq!(db.len().unwrap(), 2000);
+    assert!((0..1000).all(|n| {
+        db.get_raw(format!("key {n}").as_bytes()).unwrap() == Some(format!("{n}ab").into_bytes())
+    }));
+    db.close().unwrap();
+
+    let mut db = OpenOptions::new().open(file.path()).unwrap();
+    db.verify().unwrap();
+    assert_eq!(db.len().unwrap(), 2000);
+
+    // a modification with no room for its record keeps the old one
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .max_file_size(Some(64 * 1024))
+        .open(file.path())
+        .unwrap();
+    let value = vec![0x5au8; 1000];
+    let stored = (0..)
+        .find(|n| {
+            db.insert(format!("key {}", n), value.clone())
+                .is_err_and(|e| matches!(e, Error::DatabaseFull { .. }))
+        })
+        .unwrap();
+    let result = db
+        .entry("key 0".to_string())
+        .unwrap()
+        .and_modify(|value| value.resize(20000, 0));
+    assert!(matches!(result, Err(Error::DatabaseFull { .. })));
+    assert_eq!(db.get("key 0").unwrap(), Some(value));
+    assert_eq!(db.len().unwrap(), stored);
+}
+
+#[test]
+fn api_get_many_by_bucket() {
+    use gdbm_native::BlockSize;
+
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .block_size(BlockSize::Exactly(512))
+        .open(file.path())
+        .unwrap();
+    (0..500).for_each(|n| {
+        db.insert(format!("key {n}"), format!("value {n}")).unwrap();
+    });
+    db.close().unwrap();
+
+    // a cache of one bucket, and keys in no particular bucket order
+    let mut db = OpenOptions::new()
+        .cachesize(Some(0))
+        .open(file.path())
+        .unwrap();
+    let keys = (0..500)
+        .map(|n| format!("key {}", n * 7 % 500))
+        .collect::<Vec<_>>();
+    let values = db
+        .get_many::<_, String>(keys.iter().map(String::as_str))
+        .unwrap();
+    assert!(values
+        .iter()
+        .zip(&keys)
+        .all(|(value, key)| value.as_deref() == Some(&key.replace("key", "value"))));
+
+    // each bucket was read once
+    let buckets = db.buckets().len() as u64;
+    assert!(buckets > 1);
+    assert_eq!(db.last_op_stats().unwrap().buckets_read, buckets);
+}
+
+#[test]
+fn api_insert_many() {
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .sync(true)
+        .create()
+        .numsync(true)
+        .open(file.path())
+        .unwrap();
+    db.insert("key 7".to_string(), "old".to_string()).unwrap();
+
+    // a sync mode db syncs once, at the end
+    let numsync = db.numsync().unwrap();
+    db.insert_many((0..1000).map(|n| (format!("key {n}"), format!("value {n}"))))
+        .unwrap();
+    assert_eq!(db.numsync().unwrap(), numsync + 1);
+    assert_eq!(db.len().unwrap(), 1000);
+    db.close().unwrap();
+
+    let mut db = OpenOptions::new().open(file.path()).unwrap();
+    db.verify().unwrap();
+    assert!((0..1000).all(|n| {
+        db.get::<_, String>(format!("key {n}").as_bytes()).unwrap() == Some(format!("value {n}"))
+    }));
+}
+
+#[test]
+fn api_len_cached() {
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .open(file.path())
+        .unwrap();
+
+    (0..100).for_each(|n| {
+        db.insert(n.to_string(), "value".to_string()).unwrap();
+    });
+    db.insert("0".to_string(), "replaced".to_string()).unwrap();
+    db.remove("1").unwrap();
+    db.remove("missing").unwrap();
+    assert_eq!(db.len().unwrap(), 99);
+    assert_eq!(db.iter::<String, String>().size_hint(), (99, Some(99)));
+    db.sync().unwrap();
+    drop(db);
+
+    // counted afresh on reopen
+    let mut db = OpenOptions::new().open(file.path()).unwrap();
+    assert_eq!(db.len().unwrap(), 99);
+    assert_eq!(db.keys::<String>().count(), 99);
+}
+
+#[test]
+fn api_raw() {
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .open(file.path())
+        .unwrap();
+
+    assert_eq!(db.insert_raw(b"key", b"one").unwrap(), None);
+    assert_eq!(
+        db.insert_raw(b"key", &[0, 1, 2]).unwrap(),
+        Some(b"one".to_vec())
+    );
+    assert_eq!(db.get_raw(b"key").unwrap(), Some(vec![0, 1, 2]));
+    assert_eq!(db.get::<_, Vec<u8>>("key").unwrap(), Some(vec![0, 1, 2]));
+    assert_eq!(db.get_raw(b"missing").unwrap(), None);
+
+    assert_eq!(db.remove_raw(b"key").unwrap(), Some(vec![0, 1, 2]));
+    assert_eq!(db.remove_raw(b"key").unwrap(), None);
+    assert_eq!(db.get_raw(b"key").unwrap(), None);
+}
+
+#[test]
+fn api_usize_keys() {
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .open(file.path())
+        .unwrap();
+
+    (0..100usize).for_each(|n| {
+        db.insert(n, n.to_string()).unwrap();
+    });
+    (0..100usize).for_each(|n| {
+        assert_eq!(db.get(&n).unwrap(), Some(n.to_string()));
+        assert_eq!(
+            db.get_raw(&n.to_be_bytes()).unwrap(),
+            Some(n.to_string().into_bytes())
+        );
+    });
+    assert_eq!(db.remove(&7usize).unwrap(), Some(b"7".to_vec()));
+    assert!(!db.contains_key(&7usize).unwrap());
+}
+
+#[test]
+fn api_normalize_keys() {
+    fn lowercase(key: &[u8]) -> Vec<u8> {
+        key.to_ascii_lowercase()
+    }
+
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .open(file.path())
+        .unwrap();
+    db.insert("Old".to_string(), "1".to_string()).unwrap();
+    db.insert("Gone".to_string(), "2".to_string()).unwrap();
+    db.sync().unwrap();
+    drop(db);
+
+    let mut db = OpenOptions::new()
+        .normalize_keys(Some(lowercase))
+        .write()
+        .open(file.path())
+        .unwrap();
+    db.insert("NEW".to_string(), "3".to_string()).unwrap();
+    assert_eq!(db.get("New").unwrap(), Some("3".to_string()));
+    assert_eq!(db.get_raw(b"new").unwrap(), Some(b"3".to_vec()));
+    assert!(db.keys::<String>().any(|k| k.unwrap() == "new"));
+
+    // records stored before normalization are missed...
+    assert_eq!(db.get::<_, String>("Old").unwrap(), None);
+    drop(db);
+
+    // ...unless the key as given is probed too
+    let mut db = OpenOptions::new()
+        .normalize_keys(Some(lowercase))
+        .probe_unnormalized_keys(true)
+        .write()
+        .open(file.path())
+        .unwrap();
+    assert_eq!(db.get("Old").unwrap(), Some("1".to_string()));
+    assert!(db.contains_key("Gone").unwrap());
+
+    // replacing moves the record to its normalized key
+    assert_eq!(
+        db.insert("Old".to_string(), "4".to_string()).unwrap(),
+        Some(b"1".to_vec())
+    );
+    assert_eq!(db.get_raw(b"old").unwrap(), Some(b"4".to_vec()));
+    assert_eq!(db.remove("Gone").unwrap(), Some(b"2".to_vec()));
+    assert_eq!(db.len().unwrap(), 2);
+}
+
+#[test]
+fn api_c_string_keys() {
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .c_string_keys(true)
+        .write()
+        .create()
+        .open(file.path())
+        .unwrap();
+    db.insert("key".to_string(), "value".to_string()).unwrap();
+    assert_eq!(db.get("key").unwrap(), Some("value".to_string()));
+    assert_eq!(
+        db.keys::<String>().collect::<Result<Vec<_>, _>>().unwrap(),
+        vec!["key".to_string()]
+    );
+    db.sync().unwrap();
+    drop(db);
+
+    // stored as C programs store strlen(key) + 1 bytes
+    let mut db = OpenOptions::new().open(file.path()).unwrap();
+    assert_eq!(db.get_raw(b"key\0").unwrap(), Some(b"value".to_vec()));
+    assert_eq!(db.get_raw(b"key").unwrap(), None);
+}
+
+#[test]
+fn api_compact_c_string_keys() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("compact.db");
+    let mut db = OpenOptions::new()
+        .c_string_keys(true)
+        .write()
+        .create()
+        .open(&path)
+        .unwrap();
+    (0..100).for_each(|n| {
+        db.insert(format!("key {}", n), format!("value {}", n))
+            .unwrap();
+    });
+    (0..100).filter(|n| n % 2 == 0).for_each(|n| {
+        db.remove(format!("key {}", n).as_str()).unwrap();
+    });
+    db.compact().unwrap();
+
+    // still found by the handle, and stored NUL-terminated
+    assert_eq!(db.get("key 1").unwrap(), Some("value 1".to_string()));
+    assert_eq!(db.len().unwrap(), 50);
+    db.close().unwrap();
+
+    let mut db = OpenOptions::new().open(&path).unwrap();
+    assert_eq!(db.get_raw(b"key 1\0").unwrap(), Some(b"value 1".to_vec()));
+    assert_eq!(db.get_raw(b"key 1").unwrap(), None);
+}
+
+#[test]
+fn api_avail_policy() {
+    use gdbm_native::AvailPolicy;
+
+    let churn = |policy: AvailPolicy| {
+        let file = NamedTempFile::new().unwrap();
+        let mut db = OpenOptions::new()
+            .write()
+            .avail_policy(policy)
+            .create()
+            .open(file.path())
+            .unwrap();
+
+        (0..10).for_each(|round| {
+            (0..200).for_each(|n| {
+                db.insert(
+                    format!("key {n}"),
+                    vec![round as u8; 50 + (n + round) % 100],
+                )
+                .unwrap();
+            });
+        });
+        (0..200).for_each(|n| {
+            let value = db.get::<_, Vec<u8>>(&format!("key {n}")).unwrap().unwrap();
+            assert_eq!(value, vec![9; 50 + (n + 9) % 100]);
+        });
+        db.verify().unwrap();
+        db.sync().unwrap();
+
+        file.as_file().metadata().unwrap().len()
+    };
+
+    let default = churn(AvailPolicy::default());
+    let header_only = churn(AvailPolicy {
+        bucket_slots: 0,
+        ..AvailPolicy::default()
+    });
+    let forget_all = churn(AvailPolicy {
+        ignore_small: u32::MAX,
+        ..AvailPolicy::default()
+    });
+
+    // space that is never reused only grows the file
+    assert!(forget_all > default);
+    assert!(forget_all > header_only);
+}
+
+#[test]
+fn api_compact() {
+    use gdbm_native::{Codec, Error, Result};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    // stores values reversed once broken
+    struct Breakable(Arc<AtomicBool>);
+
+    impl Codec for Breakable {
+        fn encode(&self, mut value: Vec<u8>) -> Result<Vec<u8>> {
+            if self.0.load(Ordering::Relaxed) {
+                value.reverse();
+            }
+            Ok(value)
+        }
+
+        fn decode(&self, data: Vec<u8>) -> Result<Vec<u8>> {
+            Ok(data)
+        }
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("compact.db");
+    let broken = Arc::new(AtomicBool::new(false));
+    let mut db = OpenOptions::new()
+        .codec(Box::new(Breakable(broken.clone())))
+        .write()
+        .create()
+        .identity(true)
+        .open(&path)
+        .unwrap();
+
+    (0..2000).for_each(|n| {
+        db.insert(format!("key {}", n), format!("value {}", n).repeat(10))
+            .unwrap();
+    });
+    (0..2000).filter(|n| n % 10 != 0).for_each(|n| {
+        db.remove(format!("key {}", n).as_str()).unwrap();
+    });
+    db.sync().unwrap();
+    let size = fs::metadata(&path).unwrap().len();
+    let uuid = db.uuid();
+    let generation = db.generation();
+    let mut reader = OpenOptions::new().open(&path).unwrap();
+
+    // a rebuild that doesn't match leaves the database as it was
+    broken.store(true, Ordering::Relaxed);
+    assert!(matches!(
+        db.compact(),
+        Err(Error::CompactMismatch { key: Some(_) })
+    ));
+    assert_eq!(fs::metadata(&path).unwrap().len(), size);
+    assert!(!dir.path().join("compact.db.compact").exists());
+    broken.store(false, Ordering::Relaxed);
+
+    db.compact().unwrap();
+    assert!(fs::metadata(&path).unwrap().len() < size);
+    assert!(!dir.path().join("compact.db.compact").exists());
+    assert_eq!(db.uuid(), uuid);
+    assert!(db.generation() > generation);
+
+    // renamed over the original, which is left whole for its readers
+    assert_eq!(reader.len().unwrap(), 200);
+    assert_eq!(
+        reader.get::<_, String>("key 10").unwrap(),
+        Some("value 10".repeat(10))
+    );
+    drop(reader);
+
+    // the handle and the file both hold the records
+    db.insert("key 1".to_string(), "new".to_string()).unwrap();
+    db.close().unwrap();
+    let mut db = OpenOptions::new().open(&path).unwrap();
+    assert_eq!(db.len().unwrap(), 201);
+    (0..2000).step_by(10).for_each(|n| {
+        assert_eq!(
+            db.get::<_, String>(format!("key {}", n).as_str()).unwrap(),
+            Some(format!("value {}", n).repeat(10))
+        );
+    });
+    assert_eq!(
+        db.get::<_, String>("key 1").unwrap(),
+        Some("new".to_string())
+    );
+}
+
+#[test]
+fn api_compact_options() {
+    use gdbm_native::CompactOptions;
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::{Duration, SystemTime};
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("compact.db");
+    let mut db = OpenOptions::new().write().create().open(&path).unwrap();
+    (0..500).for_each(|n| {
+        db.insert(format!("key {}", n), format!("value {}", n))
+            .unwrap();
+    });
+    db.sync().unwrap();
+
+    let past = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+    [false, true].into_iter().for_each(|preserve_times| {
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|f| f.set_modified(past))
+            .unwrap();
+
+        db.compact_with(&CompactOptions {
+            preserve_times,
+            fsync_dir: true,
+        })
+        .unwrap();
+
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o640);
+        assert_eq!(metadata.modified().unwrap() == past, preserve_times);
+        assert_eq!(db.len().unwrap(), 500);
+    });
+}
+
+#[test]
+fn api_replace_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let live = dir.path().join("live.db");
+    let mut db = OpenOptions::new().write().create().open(&live).unwrap();
+    db.insert("key".to_string(), "old".to_string()).unwrap();
+    db.close().unwrap();
+    let mut reader = OpenOptions::new().open(&live).unwrap();
+
+    // built offline, in another directory
+    let build = tempfile::tempdir_in(dir.path()).unwrap();
+    let new_path = build.path().join("new.db");
+    let mut new_db = OpenOptions::new().write().create().open(&new_path).unwrap();
+    new_db.insert("key".to_string(), "new".to_string()).unwrap();
+
+    gdbm_native::replace_file(&live, new_db).unwrap();
+    assert!(!new_path.exists());
+
+    assert_eq!(
+        OpenOptions::new()
+            .open(&live)
+            .unwrap()
+            .get::<_, String>("key")
+            .unwrap(),
+        Some("new".to_string())
+    );
+    assert_eq!(
+        reader.get::<_, String>("key").unwrap(),
+        Some("old".to_string())
+    );
+}
+
+#[test]
+fn api_convert_on_write() {
+    use gdbm_native::Endian;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("be.db");
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .endian(Some(Endian::Big))
+        .open(&path)
+        .unwrap();
+    (0..100).for_each(|n| {
+        db.insert(format!("key {}", n), format!("value {}", n))
+            .unwrap();
+    });
+    db.close().unwrap();
+
+    let mut db = OpenOptions::new()
+        .write()
+        .convert_on_write(Endian::Little)
+        .open(&path)
+        .unwrap();
+    db.insert("key 100".to_string(), "value 100".to_string())
+        .unwrap();
+    assert_eq!(db.magic().endian(), Endian::Big);
+
+    db.compact().unwrap();
+    assert_eq!(db.magic().endian(), Endian::Little);
+    db.close().unwrap();
+
+    let mut db = OpenOptions::new().open(&path).unwrap();
+    assert_eq!(db.magic().endian(), Endian::Little);
+    assert_eq!(db.len().unwrap(), 101);
+    (0..=100).for_each(|n| {
+        assert_eq!(
+            db.get::<_, String>(format!("key {}", n).as_str()).unwrap(),
+            Some(format!("value {}", n))
+        );
+    });
+}
+
+#[test]
+fn api_shadow_metadata() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("shadow.db");
+    let mut db = OpenOptions::new()
+        .write()
+        .shadow_metadata(true)
+        .create()
+        .block_size(gdbm_native::BlockSize::Exactly(512))
+        .open(&path)
+        .unwrap();
+
+    // sync between batches, so the directory moves as it grows
+    (0..10).for_each(|batch| {
+        (0..100).for_each(|n| {
+            let n = batch * 100 + n;
+            db.insert(format!("key {}", n), format!("value {}", n))
+                .unwrap();
+        });
+        db.sync().unwrap();
+    });
+    db.close().unwrap();
+
+    let mut db = OpenOptions::new().open(&path).unwrap();
+    assert_eq!(db.len().unwrap(), 1000);
+    (0..1000).for_each(|n| {
+        assert_eq!(
+            db.get::<_, String>(format!("key {}", n).as_str()).unwrap(),
+            Some(format!("value {}", n))
+        );
+    });
+}
+
+#[test]
+fn api_overlay() {
+    use gdbm_native::Overlay;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("overlay.db");
+    let mut db = OpenOptions::new().write().create().open(&path).unwrap();
+    (0..10).for_each(|n| {
+        db.insert(format!("key {}", n), format!("value {}", n))
+            .unwrap();
+    });
+
+    let mut overlay = Overlay::new(&mut db);
+    assert_eq!(
+        overlay
+            .insert("key 0".to_string(), "new 0".to_string())
+            .unwrap(),
+        Some(b"value 0".to_vec())
+    );
+    assert_eq!(
+        overlay
+            .insert("key 10".to_string(), "value 10".to_string())
+            .unwrap(),
+        None
+    );
+    assert_eq!(overlay.remove("key 1").unwrap(), Some(b"value 1".to_vec()));
+    assert_eq!(overlay.remove("key 1").unwrap(), None);
+    assert_eq!(overlay.get("key 0").unwrap(), Some("new 0".to_string()));
+    assert_eq!(overlay.get("key 2").unwrap(), Some("value 2".to_string()));
+    assert!(!overlay.contains_key("key 1").unwrap());
+    assert!(overlay.contains_key("key 10").unwrap());
+    assert_eq!(overlay.len(), 3);
+    overlay.discard();
+
+    assert_eq!(db.len().unwrap(), 10);
+    assert_eq!(db.get("key 0").unwrap(), Some("value 0".to_string()));
+
+    let mut overlay = Overlay::new(&mut db);
+    overlay
+        .insert("key 0".to_string(), "new 0".to_string())
+        .unwrap();
+    overlay
+        .insert("key 10".to_string(), "value 10".to_string())
+        .unwrap();
+    overlay.remove("key 1").unwrap();
+    overlay.commit().unwrap();
+
+    assert_eq!(db.len().unwrap(), 10);
+    assert_eq!(db.get("key 0").unwrap(), Some("new 0".to_string()));
+    assert_eq!(db.get::<_, String>("key 1").unwrap(), None);
+    assert_eq!(db.get("key 10").unwrap(), Some("value 10".to_string()));
+}
+
+#[test]
+fn api_overlay_commit_failure() {
+    use gdbm_native::{Error, Overlay};
+
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .sync(true)
+        .max_file_size(Some(64 * 1024))
+        .open(file.path())
+        .unwrap();
+
+    let value = vec![0x5au8; 1000];
+    let stored = (0..)
+        .find(|n| {
+            db.insert(format!("key {}", n), value.clone())
+                .is_err_and(|e| matches!(e, Error::DatabaseFull { .. }))
+        })
+        .unwrap();
+
+    // the large record doesn't fit: whatever was applied before it is undone
+    let mut overlay = Overlay::new(&mut db);
+    overlay
+        .insert("key 0".to_string(), "new 0".to_string())
+        .unwrap();
+    overlay.remove("key 1").unwrap();
+    overlay
+        .insert("key".to_string(), vec![0xa5u8; 20000])
+        .unwrap();
+    let result = overlay.commit();
+    assert!(matches!(result, Err(Error::DatabaseFull { .. })));
+    drop(db);
+
+    let mut db = OpenOptions::new().open(file.path()).unwrap();
+    assert_eq!(db.len().unwrap(), stored);
+    assert_eq!(db.get::<_, Vec<u8>>("key 0").unwrap(), Some(value.clone()));
+    assert_eq!(db.get::<_, Vec<u8>>("key 1").unwrap(), Some(value));
+    assert!(!db.contains_key("key").unwrap());
+}
+
+#[test]
+fn api_digest_keys() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("digest.db");
+    let large = |n: usize| format!("{}{}", "x".repeat(100000), n);
+
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .extended(true)
+        .digest_keys(Some(64))
+        .open(&path)
+        .unwrap();
+    (0..20).for_each(|n| {
+        db.insert(format!("key {}", n), format!("value {}", n))
+            .unwrap();
+        db.insert(large(n), format!("large {}", n)).unwrap();
+    });
+    assert_eq!(
+        db.insert(large(0), "large 0 again".to_string()).unwrap(),
+        Some(b"large 0".to_vec())
+    );
+    assert_eq!(
+        db.remove(large(1).as_str()).unwrap(),
+        Some(b"large 1".to_vec())
+    );
+    db.compact().unwrap();
+    db.close().unwrap();
+
+    let mut db = OpenOptions::new().open(&path).unwrap();
+    assert_eq!(db.len().unwrap(), 39);
+    assert_eq!(
+        db.get::<_, String>(large(0).as_str()).unwrap(),
+        Some("large 0 again".to_string())
+    );
+    assert_eq!(db.get::<_, String>(large(1).as_str()).unwrap(), None);
+    assert!(db.contains_key(large(2).as_str()).unwrap());
+    assert!(!db.contains_key(large(20).as_str()).unwrap());
+
+    let mut keys = db.keys::<String>().collect::<Result<Vec<_>, _>>().unwrap();
+    keys.sort();
+    let mut expected = (0..20)
+        .map(|n| format!("key {}", n))
+        .chain((0..20).filter(|n| *n != 1).map(large))
+        .collect::<Vec<_>>();
+    expected.sort();
+    assert_eq!(keys, expected);
+
+    db.iter::<String, String>().for_each(|kv| {
+        let (key, value) = kv.unwrap();
+        match key.starts_with('x') {
+            true => assert!(value.starts_with("large")),
+            false => assert!(value.starts_with("value")),
+        }
+    });
+}
+
+#[test]
+fn api_compact_digest_keys() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("compact.db");
+    let large = |n: usize| format!("{}{}", "x".repeat(1000), n);
+
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .extended(true)
+        .digest_keys(Some(64))
+        .open(&path)
+        .unwrap();
+    (0..20).for_each(|n| {
+        db.insert(large(n), format!("large {}", n)).unwrap();
+    });
+    (0..20).filter(|n| n % 2 == 0).for_each(|n| {
+        db.remove(large(n).as_str()).unwrap();
+    });
+    db.compact().unwrap();
+
+    // still stored by digest: a 24-byte key in place of each large one
+    assert_eq!(db.layout_efficiency().unwrap().avg_key, 24);
+    assert_eq!(
+        db.get::<_, String>(large(1).as_str()).unwrap(),
+        Some("large 1".to_string())
+    );
+    db.close().unwrap();
+
+    let mut db = OpenOptions::new().open(&path).unwrap();
+    assert_eq!(db.layout_efficiency().unwrap().avg_key, 24);
+    assert_eq!(db.len().unwrap(), 10);
+    assert!(db.contains_key(large(19).as_str()).unwrap());
+}
+
+#[test]
+fn api_seal() {
+    use gdbm_native::Error;
+
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .extended(true)
+        .open(file.path())
+        .unwrap();
+    db.insert("key".to_string(), "value".to_string()).unwrap();
+    assert!(!db.is_sealed());
+    db.seal().unwrap();
+
+    // readers are unaffected
+    let mut db = OpenOptions::new().open(file.path()).unwrap();
+    assert!(db.is_sealed());
+    assert_eq!(db.get("key").unwrap(), Some("value".to_string()));
+
+    assert!(matches!(
+        OpenOptions::new().write().open(file.path()),
+        Err(Error::Sealed)
+    ));
+    assert!(matches!(
+        OpenOptions::new().write().create().open(file.path()),
+        Err(Error::Sealed)
+    ));
+
+    let mut db = OpenOptions::new()
+        .write()
+        .force_unseal(true)
+        .open(file.path())
+        .unwrap();
+    assert!(!db.is_sealed());
+    db.insert("key".to_string(), "changed".to_string()).unwrap();
+    db.close().unwrap();
+
+    let mut db = OpenOptions::new().write().open(file.path()).unwrap();
+    assert_eq!(db.get("key").unwrap(), Some("changed".to_string()));
+
+    // only extended databases can be sealed
+    let other = NamedTempFile::new().unwrap();
+    let db = OpenOptions::new()
+        .write()
+        .create()
+        .open(other.path())
+        .unwrap();
+    assert!(db.seal().is_err());
+}
+
+#[test]
+fn api_insert_detailed() {
+    use gdbm_native::BlockSize;
+
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .block_size(BlockSize::Exactly(512))
+        .open(file.path())
+        .unwrap();
+
+    let outcomes = (0..100)
+        .map(|n| {
+            db.insert_detailed(format!("key {n}"), format!("value {n}"))
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    assert!(outcomes.iter().all(|outcome| outcome.replaced.is_none()));
+    assert!(outcomes.iter().any(|outcome| outcome.caused_split));
+    assert!(!outcomes[0].caused_split);
+
+    let outcome = db
+        .insert_detailed("key 7".to_string(), "new value".to_string())
+        .unwrap();
+    assert_eq!(outcome.replaced, Some(b"value 7".to_vec()));
+    db.sync().unwrap();
+
+    // the record is at the offset reported
+    let contents = fs::read(file.path()).unwrap();
+    let offset = outcome.new_offset as usize;
+    assert_eq!(&contents[offset..offset + 14], b"key 7new value");
+}
+
+#[test]
+#[cfg(feature = "testutil")]
+fn api_force_split() {
+    use gdbm_native::Event;
+    use std::sync::{Arc, Mutex};
+
+    let file = NamedTempFile::new().unwrap();
+    let mut db = OpenOptions::new()
+        .write()
+        .create()
+        .open(file.path())
+        .unwrap();
+    (0..10).for_each(|n| {
+        db.insert(format!("key {n}"), format!("value {n}")).unwrap();
+    });
+    assert_eq!(db.buckets().len(), 1);
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let hook_events = events.clone();
+    db.set_event_hook(Some(Box::new(move |event| {
+        hook_events.lock().unwrap().push(*event)
+    })));
+
+    db.force_extend_directory().unwrap();
+    assert!(matches!(
+        events.lock().unwrap()[..],
+        [Event::DirectoryExtended { .. }]
+    ));
+    assert_eq!(db.buckets().len(), 1);
+
+    db.force_split("key 3").unwrap();
+    assert!(matches!(
+        events.lock().unwrap()[1..],
+        [Event::BucketSplit { .. }]
+    ));
+    assert_eq!(db.buckets().len(), 2);
+
+    // buckets split down to every bit of the directory extend it
+    (0..12).for_each(|_| db.force_split("key 3").unwrap());
+    let events = events.lock().unwrap().clone();
+    assert_eq!(
+        events
+            .iter()
+            .filter(|event| matches!(event, Event::BucketSplit { .. }))
+            .count(),
+        13
+    );
+    assert!(events[2..]
+        .iter()
+        .any(|event| matches!(event, Event::DirectoryExtended { .. })));
+    assert_eq!(db.buckets().len(), 14);
+    db.close().unwrap();
+
+    let mut db = OpenOptions::new().open(file.path()).unwrap();
+    db.verify().unwrap();
+    (0..10).for_each(|n| {
+        assert_eq!(
+            db.get(format!("key {n}").as_str()).unwrap(),
+            Some(format!("value {n}"))
+        );
+    });
+}